@@ -0,0 +1,215 @@
+//! Benchmarks for listing articles, with each filter, for the feed, and for serializing the
+//! pages they make, so refactors that slow the busiest queries down show up.
+//!
+//! Rows are inserted into the database in `.env.test`, or `DATABASE_URL`, which must be
+//! migrated. They're inserted in a transaction that's never committed, so nothing is left
+//! behind. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::{FutureExt, TryFutureExt};
+use futures01::Future as Future01;
+use realworld_gotham::conduit::articles::{self, ListParams, Status, Visibility};
+use realworld_gotham::conduit::{favorites, followers, users};
+use realworld_gotham::config::DatabaseConfig;
+use realworld_gotham::models::{NewArticle, NewUser};
+use realworld_gotham::web::articles::{feed_page, list_page};
+use realworld_gotham::Repo;
+use std::future::Future;
+use tokio_threadpool::ThreadPool;
+
+const AUTHORS: usize = 20;
+const ARTICLES_PER_AUTHOR: usize = 50;
+const TAGS: &[&str] = &["rust", "gotham", "diesel", "async", "web", "databases"];
+/// Pages serialized, from the default limit to beyond the default `MAX_PAGE_SIZE`.
+const PAGE_SIZES: &[i64] = &[20, 100, 500];
+
+/// The seeded database, and a threadpool for `Repo` queries to block on.
+struct Fixture {
+    pool: ThreadPool,
+    repo: Repo,
+    reader_id: i32,
+}
+
+impl Fixture {
+    fn seed() -> Fixture {
+        dotenv::from_filename(".env.test").ok();
+        let config =
+            DatabaseConfig::from_env().unwrap_or_else(|e| panic!("Invalid configuration: {}", e));
+        let repo = Repo::test(&config)
+            .unwrap_or_else(|e| panic!("Could not connect to the database: {}", e));
+        let pool = ThreadPool::new();
+        let reader_id = run(&pool, seed(repo.clone()));
+        Fixture {
+            pool,
+            repo,
+            reader_id,
+        }
+    }
+
+    fn run<F, T>(&self, future: F) -> T
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        run(&self.pool, future)
+    }
+}
+
+fn run<F, T>(pool: &ThreadPool, future: F) -> T
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    pool.spawn_handle(future.unit_error().boxed().compat())
+        .wait()
+        .expect("Future failed")
+}
+
+fn new_user(name: &str) -> NewUser {
+    NewUser {
+        username: format!("bench_{}", name),
+        email: format!("bench_{}@example.com", name),
+        password: "benchmark password".to_string(),
+    }
+}
+
+/// Insert authors with tagged articles, and a reader who follows every fourth author and
+/// favorites every third article. Returns the reader's id.
+async fn seed(repo: Repo) -> i32 {
+    let reader = users::insert(repo.clone(), new_user("reader"))
+        .await
+        .unwrap();
+    for a in 0..AUTHORS {
+        let author = users::insert(repo.clone(), new_user(&format!("author{}", a)))
+            .await
+            .unwrap();
+        if a % 4 == 0 {
+            followers::follow(repo.clone(), reader.id, author.id)
+                .await
+                .unwrap();
+        }
+        for n in 0..ARTICLES_PER_AUTHOR {
+            let article = NewArticle {
+                title: format!("Article {} by {}", n, author.username),
+                slug: format!("bench-{}-{}", author.username, n),
+                description: "A description about as long as a real one.".to_string(),
+                body: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40),
+                user_id: author.id,
+                status: Status::Published.name().to_string(),
+                publish_at: None,
+                visibility: Visibility::Public.name().to_string(),
+                cover_image: None,
+                canonical_url: None,
+            };
+            let tag_list = vec![
+                TAGS[n % TAGS.len()].to_string(),
+                TAGS[(n + a) % TAGS.len()].to_string(),
+            ];
+            let (article, _) = articles::insert_with_tags(repo.clone(), article, tag_list)
+                .await
+                .unwrap();
+            if n % 3 == 0 {
+                favorites::favorite(repo.clone(), reader.id, article.id)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+    reader.id
+}
+
+fn list(c: &mut Criterion, fixture: &Fixture) {
+    let filters = vec![
+        ("all", ListParams::default()),
+        (
+            "tag",
+            ListParams {
+                tag: Some("rust".to_string()),
+                ..ListParams::default()
+            },
+        ),
+        (
+            "author",
+            ListParams {
+                author: Some(new_user("author0").username),
+                ..ListParams::default()
+            },
+        ),
+        (
+            "favorited",
+            ListParams {
+                favorited: Some(new_user("reader").username),
+                ..ListParams::default()
+            },
+        ),
+    ];
+    let reader_id = Some(fixture.reader_id);
+    let mut group = c.benchmark_group("conduit::articles::list");
+    for (filter, params) in &filters {
+        group.bench_with_input(BenchmarkId::from_parameter(filter), params, |b, params| {
+            b.iter(|| {
+                fixture.run(articles::list(
+                    fixture.repo.clone(),
+                    reader_id,
+                    params.clone(),
+                ))
+            })
+        });
+    }
+    group.finish();
+
+    // The whole page a signed in user gets: with tags and their favorites.
+    c.bench_function("web::articles::list_page", |b| {
+        b.iter(|| {
+            let params = ListParams::default();
+            fixture.run(list_page(
+                fixture.repo.clone(),
+                Some(fixture.reader_id),
+                params,
+            ))
+        })
+    });
+}
+
+fn feed(c: &mut Criterion, fixture: &Fixture) {
+    let reader_id = fixture.reader_id;
+    c.bench_function("conduit::articles::feed", |b| {
+        b.iter(|| fixture.run(articles::feed(fixture.repo.clone(), reader_id, 20, 0)))
+    });
+    c.bench_function("web::articles::feed_page", |b| {
+        b.iter(|| fixture.run(feed_page(fixture.repo.clone(), reader_id, 20, 0)))
+    });
+}
+
+fn serialization(c: &mut Criterion, fixture: &Fixture) {
+    let mut group = c.benchmark_group("serialize ArticlesResponse");
+    for &size in PAGE_SIZES {
+        let params = ListParams {
+            limit: size,
+            ..ListParams::default()
+        };
+        let page = fixture
+            .run(list_page(
+                fixture.repo.clone(),
+                Some(fixture.reader_id),
+                params,
+            ))
+            .unwrap_or_else(|e| panic!("Could not list articles: {}", e));
+        group.throughput(Throughput::Elements(page.articles.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &page, |b, page| {
+            b.iter(|| serde_json::to_vec(page).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Seed once, as inserting the articles takes far longer than any of the benchmarks.
+fn articles(c: &mut Criterion) {
+    let fixture = Fixture::seed();
+    list(c, &fixture);
+    feed(c, &fixture);
+    serialization(c, &fixture);
+}
+
+criterion_group!(benches, articles);
+criterion_main!(benches);
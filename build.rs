@@ -0,0 +1,6 @@
+// Migrations are embedded in the binary, so rebuild when they change.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=migrations_sqlite");
+    println!("cargo:rerun-if-changed=migrations_mysql");
+}
@@ -0,0 +1 @@
+DROP TABLE followers;
\ No newline at end of file
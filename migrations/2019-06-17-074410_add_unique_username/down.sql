@@ -0,0 +1 @@
+ALTER TABLE users DROP CONSTRAINT users_username_key;
\ No newline at end of file
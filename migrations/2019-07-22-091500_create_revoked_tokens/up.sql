@@ -0,0 +1,5 @@
+-- Tokens signed out before they expire. Rows can be removed once expires_at has passed.
+CREATE TABLE revoked_tokens (
+    jti VARCHAR PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Failed logins, counted to lock out brute-force attempts. A successful login clears its rows.
+CREATE TABLE login_attempts (
+    id SERIAL PRIMARY KEY,
+    login VARCHAR NOT NULL,
+    ip VARCHAR,
+    attempted_at TIMESTAMP NOT NULL
+);
+CREATE INDEX login_attempts_login_idx ON login_attempts (login, attempted_at);
+CREATE INDEX login_attempts_ip_idx ON login_attempts (ip, attempted_at);
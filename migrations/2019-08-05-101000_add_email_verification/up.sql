@@ -0,0 +1,10 @@
+ALTER TABLE users ADD COLUMN verified BOOLEAN NOT NULL DEFAULT FALSE;
+-- Accounts from before verification existed are trusted as they are.
+UPDATE users SET verified = TRUE;
+
+-- Tokens emailed to new users, removed once used.
+CREATE TABLE email_verifications (
+    token VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,9 @@
+-- Accounts with OAuth providers that users sign in with, identified by the provider's user id.
+CREATE TABLE identities (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT identities_provider_subject_key UNIQUE (provider, subject)
+);
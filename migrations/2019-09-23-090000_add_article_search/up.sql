@@ -0,0 +1,7 @@
+-- Indexes the weighted document full-text search matches against. Queries must use
+-- exactly this expression (see `conduit::search`) for the index to be used.
+CREATE INDEX articles_search_idx ON articles USING GIN ((
+    setweight(to_tsvector('english', title), 'A')
+    || setweight(to_tsvector('english', description), 'B')
+    || setweight(to_tsvector('english', body), 'C')
+));
@@ -0,0 +1,13 @@
+-- Work queued for the background worker. Jobs are deleted once done, and kept with
+-- `failed_at` set once they've used up their attempts.
+CREATE TABLE jobs (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    run_at TIMESTAMP NOT NULL,
+    last_error TEXT,
+    failed_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX jobs_run_at_idx ON jobs (run_at);
@@ -0,0 +1,9 @@
+-- URLs that are sent the events they subscribe to, signed with their secret.
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    url VARCHAR NOT NULL,
+    secret VARCHAR NOT NULL,
+    -- The names of the events it's sent, comma separated.
+    events VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,13 @@
+-- What articles were before each edit, for their authors to look back at and restore.
+-- `version` is the article's version the snapshot was of, and `created_at` when it was
+-- replaced.
+CREATE TABLE article_revisions (
+    id SERIAL PRIMARY KEY,
+    article_id INTEGER NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    title VARCHAR NOT NULL,
+    description VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX article_revisions_article_id_idx ON article_revisions (article_id);
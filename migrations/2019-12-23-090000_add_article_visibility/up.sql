@@ -0,0 +1,3 @@
+-- Either 'public', 'unlisted' or 'private'. Unlisted articles are left out of listings but
+-- can be read by anyone with their slug, and private ones only by their authors.
+ALTER TABLE articles ADD COLUMN visibility VARCHAR NOT NULL DEFAULT 'public';
@@ -0,0 +1,8 @@
+-- Worked out whenever the body changes, so they're not recounted on every read. Existing
+-- articles are counted here, splitting on whitespace like the app does. `reading_time` is
+-- in minutes.
+ALTER TABLE articles ADD COLUMN word_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE articles ADD COLUMN reading_time INTEGER NOT NULL DEFAULT 0;
+UPDATE articles SET word_count = COALESCE(array_length(regexp_split_to_array(trim(body), '\s+'), 1), 0)
+    WHERE trim(body) <> '';
+UPDATE articles SET reading_time = (word_count + 199) / 200;
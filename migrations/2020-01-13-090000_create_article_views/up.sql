@@ -0,0 +1,10 @@
+-- Who read each article in which hour, so a reader only counts once an hour however often
+-- they reload it. `viewer` is `user:` and a user's id, or `ip:` and a hash of a visitor's
+-- address.
+CREATE TABLE article_views (
+    article_id INTEGER NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    viewer VARCHAR NOT NULL,
+    viewed_hour TIMESTAMP NOT NULL,
+    PRIMARY KEY (article_id, viewer, viewed_hour)
+);
+CREATE INDEX article_views_article_id_viewed_hour_idx ON article_views (article_id, viewed_hour);
@@ -0,0 +1,8 @@
+-- The scores of recently published articles, recomputed periodically by a background job
+-- so listing what's trending is a cheap read.
+CREATE TABLE trending_articles (
+    article_id INTEGER PRIMARY KEY REFERENCES articles(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    computed_at TIMESTAMP NOT NULL
+);
+CREATE INDEX trending_articles_score_idx ON trending_articles (score);
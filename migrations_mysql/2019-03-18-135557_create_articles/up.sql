@@ -0,0 +1,12 @@
+CREATE TABLE articles (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    title VARCHAR(255) NOT NULL,
+    slug VARCHAR(255) NOT NULL,
+    description VARCHAR(1024) NOT NULL,
+    body TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    CONSTRAINT articles_slug_key UNIQUE (slug),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- The lowercased emails are kept.
+SELECT 1;
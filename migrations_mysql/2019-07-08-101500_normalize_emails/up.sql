@@ -0,0 +1,3 @@
+-- Emails are now stored trimmed and lowercased.
+-- The default collation already compares case-insensitively, so the existing unique key suffices.
+UPDATE users SET email = lower(trim(email));
@@ -0,0 +1,2 @@
+-- Tokens issued before this time are no longer accepted.
+ALTER TABLE users ADD COLUMN password_changed_at TIMESTAMP NULL DEFAULT NULL;
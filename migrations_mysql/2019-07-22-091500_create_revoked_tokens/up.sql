@@ -0,0 +1,6 @@
+-- Tokens signed out before they expire. Rows can be removed once expires_at has passed.
+CREATE TABLE revoked_tokens (
+    jti VARCHAR(64) NOT NULL PRIMARY KEY,
+    -- The explicit default stops MySQL from setting it on every update.
+    expires_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
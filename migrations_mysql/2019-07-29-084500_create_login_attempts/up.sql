@@ -0,0 +1,9 @@
+-- Failed logins, counted to lock out brute-force attempts. A successful login clears its rows.
+CREATE TABLE login_attempts (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    login VARCHAR(255) NOT NULL,
+    ip VARCHAR(64),
+    attempted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX login_attempts_login_idx (login, attempted_at),
+    INDEX login_attempts_ip_idx (ip, attempted_at)
+);
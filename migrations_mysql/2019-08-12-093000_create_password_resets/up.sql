@@ -0,0 +1,8 @@
+-- Tokens emailed to users who forgot their password, removed once used.
+CREATE TABLE password_resets (
+    token VARCHAR(64) NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    -- The explicit default stops MySQL from setting it on every update.
+    expires_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
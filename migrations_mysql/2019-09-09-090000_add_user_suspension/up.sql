@@ -0,0 +1,2 @@
+-- Suspended users can't log in, and their tokens and API keys are turned away.
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMP NULL DEFAULT NULL;
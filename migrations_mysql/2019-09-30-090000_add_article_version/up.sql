@@ -0,0 +1,2 @@
+-- Bumped on every edit, so an edit based on an old copy of the article can be refused.
+ALTER TABLE articles ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,5 @@
+-- InnoDB drops the index it made for the `user_id` foreign key once another one covers it,
+-- so the foreign key needs one of its own again.
+ALTER TABLE articles
+    ADD INDEX articles_user_id_fk_idx (user_id),
+    DROP INDEX articles_user_id_idx;
@@ -0,0 +1,6 @@
+-- Indexes for lookups no key covers yet. Emails, slugs, and favorites and follows by who
+-- made them are already covered by their unique or primary keys. InnoDB also indexes every
+-- foreign key column on its own, so articles with a tag, who favorited an article and a
+-- user's followers need nothing more.
+-- An author's articles, newest first.
+ALTER TABLE articles ADD INDEX articles_user_id_idx (user_id, created_at);
@@ -0,0 +1,14 @@
+-- Work queued for the background worker. Jobs are deleted once done, and kept with
+-- `failed_at` set once they've used up their attempts.
+CREATE TABLE jobs (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    kind VARCHAR(64) NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- The explicit default stops MySQL from setting it on every update.
+    run_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_error TEXT,
+    failed_at TIMESTAMP NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX jobs_run_at_idx (run_at)
+);
@@ -0,0 +1,9 @@
+-- URLs that are sent the events they subscribe to, signed with their secret.
+CREATE TABLE webhooks (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    url VARCHAR(2048) NOT NULL,
+    secret VARCHAR(64) NOT NULL,
+    -- The names of the events it's sent, comma separated.
+    events VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
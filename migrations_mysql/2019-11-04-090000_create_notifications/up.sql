@@ -0,0 +1,17 @@
+-- Things other users did that concern a user: following them, or commenting on, favoriting
+-- or mentioning them in an article.
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    user_id INTEGER NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    actor_id INTEGER NOT NULL,
+    article_id INTEGER NULL,
+    comment_id INTEGER NULL,
+    read_at TIMESTAMP NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (actor_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE,
+    FOREIGN KEY (comment_id) REFERENCES comments(id) ON DELETE CASCADE,
+    INDEX notifications_user_id_idx (user_id, id)
+);
@@ -0,0 +1 @@
+ALTER TABLE comments DROP FOREIGN KEY comments_parent_id_fk, DROP COLUMN parent_id;
@@ -0,0 +1,6 @@
+-- The comment a comment replies to, if it's a reply. Replies to a deleted comment are kept,
+-- as comments on the article.
+ALTER TABLE comments
+    ADD COLUMN parent_id INTEGER NULL,
+    ADD CONSTRAINT comments_parent_id_fk
+        FOREIGN KEY (parent_id) REFERENCES comments(id) ON DELETE SET NULL;
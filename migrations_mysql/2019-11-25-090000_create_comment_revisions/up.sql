@@ -0,0 +1,10 @@
+-- What comments said before they were edited, for moderators. `created_at` is when the body
+-- was replaced.
+CREATE TABLE comment_revisions (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    comment_id INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (comment_id) REFERENCES comments(id) ON DELETE CASCADE,
+    INDEX comment_revisions_comment_id_idx (comment_id)
+);
@@ -0,0 +1,14 @@
+-- What articles were before each edit, for their authors to look back at and restore.
+-- `version` is the article's version the snapshot was of, and `created_at` when it was
+-- replaced.
+CREATE TABLE article_revisions (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    article_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    description VARCHAR(1024) NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE,
+    INDEX article_revisions_article_id_idx (article_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE articles DROP INDEX articles_publish_at_idx, DROP COLUMN publish_at;
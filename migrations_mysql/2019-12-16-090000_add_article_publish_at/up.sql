@@ -0,0 +1,4 @@
+-- When a draft is to be published by the scheduler, if it's been scheduled.
+ALTER TABLE articles
+    ADD COLUMN publish_at TIMESTAMP NULL DEFAULT NULL,
+    ADD INDEX articles_publish_at_idx (publish_at);
@@ -0,0 +1,9 @@
+-- Worked out whenever the body changes, so they're not recounted on every read. Existing
+-- articles are counted here by the spaces between their words, which is close enough until
+-- they're next edited. `reading_time` is in minutes.
+ALTER TABLE articles
+    ADD COLUMN word_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN reading_time INTEGER NOT NULL DEFAULT 0;
+UPDATE articles SET word_count = LENGTH(TRIM(body)) - LENGTH(REPLACE(TRIM(body), ' ', '')) + 1
+    WHERE TRIM(body) <> '';
+UPDATE articles SET reading_time = (word_count + 199) DIV 200;
@@ -0,0 +1,11 @@
+-- Who read each article in which hour, so a reader only counts once an hour however often
+-- they reload it. `viewer` is `user:` and a user's id, or `ip:` and a hash of a visitor's
+-- address.
+CREATE TABLE article_views (
+    article_id INTEGER NOT NULL,
+    viewer VARCHAR(64) NOT NULL,
+    viewed_hour TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (article_id, viewer, viewed_hour),
+    FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE,
+    INDEX article_views_article_id_viewed_hour_idx (article_id, viewed_hour)
+);
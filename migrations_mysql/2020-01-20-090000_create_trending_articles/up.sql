@@ -0,0 +1,9 @@
+-- The scores of recently published articles, recomputed periodically by a background job
+-- so listing what's trending is a cheap read.
+CREATE TABLE trending_articles (
+    article_id INTEGER PRIMARY KEY,
+    score DOUBLE NOT NULL,
+    computed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE,
+    INDEX trending_articles_score_idx (score)
+);
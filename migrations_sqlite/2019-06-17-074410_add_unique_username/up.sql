@@ -0,0 +1,2 @@
+-- SQLite can't add a constraint to an existing table, but a unique index does the same job.
+CREATE UNIQUE INDEX users_username_key ON users (username);
@@ -0,0 +1,4 @@
+-- Emails are now stored trimmed and lowercased.
+-- Accounts that only differ by the case of their email have to be merged by hand first.
+UPDATE users SET email = lower(trim(email));
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email));
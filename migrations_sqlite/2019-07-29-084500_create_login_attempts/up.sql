@@ -0,0 +1,9 @@
+-- Failed logins, counted to lock out brute-force attempts. A successful login clears its rows.
+CREATE TABLE login_attempts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    login VARCHAR(255) NOT NULL,
+    ip VARCHAR(64),
+    attempted_at TIMESTAMP NOT NULL
+);
+CREATE INDEX login_attempts_login_idx ON login_attempts (login, attempted_at);
+CREATE INDEX login_attempts_ip_idx ON login_attempts (ip, attempted_at);
@@ -0,0 +1,6 @@
+-- Tokens emailed to users who forgot their password, removed once used.
+CREATE TABLE password_resets (
+    token VARCHAR(64) NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Either 'user' or 'admin'.
+ALTER TABLE users ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT 'user';
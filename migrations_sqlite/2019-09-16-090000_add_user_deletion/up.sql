@@ -0,0 +1,2 @@
+-- Deleted accounts are kept, anonymized, so their articles and comments stay in place.
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP;
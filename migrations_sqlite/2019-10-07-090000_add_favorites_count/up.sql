@@ -0,0 +1,5 @@
+-- How many users favorited the article, updated along with `favorites` so lists don't have
+-- to count them for every article they show.
+ALTER TABLE articles ADD COLUMN favorites_count INTEGER NOT NULL DEFAULT 0;
+UPDATE articles
+SET favorites_count = (SELECT COUNT(*) FROM favorites WHERE favorites.article_id = articles.id);
@@ -0,0 +1,4 @@
+DROP INDEX followers_followed_id_idx;
+DROP INDEX favorites_article_id_idx;
+DROP INDEX article_tags_tag_id_idx;
+DROP INDEX articles_user_id_idx;
@@ -0,0 +1,10 @@
+-- Indexes for lookups no key covers yet. Emails (`users_email_lower_key`), slugs, and
+-- favorites and follows by who made them are already covered by their unique or primary keys.
+-- An author's articles, newest first.
+CREATE INDEX articles_user_id_idx ON articles (user_id, created_at);
+-- Articles with a tag.
+CREATE INDEX article_tags_tag_id_idx ON article_tags (tag_id);
+-- Who favorited an article, and the favorites deleted along with it.
+CREATE INDEX favorites_article_id_idx ON favorites (article_id);
+-- A user's followers, and the follows deleted along with them.
+CREATE INDEX followers_followed_id_idx ON followers (followed_id);
@@ -0,0 +1,4 @@
+-- The comment a comment replies to, if it's a reply. Replies to a deleted comment are kept,
+-- as comments on the article.
+ALTER TABLE comments ADD COLUMN parent_id INTEGER REFERENCES comments(id) ON DELETE SET NULL;
+CREATE INDEX comments_parent_id_idx ON comments (parent_id);
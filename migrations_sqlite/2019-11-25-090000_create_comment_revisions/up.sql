@@ -0,0 +1,9 @@
+-- What comments said before they were edited, for moderators. `created_at` is when the body
+-- was replaced.
+CREATE TABLE comment_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    comment_id INTEGER NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX comment_revisions_comment_id_idx ON comment_revisions (comment_id);
@@ -0,0 +1,2 @@
+-- Either 'draft' or 'published'. Drafts are only shown to their authors.
+ALTER TABLE articles ADD COLUMN status VARCHAR(16) NOT NULL DEFAULT 'published';
@@ -0,0 +1,3 @@
+-- When a draft is to be published by the scheduler, if it's been scheduled.
+ALTER TABLE articles ADD COLUMN publish_at TIMESTAMP;
+CREATE INDEX articles_publish_at_idx ON articles (publish_at);
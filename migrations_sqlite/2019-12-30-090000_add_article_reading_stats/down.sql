@@ -0,0 +1,2 @@
+ALTER TABLE articles DROP COLUMN reading_time;
+ALTER TABLE articles DROP COLUMN word_count;
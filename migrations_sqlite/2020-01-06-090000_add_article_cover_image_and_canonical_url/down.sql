@@ -0,0 +1,2 @@
+ALTER TABLE articles DROP COLUMN canonical_url;
+ALTER TABLE articles DROP COLUMN cover_image;
@@ -0,0 +1,3 @@
+-- An image to show with the article, and where it was first published if it's cross-posted.
+ALTER TABLE articles ADD COLUMN cover_image VARCHAR(2048);
+ALTER TABLE articles ADD COLUMN canonical_url VARCHAR(2048);
@@ -0,0 +1 @@
+DROP TABLE trending_articles;
@@ -0,0 +1,145 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+
+/// An Atom feed, as in RFC 4287.
+pub struct Feed {
+    /// Where the feed itself is, which is also its id.
+    pub url: String,
+    pub title: String,
+    /// When an entry last changed, for feeds with none.
+    pub updated: NaiveDateTime,
+    pub entries: Vec<Entry>,
+}
+
+pub struct Entry {
+    /// Where the entry can be read, which is also its id.
+    pub url: String,
+    pub title: String,
+    pub summary: String,
+    pub author: String,
+    pub categories: Vec<String>,
+    pub published: NaiveDateTime,
+    pub updated: NaiveDateTime,
+}
+
+impl Feed {
+    /// The feed as an XML document.
+    pub fn to_xml(&self) -> String {
+        let updated = self
+            .entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or(self.updated);
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        xml.push_str(&format!("  <id>{}</id>\n", escape(&self.url)));
+        xml.push_str(&format!("  <title>{}</title>\n", escape(&self.title)));
+        xml.push_str(&format!("  <updated>{}</updated>\n", timestamp(updated)));
+        xml.push_str(&format!(
+            "  <link rel=\"self\" href=\"{}\"/>\n",
+            escape(&self.url)
+        ));
+        for entry in &self.entries {
+            entry.push_xml(&mut xml);
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+impl Entry {
+    fn push_xml(&self, xml: &mut String) {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", escape(&self.url)));
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&self.title)));
+        xml.push_str(&format!(
+            "    <link rel=\"alternate\" href=\"{}\"/>\n",
+            escape(&self.url)
+        ));
+        xml.push_str(&format!(
+            "    <published>{}</published>\n",
+            timestamp(self.published)
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            timestamp(self.updated)
+        ));
+        xml.push_str(&format!(
+            "    <author><name>{}</name></author>\n",
+            escape(&self.author)
+        ));
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            escape(&self.summary)
+        ));
+        for category in &self.categories {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", escape(category)));
+        }
+        xml.push_str("  </entry>\n");
+    }
+}
+
+/// RFC 3339, in UTC, as Atom dates are.
+fn timestamp(time: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(time, Utc).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Escape text for element content and attribute values. Characters XML doesn't allow at
+/// all, e.g. most control characters, are dropped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_to_xml() {
+        let published = NaiveDate::from_ymd(2019, 12, 2).and_hms(9, 0, 0);
+        let feed = Feed {
+            url: "http://localhost:7878/feeds/tags/dragons.xml".to_string(),
+            title: "Articles tagged dragons".to_string(),
+            updated: published,
+            entries: vec![Entry {
+                url: "http://localhost:7878/article/how-to-train-your-dragon".to_string(),
+                title: "How to train your dragon".to_string(),
+                summary: "Ever wonder how? <Fish> & patience.".to_string(),
+                author: "jake".to_string(),
+                categories: vec!["dragons".to_string()],
+                published,
+                updated: NaiveDate::from_ymd(2019, 12, 3).and_hms(10, 30, 0),
+            }],
+        };
+        let xml = feed.to_xml();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed"));
+        assert!(xml.contains("<updated>2019-12-03T10:30:00Z</updated>\n  <link rel=\"self\""));
+        assert!(xml.contains("<published>2019-12-02T09:00:00Z</published>"));
+        assert!(xml.contains("<summary>Ever wonder how? &lt;Fish&gt; &amp; patience.</summary>"));
+        assert!(xml.contains("<category term=\"dragons\"/>"));
+        assert!(xml.ends_with("</feed>\n"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("\"Fish\" & 'chips'"),
+            "&quot;Fish&quot; &amp; &apos;chips&apos;"
+        );
+        assert_eq!(escape("bell\u{7}\nline"), "bell\nline");
+    }
+}
@@ -0,0 +1,203 @@
+use diesel::result::Error as dieselError;
+use futures::compat::Future01CompatExt;
+use futures::{FutureExt, TryFutureExt};
+use futures01::future;
+use gotham::handler::{HandlerFuture, IntoResponse};
+use gotham::middleware::Middleware;
+use gotham::state::{FromState, State};
+use gotham_derive::{NewMiddleware, StateData};
+use hyper::HeaderMap;
+
+use crate::auth::{api_key_claims, api_key_from_headers, claims_from_headers, Claims, Role};
+use crate::conduit::Repositories;
+use crate::config::JwtConfig;
+use crate::db::RepoError;
+use crate::web::errors::ApiError;
+
+/// The claims of the signed in user, put into `State` when a request carries a valid token
+/// or API key.
+#[derive(StateData, Clone, Debug)]
+pub struct CurrentUser(pub Claims);
+
+/// Authenticates requests by their JWT, or for machine clients, an API key sent in the
+/// `X-Api-Key` header.
+/// Either requires a valid token, or lets anonymous requests through while still
+/// recognising signed in users, for public endpoints that show them more.
+#[derive(Clone, NewMiddleware)]
+pub struct AuthMiddleware {
+    config: JwtConfig,
+    required: bool,
+}
+
+impl AuthMiddleware {
+    /// Reject requests without a valid token with a 401.
+    pub fn required(config: JwtConfig) -> Self {
+        AuthMiddleware {
+            config,
+            required: true,
+        }
+    }
+
+    /// Allow requests without a token. A missing, invalid or expired token is treated as anonymous.
+    pub fn optional(config: JwtConfig) -> Self {
+        AuthMiddleware {
+            config,
+            required: false,
+        }
+    }
+}
+
+impl Middleware for AuthMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+        Self: Sized,
+    {
+        let headers = HeaderMap::borrow_from(&state);
+        let credentials = match claims_from_headers(&self.config, headers) {
+            Some(claims) => Credentials::Token(claims),
+            None => match api_key_from_headers(headers) {
+                Some(key) => Credentials::ApiKey(key),
+                None if !self.required => return chain(state),
+                None => {
+                    let res = ApiError::unauthorized().into_response(&state);
+                    return Box::new(future::ok((state, res)));
+                }
+            },
+        };
+        let repositories = Repositories::borrow_from(&state).clone();
+        let required = self.required;
+        let f = async move {
+            let claims = match authenticate(&repositories, credentials).await {
+                Ok(claims) => claims,
+                Err(e) => {
+                    let res = ApiError::from(e).into_response(&state);
+                    return Ok((state, res));
+                }
+            };
+            if let Some(claims) = claims {
+                state.put(CurrentUser(claims));
+            } else if required {
+                let res = ApiError::unauthorized().into_response(&state);
+                return Ok((state, res));
+            }
+            chain(state).compat().await
+        };
+        Box::new(f.boxed().compat())
+    }
+}
+
+/// The claims to sign in with for a token that came some other way than the `Authorization`
+/// header, checked as the middleware checks tokens. `None` if it isn't accepted.
+pub async fn authenticate_token(
+    repositories: &Repositories,
+    claims: Claims,
+) -> Result<Option<Claims>, RepoError> {
+    authenticate(repositories, Credentials::Token(claims)).await
+}
+
+/// What a request authenticates with. A token takes precedence over an API key.
+enum Credentials {
+    Token(Claims),
+    ApiKey(String),
+}
+
+/// The claims to sign the request in with, or `None` if the credentials aren't accepted.
+///
+/// Besides its signature and expiry, a token is checked against the revocation list and
+/// its user, so tokens from before a password change or a logout are turned away.
+/// Suspended and deleted users are turned away whatever they authenticate with.
+/// The role comes from the user rather than the token, so a change of role applies at once.
+async fn authenticate(
+    repositories: &Repositories,
+    credentials: Credentials,
+) -> Result<Option<Claims>, RepoError> {
+    let mut claims = match credentials {
+        Credentials::Token(claims) => {
+            if let Some(jti) = claims.token_id() {
+                if repositories.tokens.is_revoked(jti.to_string()).await? {
+                    return Ok(None);
+                }
+            }
+            claims
+        }
+        Credentials::ApiKey(key) => match repositories.api_keys.authenticate(key).await {
+            Ok(user_id) => api_key_claims(user_id, Role::User),
+            Err(RepoError::Query(dieselError::NotFound)) => return Ok(None),
+            Err(e) => return Err(e),
+        },
+    };
+    let user = match repositories.users.find(claims.user_id()).await {
+        Ok(user) => user,
+        // The credentials are for a user that no longer exists.
+        Err(RepoError::Query(dieselError::NotFound)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if user.suspended_at.is_some() || user.deleted_at.is_some() {
+        return Ok(None);
+    }
+    if let Some(changed_at) = user.password_changed_at {
+        if claims.issued_before(changed_at) {
+            return Ok(None);
+        }
+    }
+    claims.role = Role::from_name(&user.role);
+    Ok(Some(claims))
+}
+
+/// Turns away requests from users without at least the given role with a 403.
+/// Goes after a required `AuthMiddleware`, in a pipeline for routes like the admin API.
+#[derive(Clone, NewMiddleware)]
+pub struct RoleMiddleware {
+    role: Role,
+}
+
+impl RoleMiddleware {
+    pub fn new(role: Role) -> Self {
+        RoleMiddleware { role }
+    }
+
+    fn check(&self, user: Option<&CurrentUser>) -> Result<(), ApiError> {
+        match user {
+            Some(user) if user.0.role() >= self.role => Ok(()),
+            Some(_) => Err(ApiError::forbidden()),
+            None => Err(ApiError::unauthorized()),
+        }
+    }
+}
+
+impl Middleware for RoleMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+        Self: Sized,
+    {
+        match self.check(CurrentUser::try_borrow_from(&state)) {
+            Ok(()) => chain(state),
+            Err(e) => {
+                let res = e.into_response(&state);
+                Box::new(future::ok((state, res)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::claims_for;
+    use hyper::StatusCode;
+
+    #[test]
+    fn test_role_check() {
+        let admin_only = RoleMiddleware::new(Role::Admin);
+        let admin = CurrentUser(claims_for(1, Role::Admin, 3600));
+        let user = CurrentUser(claims_for(2, Role::User, 3600));
+        assert!(admin_only.check(Some(&admin)).is_ok());
+        assert!(RoleMiddleware::new(Role::User).check(Some(&admin)).is_ok());
+        let forbidden = admin_only.check(Some(&user)).unwrap_err();
+        assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+        let unauthorized = admin_only.check(None).unwrap_err();
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,277 @@
+pub mod middleware;
+pub mod password;
+
+use chrono::NaiveDateTime;
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use jsonwebtoken::{decode, encode, Header, Validation};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::JwtConfig;
+
+/// The header machine clients send their API key in, instead of a token.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// What a user may do beyond managing their own account and content.
+/// Roles are ordered, each allowed everything the ones before it are.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::User
+    }
+}
+
+impl Role {
+    /// The role stored as `name`. Anything unknown is treated as an ordinary user.
+    pub fn from_name(name: &str) -> Role {
+        match name {
+            "admin" => Role::Admin,
+            _ => Role::User,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Claims {
+    sub: i32,
+    /// Tokens issued before this claim was added don't have it.
+    #[serde(default)]
+    iat: u64,
+    exp: u64,
+    /// Identifies the token so it can be revoked. Empty for tokens issued before it was added.
+    #[serde(default)]
+    jti: String,
+    /// The user's role when the token was issued, for clients to decide what to show.
+    /// Requests are authorized by the user's current role, which `AuthMiddleware` puts here.
+    #[serde(default)]
+    role: Role,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> i32 {
+        self.sub
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// When the token was issued, in seconds since the epoch.
+    pub fn issued_at(&self) -> u64 {
+        self.iat
+    }
+
+    /// When the token expires, in seconds since the epoch.
+    pub fn expires_at(&self) -> u64 {
+        self.exp
+    }
+
+    /// The token's unique id, or `None` if it doesn't have one and so can't be revoked.
+    pub fn token_id(&self) -> Option<&str> {
+        if self.jti.is_empty() {
+            None
+        } else {
+            Some(&self.jti)
+        }
+    }
+
+    /// Whether the token was issued before `time`.
+    /// `iat` only has second precision, so a token from the same second counts as after.
+    pub fn issued_before(&self, time: NaiveDateTime) -> bool {
+        (self.iat as i64) < time.timestamp()
+    }
+}
+
+pub fn encode_token(config: &JwtConfig, sub: i32, role: Role) -> String {
+    encode(
+        &Header::new(config.algorithm),
+        &claims_for(sub, role, config.ttl),
+        config.secret.as_ref(),
+    )
+    .unwrap()
+}
+
+pub fn decode_token(config: &JwtConfig, token: &str) -> Option<Claims> {
+    decode::<Claims>(
+        token,
+        config.secret.as_ref(),
+        &Validation::new(config.algorithm),
+    )
+    .ok()
+    .map(|data| data.claims)
+}
+
+/// Extract the claims from the Authorization header, if one is present and valid.
+/// The RealWorld spec uses the "Token" scheme, but "Bearer" is accepted too.
+pub fn claims_from_headers(config: &JwtConfig, headers: &HeaderMap) -> Option<Claims> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let mut parts = value.trim().splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some(scheme), Some(token)) if is_token_scheme(scheme) => {
+                    decode_token(config, token.trim())
+                }
+                _ => None,
+            }
+        })
+}
+
+/// The API key from the `X-Api-Key` header, if one is present.
+pub fn api_key_from_headers(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn is_token_scheme(scheme: &str) -> bool {
+    scheme.eq_ignore_ascii_case("Token") || scheme.eq_ignore_ascii_case("Bearer")
+}
+
+pub fn claims_for(user_id: i32, role: Role, expire_in: u64) -> Claims {
+    Claims {
+        sub: user_id,
+        iat: seconds_from_now(0),
+        exp: seconds_from_now(expire_in),
+        jti: random_token(),
+        role,
+    }
+}
+
+/// Claims for a request authenticated with an API key rather than a token.
+/// They have no token id, so the key isn't affected by logging out; it's revoked instead.
+pub fn api_key_claims(user_id: i32, role: Role) -> Claims {
+    let now = seconds_from_now(0);
+    Claims {
+        sub: user_id,
+        iat: now,
+        exp: now,
+        jti: String::new(),
+        role,
+    }
+}
+
+/// 128 random bits, hex encoded, for token ids and the tokens emailed to users.
+pub fn random_token() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+/// Seconds since the epoch, `secs` from now.
+pub fn seconds_from_now(secs: u64) -> u64 {
+    let expiry_time =
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + Duration::from_secs(secs);
+    expiry_time.as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers;
+
+    #[test]
+    fn test_encode_and_decode_token() {
+        let config = test_helpers::config().jwt;
+        let token = encode_token(&config, 42, Role::User);
+        assert_eq!(decode_token(&config, &token).unwrap().user_id(), 42);
+
+        let other = JwtConfig {
+            secret: "another secret".to_string(),
+            ..config
+        };
+        assert!(decode_token(&other, &token).is_none());
+    }
+
+    #[test]
+    fn test_claims_from_headers() {
+        let config = test_helpers::config().jwt;
+        let token = encode_token(&config, 42, Role::User);
+        for header in &[
+            format!("Token {}", token),
+            format!("Bearer {}", token),
+            format!("token {}", token),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, header.parse().unwrap());
+            let claims = claims_from_headers(&config, &headers);
+            assert_eq!(claims.map(|claims| claims.user_id()), Some(42));
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Basic {}", token).parse().unwrap());
+        assert!(claims_from_headers(&config, &headers).is_none());
+        assert!(claims_from_headers(&config, &HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_api_key_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert!(api_key_from_headers(&headers).is_none());
+        headers.insert(API_KEY_HEADER, " rwg_abc ".parse().unwrap());
+        assert_eq!(api_key_from_headers(&headers), Some("rwg_abc".to_string()));
+        assert!(api_key_claims(42, Role::User).token_id().is_none());
+    }
+
+    #[test]
+    fn test_roles() {
+        let config = test_helpers::config().jwt;
+        let token = encode_token(&config, 42, Role::Admin);
+        assert_eq!(decode_token(&config, &token).unwrap().role(), Role::Admin);
+        assert_eq!(Role::from_name(Role::Admin.name()), Role::Admin);
+        assert_eq!(Role::from_name("superuser"), Role::User);
+        assert!(Role::Admin > Role::User);
+    }
+
+    #[test]
+    fn test_claims_timestamps() {
+        let claims = claims_for(42, Role::User, 3600);
+        assert_eq!(claims.expires_at() - claims.issued_at(), 3600);
+    }
+
+    #[test]
+    fn test_token_ids_are_unique() {
+        let first = claims_for(42, Role::User, 3600);
+        let second = claims_for(42, Role::User, 3600);
+        assert_eq!(first.token_id().map(str::len), Some(32));
+        assert_ne!(first.token_id(), second.token_id());
+    }
+
+    #[test]
+    fn test_issued_before() {
+        let claims = claims_for(42, Role::User, 3600);
+        let issued_at = NaiveDateTime::from_timestamp(claims.issued_at() as i64, 0);
+        assert!(!claims.issued_before(issued_at));
+        assert!(claims.issued_before(issued_at + chrono::Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected() {
+        let config = test_helpers::config().jwt;
+        let claims = Claims {
+            sub: 42,
+            iat: seconds_from_now(0) - 7200,
+            exp: seconds_from_now(0) - 3600,
+            jti: random_token(),
+            role: Role::User,
+        };
+        let token = encode(&Header::default(), &claims, config.secret.as_ref()).unwrap();
+        assert!(decode_token(&config, &token).is_none());
+    }
+}
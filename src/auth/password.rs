@@ -0,0 +1,46 @@
+use argon2::{self, Config};
+use rand::Rng;
+
+const ARGON2_PREFIX: &str = "$argon2";
+
+/// Hash a plaintext password into an encoded argon2 string, including a random salt.
+pub fn hash(password: &str) -> Result<String, argon2::Error> {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    argon2::hash_encoded(password.as_bytes(), &salt, &Config::default())
+}
+
+/// Check a plaintext password against a stored value.
+/// Rows created before hashing was introduced still hold the plaintext password,
+/// so fall back to a direct comparison for those.
+pub fn verify(stored: &str, password: &str) -> bool {
+    if is_hashed(stored) {
+        argon2::verify_encoded(stored, password.as_bytes()).unwrap_or(false)
+    } else {
+        stored == password
+    }
+}
+
+/// Whether a stored password has already been hashed, or is a legacy plaintext value.
+pub fn is_hashed(stored: &str) -> bool {
+    stored.starts_with(ARGON2_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hashed = hash("password").unwrap();
+        assert!(is_hashed(&hashed));
+        assert!(verify(&hashed, "password"));
+        assert!(!verify(&hashed, "not the password"));
+    }
+
+    #[test]
+    fn test_verify_legacy_plaintext() {
+        assert!(!is_hashed("password"));
+        assert!(verify("password", "password"));
+        assert!(!verify("password", "other"));
+    }
+}
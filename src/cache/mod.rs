@@ -0,0 +1,46 @@
+pub mod redis;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use gotham_derive::StateData;
+use std::sync::Arc;
+
+use crate::cache::redis::RedisCache;
+use crate::config::CacheConfig;
+
+/// Short-lived storage for response bodies that are read far more often than they change.
+/// It's never needed to serve a request: failures are logged and treated as misses.
+pub trait Cache: Send + Sync {
+    fn get(&self, key: &str) -> BoxFuture<'static, Option<String>>;
+    fn set(&self, key: &str, value: String) -> BoxFuture<'static, ()>;
+    /// Drop every entry with a key starting with `prefix`.
+    fn invalidate(&self, prefix: &str) -> BoxFuture<'static, ()>;
+}
+
+/// The `Cache` handlers use, put into `State` by the `CacheMiddleware`.
+#[derive(Clone, StateData)]
+pub struct SharedCache(pub Arc<dyn Cache>);
+
+/// Caches nothing, so every request goes to the database.
+pub struct NoCache;
+
+impl Cache for NoCache {
+    fn get(&self, _key: &str) -> BoxFuture<'static, Option<String>> {
+        future::ready(None).boxed()
+    }
+
+    fn set(&self, _key: &str, _value: String) -> BoxFuture<'static, ()> {
+        future::ready(()).boxed()
+    }
+
+    fn invalidate(&self, _prefix: &str) -> BoxFuture<'static, ()> {
+        future::ready(()).boxed()
+    }
+}
+
+/// A Redis cache when one is configured, and otherwise none.
+pub fn from_config(config: &CacheConfig) -> Arc<dyn Cache> {
+    match config.redis_url {
+        Some(ref url) => Arc::new(RedisCache::new(url, config.ttl)),
+        None => Arc::new(NoCache),
+    }
+}
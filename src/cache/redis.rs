@@ -0,0 +1,129 @@
+use futures::compat::Future01CompatExt;
+use futures::future::{BoxFuture, FutureExt};
+use futures01::future::poll_fn;
+use futures01::Async;
+use log::warn;
+use r2d2::{ManageConnection, Pool};
+use redis::{Client, Connection, RedisError};
+use std::time::Duration;
+use tokio_threadpool::blocking;
+
+use crate::cache::Cache;
+
+/// Every key is stored under this, so the cache can share a Redis with other apps.
+const NAMESPACE: &str = "conduit:";
+/// How long a request waits for a Redis connection before carrying on without the cache.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// A cache in Redis, shared by every instance of the app. Entries expire after the
+/// configured TTL, so anything not explicitly invalidated is only stale for that long.
+pub struct RedisCache {
+    pool: Pool<RedisConnectionManager>,
+    ttl: Duration,
+}
+
+impl RedisCache {
+    /// A cache that connects to Redis at `url` once it's first used, so the app starts
+    /// whether or not Redis is up. `url` is checked when the config is read.
+    pub fn new(url: &str, ttl: Duration) -> Self {
+        let client = Client::open(url).expect("REDIS_URL is checked by Config::from_env");
+        let pool = Pool::builder()
+            .connection_timeout(CHECKOUT_TIMEOUT)
+            .min_idle(Some(0))
+            .build_unchecked(RedisConnectionManager { client });
+        RedisCache { pool, ttl }
+    }
+
+    /// Run a blocking Redis command with a pooled connection, on the threadpool like
+    /// `Repo::run` does. Failures are logged and become `None`.
+    fn run<F, R>(&self, f: F) -> BoxFuture<'static, Option<R>>
+    where
+        F: FnOnce(&Connection) -> Result<R, RedisError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let mut f = Some(f);
+        let blocking_command = poll_fn(move || -> Result<Async<Option<R>>, ()> {
+            blocking(|| match pool.get() {
+                Ok(conn) => (f.take().expect("command already run"))(&*conn)
+                    .map_err(|e| warn!("Cache command failed: {}", e))
+                    .ok(),
+                Err(e) => {
+                    warn!("Could not connect to the cache: {}", e);
+                    None
+                }
+            })
+            .map_err(|_| panic!("the threadpool shut down"))
+        });
+        blocking_command
+            .compat()
+            .map(|result| match result {
+                Ok(value) => value,
+                Err(()) => unreachable!(),
+            })
+            .boxed()
+    }
+}
+
+impl Cache for RedisCache {
+    fn get(&self, key: &str) -> BoxFuture<'static, Option<String>> {
+        let key = format!("{}{}", NAMESPACE, key);
+        self.run(move |conn| redis::cmd("GET").arg(key).query::<Option<String>>(conn))
+            .map(|value| value.unwrap_or(None))
+            .boxed()
+    }
+
+    fn set(&self, key: &str, value: String) -> BoxFuture<'static, ()> {
+        let key = format!("{}{}", NAMESPACE, key);
+        let ttl = self.ttl.as_secs();
+        self.run(move |conn| {
+            redis::cmd("SET")
+                .arg(key)
+                .arg(value)
+                .arg("EX")
+                .arg(ttl)
+                .query::<()>(conn)
+        })
+        .map(|_| ())
+        .boxed()
+    }
+
+    /// Finds the keys with `SCAN`, which is fine for the few keys the app caches.
+    /// Prefixes are taken literally, so mustn't contain the glob characters `*?[`.
+    fn invalidate(&self, prefix: &str) -> BoxFuture<'static, ()> {
+        let pattern = format!("{}{}*", NAMESPACE, prefix);
+        self.run(move |conn| {
+            let mut scan = redis::cmd("SCAN");
+            scan.cursor_arg(0).arg("MATCH").arg(pattern);
+            let keys: Vec<String> = scan.iter(conn)?.collect();
+            if keys.is_empty() {
+                return Ok(());
+            }
+            redis::cmd("DEL").arg(keys).query::<()>(conn)
+        })
+        .map(|_| ())
+        .boxed()
+    }
+}
+
+/// Opens Redis connections for the pool.
+struct RedisConnectionManager {
+    client: Client,
+}
+
+impl ManageConnection for RedisConnectionManager {
+    type Connection = Connection;
+    type Error = RedisError;
+
+    fn connect(&self) -> Result<Connection, RedisError> {
+        self.client.get_connection()
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<(), RedisError> {
+        redis::cmd("PING").query(&*conn)
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
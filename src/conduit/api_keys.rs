@@ -0,0 +1,191 @@
+use crate::auth::random_token;
+use crate::db::RepoError;
+use crate::models::{ApiKey, NewApiKey};
+use crate::schema::api_keys;
+use crate::Repo;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::Error as dieselError;
+use futures::future::{BoxFuture, FutureExt};
+use sha2::{Digest, Sha256};
+
+/// Keys start with this, so a leaked one is easy to recognise.
+const KEY_PREFIX: &str = "rwg_";
+/// How much of a key is kept in the clear, to tell keys apart.
+const VISIBLE_LENGTH: usize = 12;
+
+/// A new key: the prefix and 256 random bits, hex encoded.
+pub fn generate_key() -> String {
+    format!("{}{}{}", KEY_PREFIX, random_token(), random_token())
+}
+
+/// The start of `key` that's stored and shown alongside its name.
+pub fn visible_prefix(key: &str) -> String {
+    key.chars().take(VISIBLE_LENGTH).collect()
+}
+
+/// The SHA-256 of `key`, hex encoded. Keys are random, so unlike passwords they don't need
+/// a slow, salted hash, and can be looked up by theirs.
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Create a key for `user_id`. Returns the stored key along with the key itself, which
+/// can't be recovered later.
+pub async fn create(repo: Repo, user_id: i32, name: String) -> Result<(ApiKey, String), RepoError> {
+    repo.run("api_keys::create", move |conn| {
+        let key = generate_key();
+        let new_key = NewApiKey {
+            user_id,
+            name,
+            prefix: visible_prefix(&key),
+            key_hash: hash_key(&key),
+        };
+        diesel::insert_into(api_keys::table)
+            .values(&new_key)
+            .execute(&conn)?;
+        let api_key = api_keys::table
+            .filter(api_keys::key_hash.eq(&new_key.key_hash))
+            .first(&conn)?;
+        Ok((api_key, key))
+    })
+    .await
+}
+
+/// The user's keys, oldest first.
+pub async fn list(repo: Repo, user_id: i32) -> Result<Vec<ApiKey>, RepoError> {
+    repo.run("api_keys::list", move |conn| {
+        api_keys::table
+            .filter(api_keys::user_id.eq(user_id))
+            .order(api_keys::id)
+            .load(&conn)
+    })
+    .await
+}
+
+/// Revoke one of the user's keys.
+/// Fails with `NotFound` if the user has no key with that id.
+pub async fn delete(repo: Repo, user_id: i32, id: i32) -> Result<(), RepoError> {
+    repo.run("api_keys::delete", move |conn| {
+        let deleted = diesel::delete(
+            api_keys::table
+                .filter(api_keys::id.eq(id))
+                .filter(api_keys::user_id.eq(user_id)),
+        )
+        .execute(&conn)?;
+        if deleted == 0 {
+            return Err(dieselError::NotFound);
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// The id of the user `key` belongs to, noting that the key was used.
+/// Fails with `NotFound` for an unknown or revoked key.
+pub async fn authenticate(repo: Repo, key: String) -> Result<i32, RepoError> {
+    repo.run("api_keys::authenticate", move |conn| {
+        let api_key = api_keys::table
+            .filter(api_keys::key_hash.eq(hash_key(&key)))
+            .first::<ApiKey>(&conn)?;
+        diesel::update(api_keys::table.find(api_key.id))
+            .set(api_keys::last_used_at.eq(Utc::now().naive_utc()))
+            .execute(&conn)?;
+        Ok(api_key.user_id)
+    })
+    .await
+}
+
+/// Storage for the API keys scripts and bots authenticate with.
+pub trait ApiKeysRepository: Send + Sync {
+    fn create(
+        &self,
+        user_id: i32,
+        name: String,
+    ) -> BoxFuture<'static, Result<(ApiKey, String), RepoError>>;
+    fn list(&self, user_id: i32) -> BoxFuture<'static, Result<Vec<ApiKey>, RepoError>>;
+    fn delete(&self, user_id: i32, id: i32) -> BoxFuture<'static, Result<(), RepoError>>;
+    fn authenticate(&self, key: String) -> BoxFuture<'static, Result<i32, RepoError>>;
+}
+
+/// API keys stored in Postgres with Diesel.
+pub struct PgApiKeysRepository {
+    repo: Repo,
+}
+
+impl PgApiKeysRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgApiKeysRepository { repo }
+    }
+}
+
+impl ApiKeysRepository for PgApiKeysRepository {
+    fn create(
+        &self,
+        user_id: i32,
+        name: String,
+    ) -> BoxFuture<'static, Result<(ApiKey, String), RepoError>> {
+        create(self.repo.clone(), user_id, name).boxed()
+    }
+
+    fn list(&self, user_id: i32) -> BoxFuture<'static, Result<Vec<ApiKey>, RepoError>> {
+        list(self.repo.clone(), user_id).boxed()
+    }
+
+    fn delete(&self, user_id: i32, id: i32) -> BoxFuture<'static, Result<(), RepoError>> {
+        delete(self.repo.clone(), user_id, id).boxed()
+    }
+
+    fn authenticate(&self, key: String) -> BoxFuture<'static, Result<i32, RepoError>> {
+        authenticate(self.repo.clone(), key).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::users;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_keys() {
+        let key = generate_key();
+        assert_eq!(key.len(), 68);
+        assert!(visible_prefix(&key).starts_with(KEY_PREFIX));
+        assert_eq!(hash_key(&key), hash_key(&key));
+        assert_ne!(hash_key(&key), hash_key(&generate_key()));
+        assert_eq!(
+            hash_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let (api_key, key) = create(repo.clone(), user.id, "ci".to_string())
+                .await
+                .unwrap();
+            assert_ne!(api_key.key_hash, key);
+            assert!(api_key.last_used_at.is_none());
+
+            let user_id = authenticate(repo.clone(), key.clone()).await.unwrap();
+            assert_eq!(user_id, user.id);
+            let keys = list(repo.clone(), user.id).await.unwrap();
+            assert!(keys[0].last_used_at.is_some());
+
+            delete(repo.clone(), user.id, api_key.id).await.unwrap();
+            assert!(authenticate(repo.clone(), key).await.is_err());
+            assert!(delete(repo, user.id, api_key.id).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,1482 @@
+use crate::conduit;
+use crate::conduit::favorites::FavoriteStatus;
+use crate::db::{DbConnection, RepoError};
+use crate::events::{self, Event};
+use crate::models::{
+    Article, ArticleRevision, NewArticle, NewArticleRevision, Profile, UpdateArticle, User,
+};
+use crate::reading;
+use crate::schema::{
+    article_revisions, article_tags, articles, favorites, followed_tags, followers, tags, users,
+};
+use crate::slugs;
+use crate::Repo;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::sql;
+use diesel::expression::SqlLiteral;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as dieselError};
+use diesel::sql_types::Bool;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+/// How many times to try a new slug suffix before giving up on a colliding title.
+const MAX_SLUG_ATTEMPTS: usize = 5;
+/// How many of the most recent articles sharing a tag or the author are scored as related.
+const RELATED_CANDIDATES: i64 = 100;
+/// How many days it takes for a related article to count half as much for its age.
+const RELATED_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Filters and paging for listing articles.
+#[derive(Debug, Clone)]
+pub struct ListParams {
+    pub tag: Option<String>,
+    pub author: Option<String>,
+    /// Only articles favorited by the user with this username.
+    pub favorited: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+    /// Only articles that come after this one, instead of skipping `offset` of them.
+    /// Cursors hold a creation time, so this only applies when sorting by `Sort::Created`.
+    pub after: Option<Cursor>,
+    pub sort: Sort,
+    pub direction: Direction,
+    /// Drafts are only ever the viewer's own, so listing them leaves out everyone else's.
+    pub status: Status,
+    /// Only the viewer's own articles, for `author=me`.
+    pub mine: bool,
+}
+
+impl Default for ListParams {
+    fn default() -> Self {
+        ListParams {
+            tag: None,
+            author: None,
+            favorited: None,
+            limit: 20,
+            offset: 0,
+            after: None,
+            sort: Sort::Created,
+            direction: Direction::Desc,
+            status: Status::Published,
+            mine: false,
+        }
+    }
+}
+
+/// What to order listed articles by. Articles that tie are ordered by id.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    Created,
+    Updated,
+    /// How many users favorited the article.
+    Favorites,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+/// Whether an article is still being written, or out for everyone to read.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Draft,
+    Published,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status::Published
+    }
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::Published => "published",
+        }
+    }
+}
+
+/// Who can find an article, once it's published.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Listed, searchable and in followers' feeds.
+    Public,
+    /// Readable by anyone with its slug, but not listed anywhere.
+    Unlisted,
+    /// Only for its author.
+    Private,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Public
+    }
+}
+
+impl Visibility {
+    /// The visibility stored as `name`, if it's one.
+    pub fn from_name(name: &str) -> Option<Visibility> {
+        match name {
+            "public" => Some(Visibility::Public),
+            "unlisted" => Some(Visibility::Unlisted),
+            "private" => Some(Visibility::Private),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+/// A position in the list of articles, to carry on from without counting past the
+/// articles before it. Articles are listed by `(created_at, id)`, so that's what it holds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub created_at: NaiveDateTime,
+    pub id: i32,
+}
+
+impl Cursor {
+    /// The position right after `article`.
+    pub fn after(article: &Article) -> Self {
+        Cursor {
+            created_at: article.created_at,
+            id: article.id,
+        }
+    }
+
+    /// An opaque string for clients to send back. It's only hex encoded, not signed:
+    /// a made up cursor can't get at anything the list wouldn't show anyway.
+    pub fn encode(&self) -> String {
+        let position = format!(
+            "{}.{}.{}",
+            self.created_at.timestamp(),
+            self.created_at.timestamp_subsec_nanos(),
+            self.id
+        );
+        position
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// The cursor `encode` returned, or `None` if `value` isn't one.
+    pub fn decode(value: &str) -> Option<Cursor> {
+        if value.len() % 2 != 0 || !value.is_ascii() {
+            return None;
+        }
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let position = String::from_utf8(bytes).ok()?;
+        let mut parts = position.split('.');
+        let seconds = parts.next()?.parse().ok()?;
+        let nanos = parts.next()?.parse().ok()?;
+        let id = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Cursor {
+            created_at: NaiveDateTime::from_timestamp_opt(seconds, nanos)?,
+            id,
+        })
+    }
+}
+
+/// Insert an article, using its slug as a base and adding a random suffix
+/// if another article has already taken it.
+pub async fn insert(repo: Repo, article: NewArticle) -> Result<Article, RepoError> {
+    repo.transaction("articles::insert", move |conn| {
+        insert_article(conn, &article)
+    })
+    .await
+}
+
+/// Insert an article and its tags in one transaction,
+/// so an article is never left without the tags it was created with.
+pub async fn insert_with_tags(
+    repo: Repo,
+    article: NewArticle,
+    tag_list: Vec<String>,
+) -> Result<(Article, Vec<String>), RepoError> {
+    repo.transaction("articles::insert_with_tags", move |conn| {
+        let article = insert_article(conn, &article)?;
+        let tag_list = conduit::tags::attach(conn, article.id, tag_list)?;
+        Ok((article, tag_list))
+    })
+    .await
+}
+
+/// The article with `slug`, unless it's someone else's draft or private article, which
+/// `viewer_id` can't see. Unlisted articles can be found this way by anyone.
+pub async fn find_visible(
+    repo: Repo,
+    slug: String,
+    viewer_id: Option<i32>,
+) -> Result<Article, RepoError> {
+    repo.run("articles::find_visible", move |conn| {
+        let own = articles::user_id.nullable().eq(viewer_id);
+        let readable = articles::status
+            .eq(Status::Published.name())
+            .and(articles::visibility.ne(Visibility::Private.name()));
+        articles::table
+            .filter(articles::slug.eq(slug))
+            .filter(readable.or(own))
+            .first(&conn)
+    })
+    .await
+}
+
+/// Publish a draft now, even if it was scheduled for later. Articles that are already
+/// published are left as they are.
+pub async fn publish(repo: Repo, article_id: i32) -> Result<Article, RepoError> {
+    repo.transaction("articles::publish", move |conn| {
+        let published = publish_draft(conn, article_id, Utc::now().naive_utc())?;
+        let article = articles::table.find(article_id).first::<Article>(conn)?;
+        if published > 0 {
+            publish_event(conn, &article)?;
+        }
+        Ok(article)
+    })
+    .await
+}
+
+/// Publish the drafts scheduled for `now` or earlier, returning how many there were. They're
+/// dated `now`, like drafts published by hand, so they're listed as new.
+pub async fn publish_scheduled(repo: Repo, now: NaiveDateTime) -> Result<usize, RepoError> {
+    repo.transaction("articles::publish_scheduled", move |conn| {
+        let due = articles::table
+            .filter(articles::status.eq(Status::Draft.name()))
+            .filter(articles::publish_at.le(now))
+            .load::<Article>(conn)?;
+        let mut published = 0;
+        for article in &due {
+            // Unless it's been published by hand since it was read.
+            if publish_draft(conn, article.id, now)? > 0 {
+                publish_event(conn, article)?;
+                published += 1;
+            }
+        }
+        Ok(published)
+    })
+    .await
+}
+
+pub async fn find_by_slug(repo: Repo, slug: String) -> Result<Article, RepoError> {
+    repo.run("articles::find_by_slug", move |conn| {
+        articles::table.filter(articles::slug.eq(slug)).first(&conn)
+    })
+    .await
+}
+
+/// An article with everything shown alongside it: its author, its tags, and how it's been
+/// favorited, as seen by the user asking for it.
+#[derive(Debug)]
+pub struct ArticleDto {
+    pub article: Article,
+    pub author: Profile,
+    pub tag_list: Vec<String>,
+    pub favorite: FavoriteStatus,
+    pub comments_count: i64,
+}
+
+/// A row of articles joined with their authors, and whether the viewer follows the author.
+type ArticleRow = (Article, User, bool);
+
+/// A page of articles matching the given filters, in the order asked for, along with how
+/// many match in all. The page starts after `params.after` when it's set, and
+/// `params.offset` is ignored.
+///
+/// Articles are loaded with their authors in one query, then the tags and favorites of the
+/// whole page in one query each, rather than looked up article by article.
+pub async fn list(
+    repo: Repo,
+    viewer_id: Option<i32>,
+    params: ListParams,
+) -> Result<(Vec<ArticleDto>, i64), RepoError> {
+    repo.run("articles::list", move |conn| {
+        // Subqueries can't name `users` as well as the join, so look the user up first.
+        let favorited_by = match params.favorited {
+            Some(ref username) => {
+                let user_id = users::table
+                    .select(users::id)
+                    .filter(users::username.eq(username))
+                    .first::<i32>(&conn)
+                    .optional()?;
+                match user_id {
+                    Some(user_id) => Some(user_id),
+                    None => return Ok((vec![], 0)),
+                }
+            }
+            None => None,
+        };
+        let filtered = || {
+            let mut query = articles::table
+                .inner_join(users::table)
+                .filter(articles::status.eq(params.status.name()))
+                .into_boxed::<Backend>();
+            if params.mine || params.status == Status::Draft {
+                // Matches nothing without a viewer.
+                query = query.filter(articles::user_id.nullable().eq(viewer_id));
+            } else {
+                query = query.filter(articles::visibility.eq(Visibility::Public.name()));
+            }
+            if let Some(ref tag) = params.tag {
+                query = query.filter(
+                    articles::id.eq_any(
+                        article_tags::table
+                            .inner_join(tags::table)
+                            .select(article_tags::article_id)
+                            .filter(tags::tag.eq(tag.clone())),
+                    ),
+                );
+            }
+            if let Some(ref author) = params.author {
+                query = query.filter(users::username.eq(author.clone()));
+            }
+            if let Some(user_id) = favorited_by {
+                query = query.filter(
+                    articles::id.eq_any(
+                        favorites::table
+                            .select(favorites::article_id)
+                            .filter(favorites::user_id.eq(user_id)),
+                    ),
+                );
+            }
+            query
+        };
+        let query = filtered()
+            .select((
+                articles::all_columns,
+                users::all_columns,
+                following(viewer_id),
+            ))
+            .limit(params.limit);
+        let query = match (params.sort, params.direction) {
+            (Sort::Created, Direction::Asc) => {
+                query.order((articles::created_at.asc(), articles::id.asc()))
+            }
+            (Sort::Created, Direction::Desc) => {
+                query.order((articles::created_at.desc(), articles::id.desc()))
+            }
+            (Sort::Updated, Direction::Asc) => {
+                query.order((articles::updated_at.asc(), articles::id.asc()))
+            }
+            (Sort::Updated, Direction::Desc) => {
+                query.order((articles::updated_at.desc(), articles::id.desc()))
+            }
+            (Sort::Favorites, Direction::Asc) => {
+                query.order((articles::favorites_count.asc(), articles::id.asc()))
+            }
+            (Sort::Favorites, Direction::Desc) => {
+                query.order((articles::favorites_count.desc(), articles::id.desc()))
+            }
+        };
+        let query = match (params.after, params.direction) {
+            (Some(cursor), Direction::Desc) => {
+                let same_time_lower_id = articles::created_at
+                    .eq(cursor.created_at)
+                    .and(articles::id.lt(cursor.id));
+                query.filter(
+                    articles::created_at
+                        .lt(cursor.created_at)
+                        .or(same_time_lower_id),
+                )
+            }
+            (Some(cursor), Direction::Asc) => {
+                let same_time_higher_id = articles::created_at
+                    .eq(cursor.created_at)
+                    .and(articles::id.gt(cursor.id));
+                query.filter(
+                    articles::created_at
+                        .gt(cursor.created_at)
+                        .or(same_time_higher_id),
+                )
+            }
+            (None, _) => query.offset(params.offset),
+        };
+        let rows = query.load::<ArticleRow>(&conn)?;
+        let count = filtered().count().get_result(&conn)?;
+        Ok((with_details(&conn, viewer_id, rows)?, count))
+    })
+    .await
+}
+
+type Backend = <DbConnection as Connection>::Backend;
+
+/// Whether `viewer_id` follows the author of the article in the outer query.
+fn following(viewer_id: Option<i32>) -> SqlLiteral<Bool> {
+    match viewer_id {
+        // An id is just a number, so it can't change what the query means.
+        Some(viewer_id) => sql(&format!(
+            "EXISTS (SELECT 1 FROM followers WHERE followers.follower_id = {} \
+             AND followers.followed_id = articles.user_id)",
+            viewer_id
+        )),
+        None => sql("1 = 0"),
+    }
+}
+
+/// Articles written by users that `user_id` follows, most recent first, along with how
+/// many there are in all. With `with_followed_tags`, other users' articles with the tags
+/// `user_id` follows are in it too. Loaded like `list` loads them.
+pub async fn feed(
+    repo: Repo,
+    user_id: i32,
+    with_followed_tags: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<ArticleDto>, i64), RepoError> {
+    repo.run("articles::feed", move |conn| {
+        let followed = articles::user_id.eq_any(
+            followers::table
+                .select(followers::followed_id)
+                .filter(followers::follower_id.eq(user_id)),
+        );
+        let tagged = articles::id.eq_any(
+            article_tags::table.select(article_tags::article_id).filter(
+                article_tags::tag_id.eq_any(
+                    followed_tags::table
+                        .select(followed_tags::tag_id)
+                        .filter(followed_tags::user_id.eq(user_id)),
+                ),
+            ),
+        );
+        let filtered = || {
+            let query = articles::table
+                .inner_join(users::table)
+                .filter(articles::status.eq(Status::Published.name()))
+                .filter(articles::visibility.eq(Visibility::Public.name()))
+                .into_boxed::<Backend>();
+            if with_followed_tags {
+                let others_tagged = articles::user_id.ne(user_id).and(tagged.clone());
+                query.filter(followed.clone().or(others_tagged))
+            } else {
+                query.filter(followed.clone())
+            }
+        };
+        let rows = filtered()
+            .select((
+                articles::all_columns,
+                users::all_columns,
+                following(Some(user_id)),
+            ))
+            .order((articles::created_at.desc(), articles::id.desc()))
+            .limit(limit)
+            .offset(offset)
+            .load::<ArticleRow>(&conn)?;
+        let count = filtered().count().get_result(&conn)?;
+        Ok((with_details(&conn, Some(user_id), rows)?, count))
+    })
+    .await
+}
+
+/// Up to `limit` articles to read after `article`: public ones sharing its tags or its
+/// author, best first. The most recent candidates are loaded in one query, then ranked by
+/// how much they have in common with it, how new they are and how often they were
+/// favorited.
+pub async fn related(
+    repo: Repo,
+    viewer_id: Option<i32>,
+    article: Article,
+    limit: i64,
+) -> Result<Vec<ArticleDto>, RepoError> {
+    repo.run("articles::related", move |conn| {
+        let shares_tag = articles::id.eq_any(
+            article_tags::table.select(article_tags::article_id).filter(
+                article_tags::tag_id.eq_any(
+                    article_tags::table
+                        .select(article_tags::tag_id)
+                        .filter(article_tags::article_id.eq(article.id)),
+                ),
+            ),
+        );
+        let rows = articles::table
+            .inner_join(users::table)
+            .select((
+                articles::all_columns,
+                users::all_columns,
+                following(viewer_id),
+            ))
+            .filter(articles::id.ne(article.id))
+            .filter(articles::status.eq(Status::Published.name()))
+            .filter(articles::visibility.eq(Visibility::Public.name()))
+            .filter(articles::user_id.eq(article.user_id).or(shares_tag))
+            .order((articles::created_at.desc(), articles::id.desc()))
+            .limit(RELATED_CANDIDATES)
+            .load::<ArticleRow>(&conn)?;
+        let tag_list = conduit::tags::by_article(&conn, &[article.id])?
+            .remove(&article.id)
+            .unwrap_or_default();
+        let now = Utc::now().naive_utc();
+        let mut scored: Vec<(f64, ArticleDto)> = with_details(&conn, viewer_id, rows)?
+            .into_iter()
+            .map(|dto| {
+                let shared_tags = dto
+                    .tag_list
+                    .iter()
+                    .filter(|tag| tag_list.contains(tag))
+                    .count();
+                let score = related_score(
+                    shared_tags,
+                    dto.article.user_id == article.user_id,
+                    now - dto.article.created_at,
+                    dto.favorite.count,
+                );
+                (score, dto)
+            })
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(scored
+            .into_iter()
+            .take(limit as usize)
+            .map(|(_, dto)| dto)
+            .collect())
+    })
+    .await
+}
+
+/// How related an article is: two points for each tag it shares, one for having the same
+/// author, halved for each month of its age and multiplied up as it's favorited.
+fn related_score(shared_tags: usize, same_author: bool, age: Duration, favorites: i64) -> f64 {
+    let relevance = 2.0 * shared_tags as f64 + if same_author { 1.0 } else { 0.0 };
+    let days = age.num_seconds().max(0) as f64 / (24.0 * 3600.0);
+    let recency = 0.5f64.powf(days / RELATED_HALF_LIFE_DAYS);
+    let popularity = 1.0 + (favorites.max(0) as f64).ln_1p();
+    relevance * recency * popularity
+}
+
+/// The given articles with their details, in the same order, for articles that were found
+/// some other way than `list`, e.g. by slug or by a search. The articles aren't read again,
+/// only their authors, so one deleted since can't go missing from the result. It's
+/// `NotFound` if an author is gone.
+pub async fn details(
+    repo: Repo,
+    viewer_id: Option<i32>,
+    articles: Vec<Article>,
+) -> Result<Vec<ArticleDto>, RepoError> {
+    repo.run("articles::details", move |conn| {
+        let author_ids: Vec<i32> = articles.iter().map(|article| article.user_id).collect();
+        let authors: HashMap<i32, User> = users::table
+            .filter(users::id.eq_any(&author_ids))
+            .load::<User>(&conn)?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect();
+        let followed: Vec<i32> = match viewer_id {
+            Some(viewer_id) => followers::table
+                .filter(followers::follower_id.eq(viewer_id))
+                .filter(followers::followed_id.eq_any(&author_ids))
+                .select(followers::followed_id)
+                .load(&conn)?,
+            None => Vec::new(),
+        };
+        let rows = articles
+            .into_iter()
+            .map(|article| {
+                let author = authors.get(&article.user_id).cloned();
+                let following = followed.contains(&article.user_id);
+                author.map(|author| (article, author, following))
+            })
+            .collect::<Option<Vec<ArticleRow>>>()
+            .ok_or(dieselError::NotFound)?;
+        with_details(&conn, viewer_id, rows)
+    })
+    .await
+}
+
+/// Add the tags, favorites and comment counts of all the articles, with a query for each.
+fn with_details(
+    conn: &DbConnection,
+    viewer_id: Option<i32>,
+    rows: Vec<ArticleRow>,
+) -> QueryResult<Vec<ArticleDto>> {
+    let ids: Vec<i32> = rows.iter().map(|(article, _, _)| article.id).collect();
+    let mut tags_by_article = conduit::tags::by_article(conn, &ids)?;
+    let favorited = conduit::favorites::favorited_by(conn, viewer_id, &ids)?;
+    let comments_counts = conduit::comments::counts(conn, &ids)?;
+    Ok(rows
+        .into_iter()
+        .map(|(article, author, following)| ArticleDto {
+            author: Profile::from_user(author, following),
+            tag_list: tags_by_article.remove(&article.id).unwrap_or_default(),
+            favorite: FavoriteStatus {
+                favorited: favorited.contains(&article.id),
+                count: i64::from(article.favorites_count),
+            },
+            comments_count: comments_counts.get(&article.id).cloned().unwrap_or(0),
+            article,
+        })
+        .collect())
+}
+
+/// Apply `article` as an edit, as long as the article is still at `version`, i.e. nobody has
+/// edited it since that copy was read. Every edit bumps the version and keeps what the
+/// article was as a revision, and `Ok(None)` means the article had moved on, so the edit
+/// wasn't made.
+pub async fn update(
+    repo: Repo,
+    article_id: i32,
+    version: i32,
+    article: UpdateArticle,
+) -> Result<Option<Article>, RepoError> {
+    repo.transaction("articles::update", move |conn| {
+        edit(conn, article_id, version, &article)
+    })
+    .await
+}
+
+/// Edit the article back to what it was in one of its revisions, as an edit like any
+/// other. Fails with `NotFound` if the revision isn't one of the article's.
+pub async fn restore(repo: Repo, article_id: i32, revision_id: i32) -> Result<Article, RepoError> {
+    repo.transaction("articles::restore", move |conn| {
+        let revision = article_revisions::table
+            .find(revision_id)
+            .filter(article_revisions::article_id.eq(article_id))
+            .first::<ArticleRevision>(conn)?;
+        let version = articles::table
+            .find(article_id)
+            .select(articles::version)
+            .first(conn)?;
+        let article = UpdateArticle {
+            title: Some(revision.title),
+            description: Some(revision.description),
+            body: Some(revision.body),
+            ..UpdateArticle::default()
+        };
+        // The article can't move on from `version` within this transaction.
+        edit(conn, article_id, version, &article)?.ok_or(dieselError::NotFound)
+    })
+    .await
+}
+
+/// What the article was before each of its edits, most recent first.
+pub async fn revisions(repo: Repo, article_id: i32) -> Result<Vec<ArticleRevision>, RepoError> {
+    repo.run("articles::revisions", move |conn| {
+        article_revisions::table
+            .filter(article_revisions::article_id.eq(article_id))
+            .order(article_revisions::id.desc())
+            .load(&conn)
+    })
+    .await
+}
+
+/// Make an edit, keeping the article as it was as a revision. Call it in a transaction.
+fn edit(
+    conn: &DbConnection,
+    article_id: i32,
+    version: i32,
+    article: &UpdateArticle,
+) -> QueryResult<Option<Article>> {
+    let previous = articles::table
+        .find(article_id)
+        .filter(articles::version.eq(version))
+        .first::<Article>(conn)
+        .optional()?;
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return Ok(None),
+    };
+    if article.title.is_none()
+        && article.description.is_none()
+        && article.body.is_none()
+        && article.publish_at.is_none()
+        && article.visibility.is_none()
+        && article.cover_image.is_none()
+        && article.canonical_url.is_none()
+    {
+        // Nothing to change, so just return the article as is.
+        return Ok(Some(previous));
+    }
+    let word_count = article.body.as_ref().map(|body| reading::word_count(body));
+    let article = &UpdateArticle {
+        word_count,
+        reading_time: word_count.map(reading::reading_time),
+        ..article.clone()
+    };
+    let result = match article.title {
+        Some(ref title) => with_unique_slug(conn, &slugs::slugify(title), |slug| {
+            let article = UpdateArticle {
+                slug: Some(slug.to_string()),
+                ..article.clone()
+            };
+            update_article(conn, article_id, version, &article)
+        }),
+        None => update_article(conn, article_id, version, article),
+    };
+    let updated = match result {
+        Ok(updated) => updated,
+        Err(dieselError::NotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let revision = NewArticleRevision {
+        article_id,
+        version: previous.version,
+        title: previous.title,
+        description: previous.description,
+        body: previous.body,
+    };
+    diesel::insert_into(article_revisions::table)
+        .values(&revision)
+        .execute(conn)?;
+    Ok(Some(updated))
+}
+
+pub async fn delete(repo: Repo, article_id: i32) -> Result<(), RepoError> {
+    repo.run("articles::delete", move |conn| {
+        diesel::delete(articles::table.find(article_id))
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+/// Publishes `ArticlePublished` unless it's a draft, so it should be called in a transaction.
+fn insert_article(conn: &DbConnection, article: &NewArticle) -> QueryResult<Article> {
+    let article = with_unique_slug(conn, &article.slug, |slug| {
+        let article = NewArticle {
+            slug: slug.to_string(),
+            ..article.clone()
+        };
+        insert_returning(conn, &article)
+    })?;
+    if article.status == Status::Published.name() {
+        publish_event(conn, &article)?;
+    }
+    Ok(article)
+}
+
+/// Mark a draft published at `now`, which is when listings, the feed and trending take it
+/// to have been written. Returns 0 if it isn't a draft.
+fn publish_draft(conn: &DbConnection, article_id: i32, now: NaiveDateTime) -> QueryResult<usize> {
+    diesel::update(
+        articles::table
+            .find(article_id)
+            .filter(articles::status.eq(Status::Draft.name())),
+    )
+    .set((
+        articles::status.eq(Status::Published.name()),
+        articles::publish_at.eq(None::<NaiveDateTime>),
+        articles::created_at.eq(now),
+    ))
+    .execute(conn)
+}
+
+/// Let followers, webhooks and the like know the article is out, if it's public.
+fn publish_event(conn: &DbConnection, article: &Article) -> QueryResult<()> {
+    if article.visibility != Visibility::Public.name() {
+        return Ok(());
+    }
+    events::publish(
+        conn,
+        Event::ArticlePublished {
+            article_id: article.id,
+            slug: article.slug.clone(),
+            author_id: article.user_id,
+        },
+    )
+}
+
+#[cfg(feature = "postgres")]
+fn insert_returning(conn: &DbConnection, article: &NewArticle) -> QueryResult<Article> {
+    diesel::insert_into(articles::table)
+        .values((article, reading_stats(&article.body)))
+        .get_result(conn)
+}
+
+/// SQLite and MySQL have no `RETURNING`, so read the new row back by its id.
+#[cfg(not(feature = "postgres"))]
+fn insert_returning(conn: &DbConnection, article: &NewArticle) -> QueryResult<Article> {
+    diesel::insert_into(articles::table)
+        .values((article, reading_stats(&article.body)))
+        .execute(conn)?;
+    articles::table
+        .find(crate::db::inserted_id(conn)?)
+        .first(conn)
+}
+
+type ReadingStats = (
+    diesel::dsl::Eq<articles::word_count, i32>,
+    diesel::dsl::Eq<articles::reading_time, i32>,
+);
+
+/// The word count and reading time to store along with a new article's `body`.
+fn reading_stats(body: &str) -> ReadingStats {
+    let word_count = reading::word_count(body);
+    (
+        articles::word_count.eq(word_count),
+        articles::reading_time.eq(reading::reading_time(word_count)),
+    )
+}
+
+/// Update the article if it's at `version`, or else fail with `NotFound`.
+#[cfg(feature = "postgres")]
+fn update_article(
+    conn: &DbConnection,
+    article_id: i32,
+    version: i32,
+    article: &UpdateArticle,
+) -> QueryResult<Article> {
+    diesel::update(
+        articles::table
+            .find(article_id)
+            .filter(articles::version.eq(version)),
+    )
+    .set((article, articles::version.eq(articles::version + 1)))
+    .get_result(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn update_article(
+    conn: &DbConnection,
+    article_id: i32,
+    version: i32,
+    article: &UpdateArticle,
+) -> QueryResult<Article> {
+    let updated = diesel::update(
+        articles::table
+            .find(article_id)
+            .filter(articles::version.eq(version)),
+    )
+    .set((article, articles::version.eq(articles::version + 1)))
+    .execute(conn)?;
+    if updated == 0 {
+        return Err(dieselError::NotFound);
+    }
+    articles::table.find(article_id).first(conn)
+}
+
+/// Run `query` with `slug`, retrying with suffixed variants while it collides with an existing slug.
+/// Each attempt runs in its own savepoint so a collision doesn't abort an enclosing transaction.
+fn with_unique_slug<F>(conn: &DbConnection, slug: &str, query: F) -> QueryResult<Article>
+where
+    F: Fn(&str) -> QueryResult<Article>,
+{
+    let mut candidate = slug.to_string();
+    for _ in 1..MAX_SLUG_ATTEMPTS {
+        match conn.transaction(|| query(&candidate)) {
+            Err(ref e) if is_slug_collision(e) => candidate = slugs::with_suffix(slug),
+            result => return result,
+        }
+    }
+    conn.transaction(|| query(&candidate))
+}
+
+fn is_slug_collision(e: &dieselError) -> bool {
+    match e {
+        dieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+            // SQLite doesn't name the constraint, only the column,
+            // and MySQL names it in the message.
+            info.constraint_name() == Some("articles_slug_key")
+                || info.message().ends_with("articles.slug")
+                || info.message().contains("articles_slug_key")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::{favorites, followers, tags, users};
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+    use chrono::Utc;
+
+    #[test]
+    fn test_create_article() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+            assert!(find_by_slug(repo, article.slug).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_create_article_with_tags() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let tag_list = vec!["dragons".to_string(), "training".to_string()];
+            let (article, tag_list) =
+                insert_with_tags(repo.clone(), generate::new_article(user.id), tag_list)
+                    .await
+                    .unwrap();
+            assert_eq!(
+                tag_list,
+                vec!["dragons".to_string(), "training".to_string()]
+            );
+
+            let params = ListParams {
+                tag: Some("dragons".to_string()),
+                ..Default::default()
+            };
+            let (tagged, _) = list(repo, None, params).await.unwrap();
+            assert!(tagged.iter().any(|a| a.article.id == article.id));
+        });
+    }
+
+    #[test]
+    fn test_update_and_delete_article() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+
+            let changes = UpdateArticle {
+                title: Some(format!("Updated title {}", article.id)),
+                ..Default::default()
+            };
+            let updated = update(repo.clone(), article.id, article.version, changes)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(updated.slug, format!("updated-title-{}", article.id));
+            assert_eq!(updated.body, article.body);
+            assert_eq!(updated.version, article.version + 1);
+            assert_eq!(updated.word_count, article.word_count);
+
+            let changes = UpdateArticle {
+                body: Some("word ".repeat(201)),
+                ..Default::default()
+            };
+            let updated = update(repo.clone(), article.id, updated.version, changes)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(updated.word_count, 201);
+            assert_eq!(updated.reading_time, 2);
+
+            let canonical_url = "https://example.com/first-published-here".to_string();
+            let changes = UpdateArticle {
+                canonical_url: Some(canonical_url.clone()),
+                ..Default::default()
+            };
+            let updated = update(repo.clone(), article.id, updated.version, changes)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(updated.canonical_url, Some(canonical_url));
+            assert_eq!(updated.cover_image, None);
+
+            delete(repo.clone(), article.id).await.unwrap();
+            match find_by_slug(repo, updated.slug).await {
+                Err(RepoError::Query(dieselError::NotFound)) => (),
+                other => panic!("Expected NotFound, got {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn test_update_outdated_version() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+            let changes = UpdateArticle {
+                body: Some("First edit".to_string()),
+                ..Default::default()
+            };
+            update(repo.clone(), article.id, article.version, changes.clone())
+                .await
+                .unwrap()
+                .unwrap();
+
+            let stale = update(repo.clone(), article.id, article.version, changes)
+                .await
+                .unwrap();
+            assert!(stale.is_none());
+        });
+    }
+
+    #[test]
+    fn test_revisions_and_restore() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+            let changes = UpdateArticle {
+                body: Some("Bad edit".to_string()),
+                ..Default::default()
+            };
+            update(repo.clone(), article.id, article.version, changes)
+                .await
+                .unwrap()
+                .unwrap();
+
+            let history = revisions(repo.clone(), article.id).await.unwrap();
+            assert_eq!(history.len(), 1);
+            assert_eq!(history[0].version, article.version);
+            assert_eq!(history[0].body, article.body);
+
+            let restored = restore(repo.clone(), article.id, history[0].id)
+                .await
+                .unwrap();
+            assert_eq!(restored.body, article.body);
+            assert_eq!(restored.version, article.version + 2);
+            let history = revisions(repo, article.id).await.unwrap();
+            assert_eq!(history[0].body, "Bad edit");
+        });
+    }
+
+    #[test]
+    fn test_drafts() {
+        let repo = repo();
+        block_on(async move {
+            let author = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let reader = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let draft = NewArticle {
+                status: Status::Draft.name().to_string(),
+                ..generate::new_article(author.id)
+            };
+            let draft = insert(repo.clone(), draft).await.unwrap();
+
+            let by_author = ListParams {
+                author: Some(author.username.clone()),
+                ..Default::default()
+            };
+            let (published, _) = list(repo.clone(), Some(author.id), by_author)
+                .await
+                .unwrap();
+            assert!(published.is_empty());
+            let drafts = ListParams {
+                status: Status::Draft,
+                mine: true,
+                ..Default::default()
+            };
+            let (own, _) = list(repo.clone(), Some(author.id), drafts.clone())
+                .await
+                .unwrap();
+            assert_eq!(own.len(), 1);
+            let (others, _) = list(repo.clone(), Some(reader.id), drafts).await.unwrap();
+            assert!(others.is_empty());
+            let slug = draft.slug.clone();
+            assert!(find_visible(repo.clone(), slug, Some(reader.id))
+                .await
+                .is_err());
+
+            let published = publish(repo.clone(), draft.id).await.unwrap();
+            assert_eq!(published.status, Status::Published.name());
+            assert!(find_visible(repo, draft.slug, None).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_publish_scheduled() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let now = Utc::now().naive_utc();
+            let scheduled = NewArticle {
+                status: Status::Draft.name().to_string(),
+                publish_at: Some(now + chrono::Duration::hours(1)),
+                ..generate::new_article(user.id)
+            };
+            let scheduled = insert(repo.clone(), scheduled).await.unwrap();
+
+            publish_scheduled(repo.clone(), now).await.unwrap();
+            let article = find_by_slug(repo.clone(), scheduled.slug.clone())
+                .await
+                .unwrap();
+            assert_eq!(article.status, Status::Draft.name());
+
+            let later = now + chrono::Duration::hours(2);
+            assert!(publish_scheduled(repo.clone(), later).await.unwrap() >= 1);
+            let article = find_by_slug(repo, scheduled.slug).await.unwrap();
+            assert_eq!(article.status, Status::Published.name());
+            assert_eq!(article.publish_at, None);
+            // It's dated when it went out, not when it was drafted.
+            assert!(article.created_at > now + chrono::Duration::hours(1));
+        });
+    }
+
+    #[test]
+    fn test_related_score() {
+        let day = Duration::days(1);
+        assert_eq!(related_score(1, false, Duration::zero(), 0), 2.0);
+        assert_eq!(related_score(1, true, day * 30, 0), 1.5);
+        assert!(related_score(2, false, day, 0) > related_score(1, true, day, 0));
+        assert!(related_score(1, false, day, 10) > related_score(1, false, day, 0));
+        assert!(related_score(1, false, day, 0) > related_score(1, false, day * 60, 0));
+    }
+
+    #[test]
+    fn test_related() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let other = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id)
+                .tags(&["dragons", "training"])
+                .insert(repo.clone())
+                .await;
+            let by_author = generate::article(author.id).insert(repo.clone()).await;
+            let one_tag = generate::article(other.id)
+                .tags(&["dragons"])
+                .insert(repo.clone())
+                .await;
+            let both_tags = generate::article(other.id)
+                .tags(&["training", "dragons"])
+                .insert(repo.clone())
+                .await;
+            generate::article(other.id)
+                .tags(&["unrelated"])
+                .insert(repo.clone())
+                .await;
+
+            let related = related(repo, None, article, 10).await.unwrap();
+            let ids: Vec<i32> = related.iter().map(|dto| dto.article.id).collect();
+            assert_eq!(ids, vec![both_tags.id, one_tag.id, by_author.id]);
+        });
+    }
+
+    #[test]
+    fn test_visibility() {
+        let repo = repo();
+        block_on(async move {
+            let author = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let reader = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let unlisted = NewArticle {
+                visibility: Visibility::Unlisted.name().to_string(),
+                ..generate::new_article(author.id)
+            };
+            let unlisted = insert(repo.clone(), unlisted).await.unwrap();
+            let private = NewArticle {
+                visibility: Visibility::Private.name().to_string(),
+                ..generate::new_article(author.id)
+            };
+            let private = insert(repo.clone(), private).await.unwrap();
+
+            let by_author = ListParams {
+                author: Some(author.username.clone()),
+                ..Default::default()
+            };
+            let (listed, _) = list(repo.clone(), Some(reader.id), by_author)
+                .await
+                .unwrap();
+            assert!(listed.is_empty());
+            let mine = ListParams {
+                mine: true,
+                ..Default::default()
+            };
+            let (own, _) = list(repo.clone(), Some(author.id), mine).await.unwrap();
+            assert_eq!(own.len(), 2);
+
+            let slug = unlisted.slug;
+            assert!(find_visible(repo.clone(), slug, None).await.is_ok());
+            let slug = private.slug;
+            assert!(find_visible(repo.clone(), slug.clone(), Some(reader.id))
+                .await
+                .is_err());
+            assert!(find_visible(repo, slug, Some(author.id)).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_list_by_author() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+            insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+
+            let params = ListParams {
+                author: Some(user.username.clone()),
+                ..Default::default()
+            };
+            let (articles, count) = list(repo.clone(), None, params).await.unwrap();
+            assert_eq!(articles.len(), 2);
+            assert_eq!(count, 2);
+            assert!(articles.iter().all(|a| a.article.user_id == user.id));
+            assert!(articles.iter().all(|a| a.author.username == user.username));
+
+            let params = ListParams {
+                author: Some(user.username),
+                limit: 1,
+                offset: 1,
+                ..Default::default()
+            };
+            let (articles, count) = list(repo, None, params).await.unwrap();
+            assert_eq!(articles.len(), 1);
+            assert_eq!(count, 2);
+        });
+    }
+
+    #[test]
+    fn test_list_with_details() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let reader = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id)
+                .tags(&["dragons", "training"])
+                .insert(repo.clone())
+                .await;
+            generate::follow(repo.clone(), &reader, &author).await;
+            generate::favorite(repo.clone(), &reader, &article).await;
+
+            let params = ListParams {
+                author: Some(author.username.clone()),
+                ..Default::default()
+            };
+            let (articles, _) = list(repo.clone(), Some(reader.id), params.clone())
+                .await
+                .unwrap();
+            assert_eq!(articles[0].author.username, author.username);
+            assert!(articles[0].author.following);
+            assert_eq!(articles[0].tag_list, vec!["dragons", "training"]);
+            assert!(articles[0].favorite.favorited);
+            assert_eq!(articles[0].favorite.count, 1);
+
+            let (articles, _) = list(repo.clone(), None, params).await.unwrap();
+            assert!(!articles[0].author.following);
+            assert!(!articles[0].favorite.favorited);
+            assert_eq!(articles[0].favorite.count, 1);
+
+            let favorited = ListParams {
+                favorited: Some(reader.username.clone()),
+                ..Default::default()
+            };
+            let (articles, count) = list(repo.clone(), None, favorited).await.unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(articles[0].article.id, article.id);
+
+            let found = details(repo, Some(reader.id), vec![article]).await.unwrap();
+            assert!(found[0].author.following);
+            assert!(found[0].favorite.favorited);
+        });
+    }
+
+    #[test]
+    fn test_details_of_deleted_article() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let first = generate::article(author.id).insert(repo.clone()).await;
+            let second = generate::article(author.id).insert(repo.clone()).await;
+            let ids = vec![first.id, second.id];
+            // Deleted after it was read, e.g. by a concurrent request.
+            delete(repo.clone(), first.id).await.unwrap();
+
+            let found = details(repo, None, vec![first, second]).await.unwrap();
+            let found_ids: Vec<i32> = found.iter().map(|dto| dto.article.id).collect();
+            assert_eq!(found_ids, ids);
+        });
+    }
+
+    #[test]
+    fn test_list_after_cursor() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            for _ in 0..3 {
+                insert(repo.clone(), generate::new_article(user.id))
+                    .await
+                    .unwrap();
+            }
+            let params = ListParams {
+                author: Some(user.username.clone()),
+                ..Default::default()
+            };
+            let (all, _) = list(repo.clone(), None, params.clone()).await.unwrap();
+
+            let first_page = ListParams {
+                limit: 2,
+                ..params.clone()
+            };
+            let (page, count) = list(repo.clone(), None, first_page).await.unwrap();
+            assert_eq!(page.len(), 2);
+            assert_eq!(count, 3);
+            let second_page = ListParams {
+                limit: 2,
+                after: Some(Cursor::after(&page[1].article)),
+                ..params
+            };
+            let (rest, count) = list(repo, None, second_page).await.unwrap();
+            assert_eq!(count, 3);
+            assert_eq!(rest.len(), 1);
+            assert_eq!(rest[0].article.id, all[2].article.id);
+        });
+    }
+
+    #[test]
+    fn test_list_sorted() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let older = insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+            let newer = insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+            favorites::favorite(repo.clone(), user.id, older.id)
+                .await
+                .unwrap();
+            let params = ListParams {
+                author: Some(user.username),
+                ..Default::default()
+            };
+
+            let by_favorites = ListParams {
+                sort: Sort::Favorites,
+                ..params.clone()
+            };
+            let (articles, _) = list(repo.clone(), None, by_favorites).await.unwrap();
+            assert_eq!(articles[0].article.id, older.id);
+            let oldest_first = ListParams {
+                direction: Direction::Asc,
+                ..params
+            };
+            let (articles, _) = list(repo, None, oldest_first).await.unwrap();
+            assert_eq!(articles[0].article.id, older.id);
+            assert_eq!(articles[1].article.id, newer.id);
+        });
+    }
+
+    #[test]
+    fn test_cursor_encoding() {
+        let cursor = Cursor {
+            created_at: NaiveDateTime::from_timestamp(1_567_000_000, 123_456_000),
+            id: 42,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("not a cursor"), None);
+        assert_eq!(Cursor::decode("31322e33"), None);
+    }
+
+    #[test]
+    fn test_feed() {
+        let repo = repo();
+        block_on(async move {
+            let reader = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let followed = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let other = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            followers::follow(repo.clone(), reader.id, followed.id)
+                .await
+                .unwrap();
+            let article = insert(repo.clone(), generate::new_article(followed.id))
+                .await
+                .unwrap();
+            insert(repo.clone(), generate::new_article(other.id))
+                .await
+                .unwrap();
+
+            let (feed, count) = feed(repo, reader.id, false, 20, 0).await.unwrap();
+            assert_eq!(feed.len(), 1);
+            assert_eq!(count, 1);
+            assert_eq!(feed[0].article.id, article.id);
+            assert!(feed[0].author.following);
+        });
+    }
+
+    #[test]
+    fn test_feed_with_followed_tags() {
+        let repo = repo();
+        block_on(async move {
+            let reader = generate::user().insert(repo.clone()).await;
+            let other = generate::user().insert(repo.clone()).await;
+            let tagged = generate::article(other.id)
+                .tags(&["dragons"])
+                .insert(repo.clone())
+                .await;
+            generate::article(other.id)
+                .tags(&["fish"])
+                .insert(repo.clone())
+                .await;
+            generate::article(reader.id)
+                .tags(&["dragons"])
+                .insert(repo.clone())
+                .await;
+            tags::follow(repo.clone(), reader.id, "dragons".into())
+                .await
+                .unwrap();
+
+            let (feed, count) = feed(repo.clone(), reader.id, false, 20, 0).await.unwrap();
+            assert!(feed.is_empty());
+            assert_eq!(count, 0);
+            // Only other users' articles with a followed tag are added.
+            let (feed, count) = feed(repo, reader.id, true, 20, 0).await.unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(feed[0].article.id, tagged.id);
+            assert!(!feed[0].author.following);
+        });
+    }
+
+    #[test]
+    fn test_slug_collision() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = generate::new_article(user.id);
+            let first = insert(repo.clone(), article.clone()).await.unwrap();
+            let second = insert(repo, article.clone()).await.unwrap();
+            assert!(first.slug.starts_with(&article.slug));
+            assert!(second.slug.starts_with(&article.slug));
+            assert_ne!(first.slug, second.slug);
+        });
+    }
+}
@@ -0,0 +1,414 @@
+use crate::db::{DbConnection, RepoError};
+use crate::events::{self, Event};
+use crate::mentions;
+use crate::models::{Comment, CommentRevision, NewComment, NewCommentRevision, NewMention};
+use crate::schema::{articles, comment_revisions, comments, mentions as mentions_table, users};
+use crate::Repo;
+
+use chrono::Utc;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+/// Add a comment, and record who it mentions by `@username`: users that exist and haven't
+/// deleted their account. Publishes `CommentAdded`, and `UserMentioned` for each of them.
+pub async fn insert(repo: Repo, comment: NewComment) -> Result<Comment, RepoError> {
+    repo.transaction("comments::insert", move |conn| {
+        let comment = insert_comment(conn, &comment)?;
+        let article_author_id = articles::table
+            .find(comment.article_id)
+            .select(articles::user_id)
+            .first(conn)?;
+        events::publish(
+            conn,
+            Event::CommentAdded {
+                comment_id: comment.id,
+                article_id: comment.article_id,
+                author_id: comment.user_id,
+                article_author_id,
+            },
+        )?;
+        mention(conn, &comment)?;
+        Ok(comment)
+    })
+    .await
+}
+
+/// Replace a comment's body, keeping what it said before as a revision. Users it newly
+/// mentions are notified, and those it no longer mentions are forgotten.
+pub async fn update(repo: Repo, comment_id: i32, body: String) -> Result<Comment, RepoError> {
+    repo.transaction("comments::update", move |conn| {
+        let previous = comments::table.find(comment_id).first::<Comment>(conn)?;
+        let revision = NewCommentRevision {
+            comment_id,
+            body: previous.body,
+        };
+        diesel::insert_into(comment_revisions::table)
+            .values(&revision)
+            .execute(conn)?;
+        diesel::update(comments::table.find(comment_id))
+            .set((
+                comments::body.eq(body),
+                comments::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        let comment = comments::table.find(comment_id).first(conn)?;
+        mention(conn, &comment)?;
+        Ok(comment)
+    })
+    .await
+}
+
+/// What a comment said before each of its edits, oldest first. Fails with `NotFound` if
+/// there's no such comment.
+pub async fn revisions(repo: Repo, comment_id: i32) -> Result<Vec<CommentRevision>, RepoError> {
+    repo.run("comments::revisions", move |conn| {
+        comments::table
+            .find(comment_id)
+            .select(comments::id)
+            .first::<i32>(&conn)?;
+        comment_revisions::table
+            .filter(comment_revisions::comment_id.eq(comment_id))
+            .order(comment_revisions::id)
+            .load(&conn)
+    })
+    .await
+}
+
+/// Which comments are listed first.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Oldest,
+    Newest,
+}
+
+/// A page of the comments on an article, in `order`, along with how many there are in all.
+/// Comments made at the same time are in the order they were added.
+pub async fn list(
+    repo: Repo,
+    article_id: i32,
+    order: Order,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<Comment>, i64), RepoError> {
+    repo.run("comments::list", move |conn| {
+        let query = comments::table
+            .filter(comments::article_id.eq(article_id))
+            .limit(limit)
+            .offset(offset);
+        let page = match order {
+            Order::Oldest => query
+                .order((comments::created_at.asc(), comments::id.asc()))
+                .load(&conn)?,
+            Order::Newest => query
+                .order((comments::created_at.desc(), comments::id.desc()))
+                .load(&conn)?,
+        };
+        let count = comments::table
+            .filter(comments::article_id.eq(article_id))
+            .count()
+            .get_result(&conn)?;
+        Ok((page, count))
+    })
+    .await
+}
+
+/// How many comments each of the given articles has, in one query. Articles without any
+/// are left out.
+pub fn counts(conn: &DbConnection, article_ids: &[i32]) -> QueryResult<HashMap<i32, i64>> {
+    let rows = comments::table
+        .filter(comments::article_id.eq_any(article_ids))
+        .group_by(comments::article_id)
+        .select((comments::article_id, count_star()))
+        .load::<(i32, i64)>(conn)?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Find a comment, as long as it belongs to the given article.
+pub async fn find(repo: Repo, article_id: i32, comment_id: i32) -> Result<Comment, RepoError> {
+    repo.run("comments::find", move |conn| {
+        comments::table
+            .find(comment_id)
+            .filter(comments::article_id.eq(article_id))
+            .first(&conn)
+    })
+    .await
+}
+
+/// Bring the mentions stored for `comment` in line with its body, and publish
+/// `UserMentioned` for each user who wasn't mentioned before.
+fn mention(conn: &DbConnection, comment: &Comment) -> QueryResult<()> {
+    let usernames = mentions::usernames(&comment.body);
+    let user_ids: Vec<i32> = if usernames.is_empty() {
+        vec![]
+    } else {
+        users::table
+            .filter(users::username.eq_any(&usernames))
+            .filter(users::deleted_at.is_null())
+            .select(users::id)
+            .load(conn)?
+    };
+    let before: Vec<i32> = mentions_table::table
+        .filter(mentions_table::comment_id.eq(comment.id))
+        .select(mentions_table::user_id)
+        .load(conn)?;
+    diesel::delete(
+        mentions_table::table
+            .filter(mentions_table::comment_id.eq(comment.id))
+            .filter(mentions_table::user_id.ne_all(&user_ids)),
+    )
+    .execute(conn)?;
+    let added: Vec<NewMention> = user_ids
+        .into_iter()
+        .filter(|user_id| !before.contains(user_id))
+        .map(|user_id| NewMention {
+            comment_id: comment.id,
+            user_id,
+        })
+        .collect();
+    if added.is_empty() {
+        return Ok(());
+    }
+    diesel::insert_into(mentions_table::table)
+        .values(&added)
+        .execute(conn)?;
+    for mention in added {
+        events::publish(
+            conn,
+            Event::UserMentioned {
+                comment_id: comment.id,
+                article_id: comment.article_id,
+                author_id: comment.user_id,
+                user_id: mention.user_id,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// The usernames each of the given comments mentions, by comment id, in one query.
+/// Comments without mentions are left out.
+pub async fn mentioned(
+    repo: Repo,
+    comment_ids: Vec<i32>,
+) -> Result<HashMap<i32, Vec<String>>, RepoError> {
+    repo.run("comments::mentioned", move |conn| {
+        let rows = mentions_table::table
+            .inner_join(users::table)
+            .filter(mentions_table::comment_id.eq_any(&comment_ids))
+            .order(users::username)
+            .select((mentions_table::comment_id, users::username))
+            .load::<(i32, String)>(&conn)?;
+        let mut by_comment: HashMap<i32, Vec<String>> = HashMap::new();
+        for (comment_id, username) in rows {
+            by_comment.entry(comment_id).or_default().push(username);
+        }
+        Ok(by_comment)
+    })
+    .await
+}
+
+pub async fn delete(repo: Repo, comment_id: i32) -> Result<(), RepoError> {
+    repo.run("comments::delete", move |conn| {
+        diesel::delete(comments::table.find(comment_id))
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+#[cfg(feature = "postgres")]
+fn insert_comment(conn: &DbConnection, comment: &NewComment) -> QueryResult<Comment> {
+    diesel::insert_into(comments::table)
+        .values(comment)
+        .get_result(conn)
+}
+
+/// SQLite and MySQL have no `RETURNING`, so read the new row back by its id.
+#[cfg(not(feature = "postgres"))]
+fn insert_comment(conn: &DbConnection, comment: &NewComment) -> QueryResult<Comment> {
+    diesel::insert_into(comments::table)
+        .values(comment)
+        .execute(conn)?;
+    comments::table
+        .find(crate::db::inserted_id(conn)?)
+        .first(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::{articles, users};
+    use crate::repo;
+    use crate::schema::jobs;
+    use crate::test_helpers::{block_on, generate};
+    use diesel::result::Error as dieselError;
+
+    #[test]
+    fn test_comment_lifecycle() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = articles::insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+
+            let comment = generate::comment(article.id, user.id)
+                .body("First!")
+                .insert(repo.clone())
+                .await;
+            let (comments, count) = list(repo.clone(), article.id, Order::Oldest, 10, 0)
+                .await
+                .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(comments[0].body, "First!");
+
+            delete(repo.clone(), comment.id).await.unwrap();
+            match find(repo, article.id, comment.id).await {
+                Err(RepoError::Query(dieselError::NotFound)) => (),
+                other => panic!("Expected NotFound, got {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn test_mentions() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let jake = generate::user().insert(repo.clone()).await;
+            let jane = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+
+            let body = format!(
+                "@{} and @{}, meet @{} and @nobody",
+                jane.username, jake.username, jane.username
+            );
+            let comment = generate::comment(article.id, author.id)
+                .body(&body)
+                .insert(repo.clone())
+                .await;
+            let plain = generate::comment(article.id, author.id)
+                .body("No one")
+                .insert(repo.clone())
+                .await;
+            let mut mentioned = mentioned(repo.clone(), vec![comment.id, plain.id])
+                .await
+                .unwrap();
+            let mut expected = vec![jake.username, jane.username];
+            expected.sort();
+            assert_eq!(mentioned.remove(&comment.id), Some(expected));
+            assert!(mentioned.is_empty());
+
+            let published = repo
+                .run("test", |conn| {
+                    jobs::table.select(jobs::payload).load::<String>(&conn)
+                })
+                .await
+                .unwrap();
+            let mentions = published
+                .iter()
+                .filter(|payload| payload.contains("userMentioned"))
+                .count();
+            assert_eq!(mentions, 2);
+        });
+    }
+
+    #[test]
+    fn test_update() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let jake = generate::user().insert(repo.clone()).await;
+            let jane = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+            let first = format!("Hi @{}", jake.username);
+            let comment = generate::comment(article.id, author.id)
+                .body(&first)
+                .insert(repo.clone())
+                .await;
+
+            let second = format!("Hi @{}", jane.username);
+            let updated = update(repo.clone(), comment.id, second.clone())
+                .await
+                .unwrap();
+            assert_eq!(updated.body, second);
+            assert!(updated.updated_at >= comment.updated_at);
+            update(repo.clone(), comment.id, "Hi all".to_string())
+                .await
+                .unwrap();
+
+            let bodies: Vec<String> = revisions(repo.clone(), comment.id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|revision| revision.body)
+                .collect();
+            assert_eq!(bodies, vec![first, second]);
+            // Nobody is mentioned any more, but both were notified once.
+            assert!(mentioned(repo.clone(), vec![comment.id])
+                .await
+                .unwrap()
+                .is_empty());
+            let published = repo
+                .run("test", |conn| {
+                    jobs::table.select(jobs::payload).load::<String>(&conn)
+                })
+                .await
+                .unwrap();
+            let mentions = published
+                .iter()
+                .filter(|payload| payload.contains("userMentioned"))
+                .count();
+            assert_eq!(mentions, 2);
+
+            assert!(update(repo.clone(), comment.id + 1, "Gone".to_string())
+                .await
+                .is_err());
+            assert!(revisions(repo, comment.id + 1).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_list_pages() {
+        let repo = repo();
+        block_on(async move {
+            let user = generate::user().insert(repo.clone()).await;
+            let article = generate::article(user.id).insert(repo.clone()).await;
+            let other = generate::article(user.id).insert(repo.clone()).await;
+            let mut ids = vec![];
+            for _ in 0..3 {
+                let comment = generate::comment(article.id, user.id)
+                    .insert(repo.clone())
+                    .await;
+                ids.push(comment.id);
+            }
+
+            let page_ids = |page: Vec<Comment>| -> Vec<i32> { page.iter().map(|c| c.id).collect() };
+            let (page, count) = list(repo.clone(), article.id, Order::Oldest, 2, 0)
+                .await
+                .unwrap();
+            assert_eq!((page_ids(page), count), (vec![ids[0], ids[1]], 3));
+            let (page, _) = list(repo.clone(), article.id, Order::Oldest, 2, 2)
+                .await
+                .unwrap();
+            assert_eq!(page_ids(page), vec![ids[2]]);
+            let (page, _) = list(repo.clone(), article.id, Order::Newest, 2, 0)
+                .await
+                .unwrap();
+            assert_eq!(page_ids(page), vec![ids[2], ids[1]]);
+
+            let article_ids = [article.id, other.id];
+            let counts = repo
+                .run("test", move |conn| counts(&conn, &article_ids))
+                .await
+                .unwrap();
+            assert_eq!(counts.get(&article.id), Some(&3));
+            assert_eq!(counts.get(&other.id), None);
+        });
+    }
+}
@@ -0,0 +1,132 @@
+use crate::conduit;
+use crate::db::RepoError;
+use crate::models::{Article, Comment, User};
+use crate::schema::{articles, comments, favorites, followers, users};
+use crate::Repo;
+
+use diesel::prelude::*;
+use serde_derive::Serialize;
+
+/// Everything stored about a user, for them to download.
+#[derive(Serialize, Debug)]
+pub struct Export {
+    pub user: User,
+    pub articles: Vec<ExportedArticle>,
+    pub comments: Vec<Comment>,
+    /// Slugs of the articles the user favorited.
+    pub favorites: Vec<String>,
+    /// Usernames of the users they follow.
+    pub following: Vec<String>,
+    /// Usernames of the users following them.
+    pub followers: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedArticle {
+    #[serde(flatten)]
+    pub article: Article,
+    pub tag_list: Vec<String>,
+}
+
+/// Gather the user's data in one transaction, so it's consistent.
+/// Fails with `NotFound` when there's no such user.
+pub async fn export(repo: Repo, user_id: i32) -> Result<Export, RepoError> {
+    repo.transaction("exports::export", move |conn| {
+        let user = users::table.find(user_id).first::<User>(conn)?;
+        let articles = articles::table
+            .filter(articles::user_id.eq(user_id))
+            .order(articles::created_at.asc())
+            .load::<Article>(conn)?;
+        let article_ids: Vec<i32> = articles.iter().map(|article| article.id).collect();
+        let mut tags_by_article = conduit::tags::by_article(conn, &article_ids)?;
+        let articles = articles
+            .into_iter()
+            .map(|article| ExportedArticle {
+                tag_list: tags_by_article.remove(&article.id).unwrap_or_default(),
+                article,
+            })
+            .collect();
+
+        let comments = comments::table
+            .filter(comments::user_id.eq(user_id))
+            .order(comments::created_at.asc())
+            .load::<Comment>(conn)?;
+        let favorites = articles::table
+            .filter(
+                articles::id.eq_any(
+                    favorites::table
+                        .select(favorites::article_id)
+                        .filter(favorites::user_id.eq(user_id)),
+                ),
+            )
+            .select(articles::slug)
+            .order(articles::slug.asc())
+            .load::<String>(conn)?;
+        let following = users::table
+            .filter(
+                users::id.eq_any(
+                    followers::table
+                        .select(followers::followed_id)
+                        .filter(followers::follower_id.eq(user_id)),
+                ),
+            )
+            .select(users::username)
+            .order(users::username.asc())
+            .load::<String>(conn)?;
+        let followers = users::table
+            .filter(
+                users::id.eq_any(
+                    followers::table
+                        .select(followers::follower_id)
+                        .filter(followers::followed_id.eq(user_id)),
+                ),
+            )
+            .select(users::username)
+            .order(users::username.asc())
+            .load::<String>(conn)?;
+
+        Ok(Export {
+            user,
+            articles,
+            comments,
+            favorites,
+            following,
+            followers,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_export() {
+        let repo = repo();
+        block_on(async move {
+            let user = generate::user().insert(repo.clone()).await;
+            let other = generate::user().insert(repo.clone()).await;
+            let article = generate::article(user.id)
+                .tags(&["dragons"])
+                .insert(repo.clone())
+                .await;
+            let theirs = generate::article(other.id).insert(repo.clone()).await;
+            generate::favorite(repo.clone(), &user, &theirs).await;
+            generate::follow(repo.clone(), &user, &other).await;
+
+            let export = export(repo.clone(), user.id).await.unwrap();
+            assert_eq!(export.user.id, user.id);
+            assert_eq!(export.articles.len(), 1);
+            assert_eq!(export.articles[0].article.slug, article.slug);
+            assert_eq!(export.articles[0].tag_list, vec!["dragons".to_string()]);
+            assert_eq!(export.favorites, vec![theirs.slug]);
+            assert_eq!(export.following, vec![other.username]);
+            assert!(export.followers.is_empty());
+            assert!(export.comments.is_empty());
+        });
+    }
+}
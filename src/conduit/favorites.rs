@@ -0,0 +1,176 @@
+use crate::db::{DbConnection, RepoError};
+use crate::events::{self, Event};
+use crate::models::NewFavorite;
+use crate::schema::{articles, favorites};
+use crate::Repo;
+
+use diesel::prelude::*;
+use std::collections::HashSet;
+
+/// How an article has been favorited, from the point of view of the current user.
+#[derive(Debug, Clone, Default)]
+pub struct FavoriteStatus {
+    pub favorited: bool,
+    pub count: i64,
+}
+
+/// Favorite an article, and count it, unless the user had already favorited it.
+/// Publishes `ArticleFavorited` the first time.
+pub async fn favorite(repo: Repo, user_id: i32, article_id: i32) -> Result<(), RepoError> {
+    repo.transaction("favorites::favorite", move |conn| {
+        let favorite = NewFavorite {
+            user_id,
+            article_id,
+        };
+        if insert_or_ignore(conn, &favorite)? > 0 {
+            change_count(conn, article_id, 1)?;
+            let article_author_id = articles::table
+                .find(article_id)
+                .select(articles::user_id)
+                .first(conn)?;
+            events::publish(
+                conn,
+                Event::ArticleFavorited {
+                    article_id,
+                    user_id,
+                    article_author_id,
+                },
+            )?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub async fn unfavorite(repo: Repo, user_id: i32, article_id: i32) -> Result<(), RepoError> {
+    repo.transaction("favorites::unfavorite", move |conn| {
+        let deleted = diesel::delete(favorites::table.find((user_id, article_id))).execute(conn)?;
+        if deleted > 0 {
+            change_count(conn, article_id, -1)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Add `change` to an article's `favorites_count`. It's added in the `UPDATE` itself, rather
+/// than read and written back, so favorites made at the same time aren't lost.
+fn change_count(conn: &DbConnection, article_id: i32, change: i32) -> QueryResult<usize> {
+    diesel::update(articles::table.find(article_id))
+        .set(articles::favorites_count.eq(articles::favorites_count + change))
+        .execute(conn)
+}
+
+/// Remove all of a user's favorites, taking them off the articles' counts, e.g. before the
+/// user is deleted. Takes a connection so it can be part of a larger transaction.
+pub fn remove_all(conn: &DbConnection, user_id: i32) -> QueryResult<usize> {
+    let favorited = favorites::table
+        .select(favorites::article_id)
+        .filter(favorites::user_id.eq(user_id));
+    diesel::update(articles::table.filter(articles::id.eq_any(favorited)))
+        .set(articles::favorites_count.eq(articles::favorites_count - 1))
+        .execute(conn)?;
+    diesel::delete(favorites::table.filter(favorites::user_id.eq(user_id))).execute(conn)
+}
+
+/// Which of the given articles `user_id` favorited, in one query.
+pub fn favorited_by(
+    conn: &DbConnection,
+    user_id: Option<i32>,
+    article_ids: &[i32],
+) -> QueryResult<HashSet<i32>> {
+    match user_id {
+        Some(user_id) => favorites::table
+            .filter(favorites::user_id.eq(user_id))
+            .filter(favorites::article_id.eq_any(article_ids))
+            .select(favorites::article_id)
+            .load::<i32>(conn)
+            .map(|ids| ids.into_iter().collect()),
+        None => Ok(HashSet::new()),
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn insert_or_ignore(conn: &DbConnection, favorite: &NewFavorite) -> QueryResult<usize> {
+    diesel::insert_into(favorites::table)
+        .values(favorite)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_or_ignore(conn: &DbConnection, favorite: &NewFavorite) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(favorites::table)
+        .values(favorite)
+        .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::{articles, users};
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_favorite_and_unfavorite() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = articles::insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+
+            favorite(repo.clone(), user.id, article.id).await.unwrap();
+            // Favoriting twice is harmless, and only counts once.
+            favorite(repo.clone(), user.id, article.id).await.unwrap();
+            let (user_id, article_id) = (user.id, article.id);
+            let favorited = repo
+                .run("test", move |conn| {
+                    favorited_by(&conn, Some(user_id), &[article_id])
+                })
+                .await
+                .unwrap();
+            assert!(favorited.contains(&article_id));
+            let found = articles::find_by_slug(repo.clone(), article.slug.clone())
+                .await
+                .unwrap();
+            assert_eq!(found.favorites_count, 1);
+
+            unfavorite(repo.clone(), user.id, article.id).await.unwrap();
+            unfavorite(repo.clone(), user.id, article.id).await.unwrap();
+            let favorited = repo
+                .run("test", move |conn| {
+                    favorited_by(&conn, Some(user_id), &[article_id])
+                })
+                .await
+                .unwrap();
+            assert!(favorited.is_empty());
+            let found = articles::find_by_slug(repo, article.slug).await.unwrap();
+            assert_eq!(found.favorites_count, 0);
+        });
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let user = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+            generate::favorite(repo.clone(), &author, &article).await;
+            generate::favorite(repo.clone(), &user, &article).await;
+
+            let user_id = user.id;
+            let removed = repo
+                .run("test", move |conn| remove_all(&conn, user_id))
+                .await
+                .unwrap();
+            assert_eq!(removed, 1);
+            let found = articles::find_by_slug(repo, article.slug).await.unwrap();
+            assert_eq!(found.favorites_count, 1);
+        });
+    }
+}
@@ -0,0 +1,96 @@
+use crate::db::{DbConnection, RepoError};
+use crate::events::{self, Event};
+use crate::models::NewFollower;
+use crate::schema::followers;
+use crate::Repo;
+
+use diesel::dsl::exists;
+use diesel::prelude::*;
+
+/// Follow a user. Publishes `UserFollowed`, unless they were already followed.
+pub async fn follow(repo: Repo, follower_id: i32, followed_id: i32) -> Result<(), RepoError> {
+    repo.transaction("followers::follow", move |conn| {
+        let follower = NewFollower {
+            follower_id,
+            followed_id,
+        };
+        if insert_or_ignore(conn, &follower)? > 0 {
+            events::publish(
+                conn,
+                Event::UserFollowed {
+                    follower_id,
+                    followed_id,
+                },
+            )?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub async fn unfollow(repo: Repo, follower_id: i32, followed_id: i32) -> Result<(), RepoError> {
+    repo.run("followers::unfollow", move |conn| {
+        diesel::delete(followers::table.find((follower_id, followed_id)))
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+pub async fn is_following(
+    repo: Repo,
+    follower_id: i32,
+    followed_id: i32,
+) -> Result<bool, RepoError> {
+    repo.run("followers::is_following", move |conn| {
+        diesel::select(exists(followers::table.find((follower_id, followed_id)))).get_result(&conn)
+    })
+    .await
+}
+
+#[cfg(feature = "postgres")]
+fn insert_or_ignore(conn: &DbConnection, follower: &NewFollower) -> QueryResult<usize> {
+    diesel::insert_into(followers::table)
+        .values(follower)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_or_ignore(conn: &DbConnection, follower: &NewFollower) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(followers::table)
+        .values(follower)
+        .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_follow_and_unfollow() {
+        let repo = repo();
+        block_on(async move {
+            let follower = generate::user().insert(repo.clone()).await;
+            let followed = generate::user().insert(repo.clone()).await;
+
+            follow(repo.clone(), follower.id, followed.id)
+                .await
+                .unwrap();
+            // Following twice is harmless.
+            follow(repo.clone(), follower.id, followed.id)
+                .await
+                .unwrap();
+            assert!(is_following(repo.clone(), follower.id, followed.id)
+                .await
+                .unwrap());
+
+            unfollow(repo.clone(), follower.id, followed.id)
+                .await
+                .unwrap();
+            assert!(!is_following(repo, follower.id, followed.id).await.unwrap());
+        });
+    }
+}
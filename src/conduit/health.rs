@@ -0,0 +1,12 @@
+use crate::db::RepoError;
+use crate::Repo;
+
+use diesel::prelude::*;
+
+/// Check a connection can be checked out of the pool and run a query.
+pub async fn ping(repo: Repo) -> Result<(), RepoError> {
+    repo.run("health::ping", move |conn| {
+        diesel::sql_query("SELECT 1").execute(&conn).map(|_| ())
+    })
+    .await
+}
@@ -0,0 +1,144 @@
+use crate::auth::random_token;
+use crate::conduit::users;
+use crate::db::{DbConnection, RepoError};
+use crate::models::{ExternalProfile, NewIdentity, NewUser, User};
+use crate::schema::{identities, users as users_table};
+use crate::slugs;
+use crate::Repo;
+
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use diesel::result::Error as dieselError;
+use futures::future::{BoxFuture, FutureExt};
+
+/// Find the user who signed in with an OAuth provider. The first time, the identity is linked
+/// to the account with the same email, or a new account is created for it.
+/// Fails with `NotFound` for a new identity without a verified email.
+pub async fn sign_in(repo: Repo, profile: ExternalProfile) -> Result<User, RepoError> {
+    repo.transaction("identities::sign_in", move |conn| {
+        let linked = identities::table
+            .filter(identities::provider.eq(&profile.provider))
+            .filter(identities::subject.eq(&profile.subject))
+            .select(identities::user_id)
+            .first::<i32>(conn)
+            .optional()?;
+        if let Some(user_id) = linked {
+            return users_table::table.find(user_id).first(conn);
+        }
+
+        let email = profile.email.clone().ok_or(dieselError::NotFound)?;
+        let user = match users::find_by_email(conn, &email).optional()? {
+            Some(user) => user,
+            None => {
+                let new_user = NewUser {
+                    username: available_username(conn, &profile.username)?,
+                    email,
+                    // Only usable after a password reset.
+                    password: random_token(),
+                };
+                users::create(conn, new_user)?
+            }
+        };
+        let identity = NewIdentity {
+            user_id: user.id,
+            provider: profile.provider,
+            subject: profile.subject,
+        };
+        diesel::insert_into(identities::table)
+            .values(&identity)
+            .execute(conn)?;
+        // The provider has verified the email.
+        diesel::update(users_table::table.find(user.id))
+            .set(users_table::verified.eq(true))
+            .execute(conn)?;
+        users_table::table.find(user.id).first(conn)
+    })
+    .await
+}
+
+/// `wanted`, or if someone already has it, `wanted` with a random suffix.
+fn available_username(conn: &DbConnection, wanted: &str) -> QueryResult<String> {
+    let mut username = wanted.to_string();
+    loop {
+        let taken = users_table::table.filter(users_table::username.eq(&username));
+        if !diesel::select(exists(taken)).get_result::<bool>(conn)? {
+            return Ok(username);
+        }
+        username = slugs::with_suffix(wanted);
+    }
+}
+
+/// Storage for the OAuth identities users sign in with.
+pub trait IdentitiesRepository: Send + Sync {
+    fn sign_in(&self, profile: ExternalProfile) -> BoxFuture<'static, Result<User, RepoError>>;
+}
+
+/// Identities stored in Postgres with Diesel.
+pub struct PgIdentitiesRepository {
+    repo: Repo,
+}
+
+impl PgIdentitiesRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgIdentitiesRepository { repo }
+    }
+}
+
+impl IdentitiesRepository for PgIdentitiesRepository {
+    fn sign_in(&self, profile: ExternalProfile) -> BoxFuture<'static, Result<User, RepoError>> {
+        sign_in(self.repo.clone(), profile).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    fn profile(subject: &str, username: String, email: Option<String>) -> ExternalProfile {
+        ExternalProfile {
+            provider: "github".to_string(),
+            subject: subject.to_string(),
+            username,
+            email,
+        }
+    }
+
+    #[test]
+    fn test_sign_in_creates_an_account() {
+        let repo = repo();
+        block_on(async move {
+            let taken = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let email = Some(generate::new_user().email);
+            let new = profile("1001", taken.username.clone(), email);
+
+            let user = sign_in(repo.clone(), new.clone()).await.unwrap();
+            assert_ne!(user.username, taken.username);
+            assert!(user.username.starts_with(&taken.username));
+            assert!(user.verified);
+            // Signing in again finds the same account.
+            assert_eq!(sign_in(repo, new).await.unwrap().id, user.id);
+        });
+    }
+
+    #[test]
+    fn test_sign_in_links_by_email() {
+        let repo = repo();
+        block_on(async move {
+            let existing = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let email = Some(existing.email.to_uppercase());
+            let user = sign_in(repo.clone(), profile("1002", "octocat".to_string(), email))
+                .await
+                .unwrap();
+            assert_eq!(user.id, existing.id);
+
+            let without_email = profile("1003", "octocat".to_string(), None);
+            assert!(sign_in(repo, without_email).await.is_err());
+        });
+    }
+}
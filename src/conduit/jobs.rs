@@ -0,0 +1,148 @@
+use crate::db::{DbConnection, RepoError};
+use crate::models::{NewJob, QueuedJob};
+use crate::schema::jobs;
+use crate::Repo;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+/// Add a job to the queue.
+pub async fn enqueue(repo: Repo, job: NewJob) -> Result<(), RepoError> {
+    repo.run("jobs::enqueue", move |conn| insert(&conn, &job))
+        .await
+}
+
+/// Add a job to the queue on `conn`, so it's only queued if the transaction it's part of
+/// commits.
+pub fn insert(conn: &DbConnection, job: &NewJob) -> QueryResult<()> {
+    diesel::insert_into(jobs::table)
+        .values(job)
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Take the job that has been due the longest, if any is, counting an attempt at it.
+///
+/// Its `run_at` is moved to `lease_until`, so other workers leave it alone, and it runs again
+/// from then should this worker die before finishing it. The update only applies while
+/// `run_at` is as it was read, so when two workers pick the same job only one gets it, and
+/// the other gets `None` for now.
+pub async fn claim(repo: Repo, lease_until: NaiveDateTime) -> Result<Option<QueuedJob>, RepoError> {
+    repo.transaction("jobs::claim", move |conn| {
+        let due = jobs::table
+            .filter(jobs::failed_at.is_null())
+            .filter(jobs::run_at.le(Utc::now().naive_utc()))
+            .order((jobs::run_at, jobs::id))
+            .first::<QueuedJob>(conn)
+            .optional()?;
+        let job = match due {
+            Some(job) => job,
+            None => return Ok(None),
+        };
+        let claimed = diesel::update(
+            jobs::table
+                .filter(jobs::id.eq(job.id))
+                .filter(jobs::run_at.eq(job.run_at)),
+        )
+        .set((
+            jobs::run_at.eq(lease_until),
+            jobs::attempts.eq(jobs::attempts + 1),
+        ))
+        .execute(conn)?;
+        if claimed == 0 {
+            return Ok(None);
+        }
+        Ok(Some(QueuedJob {
+            attempts: job.attempts + 1,
+            run_at: lease_until,
+            ..job
+        }))
+    })
+    .await
+}
+
+/// Remove a job that's been done.
+pub async fn complete(repo: Repo, id: i32) -> Result<(), RepoError> {
+    repo.run("jobs::complete", move |conn| {
+        diesel::delete(jobs::table.find(id))
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+/// Note why an attempt at a job failed. It runs again at `retry_at`, or, when that's `None`,
+/// it's given up on.
+pub async fn fail(
+    repo: Repo,
+    id: i32,
+    error: String,
+    retry_at: Option<NaiveDateTime>,
+) -> Result<(), RepoError> {
+    repo.run("jobs::fail", move |conn| {
+        let failed = jobs::table.find(id);
+        match retry_at {
+            Some(retry_at) => diesel::update(failed)
+                .set((jobs::last_error.eq(error), jobs::run_at.eq(retry_at)))
+                .execute(&conn),
+            None => diesel::update(failed)
+                .set((
+                    jobs::last_error.eq(error),
+                    jobs::failed_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(&conn),
+        }
+        .map(|_| ())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::block_on;
+    use chrono::Duration;
+
+    fn new_job(run_at: NaiveDateTime) -> NewJob {
+        NewJob {
+            kind: "Test".to_string(),
+            payload: "{}".to_string(),
+            run_at,
+        }
+    }
+
+    #[test]
+    fn test_claim() {
+        let repo = repo();
+        block_on(async move {
+            let now = Utc::now().naive_utc();
+            enqueue(repo.clone(), new_job(now + Duration::hours(1)))
+                .await
+                .unwrap();
+            assert!(claim(repo.clone(), now).await.unwrap().is_none());
+
+            enqueue(repo.clone(), new_job(now - Duration::seconds(1)))
+                .await
+                .unwrap();
+            let lease_until = now + Duration::minutes(5);
+            let job = claim(repo.clone(), lease_until).await.unwrap().unwrap();
+            assert_eq!(job.attempts, 1);
+            // Claimed, it isn't due again until the lease is up.
+            assert!(claim(repo.clone(), lease_until).await.unwrap().is_none());
+
+            fail(repo.clone(), job.id, "Failed".to_string(), Some(now))
+                .await
+                .unwrap();
+            let retried = claim(repo.clone(), lease_until).await.unwrap().unwrap();
+            assert_eq!(retried.id, job.id);
+            assert_eq!(retried.attempts, 2);
+            assert_eq!(retried.last_error, Some("Failed".to_string()));
+
+            fail(repo.clone(), job.id, "Failed again".to_string(), None)
+                .await
+                .unwrap();
+            assert!(claim(repo.clone(), lease_until).await.unwrap().is_none());
+        });
+    }
+}
@@ -0,0 +1,172 @@
+use crate::db::RepoError;
+use crate::models::NewLoginAttempt;
+use crate::schema::login_attempts;
+use crate::Repo;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use futures::future::{BoxFuture, FutureExt};
+
+pub async fn record_failure(
+    repo: Repo,
+    login: String,
+    ip: Option<String>,
+) -> Result<(), RepoError> {
+    repo.run("login_attempts::record_failure", move |conn| {
+        let attempt = NewLoginAttempt {
+            login,
+            ip,
+            attempted_at: Utc::now().naive_utc(),
+        };
+        diesel::insert_into(login_attempts::table)
+            .values(&attempt)
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+/// Forget the failed attempts for `login`, after it signs in successfully.
+pub async fn clear(repo: Repo, login: String) -> Result<(), RepoError> {
+    repo.run("login_attempts::clear", move |conn| {
+        diesel::delete(login_attempts::table.filter(login_attempts::login.eq(login)))
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+/// When the latest failures for `login` since `since` happened, newest first.
+pub async fn failures_for_login(
+    repo: Repo,
+    login: String,
+    since: NaiveDateTime,
+    limit: u32,
+) -> Result<Vec<NaiveDateTime>, RepoError> {
+    repo.run("login_attempts::failures_for_login", move |conn| {
+        login_attempts::table
+            .select(login_attempts::attempted_at)
+            .filter(login_attempts::login.eq(login))
+            .filter(login_attempts::attempted_at.gt(since))
+            .order(login_attempts::attempted_at.desc())
+            .limit(limit.into())
+            .load(&conn)
+    })
+    .await
+}
+
+/// When the latest failures from `ip` since `since` happened, newest first.
+pub async fn failures_from_ip(
+    repo: Repo,
+    ip: String,
+    since: NaiveDateTime,
+    limit: u32,
+) -> Result<Vec<NaiveDateTime>, RepoError> {
+    repo.run("login_attempts::failures_from_ip", move |conn| {
+        login_attempts::table
+            .select(login_attempts::attempted_at)
+            .filter(login_attempts::ip.eq(ip))
+            .filter(login_attempts::attempted_at.gt(since))
+            .order(login_attempts::attempted_at.desc())
+            .limit(limit.into())
+            .load(&conn)
+    })
+    .await
+}
+
+/// Storage for failed logins, counted to lock out brute-force attempts.
+pub trait LoginAttemptsRepository: Send + Sync {
+    fn record_failure(
+        &self,
+        login: String,
+        ip: Option<String>,
+    ) -> BoxFuture<'static, Result<(), RepoError>>;
+    fn clear(&self, login: String) -> BoxFuture<'static, Result<(), RepoError>>;
+    fn failures_for_login(
+        &self,
+        login: String,
+        since: NaiveDateTime,
+        limit: u32,
+    ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>>;
+    fn failures_from_ip(
+        &self,
+        ip: String,
+        since: NaiveDateTime,
+        limit: u32,
+    ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>>;
+}
+
+/// Login attempts stored in Postgres with Diesel.
+pub struct PgLoginAttemptsRepository {
+    repo: Repo,
+}
+
+impl PgLoginAttemptsRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgLoginAttemptsRepository { repo }
+    }
+}
+
+impl LoginAttemptsRepository for PgLoginAttemptsRepository {
+    fn record_failure(
+        &self,
+        login: String,
+        ip: Option<String>,
+    ) -> BoxFuture<'static, Result<(), RepoError>> {
+        record_failure(self.repo.clone(), login, ip).boxed()
+    }
+
+    fn clear(&self, login: String) -> BoxFuture<'static, Result<(), RepoError>> {
+        clear(self.repo.clone(), login).boxed()
+    }
+
+    fn failures_for_login(
+        &self,
+        login: String,
+        since: NaiveDateTime,
+        limit: u32,
+    ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>> {
+        failures_for_login(self.repo.clone(), login, since, limit).boxed()
+    }
+
+    fn failures_from_ip(
+        &self,
+        ip: String,
+        since: NaiveDateTime,
+        limit: u32,
+    ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>> {
+        failures_from_ip(self.repo.clone(), ip, since, limit).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::block_on;
+    use chrono::Duration;
+
+    #[test]
+    fn test_record_and_clear_failures() {
+        let repo = repo();
+        block_on(async move {
+            let login = "brute@example.com".to_string();
+            let ip = Some("192.0.2.1".to_string());
+            let since = Utc::now().naive_utc() - Duration::minutes(15);
+            for _ in 0..3 {
+                record_failure(repo.clone(), login.clone(), ip.clone())
+                    .await
+                    .unwrap();
+            }
+
+            let failures = failures_for_login(repo.clone(), login.clone(), since, 2).await;
+            assert_eq!(failures.unwrap().len(), 2);
+            let failures = failures_from_ip(repo.clone(), "192.0.2.1".to_string(), since, 5).await;
+            assert_eq!(failures.unwrap().len(), 3);
+
+            clear(repo.clone(), login.clone()).await.unwrap();
+            let failures = failures_for_login(repo, login, since, 5).await;
+            assert!(failures.unwrap().is_empty());
+        });
+    }
+}
@@ -1 +1,59 @@
-pub mod users;
\ No newline at end of file
+pub mod api_keys;
+pub mod articles;
+pub mod comments;
+pub mod exports;
+pub mod favorites;
+pub mod followers;
+pub mod health;
+pub mod identities;
+pub mod jobs;
+pub mod login_attempts;
+pub mod notifications;
+pub mod password_resets;
+pub mod search;
+pub mod tags;
+pub mod tokens;
+pub mod trending;
+pub mod users;
+pub mod verifications;
+pub mod views;
+pub mod webhooks;
+
+use gotham_derive::StateData;
+use std::sync::Arc;
+
+use crate::conduit::api_keys::{ApiKeysRepository, PgApiKeysRepository};
+use crate::conduit::identities::{IdentitiesRepository, PgIdentitiesRepository};
+use crate::conduit::login_attempts::{LoginAttemptsRepository, PgLoginAttemptsRepository};
+use crate::conduit::password_resets::{PasswordResetsRepository, PgPasswordResetsRepository};
+use crate::conduit::tokens::{PgTokensRepository, TokensRepository};
+use crate::conduit::users::{PgUsersRepository, UsersRepository};
+use crate::conduit::verifications::{PgVerificationsRepository, VerificationsRepository};
+use crate::Repo;
+
+/// The storage handlers use, put into `State` by the `RepositoriesMiddleware`.
+#[derive(Clone, StateData)]
+pub struct Repositories {
+    pub users: Arc<dyn UsersRepository>,
+    pub tokens: Arc<dyn TokensRepository>,
+    pub login_attempts: Arc<dyn LoginAttemptsRepository>,
+    pub verifications: Arc<dyn VerificationsRepository>,
+    pub password_resets: Arc<dyn PasswordResetsRepository>,
+    pub identities: Arc<dyn IdentitiesRepository>,
+    pub api_keys: Arc<dyn ApiKeysRepository>,
+}
+
+impl Repositories {
+    /// Repositories backed by the Postgres connection pool.
+    pub fn postgres(repo: Repo) -> Self {
+        Repositories {
+            users: Arc::new(PgUsersRepository::new(repo.clone())),
+            tokens: Arc::new(PgTokensRepository::new(repo.clone())),
+            login_attempts: Arc::new(PgLoginAttemptsRepository::new(repo.clone())),
+            verifications: Arc::new(PgVerificationsRepository::new(repo.clone())),
+            password_resets: Arc::new(PgPasswordResetsRepository::new(repo.clone())),
+            identities: Arc::new(PgIdentitiesRepository::new(repo.clone())),
+            api_keys: Arc::new(PgApiKeysRepository::new(repo)),
+        }
+    }
+}
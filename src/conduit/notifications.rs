@@ -0,0 +1,309 @@
+use crate::db::{DbConnection, RepoError};
+use crate::events::Event;
+use crate::models::{NewNotification, Notification, Profile, User};
+use crate::schema::{articles, comments, followers, notifications, users};
+use crate::Repo;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use diesel::result::Error as dieselError;
+use std::collections::{HashMap, HashSet};
+
+/// A notification with who caused it, as the user it's for sees them, and the article it's
+/// about, if it's about one.
+#[derive(Debug)]
+pub struct NotificationDto {
+    pub notification: Notification,
+    pub actor: Profile,
+    pub article: Option<ArticleLink>,
+}
+
+/// Enough of an article to link to it.
+#[derive(Debug, Clone)]
+pub struct ArticleLink {
+    pub slug: String,
+    pub title: String,
+}
+
+/// Store a notification for the recipient of `event`, if it has one and is the kind of event
+/// users are notified of. Nothing is stored if what it's about has been deleted since.
+pub fn notify(conn: &DbConnection, event: &Event, occurred_at: NaiveDateTime) -> QueryResult<()> {
+    let user_id = match event.recipient() {
+        Some(user_id) => user_id,
+        None => return Ok(()),
+    };
+    let (kind, actor_id, article_id, comment_id) = match *event {
+        Event::UserFollowed { follower_id, .. } => ("follow", follower_id, None, None),
+        Event::CommentAdded {
+            comment_id,
+            article_id,
+            author_id,
+            ..
+        } => ("comment", author_id, Some(article_id), Some(comment_id)),
+        Event::UserMentioned {
+            comment_id,
+            article_id,
+            author_id,
+            ..
+        } => ("mention", author_id, Some(article_id), Some(comment_id)),
+        Event::ArticleFavorited {
+            article_id,
+            user_id,
+            ..
+        } => ("favorite", user_id, Some(article_id), None),
+        _ => return Ok(()),
+    };
+    let notification = NewNotification {
+        user_id,
+        kind: kind.to_string(),
+        actor_id,
+        article_id,
+        comment_id,
+        created_at: occurred_at,
+    };
+    if still_exists(conn, &notification)? {
+        diesel::insert_into(notifications::table)
+            .values(&notification)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Whether the users, article and comment a notification refers to are all still there.
+fn still_exists(conn: &DbConnection, notification: &NewNotification) -> QueryResult<bool> {
+    let user_ids = vec![notification.user_id, notification.actor_id];
+    let users_count: i64 = users::table
+        .filter(users::id.eq_any(user_ids))
+        .count()
+        .get_result(conn)?;
+    if users_count < 2 {
+        return Ok(false);
+    }
+    if let Some(article_id) = notification.article_id {
+        if !diesel::select(exists(articles::table.find(article_id))).get_result(conn)? {
+            return Ok(false);
+        }
+    }
+    if let Some(comment_id) = notification.comment_id {
+        if !diesel::select(exists(comments::table.find(comment_id))).get_result(conn)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// A page of the notifications for `user_id`, newest first, along with how many there are
+/// in all.
+pub async fn list(
+    repo: Repo,
+    user_id: i32,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<NotificationDto>, i64), RepoError> {
+    repo.run("notifications::list", move |conn| {
+        let page = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .order(notifications::id.desc())
+            .limit(limit)
+            .offset(offset)
+            .load::<Notification>(&conn)?;
+        let count = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .count()
+            .get_result(&conn)?;
+        Ok((with_details(&conn, user_id, page)?, count))
+    })
+    .await
+}
+
+/// How many of the notifications for `user_id` haven't been read yet.
+pub async fn unread_count(repo: Repo, user_id: i32) -> Result<i64, RepoError> {
+    repo.run("notifications::unread_count", move |conn| {
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null())
+            .count()
+            .get_result(&conn)
+    })
+    .await
+}
+
+/// Mark a notification read, unless it already was. Fails with `NotFound` if it isn't one
+/// of the notifications for `user_id`.
+pub async fn mark_read(repo: Repo, user_id: i32, id: i32) -> Result<NotificationDto, RepoError> {
+    repo.transaction("notifications::mark_read", move |conn| {
+        let unread = notifications::table
+            .find(id)
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null());
+        diesel::update(unread)
+            .set(notifications::read_at.eq(Utc::now().naive_utc()))
+            .execute(conn)?;
+        let notification = notifications::table
+            .find(id)
+            .filter(notifications::user_id.eq(user_id))
+            .first::<Notification>(conn)?;
+        with_details(conn, user_id, vec![notification])?
+            .pop()
+            .ok_or(dieselError::NotFound)
+    })
+    .await
+}
+
+/// Add the actors and articles of all the notifications, with a query for each.
+fn with_details(
+    conn: &DbConnection,
+    user_id: i32,
+    notifications: Vec<Notification>,
+) -> QueryResult<Vec<NotificationDto>> {
+    let actor_ids: Vec<i32> = notifications.iter().map(|n| n.actor_id).collect();
+    let article_ids: Vec<i32> = notifications.iter().filter_map(|n| n.article_id).collect();
+    let actors: HashMap<i32, User> = users::table
+        .filter(users::id.eq_any(&actor_ids))
+        .load::<User>(conn)?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+    let followed: HashSet<i32> = followers::table
+        .filter(followers::follower_id.eq(user_id))
+        .filter(followers::followed_id.eq_any(&actor_ids))
+        .select(followers::followed_id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect();
+    let links: HashMap<i32, ArticleLink> = articles::table
+        .filter(articles::id.eq_any(&article_ids))
+        .select((articles::id, articles::slug, articles::title))
+        .load::<(i32, String, String)>(conn)?
+        .into_iter()
+        .map(|(id, slug, title)| (id, ArticleLink { slug, title }))
+        .collect();
+    // Deleting a user or an article deletes the notifications about it, so each is found.
+    Ok(notifications
+        .into_iter()
+        .filter_map(|notification| {
+            let actor = actors.get(&notification.actor_id)?.clone();
+            let following = followed.contains(&actor.id);
+            let article = notification
+                .article_id
+                .and_then(|article_id| links.get(&article_id).cloned());
+            Some(NotificationDto {
+                actor: Profile::from_user(actor, following),
+                article,
+                notification,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::{articles, favorites, followers};
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    async fn notify_now(repo: Repo, event: Event) {
+        repo.transaction("test", move |conn| {
+            notify(conn, &event, Utc::now().naive_utc())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn test_notify_and_list() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let reader = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+
+            let followed = Event::UserFollowed {
+                follower_id: reader.id,
+                followed_id: author.id,
+            };
+            followers::follow(repo.clone(), author.id, reader.id)
+                .await
+                .unwrap();
+            notify_now(repo.clone(), followed).await;
+            let favorited = Event::ArticleFavorited {
+                article_id: article.id,
+                user_id: reader.id,
+                article_author_id: author.id,
+            };
+            notify_now(repo.clone(), favorited).await;
+            // Nobody is notified of what they did themselves.
+            let own_favorite = Event::ArticleFavorited {
+                article_id: article.id,
+                user_id: author.id,
+                article_author_id: author.id,
+            };
+            notify_now(repo.clone(), own_favorite).await;
+
+            let (page, count) = list(repo.clone(), author.id, 10, 0).await.unwrap();
+            assert_eq!(count, 2);
+            let kinds: Vec<&str> = page.iter().map(|n| n.notification.kind.as_str()).collect();
+            assert_eq!(kinds, vec!["favorite", "follow"]);
+            assert_eq!(page[0].actor.username, reader.username);
+            assert!(page[0].actor.following);
+            assert_eq!(page[0].article.as_ref().unwrap().slug, article.slug);
+            assert!(page[1].article.is_none());
+
+            let (page, count) = list(repo.clone(), author.id, 1, 1).await.unwrap();
+            assert_eq!((page.len(), count), (1, 2));
+            assert_eq!(list(repo, reader.id, 10, 0).await.unwrap().1, 0);
+        });
+    }
+
+    #[test]
+    fn test_notify_skips_deleted() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let reader = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+            favorites::favorite(repo.clone(), reader.id, article.id)
+                .await
+                .unwrap();
+            articles::delete(repo.clone(), article.id).await.unwrap();
+
+            let favorited = Event::ArticleFavorited {
+                article_id: article.id,
+                user_id: reader.id,
+                article_author_id: author.id,
+            };
+            notify_now(repo.clone(), favorited).await;
+            assert_eq!(unread_count(repo, author.id).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn test_mark_read() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let reader = generate::user().insert(repo.clone()).await;
+            let followed = Event::UserFollowed {
+                follower_id: reader.id,
+                followed_id: author.id,
+            };
+            notify_now(repo.clone(), followed).await;
+            assert_eq!(unread_count(repo.clone(), author.id).await.unwrap(), 1);
+            let (page, _) = list(repo.clone(), author.id, 10, 0).await.unwrap();
+            let id = page[0].notification.id;
+
+            // Only the user it's for can read it.
+            assert!(mark_read(repo.clone(), reader.id, id).await.is_err());
+            assert_eq!(unread_count(repo.clone(), author.id).await.unwrap(), 1);
+
+            let read = mark_read(repo.clone(), author.id, id).await.unwrap();
+            let read_at = read.notification.read_at.unwrap();
+            assert_eq!(unread_count(repo.clone(), author.id).await.unwrap(), 0);
+            // Reading it again keeps when it was first read.
+            let again = mark_read(repo, author.id, id).await.unwrap();
+            assert_eq!(again.notification.read_at, Some(read_at));
+        });
+    }
+}
@@ -0,0 +1,174 @@
+use crate::auth::random_token;
+use crate::conduit::users;
+use crate::db::RepoError;
+use crate::models::{NewPasswordReset, User};
+use crate::schema::password_resets;
+use crate::Repo;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use futures::future::{BoxFuture, FutureExt};
+
+/// Create a reset token for the user with `email`, valid until `expires_at`.
+/// Fails with `NotFound` when no user has that email.
+pub async fn create(
+    repo: Repo,
+    email: String,
+    expires_at: NaiveDateTime,
+) -> Result<(User, String), RepoError> {
+    repo.run("password_resets::create", move |conn| {
+        let user = users::find_by_email(&conn, &email)?;
+        let reset = NewPasswordReset {
+            token: random_token(),
+            user_id: user.id,
+            expires_at,
+        };
+        diesel::insert_into(password_resets::table)
+            .values(&reset)
+            .execute(&conn)?;
+        Ok((user, reset.token))
+    })
+    .await
+}
+
+/// Set a new password with a reset token. Every outstanding token for the user is used up,
+/// as are their login tokens.
+/// Fails with `NotFound` for an unknown, used or expired token.
+pub async fn reset(repo: Repo, token: String, new_password: String) -> Result<User, RepoError> {
+    repo.transaction("password_resets::reset", move |conn| {
+        let user_id = password_resets::table
+            .find(&token)
+            .filter(password_resets::expires_at.gt(Utc::now().naive_utc()))
+            .select(password_resets::user_id)
+            .first::<i32>(conn)?;
+        diesel::delete(password_resets::table.filter(password_resets::user_id.eq(user_id)))
+            .execute(conn)?;
+        users::set_password(conn, user_id, &new_password)
+    })
+    .await
+}
+
+/// Delete reset tokens that have expired unused. Returns how many there were.
+pub async fn purge_expired(repo: Repo) -> Result<usize, RepoError> {
+    repo.run("password_resets::purge_expired", move |conn| {
+        let expired = password_resets::expires_at.le(Utc::now().naive_utc());
+        diesel::delete(password_resets::table.filter(expired)).execute(&conn)
+    })
+    .await
+}
+
+/// Storage for the tokens emailed to users who forgot their password.
+pub trait PasswordResetsRepository: Send + Sync {
+    fn create(
+        &self,
+        email: String,
+        expires_at: NaiveDateTime,
+    ) -> BoxFuture<'static, Result<(User, String), RepoError>>;
+    fn reset(
+        &self,
+        token: String,
+        new_password: String,
+    ) -> BoxFuture<'static, Result<User, RepoError>>;
+}
+
+/// Reset tokens stored in Postgres with Diesel.
+pub struct PgPasswordResetsRepository {
+    repo: Repo,
+}
+
+impl PgPasswordResetsRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgPasswordResetsRepository { repo }
+    }
+}
+
+impl PasswordResetsRepository for PgPasswordResetsRepository {
+    fn create(
+        &self,
+        email: String,
+        expires_at: NaiveDateTime,
+    ) -> BoxFuture<'static, Result<(User, String), RepoError>> {
+        create(self.repo.clone(), email, expires_at).boxed()
+    }
+
+    fn reset(
+        &self,
+        token: String,
+        new_password: String,
+    ) -> BoxFuture<'static, Result<User, RepoError>> {
+        reset(self.repo.clone(), token, new_password).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+    use chrono::Duration;
+
+    #[test]
+    fn test_reset() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+            let email = user.email.to_uppercase();
+            let (found, token) = create(repo.clone(), email, expires_at).await.unwrap();
+            assert_eq!(found.id, user.id);
+
+            let new_password = "correct horse battery staple".to_string();
+            let updated = reset(repo.clone(), token.clone(), new_password.clone())
+                .await
+                .unwrap();
+            assert!(updated.password_changed_at.is_some());
+            let login = users::find_by_login(repo.clone(), user.username, new_password).await;
+            assert!(login.is_ok());
+            // Tokens can only be used once.
+            assert!(reset(repo, token, "another password".to_string())
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let expires_at = Utc::now().naive_utc() - Duration::minutes(1);
+            let (_, token) = create(repo.clone(), user.email, expires_at).await.unwrap();
+            assert!(
+                reset(repo, token, "correct horse battery staple".to_string())
+                    .await
+                    .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let now = Utc::now().naive_utc();
+            let email = user.email;
+            create(repo.clone(), email.clone(), now - Duration::minutes(1))
+                .await
+                .unwrap();
+            let (_, token) = create(repo.clone(), email, now + Duration::hours(1))
+                .await
+                .unwrap();
+
+            assert_eq!(purge_expired(repo.clone()).await.unwrap(), 1);
+            let new_password = "correct horse battery staple".to_string();
+            assert!(reset(repo, token, new_password).await.is_ok());
+        });
+    }
+}
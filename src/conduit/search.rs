@@ -0,0 +1,283 @@
+#[cfg(not(feature = "postgres"))]
+use crate::conduit::articles::{Status, Visibility};
+use crate::db::{DbConnection, RepoError};
+use crate::models::Article;
+use crate::schema::articles;
+use crate::Repo;
+
+use diesel::prelude::*;
+#[cfg(feature = "postgres")]
+use diesel::sql_types::BigInt;
+use diesel::sql_types::{Float, Integer, Text};
+use std::collections::HashMap;
+
+/// An article matching a search, with how well it matches and an excerpt showing where.
+#[derive(Debug)]
+pub struct SearchResult {
+    pub article: Article,
+    /// Parts of the body as HTML, with the matching words between `<mark>` and `</mark>`.
+    /// Everything else the author wrote is escaped.
+    pub snippet: String,
+    pub rank: f32,
+}
+
+/// Public, published articles matching the words in `query`, best match first, along with how many
+/// match in all. Words in the title count for more than those in the description, and those
+/// in the description for more than those in the body.
+pub async fn search(
+    repo: Repo,
+    query: String,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<SearchResult>, i64), RepoError> {
+    repo.run("search::search", move |conn| {
+        let matches = find_matches(&conn, &query, limit, offset)?;
+        let count = count_matches(&conn, &query)?;
+        let ids: Vec<i32> = matches.iter().map(|found| found.id).collect();
+        let mut articles: HashMap<i32, Article> = articles::table
+            .filter(articles::id.eq_any(ids))
+            .load::<Article>(&conn)?
+            .into_iter()
+            .map(|article| (article.id, article))
+            .collect();
+        let results = matches
+            .into_iter()
+            .filter_map(|found| {
+                articles.remove(&found.id).map(|article| SearchResult {
+                    article,
+                    snippet: highlight(&found.snippet),
+                    rank: found.rank,
+                })
+            })
+            .collect();
+        Ok((results, count))
+    })
+    .await
+}
+
+/// Rebuild the search index, e.g. to compact it after many edits. Postgres keeps it up to
+/// date as articles change, so results are right without this.
+#[cfg(feature = "postgres")]
+pub async fn reindex(repo: Repo) -> Result<(), RepoError> {
+    repo.run("search::reindex", move |conn| {
+        diesel::sql_query("REINDEX INDEX articles_search_idx")
+            .execute(&conn)
+            .map(|_| ())
+    })
+    .await
+}
+
+/// SQLite and MySQL search without an index, so there's nothing to rebuild.
+#[cfg(not(feature = "postgres"))]
+pub async fn reindex(_repo: Repo) -> Result<(), RepoError> {
+    Ok(())
+}
+
+/// What `ts_headline` puts before and after each matching word: control characters
+/// nobody types, which become `<mark>` tags once the rest is escaped.
+const START_SEL: char = '\u{2}';
+const STOP_SEL: char = '\u{3}';
+
+/// Escape a snippet as HTML, then mark the matching words.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            START_SEL => html.push_str("<mark>"),
+            STOP_SEL => html.push_str("</mark>"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+#[derive(QueryableByName)]
+struct Match {
+    #[sql_type = "Integer"]
+    id: i32,
+    #[sql_type = "Text"]
+    snippet: String,
+    #[sql_type = "Float"]
+    rank: f32,
+}
+
+#[cfg(feature = "postgres")]
+#[derive(QueryableByName)]
+struct MatchCount {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+/// The weighted document the GIN index in the `add_article_search` migration is built on.
+/// Queries have to use the same expression for Postgres to use the index.
+#[cfg(feature = "postgres")]
+const DOCUMENT: &str = "(setweight(to_tsvector('english', title), 'A') \
+                        || setweight(to_tsvector('english', description), 'B') \
+                        || setweight(to_tsvector('english', body), 'C'))";
+
+#[cfg(feature = "postgres")]
+fn find_matches(
+    conn: &DbConnection,
+    query: &str,
+    limit: i64,
+    offset: i64,
+) -> QueryResult<Vec<Match>> {
+    diesel::sql_query(format!(
+        "SELECT id, ts_rank({document}, query) AS rank, \
+         ts_headline('english', body, query, \
+         'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', MaxFragments=2') AS snippet \
+         FROM articles, plainto_tsquery('english', $1) query \
+         WHERE status = 'published' AND visibility = 'public' AND {document} @@ query \
+         ORDER BY rank DESC, id DESC LIMIT $2 OFFSET $3",
+        document = DOCUMENT
+    ))
+    .bind::<Text, _>(query)
+    .bind::<BigInt, _>(limit)
+    .bind::<BigInt, _>(offset)
+    .load(conn)
+}
+
+#[cfg(feature = "postgres")]
+fn count_matches(conn: &DbConnection, query: &str) -> QueryResult<i64> {
+    let counted: MatchCount = diesel::sql_query(format!(
+        "SELECT COUNT(*) AS count FROM articles \
+         WHERE status = 'published' AND visibility = 'public' \
+         AND {document} @@ plainto_tsquery('english', $1)",
+        document = DOCUMENT
+    ))
+    .bind::<Text, _>(query)
+    .get_result(conn)?;
+    Ok(counted.count)
+}
+
+/// SQLite and MySQL have no `tsvector`, so look for the query as it's written anywhere
+/// in the title, description or body instead. Matches are listed newest first, unranked,
+/// with the description as their snippet.
+#[cfg(not(feature = "postgres"))]
+fn find_matches(
+    conn: &DbConnection,
+    query: &str,
+    limit: i64,
+    offset: i64,
+) -> QueryResult<Vec<Match>> {
+    let pattern = crate::conduit::users::contains(query);
+    let found = articles::table
+        .select((articles::id, articles::description))
+        .filter(
+            articles::title
+                .like(pattern.clone())
+                .escape('\\')
+                .or(articles::description.like(pattern.clone()).escape('\\'))
+                .or(articles::body.like(pattern).escape('\\')),
+        )
+        .filter(articles::status.eq(Status::Published.name()))
+        .filter(articles::visibility.eq(Visibility::Public.name()))
+        .order((articles::created_at.desc(), articles::id.desc()))
+        .limit(limit)
+        .offset(offset)
+        .load::<(i32, String)>(conn)?;
+    Ok(found
+        .into_iter()
+        .map(|(id, snippet)| Match {
+            id,
+            snippet,
+            rank: 0.0,
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "postgres"))]
+fn count_matches(conn: &DbConnection, query: &str) -> QueryResult<i64> {
+    let pattern = crate::conduit::users::contains(query);
+    articles::table
+        .filter(
+            articles::title
+                .like(pattern.clone())
+                .escape('\\')
+                .or(articles::description.like(pattern.clone()).escape('\\'))
+                .or(articles::body.like(pattern).escape('\\')),
+        )
+        .filter(articles::status.eq(Status::Published.name()))
+        .filter(articles::visibility.eq(Visibility::Public.name()))
+        .count()
+        .get_result(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::users;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_search() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let in_title = generate::article(user.id)
+                .title("Training zephyrs")
+                .insert(repo.clone())
+                .await;
+            let in_body = generate::article(user.id)
+                .body("Nothing tames a zephyr like patience.")
+                .insert(repo.clone())
+                .await;
+            generate::article(user.id).insert(repo.clone()).await;
+
+            let (results, count) = search(repo.clone(), "zephyr".to_string(), 20, 0)
+                .await
+                .unwrap();
+            assert_eq!(count, 2);
+            let ids: Vec<i32> = results.iter().map(|found| found.article.id).collect();
+            assert!(ids.contains(&in_title.id));
+            assert!(ids.contains(&in_body.id));
+            if cfg!(feature = "postgres") {
+                assert_eq!(ids[0], in_title.id);
+                assert!(results[1].snippet.contains("<mark>zephyr</mark>"));
+            }
+        });
+    }
+
+    #[test]
+    fn test_snippets_are_escaped() {
+        let repo = repo();
+        block_on(async move {
+            let user = generate::user().insert(repo.clone()).await;
+            let article = generate::article(user.id)
+                .body("<script>alert('zephyr')</script> Nothing tames a zephyr & <b>nothing</b>.")
+                .insert(repo.clone())
+                .await;
+
+            let (results, _) = search(repo, "zephyr".to_string(), 20, 0).await.unwrap();
+            let found = results
+                .iter()
+                .find(|found| found.article.id == article.id)
+                .unwrap();
+            assert!(!found.snippet.contains("<script"), "{}", found.snippet);
+            assert!(!found.snippet.contains("<b>"), "{}", found.snippet);
+            if cfg!(feature = "postgres") {
+                assert!(
+                    found.snippet.contains("<mark>zephyr</mark>"),
+                    "{}",
+                    found.snippet
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("<script>\u{2}zephyr\u{3}</script> & 'wind'"),
+            "&lt;script&gt;<mark>zephyr</mark>&lt;/script&gt; &amp; &#39;wind&#39;"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use crate::conduit::articles::{Status, Visibility};
+use crate::db::{DbConnection, RepoError};
+use crate::models::{NewArticleTag, NewFollowedTag, NewTag};
+use crate::schema::{article_tags, articles, followed_tags, tags};
+use crate::Repo;
+
+use diesel::dsl::{count_star, exists};
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Which tags are listed first.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    /// Alphabetically.
+    Name,
+    /// The tags of the most articles first.
+    Popular,
+}
+
+/// A tag, with how many articles anyone can read have it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    pub tag: String,
+    pub articles_count: i64,
+}
+
+type Backend = <DbConnection as Connection>::Backend;
+
+/// The tags of the published public articles, alphabetically. Tags that are only on drafts
+/// or hidden articles are left out, so their names stay private.
+pub async fn list(repo: Repo) -> Result<Vec<String>, RepoError> {
+    repo.run("tags::list", move |conn| {
+        article_tags::table
+            .inner_join(tags::table)
+            .inner_join(articles::table)
+            .filter(articles::status.eq(Status::Published.name()))
+            .filter(articles::visibility.eq(Visibility::Public.name()))
+            .select(tags::tag)
+            .distinct()
+            .order(tags::tag.asc())
+            .load(&conn)
+    })
+    .await
+}
+
+/// The tags of the published public articles, with how many of them have each, in `order`.
+/// Tags that are only on drafts or hidden articles are left out.
+pub async fn counts(
+    repo: Repo,
+    order: Order,
+    limit: Option<i64>,
+) -> Result<Vec<TagCount>, RepoError> {
+    repo.run("tags::counts", move |conn| {
+        let query = article_tags::table
+            .inner_join(tags::table)
+            .inner_join(articles::table)
+            .filter(articles::status.eq(Status::Published.name()))
+            .filter(articles::visibility.eq(Visibility::Public.name()))
+            .group_by(tags::tag)
+            .select((tags::tag, count_star()))
+            .into_boxed::<Backend>();
+        let mut query = match order {
+            Order::Name => query.order(tags::tag.asc()),
+            Order::Popular => query.order((count_star().desc(), tags::tag.asc())),
+        };
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        let rows = query.load::<(String, i64)>(&conn)?;
+        Ok(rows
+            .into_iter()
+            .map(|(tag, articles_count)| TagCount {
+                tag,
+                articles_count,
+            })
+            .collect())
+    })
+    .await
+}
+
+/// A tag, with how many published public articles have it. It's `NotFound` if none do.
+pub async fn find(repo: Repo, tag: String) -> Result<TagCount, RepoError> {
+    repo.run("tags::find", move |conn| {
+        let tag_id = public_tag_id(&conn, &tag)?;
+        let articles_count = article_tags::table
+            .inner_join(articles::table)
+            .filter(article_tags::tag_id.eq(tag_id))
+            .filter(articles::status.eq(Status::Published.name()))
+            .filter(articles::visibility.eq(Visibility::Public.name()))
+            .count()
+            .get_result(&conn)?;
+        Ok(TagCount {
+            tag,
+            articles_count,
+        })
+    })
+    .await
+}
+
+/// Follow a tag, to have articles with it in the user's feed. It's `NotFound` if no
+/// published public article has it.
+pub async fn follow(repo: Repo, user_id: i32, tag: String) -> Result<(), RepoError> {
+    repo.run("tags::follow", move |conn| {
+        let tag_id = public_tag_id(&conn, &tag)?;
+        insert_followed(&conn, &NewFollowedTag { user_id, tag_id }).map(|_| ())
+    })
+    .await
+}
+
+pub async fn unfollow(repo: Repo, user_id: i32, tag: String) -> Result<(), RepoError> {
+    repo.run("tags::unfollow", move |conn| {
+        let tag_ids = tags::table.filter(tags::tag.eq(tag)).select(tags::id);
+        diesel::delete(
+            followed_tags::table
+                .filter(followed_tags::user_id.eq(user_id))
+                .filter(followed_tags::tag_id.eq_any(tag_ids)),
+        )
+        .execute(&conn)
+        .map(|_| ())
+    })
+    .await
+}
+
+pub async fn is_following(repo: Repo, user_id: i32, tag: String) -> Result<bool, RepoError> {
+    repo.run("tags::is_following", move |conn| {
+        diesel::select(exists(
+            followed_tags::table
+                .inner_join(tags::table)
+                .filter(followed_tags::user_id.eq(user_id))
+                .filter(tags::tag.eq(tag)),
+        ))
+        .get_result(&conn)
+    })
+    .await
+}
+
+/// The id of a tag, if a published public article has it. Otherwise it's `NotFound`, the
+/// same as for tags that don't exist, so private tags can't be told apart from them.
+fn public_tag_id(conn: &DbConnection, tag: &str) -> QueryResult<i32> {
+    article_tags::table
+        .inner_join(tags::table)
+        .inner_join(articles::table)
+        .filter(tags::tag.eq(tag))
+        .filter(articles::status.eq(Status::Published.name()))
+        .filter(articles::visibility.eq(Visibility::Public.name()))
+        .select(tags::id)
+        .first(conn)
+}
+
+/// Tag an article, creating any tags that don't exist yet.
+/// Tags are trimmed and de-duplicated, and the resulting tag list is returned.
+/// Takes a connection rather than a `Repo`, so it can be part of a larger transaction.
+pub fn attach(
+    conn: &DbConnection,
+    article_id: i32,
+    tag_list: Vec<String>,
+) -> QueryResult<Vec<String>> {
+    let mut tag_list: Vec<String> = tag_list
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tag_list.sort();
+    tag_list.dedup();
+    if tag_list.is_empty() {
+        return Ok(tag_list);
+    }
+
+    let new_tags: Vec<NewTag> = tag_list
+        .iter()
+        .map(|tag| NewTag { tag: tag.clone() })
+        .collect();
+    insert_tags(conn, &new_tags)?;
+
+    let tag_ids: Vec<i32> = tags::table
+        .filter(tags::tag.eq_any(&tag_list))
+        .select(tags::id)
+        .load(conn)?;
+    let links: Vec<NewArticleTag> = tag_ids
+        .into_iter()
+        .map(|tag_id| NewArticleTag { article_id, tag_id })
+        .collect();
+    insert_links(conn, &links)?;
+    Ok(tag_list)
+}
+
+/// Insert tags, skipping any that already exist.
+#[cfg(feature = "postgres")]
+fn insert_tags(conn: &DbConnection, new_tags: &[NewTag]) -> QueryResult<usize> {
+    diesel::insert_into(tags::table)
+        .values(new_tags)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_tags(conn: &DbConnection, new_tags: &[NewTag]) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(tags::table)
+        .values(new_tags)
+        .execute(conn)
+}
+
+#[cfg(feature = "postgres")]
+fn insert_links(conn: &DbConnection, links: &[NewArticleTag]) -> QueryResult<usize> {
+    diesel::insert_into(article_tags::table)
+        .values(links)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_links(conn: &DbConnection, links: &[NewArticleTag]) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(article_tags::table)
+        .values(links)
+        .execute(conn)
+}
+
+#[cfg(feature = "postgres")]
+fn insert_followed(conn: &DbConnection, followed: &NewFollowedTag) -> QueryResult<usize> {
+    diesel::insert_into(followed_tags::table)
+        .values(followed)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_followed(conn: &DbConnection, followed: &NewFollowedTag) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(followed_tags::table)
+        .values(followed)
+        .execute(conn)
+}
+
+/// The tags of each of the given articles, keyed by article id, in one query.
+pub fn by_article(
+    conn: &DbConnection,
+    article_ids: &[i32],
+) -> QueryResult<HashMap<i32, Vec<String>>> {
+    let rows = article_tags::table
+        .inner_join(tags::table)
+        .filter(article_tags::article_id.eq_any(article_ids))
+        .select((article_tags::article_id, tags::tag))
+        .order(tags::tag.asc())
+        .load::<(i32, String)>(conn)?;
+    let mut tags_by_article = HashMap::new();
+    for (article_id, tag) in rows {
+        tags_by_article
+            .entry(article_id)
+            .or_insert_with(Vec::new)
+            .push(tag);
+    }
+    Ok(tags_by_article)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::{articles, users};
+    use crate::models::NewArticle;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_attach_tags() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            let article = articles::insert(repo.clone(), generate::new_article(user.id))
+                .await
+                .unwrap();
+
+            let tag_list = vec![
+                "rust".to_string(),
+                " gotham ".to_string(),
+                "rust".to_string(),
+                "".to_string(),
+            ];
+            let article_id = article.id;
+            let attached = repo
+                .run("test", move |conn| attach(&conn, article_id, tag_list))
+                .await
+                .unwrap();
+            assert_eq!(attached, vec!["gotham".to_string(), "rust".to_string()]);
+
+            let tags = repo
+                .run("test", move |conn| by_article(&conn, &[article_id]))
+                .await
+                .unwrap();
+            assert_eq!(tags[&article.id], attached);
+
+            let all_tags = list(repo).await.unwrap();
+            assert!(all_tags.contains(&"rust".to_string()));
+            assert!(all_tags.contains(&"gotham".to_string()));
+        });
+    }
+
+    #[test]
+    fn test_follow_and_unfollow() {
+        let repo = repo();
+        block_on(async move {
+            let user = generate::user().insert(repo.clone()).await;
+            generate::article(user.id)
+                .tags(&["dragons"])
+                .insert(repo.clone())
+                .await;
+            let following = || is_following(repo.clone(), user.id, "dragons".to_string());
+
+            follow(repo.clone(), user.id, "dragons".to_string())
+                .await
+                .unwrap();
+            // Following twice is harmless.
+            follow(repo.clone(), user.id, "dragons".to_string())
+                .await
+                .unwrap();
+            assert!(following().await.unwrap());
+            unfollow(repo.clone(), user.id, "dragons".to_string())
+                .await
+                .unwrap();
+            assert!(!following().await.unwrap());
+
+            let missing = follow(repo.clone(), user.id, "no-such-tag".to_string()).await;
+            assert!(missing.is_err());
+        });
+    }
+
+    #[test]
+    fn test_counts_and_find() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            // Unique, so tags other tests leave behind don't get in the way.
+            let popular = format!("popular-{}", author.id);
+            let rare = format!("rare-{}", author.id);
+            generate::article(author.id)
+                .tags(&[popular.as_str(), rare.as_str()])
+                .insert(repo.clone())
+                .await;
+            generate::article(author.id)
+                .tags(&[popular.as_str()])
+                .insert(repo.clone())
+                .await;
+            let count = |tag: &str, articles_count| TagCount {
+                tag: tag.to_string(),
+                articles_count,
+            };
+
+            let popular_first = counts(repo.clone(), Order::Popular, None).await.unwrap();
+            let ours: Vec<TagCount> = popular_first
+                .into_iter()
+                .filter(|counted| counted.tag == popular || counted.tag == rare)
+                .collect();
+            assert_eq!(ours, vec![count(&popular, 2), count(&rare, 1)]);
+            let top = counts(repo.clone(), Order::Popular, Some(1)).await.unwrap();
+            assert_eq!(top.len(), 1);
+
+            assert_eq!(
+                find(repo.clone(), rare.clone()).await.unwrap(),
+                count(&rare, 1)
+            );
+            assert!(find(repo, "no-such-tag".to_string()).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_draft_tags_stay_private() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let secret = format!("secret-{}", author.id);
+            let draft = NewArticle {
+                status: Status::Draft.name().to_string(),
+                ..generate::new_article(author.id)
+            };
+            articles::insert_with_tags(repo.clone(), draft, vec![secret.clone()])
+                .await
+                .unwrap();
+
+            assert!(!list(repo.clone()).await.unwrap().contains(&secret));
+            assert!(find(repo.clone(), secret.clone()).await.is_err());
+            assert!(follow(repo, author.id, secret).await.is_err());
+        });
+    }
+}
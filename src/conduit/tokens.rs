@@ -0,0 +1,127 @@
+use crate::db::{DbConnection, RepoError};
+use crate::models::NewRevokedToken;
+use crate::schema::revoked_tokens;
+use crate::Repo;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use futures::future::{BoxFuture, FutureExt};
+
+/// Revoke the token with the id `jti` until it expires.
+pub async fn revoke(repo: Repo, jti: String, expires_at: NaiveDateTime) -> Result<(), RepoError> {
+    repo.run("tokens::revoke", move |conn| {
+        let token = NewRevokedToken { jti, expires_at };
+        insert_or_ignore(&conn, &token).map(|_| ())
+    })
+    .await
+}
+
+pub async fn is_revoked(repo: Repo, jti: String) -> Result<bool, RepoError> {
+    repo.run("tokens::is_revoked", move |conn| {
+        diesel::select(exists(revoked_tokens::table.find(jti))).get_result(&conn)
+    })
+    .await
+}
+
+/// Forget revoked tokens that have expired since, as they're refused anyway.
+/// Returns how many there were.
+pub async fn purge_expired(repo: Repo) -> Result<usize, RepoError> {
+    repo.run("tokens::purge_expired", move |conn| {
+        let expired = revoked_tokens::expires_at.lt(Utc::now().naive_utc());
+        diesel::delete(revoked_tokens::table.filter(expired)).execute(&conn)
+    })
+    .await
+}
+
+#[cfg(feature = "postgres")]
+fn insert_or_ignore(conn: &DbConnection, token: &NewRevokedToken) -> QueryResult<usize> {
+    diesel::insert_into(revoked_tokens::table)
+        .values(token)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_or_ignore(conn: &DbConnection, token: &NewRevokedToken) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(revoked_tokens::table)
+        .values(token)
+        .execute(conn)
+}
+
+/// Storage for tokens that were signed out, checked on every authenticated request.
+pub trait TokensRepository: Send + Sync {
+    fn revoke(
+        &self,
+        jti: String,
+        expires_at: NaiveDateTime,
+    ) -> BoxFuture<'static, Result<(), RepoError>>;
+    fn is_revoked(&self, jti: String) -> BoxFuture<'static, Result<bool, RepoError>>;
+}
+
+/// Revoked tokens stored in Postgres with Diesel.
+pub struct PgTokensRepository {
+    repo: Repo,
+}
+
+impl PgTokensRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgTokensRepository { repo }
+    }
+}
+
+impl TokensRepository for PgTokensRepository {
+    fn revoke(
+        &self,
+        jti: String,
+        expires_at: NaiveDateTime,
+    ) -> BoxFuture<'static, Result<(), RepoError>> {
+        revoke(self.repo.clone(), jti, expires_at).boxed()
+    }
+
+    fn is_revoked(&self, jti: String) -> BoxFuture<'static, Result<bool, RepoError>> {
+        is_revoked(self.repo.clone(), jti).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::block_on;
+    use chrono::Duration;
+
+    #[test]
+    fn test_revoke() {
+        let repo = repo();
+        block_on(async move {
+            let jti = "0123456789abcdef".to_string();
+            let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+            assert!(!is_revoked(repo.clone(), jti.clone()).await.unwrap());
+
+            revoke(repo.clone(), jti.clone(), expires_at).await.unwrap();
+            // Signing out twice is harmless.
+            revoke(repo.clone(), jti.clone(), expires_at).await.unwrap();
+            assert!(is_revoked(repo, jti).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let repo = repo();
+        block_on(async move {
+            let now = Utc::now().naive_utc();
+            let (expired, current) = ("expired".to_string(), "current".to_string());
+            revoke(repo.clone(), expired.clone(), now - Duration::minutes(1))
+                .await
+                .unwrap();
+            revoke(repo.clone(), current.clone(), now + Duration::hours(1))
+                .await
+                .unwrap();
+
+            assert_eq!(purge_expired(repo.clone()).await.unwrap(), 1);
+            assert!(!is_revoked(repo.clone(), expired).await.unwrap());
+            assert!(is_revoked(repo, current).await.unwrap());
+        });
+    }
+}
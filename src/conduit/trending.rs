@@ -0,0 +1,171 @@
+use crate::conduit::articles::{Status, Visibility};
+use crate::db::RepoError;
+use crate::models::{Article, NewTrendingArticle};
+use crate::schema::{article_views, articles, comments, favorites, trending_articles};
+use crate::Repo;
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+/// How recently an article must have been published to be trending.
+const WINDOW_DAYS: i64 = 7;
+/// How long it takes for a view, favorite or comment to count half as much.
+const HALF_LIFE_HOURS: f64 = 24.0;
+const VIEW_WEIGHT: f64 = 1.0;
+const FAVORITE_WEIGHT: f64 = 5.0;
+const COMMENT_WEIGHT: f64 = 3.0;
+
+/// Score the public articles published in the last week by their views, favorites and
+/// comments, each counting for less the older it is, and replace the stored scores with
+/// them. Articles nobody has reacted to aren't stored. Returns how many were.
+pub async fn refresh(repo: Repo, now: NaiveDateTime) -> Result<usize, RepoError> {
+    repo.transaction("trending::refresh", move |conn| {
+        let since = now - Duration::days(WINDOW_DAYS);
+        let ids = articles::table
+            .filter(articles::status.eq(Status::Published.name()))
+            .filter(articles::visibility.eq(Visibility::Public.name()))
+            .filter(articles::created_at.ge(since))
+            .select(articles::id)
+            .load::<i32>(conn)?;
+        let mut scores: HashMap<i32, f64> = HashMap::new();
+        let mut add = |article_id: i32, weight: f64, at: NaiveDateTime| {
+            *scores.entry(article_id).or_insert(0.0) += decayed(weight, now - at);
+        };
+
+        let hourly_views = article_views::table
+            .filter(article_views::article_id.eq_any(&ids))
+            .filter(article_views::viewed_hour.ge(since))
+            .group_by((article_views::article_id, article_views::viewed_hour))
+            .select((
+                article_views::article_id,
+                article_views::viewed_hour,
+                count_star(),
+            ))
+            .load::<(i32, NaiveDateTime, i64)>(conn)?;
+        for (article_id, hour, count) in hourly_views {
+            add(article_id, VIEW_WEIGHT * count as f64, hour);
+        }
+        let favorited_at = favorites::table
+            .filter(favorites::article_id.eq_any(&ids))
+            .select((favorites::article_id, favorites::created_at))
+            .load::<(i32, NaiveDateTime)>(conn)?;
+        for (article_id, at) in favorited_at {
+            add(article_id, FAVORITE_WEIGHT, at);
+        }
+        let commented_at = comments::table
+            .filter(comments::article_id.eq_any(&ids))
+            .select((comments::article_id, comments::created_at))
+            .load::<(i32, NaiveDateTime)>(conn)?;
+        for (article_id, at) in commented_at {
+            add(article_id, COMMENT_WEIGHT, at);
+        }
+
+        let rows: Vec<NewTrendingArticle> = scores
+            .into_iter()
+            .map(|(article_id, score)| NewTrendingArticle {
+                article_id,
+                score,
+                computed_at: now,
+            })
+            .collect();
+        diesel::delete(trending_articles::table).execute(conn)?;
+        if !rows.is_empty() {
+            diesel::insert_into(trending_articles::table)
+                .values(&rows)
+                .execute(conn)?;
+        }
+        Ok(rows.len())
+    })
+    .await
+}
+
+/// A page of the trending articles, highest score first, along with how many there are in
+/// all. Articles made private or unpublished since the scores were computed are left out.
+pub async fn list(repo: Repo, limit: i64, offset: i64) -> Result<(Vec<Article>, i64), RepoError> {
+    repo.run("trending::list", move |conn| {
+        let listed = || {
+            trending_articles::table
+                .inner_join(articles::table)
+                .filter(articles::status.eq(Status::Published.name()))
+                .filter(articles::visibility.eq(Visibility::Public.name()))
+        };
+        let articles = listed()
+            .select(articles::all_columns)
+            .order((trending_articles::score.desc(), articles::id.desc()))
+            .limit(limit)
+            .offset(offset)
+            .load::<Article>(&conn)?;
+        let count = listed().count().get_result(&conn)?;
+        Ok((articles, count))
+    })
+    .await
+}
+
+/// What something weighing `weight` counts for after `age`.
+fn decayed(weight: f64, age: Duration) -> f64 {
+    let hours = age.num_seconds().max(0) as f64 / 3600.0;
+    weight * 0.5f64.powf(hours / HALF_LIFE_HOURS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::favorites;
+    use crate::conduit::views::{self, Viewer};
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+    use chrono::Utc;
+
+    #[test]
+    fn test_decayed() {
+        assert_eq!(decayed(4.0, Duration::zero()), 4.0);
+        assert_eq!(decayed(4.0, Duration::hours(24)), 2.0);
+        assert_eq!(decayed(4.0, Duration::hours(48)), 1.0);
+        // Anything from the future counts as from now.
+        assert_eq!(decayed(4.0, Duration::hours(-1)), 4.0);
+    }
+
+    #[test]
+    fn test_refresh_and_list() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let reader = generate::user().insert(repo.clone()).await;
+            let viewed = generate::article(author.id).insert(repo.clone()).await;
+            let favorited = generate::article(author.id).insert(repo.clone()).await;
+            generate::article(author.id).insert(repo.clone()).await;
+
+            let now = Utc::now().naive_utc();
+            views::record(
+                repo.clone(),
+                viewed.slug.clone(),
+                Viewer::User(reader.id),
+                now,
+            )
+            .await
+            .unwrap();
+            favorites::favorite(repo.clone(), reader.id, favorited.id)
+                .await
+                .unwrap();
+
+            let ours = [viewed.id, favorited.id];
+            let trending = |repo: Repo| async move {
+                let (articles, _) = list(repo, 100, 0).await.unwrap();
+                articles
+                    .into_iter()
+                    .map(|article| article.id)
+                    .filter(|id| ours.contains(id))
+                    .collect::<Vec<_>>()
+            };
+            refresh(repo.clone(), now).await.unwrap();
+            // Favorites count for more than views, and unread articles aren't listed.
+            assert_eq!(trending(repo.clone()).await, vec![favorited.id, viewed.id]);
+
+            // The scores are replaced, not added to.
+            refresh(repo.clone(), now).await.unwrap();
+            assert_eq!(trending(repo.clone()).await, vec![favorited.id, viewed.id]);
+        });
+    }
+}
@@ -1,79 +1,677 @@
-use crate::models::{NewUser, User};
-use crate::schema::users;
+use crate::auth::{password, random_token, Role};
+use crate::conduit;
+use crate::db::{DbConnection, RepoError};
+use crate::events::{self, Event};
+use crate::models::{NewUser, UpdateUser, User};
+use crate::schema::{
+    api_keys, articles, comments, email_verifications, followed_tags, followers, identities,
+    notifications, password_resets, users,
+};
 use crate::Repo;
 
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::result::Error as dieselError;
-use futures::Future;
-
-pub fn insert(repo: Repo, user: NewUser) -> impl Future<Item = User, Error = dieselError> {
-    repo.run(move |conn| {
-        // TODO: store password not in plain text, later
-        diesel::insert_into(users::table)
-            .values(&user)
-            .get_result(&conn)
-    })
+use futures::future::{BoxFuture, FutureExt};
+
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+/// Emails are stored trimmed and lowercased, so addresses differing only in case
+/// can't be registered twice.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub async fn insert(repo: Repo, user: NewUser) -> Result<User, RepoError> {
+    repo.transaction("users::insert", move |conn| create(conn, user))
+        .await
 }
 
-pub fn find(repo: Repo, user_id: i32) -> impl Future<Item = User, Error = dieselError> {
+/// Store a new user, with their email normalized and their password hashed.
+/// Publishes `UserRegistered`, so it should be called in a transaction.
+pub fn create(conn: &DbConnection, user: NewUser) -> QueryResult<User> {
+    let user = NewUser {
+        email: normalize_email(&user.email),
+        password: hash_password(&user.password)?,
+        ..user
+    };
+    let user = insert_user(conn, &user)?;
+    events::publish(
+        conn,
+        Event::UserRegistered {
+            user_id: user.id,
+            username: user.username.clone(),
+        },
+    )?;
+    Ok(user)
+}
+
+pub async fn find(repo: Repo, user_id: i32) -> Result<User, RepoError> {
     use crate::schema::users::dsl::*;
-    repo.run(move |conn| users.find(user_id).first(&conn))
+    repo.run("users::find", move |conn| users.find(user_id).first(&conn))
+        .await
+}
+
+pub async fn find_by_username(repo: Repo, username: String) -> Result<User, RepoError> {
+    repo.run("users::find_by_username", move |conn| {
+        users::table
+            .filter(users::username.eq(username))
+            .first(&conn)
+    })
+    .await
 }
 
-pub fn find_by_email_password(
+/// Edit a user's profile. The password is left alone: it's changed with `change_password`,
+/// which checks the current one, or with a reset token.
+pub async fn update(repo: Repo, user_id: i32, user: UpdateUser) -> Result<User, RepoError> {
+    repo.run("users::update", move |conn| {
+        let user = UpdateUser {
+            email: user.email.as_ref().map(|email| normalize_email(email)),
+            password: None,
+            password_changed_at: None,
+            ..user
+        };
+        match update_user(&conn, user_id, &user) {
+            // Diesel refuses to run an update with no fields set, so just return the user as is.
+            Err(dieselError::QueryBuilderError(_)) => users::table.find(user_id).first(&conn),
+            result => result,
+        }
+    })
+    .await
+}
+
+/// Change a user's password, provided they know their current one.
+/// Tokens issued before the change are no longer accepted.
+/// Fails with `NotFound` when the current password is wrong.
+pub async fn change_password(
     repo: Repo,
-    user_email: String,
+    user_id: i32,
+    current_password: String,
+    new_password: String,
+) -> Result<User, RepoError> {
+    repo.run("users::change_password", move |conn| {
+        let user = users::table.find(user_id).first::<User>(&conn)?;
+        if !password::verify(&user.password, &current_password) {
+            return Err(dieselError::NotFound);
+        }
+        set_password(&conn, user_id, &new_password)
+    })
+    .await
+}
+
+/// Hash and store a new password, marking when it changed so older tokens are turned away.
+pub fn set_password(conn: &DbConnection, user_id: i32, plaintext: &str) -> QueryResult<User> {
+    let changes = UpdateUser {
+        password: Some(hash_password(plaintext)?),
+        password_changed_at: Some(Utc::now().naive_utc()),
+        ..UpdateUser::default()
+    };
+    update_user(conn, user_id, &changes)
+}
+
+/// Find a user by their email, ignoring case.
+pub fn find_by_email(conn: &DbConnection, email: &str) -> QueryResult<User> {
+    users::table
+        .filter(lower(users::email).eq(normalize_email(email)))
+        .first(conn)
+}
+
+/// Which users to list for admins. The filters match part of an email or username,
+/// ignoring case.
+#[derive(Debug, Clone)]
+pub struct ListParams {
+    pub email: Option<String>,
+    pub username: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Default for ListParams {
+    fn default() -> Self {
+        ListParams {
+            email: None,
+            username: None,
+            limit: 20,
+            offset: 0,
+        }
+    }
+}
+
+type Backend = <DbConnection as Connection>::Backend;
+
+fn filtered(params: &ListParams) -> users::BoxedQuery<'static, Backend> {
+    let mut query = users::table.into_boxed();
+    if let Some(ref email) = params.email {
+        let pattern = contains(&normalize_email(email));
+        query = query.filter(users::email.like(pattern).escape('\\'));
+    }
+    if let Some(ref username) = params.username {
+        let pattern = contains(&username.to_lowercase());
+        query = query.filter(lower(users::username).like(pattern).escape('\\'));
+    }
+    query
+}
+
+/// A `LIKE` pattern matching values that contain `value`.
+pub fn contains(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// A page of users matching `params`, oldest first, along with how many match in all.
+pub async fn list(repo: Repo, params: ListParams) -> Result<(Vec<User>, i64), RepoError> {
+    repo.run("users::list", move |conn| {
+        let users = filtered(&params)
+            .order(users::id)
+            .limit(params.limit)
+            .offset(params.offset)
+            .load(&conn)?;
+        let count = filtered(&params).count().get_result(&conn)?;
+        Ok((users, count))
+    })
+    .await
+}
+
+/// Suspend a user, or lift their suspension.
+/// Fails with `NotFound` when there's no such user.
+pub async fn set_suspended(repo: Repo, user_id: i32, suspended: bool) -> Result<User, RepoError> {
+    repo.run("users::set_suspended", move |conn| {
+        let suspended_at = if suspended {
+            Some(Utc::now().naive_utc())
+        } else {
+            None
+        };
+        diesel::update(users::table.find(user_id))
+            .set(users::suspended_at.eq(suspended_at))
+            .execute(&conn)?;
+        users::table.find(user_id).first(&conn)
+    })
+    .await
+}
+
+/// Delete a user, along with everything of theirs.
+/// Fails with `NotFound` when there's no such user.
+pub async fn delete(repo: Repo, user_id: i32) -> Result<(), RepoError> {
+    repo.transaction("users::delete", move |conn| {
+        // Favorites would go with the user anyway, but the counts they added wouldn't.
+        conduit::favorites::remove_all(conn, user_id)?;
+        let deleted = diesel::delete(users::table.find(user_id)).execute(conn)?;
+        if deleted == 0 {
+            return Err(dieselError::NotFound);
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// The username a deleted account is left with.
+pub fn anonymous_username(user_id: i32) -> String {
+    format!("deleted-user-{}", user_id)
+}
+
+/// Delete an account on the user's request, without deleting what they wrote.
+/// The row is kept with its personal details replaced, so articles and comments keep
+/// an author, while follows, followed tags, favorites, notifications and ways to sign in are
+/// removed.
+/// Fails with `NotFound` when there's no such user.
+pub async fn anonymize(repo: Repo, user_id: i32) -> Result<(), RepoError> {
+    repo.transaction("users::anonymize", move |conn| {
+        let now = Utc::now().naive_utc();
+        let username = anonymous_username(user_id);
+        let updated = diesel::update(users::table.find(user_id))
+            .set((
+                users::email.eq(format!("{}@deleted.invalid", username)),
+                users::username.eq(username),
+                // Nobody knows it, so it can't be logged in to.
+                users::password.eq(hash_password(&random_token())?),
+                users::bio.eq(None::<String>),
+                users::image.eq(None::<String>),
+                users::role.eq(Role::User.name()),
+                users::password_changed_at.eq(now),
+                users::deleted_at.eq(now),
+            ))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(dieselError::NotFound);
+        }
+        diesel::delete(
+            followers::table.filter(
+                followers::follower_id
+                    .eq(user_id)
+                    .or(followers::followed_id.eq(user_id)),
+            ),
+        )
+        .execute(conn)?;
+        diesel::delete(followed_tags::table.filter(followed_tags::user_id.eq(user_id)))
+            .execute(conn)?;
+        conduit::favorites::remove_all(conn, user_id)?;
+        diesel::delete(
+            notifications::table.filter(
+                notifications::user_id
+                    .eq(user_id)
+                    .or(notifications::actor_id.eq(user_id)),
+            ),
+        )
+        .execute(conn)?;
+        diesel::delete(api_keys::table.filter(api_keys::user_id.eq(user_id))).execute(conn)?;
+        diesel::delete(identities::table.filter(identities::user_id.eq(user_id))).execute(conn)?;
+        diesel::delete(email_verifications::table.filter(email_verifications::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(password_resets::table.filter(password_resets::user_id.eq(user_id)))
+            .execute(conn)?;
+        Ok(())
+    })
+    .await
+}
+
+/// Delete, for good, accounts deleted before `deleted_before` that have no articles or
+/// comments left to be the author of. Returns how many there were.
+pub async fn purge_deleted(repo: Repo, deleted_before: NaiveDateTime) -> Result<usize, RepoError> {
+    repo.run("users::purge_deleted", move |conn| {
+        let authors = articles::table.select(articles::user_id);
+        let commenters = comments::table.select(comments::user_id);
+        diesel::delete(
+            users::table
+                .filter(users::deleted_at.lt(deleted_before))
+                .filter(users::id.ne_all(authors))
+                .filter(users::id.ne_all(commenters)),
+        )
+        .execute(&conn)
+    })
+    .await
+}
+
+/// Authenticate a user by either their email or their username, along with their password.
+/// A login containing an `@` is taken to be an email, which is matched case-insensitively.
+pub async fn find_by_login(
+    repo: Repo,
+    login: String,
     user_password: String,
-) -> impl Future<Item = User, Error = dieselError> {
-    use crate::schema::users::dsl::*;
-    repo.run(|conn| {
-        users
-            .filter(email.eq(user_email))
-            .filter(password.eq(user_password))
-            .first::<User>(&conn)
+) -> Result<User, RepoError> {
+    repo.run("users::find_by_login", move |conn| {
+        let login = login.trim();
+        let user = if login.contains('@') {
+            find_by_email(&conn, login)?
+        } else {
+            users::table
+                .filter(users::username.eq(login))
+                .first::<User>(&conn)?
+        };
+        if !password::verify(&user.password, &user_password) {
+            return Err(dieselError::NotFound);
+        }
+        if password::is_hashed(&user.password) {
+            return Ok(user);
+        }
+        // Legacy rows still hold a plaintext password; upgrade them now we know it.
+        let upgrade = UpdateUser {
+            password: Some(hash_password(&user_password)?),
+            ..UpdateUser::default()
+        };
+        update_user(&conn, user.id, &upgrade)
     })
+    .await
+}
+
+#[cfg(feature = "postgres")]
+fn insert_user(conn: &DbConnection, user: &NewUser) -> QueryResult<User> {
+    diesel::insert_into(users::table)
+        .values(user)
+        .get_result(conn)
+}
+
+/// SQLite and MySQL have no `RETURNING`, so read the new row back by its id.
+#[cfg(not(feature = "postgres"))]
+fn insert_user(conn: &DbConnection, user: &NewUser) -> QueryResult<User> {
+    diesel::insert_into(users::table)
+        .values(user)
+        .execute(conn)?;
+    users::table.find(crate::db::inserted_id(conn)?).first(conn)
+}
+
+#[cfg(feature = "postgres")]
+fn update_user(conn: &DbConnection, user_id: i32, user: &UpdateUser) -> QueryResult<User> {
+    diesel::update(users::table.find(user_id))
+        .set(user)
+        .get_result(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn update_user(conn: &DbConnection, user_id: i32, user: &UpdateUser) -> QueryResult<User> {
+    diesel::update(users::table.find(user_id))
+        .set(user)
+        .execute(conn)?;
+    users::table.find(user_id).first(conn)
+}
+
+fn hash_password(plaintext: &str) -> Result<String, dieselError> {
+    password::hash(plaintext).map_err(|e| dieselError::SerializationError(Box::new(e)))
+}
+
+/// Storage for users.
+///
+/// Handlers go through this trait, found in `State` via `Repositories`, rather than
+/// calling the functions above directly, so their tests can swap in an in-memory fake.
+pub trait UsersRepository: Send + Sync {
+    fn insert(&self, user: NewUser) -> BoxFuture<'static, Result<User, RepoError>>;
+    fn find(&self, user_id: i32) -> BoxFuture<'static, Result<User, RepoError>>;
+    fn find_by_username(&self, username: String) -> BoxFuture<'static, Result<User, RepoError>>;
+    fn update(&self, user_id: i32, user: UpdateUser)
+        -> BoxFuture<'static, Result<User, RepoError>>;
+    fn find_by_login(
+        &self,
+        login: String,
+        password: String,
+    ) -> BoxFuture<'static, Result<User, RepoError>>;
+    fn change_password(
+        &self,
+        user_id: i32,
+        current_password: String,
+        new_password: String,
+    ) -> BoxFuture<'static, Result<User, RepoError>>;
+    fn list(&self, params: ListParams) -> BoxFuture<'static, Result<(Vec<User>, i64), RepoError>>;
+    fn set_suspended(
+        &self,
+        user_id: i32,
+        suspended: bool,
+    ) -> BoxFuture<'static, Result<User, RepoError>>;
+    fn delete(&self, user_id: i32) -> BoxFuture<'static, Result<(), RepoError>>;
+    fn anonymize(&self, user_id: i32) -> BoxFuture<'static, Result<(), RepoError>>;
+}
+
+/// Users stored in Postgres with Diesel.
+pub struct PgUsersRepository {
+    repo: Repo,
+}
+
+impl PgUsersRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgUsersRepository { repo }
+    }
+}
+
+impl UsersRepository for PgUsersRepository {
+    fn insert(&self, user: NewUser) -> BoxFuture<'static, Result<User, RepoError>> {
+        insert(self.repo.clone(), user).boxed()
+    }
+
+    fn find(&self, user_id: i32) -> BoxFuture<'static, Result<User, RepoError>> {
+        find(self.repo.clone(), user_id).boxed()
+    }
+
+    fn find_by_username(&self, username: String) -> BoxFuture<'static, Result<User, RepoError>> {
+        find_by_username(self.repo.clone(), username).boxed()
+    }
+
+    fn update(
+        &self,
+        user_id: i32,
+        user: UpdateUser,
+    ) -> BoxFuture<'static, Result<User, RepoError>> {
+        update(self.repo.clone(), user_id, user).boxed()
+    }
+
+    fn find_by_login(
+        &self,
+        login: String,
+        password: String,
+    ) -> BoxFuture<'static, Result<User, RepoError>> {
+        find_by_login(self.repo.clone(), login, password).boxed()
+    }
+
+    fn change_password(
+        &self,
+        user_id: i32,
+        current_password: String,
+        new_password: String,
+    ) -> BoxFuture<'static, Result<User, RepoError>> {
+        change_password(self.repo.clone(), user_id, current_password, new_password).boxed()
+    }
+
+    fn list(&self, params: ListParams) -> BoxFuture<'static, Result<(Vec<User>, i64), RepoError>> {
+        list(self.repo.clone(), params).boxed()
+    }
+
+    fn set_suspended(
+        &self,
+        user_id: i32,
+        suspended: bool,
+    ) -> BoxFuture<'static, Result<User, RepoError>> {
+        set_suspended(self.repo.clone(), user_id, suspended).boxed()
+    }
+
+    fn delete(&self, user_id: i32) -> BoxFuture<'static, Result<(), RepoError>> {
+        delete(self.repo.clone(), user_id).boxed()
+    }
+
+    fn anonymize(&self, user_id: i32) -> BoxFuture<'static, Result<(), RepoError>> {
+        anonymize(self.repo.clone(), user_id).boxed()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::repo;
-    use crate::test_helpers::generate;
-    use tokio_threadpool::ThreadPool;
+    use crate::test_helpers::{block_on, generate};
 
     #[test]
     fn test_create_user() {
-        let pool = ThreadPool::new();
         let repo = repo();
-
-        let new_user = generate::new_user();
-        let future =
-            insert(repo.clone(), new_user).and_then(move |user| find(repo.clone(), user.id));
-        let results = wait_for(&pool, future);
-        assert!(results.is_ok());
+        block_on(async move {
+            let user = insert(repo.clone(), generate::new_user()).await.unwrap();
+            assert!(find(repo, user.id).await.is_ok());
+        });
     }
 
     #[test]
     fn test_authenticate_user() {
-        let pool = ThreadPool::new();
         let repo = repo();
-        // Create a new user
-        let new_user = generate::new_user();
-        let future = insert(repo.clone(), new_user)
-            .and_then(move |user| find_by_email_password(repo.clone(), user.email, user.password));
-
-        // Check the user is in the database.
-        let results = wait_for(&pool, future);
-        assert!(results.is_ok());
-    }
-
-    fn wait_for<T>(
-        pool: &ThreadPool,
-        future: impl Future<Item = T, Error = dieselError> + Send + 'static,
-    ) -> Result<T, dieselError>
-    where
-        T: Send + 'static,
-    {
-        pool.spawn_handle(future).wait()
+        block_on(async move {
+            // Create a new user
+            let new_user = generate::new_user();
+            let plaintext = new_user.password.clone();
+            let user = insert(repo.clone(), new_user).await.unwrap();
+
+            // Check the user is in the database.
+            let result = find_by_login(repo, user.email, plaintext).await;
+            assert!(result.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_login_by_username_or_email() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let plaintext = new_user.password.clone();
+            let user = insert(repo.clone(), new_user).await.unwrap();
+
+            let username = user.username.clone();
+            let by_username = find_by_login(repo.clone(), username, plaintext.clone()).await;
+            assert_eq!(by_username.unwrap().id, user.id);
+
+            let email = user.email.to_uppercase();
+            let by_email = find_by_login(repo, email, plaintext).await;
+            assert_eq!(by_email.unwrap().id, user.id);
+        });
+    }
+
+    #[test]
+    fn test_email_is_normalized() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let email = new_user.email.clone();
+            let user = insert(
+                repo.clone(),
+                NewUser {
+                    email: format!(" {} ", email.to_uppercase()),
+                    ..new_user
+                },
+            )
+            .await
+            .unwrap();
+            assert_eq!(user.email, email.to_lowercase());
+
+            // The same address in a different case is taken.
+            let duplicate = generate::user().email(&email.to_lowercase()).build();
+            assert!(insert(repo, duplicate).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_change_password() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let plaintext = new_user.password.clone();
+            let user = insert(repo.clone(), new_user).await.unwrap();
+            assert!(user.password_changed_at.is_none());
+
+            let new_password = "correct horse battery staple".to_string();
+            let wrong = "not my password".to_string();
+            let result = change_password(repo.clone(), user.id, wrong, new_password.clone()).await;
+            assert!(result.is_err());
+
+            let result = change_password(repo.clone(), user.id, plaintext, new_password.clone());
+            assert!(result.await.unwrap().password_changed_at.is_some());
+            assert!(find_by_login(repo, user.email, new_password).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_password_is_hashed() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let plaintext = new_user.password.clone();
+            let user = insert(repo, new_user).await.unwrap();
+            assert_ne!(user.password, plaintext);
+            assert!(password::verify(&user.password, &plaintext));
+        });
+    }
+
+    #[test]
+    fn test_find_by_username() {
+        let repo = repo();
+        block_on(async move {
+            let user = insert(repo.clone(), generate::new_user()).await.unwrap();
+            assert!(find_by_username(repo, user.username).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_update_user() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let changes = UpdateUser {
+                bio: Some("I like to skateboard".to_string()),
+                password: Some("new password".to_string()),
+                ..Default::default()
+            };
+            let user = insert(repo.clone(), new_user.clone()).await.unwrap();
+            let user = update(repo, user.id, changes).await.unwrap();
+            assert_eq!(user.username, new_user.username);
+            assert_eq!(user.bio, Some("I like to skateboard".to_string()));
+            // Passwords are only changed knowing the current one.
+            assert!(password::verify(&user.password, &new_user.password));
+            assert!(user.password_changed_at.is_none());
+        });
+    }
+
+    #[test]
+    fn test_update_user_without_changes() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let user = insert(repo.clone(), new_user.clone()).await.unwrap();
+            let user = update(repo, user.id, UpdateUser::default()).await.unwrap();
+            assert_eq!(user.email, new_user.email);
+        });
+    }
+
+    #[test]
+    fn test_authenticate_user_wrong_password() {
+        let repo = repo();
+        block_on(async move {
+            let user = insert(repo.clone(), generate::new_user()).await.unwrap();
+            let result = find_by_login(repo, user.email, "wrong password".to_string()).await;
+            match result {
+                Err(RepoError::Query(dieselError::NotFound)) => (),
+                other => panic!("Expected NotFound, got {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn test_list_suspend_and_delete() {
+        let repo = repo();
+        block_on(async move {
+            let user = insert(repo.clone(), generate::new_user()).await.unwrap();
+            let params = ListParams {
+                username: Some(user.username.to_uppercase()),
+                ..ListParams::default()
+            };
+            let (found, count) = list(repo.clone(), params).await.unwrap();
+            assert!(found.iter().any(|found| found.id == user.id));
+            assert_eq!(count, found.len() as i64);
+            let params = ListParams {
+                email: Some("%".to_string()),
+                ..ListParams::default()
+            };
+            assert_eq!(list(repo.clone(), params).await.unwrap().1, 0);
+
+            let suspended = set_suspended(repo.clone(), user.id, true).await.unwrap();
+            assert!(suspended.suspended_at.is_some());
+            let restored = set_suspended(repo.clone(), user.id, false).await.unwrap();
+            assert!(restored.suspended_at.is_none());
+
+            delete(repo.clone(), user.id).await.unwrap();
+            assert!(find(repo.clone(), user.id).await.is_err());
+            assert!(delete(repo, user.id).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let user = insert(repo.clone(), new_user.clone()).await.unwrap();
+            anonymize(repo.clone(), user.id).await.unwrap();
+
+            let deleted = find(repo.clone(), user.id).await.unwrap();
+            assert!(deleted.deleted_at.is_some());
+            assert_eq!(deleted.username, anonymous_username(user.id));
+            assert_ne!(deleted.email, user.email);
+            let login = find_by_login(repo.clone(), new_user.email, new_user.password).await;
+            assert!(login.is_err());
+            assert!(anonymize(repo, -1).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_purge_deleted() {
+        let repo = repo();
+        block_on(async move {
+            let deleted = generate::user().insert(repo.clone()).await;
+            let author = generate::user().insert(repo.clone()).await;
+            generate::article(author.id).insert(repo.clone()).await;
+            anonymize(repo.clone(), deleted.id).await.unwrap();
+            anonymize(repo.clone(), author.id).await.unwrap();
+
+            let an_hour_ago = Utc::now().naive_utc() - chrono::Duration::hours(1);
+            assert_eq!(purge_deleted(repo.clone(), an_hour_ago).await.unwrap(), 0);
+            let in_an_hour = Utc::now().naive_utc() + chrono::Duration::hours(1);
+            assert_eq!(purge_deleted(repo.clone(), in_an_hour).await.unwrap(), 1);
+            assert!(find(repo.clone(), deleted.id).await.is_err());
+            // Their article still needs an author.
+            assert!(find(repo, author.id).await.is_ok());
+        });
     }
 }
@@ -0,0 +1,94 @@
+use crate::auth::random_token;
+use crate::db::RepoError;
+use crate::models::{NewEmailVerification, User};
+use crate::schema::{email_verifications, users};
+use crate::Repo;
+
+use diesel::prelude::*;
+use futures::future::{BoxFuture, FutureExt};
+
+/// Create a token for `user_id` to verify their email with.
+pub async fn create(repo: Repo, user_id: i32) -> Result<String, RepoError> {
+    repo.run("verifications::create", move |conn| {
+        let verification = NewEmailVerification {
+            token: random_token(),
+            user_id,
+        };
+        diesel::insert_into(email_verifications::table)
+            .values(&verification)
+            .execute(&conn)
+            .map(|_| verification.token)
+    })
+    .await
+}
+
+/// Mark the user the token was created for as verified, and use up the token.
+/// Fails with `NotFound` for an unknown or already used token.
+pub async fn verify(repo: Repo, token: String) -> Result<User, RepoError> {
+    repo.transaction("verifications::verify", move |conn| {
+        let user_id = email_verifications::table
+            .find(&token)
+            .select(email_verifications::user_id)
+            .first::<i32>(conn)?;
+        diesel::update(users::table.find(user_id))
+            .set(users::verified.eq(true))
+            .execute(conn)?;
+        diesel::delete(email_verifications::table.filter(email_verifications::user_id.eq(user_id)))
+            .execute(conn)?;
+        users::table.find(user_id).first(conn)
+    })
+    .await
+}
+
+/// Storage for the tokens emailed to new users.
+pub trait VerificationsRepository: Send + Sync {
+    fn create(&self, user_id: i32) -> BoxFuture<'static, Result<String, RepoError>>;
+    fn verify(&self, token: String) -> BoxFuture<'static, Result<User, RepoError>>;
+}
+
+/// Verification tokens stored in Postgres with Diesel.
+pub struct PgVerificationsRepository {
+    repo: Repo,
+}
+
+impl PgVerificationsRepository {
+    pub fn new(repo: Repo) -> Self {
+        PgVerificationsRepository { repo }
+    }
+}
+
+impl VerificationsRepository for PgVerificationsRepository {
+    fn create(&self, user_id: i32) -> BoxFuture<'static, Result<String, RepoError>> {
+        create(self.repo.clone(), user_id).boxed()
+    }
+
+    fn verify(&self, token: String) -> BoxFuture<'static, Result<User, RepoError>> {
+        verify(self.repo.clone(), token).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::users;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_verify() {
+        let repo = repo();
+        block_on(async move {
+            let user = users::insert(repo.clone(), generate::new_user())
+                .await
+                .unwrap();
+            assert!(!user.verified);
+            let token = create(repo.clone(), user.id).await.unwrap();
+
+            let verified = verify(repo.clone(), token.clone()).await.unwrap();
+            assert_eq!(verified.id, user.id);
+            assert!(verified.verified);
+            // Tokens can only be used once.
+            assert!(verify(repo, token).await.is_err());
+        });
+    }
+}
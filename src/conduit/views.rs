@@ -0,0 +1,242 @@
+use crate::db::{DbConnection, RepoError};
+use crate::models::NewArticleView;
+use crate::schema::{article_views, articles, comments, favorites};
+use crate::webhooks::hmac_sha256;
+use crate::Repo;
+
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+/// Who viewed an article. Visitors are told apart by their address, which is only kept
+/// hashed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Viewer {
+    User(i32),
+    Visitor(String),
+}
+
+impl Viewer {
+    /// A visitor from `ip`, hashed with `secret` so addresses can't be recovered by hashing
+    /// every one of them.
+    pub fn visitor(ip: &str, secret: &str) -> Self {
+        let mac = hmac_sha256(secret.as_bytes(), ip.as_bytes());
+        let hash: String = mac[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Viewer::Visitor(hash)
+    }
+
+    /// How the viewer is stored.
+    fn key(&self) -> String {
+        match self {
+            Viewer::User(user_id) => format!("user:{}", user_id),
+            Viewer::Visitor(hash) => format!("ip:{}", hash),
+        }
+    }
+}
+
+/// How an article has been read and reacted to: in all, and for each day of a window.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ArticleStats {
+    pub views: i64,
+    pub favorites: i64,
+    pub comments: i64,
+    pub days: Vec<DailyStats>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DailyStats {
+    pub date: NaiveDate,
+    pub views: i64,
+    pub favorites: i64,
+    pub comments: i64,
+}
+
+/// Count a view of the article with `slug`, unless the viewer already viewed it within the
+/// same hour. Authors reading their own articles aren't counted, and neither are articles
+/// deleted since. Returns whether the view was counted.
+pub async fn record(
+    repo: Repo,
+    slug: String,
+    viewer: Viewer,
+    viewed_at: NaiveDateTime,
+) -> Result<bool, RepoError> {
+    repo.run("views::record", move |conn| {
+        let article = articles::table
+            .filter(articles::slug.eq(slug))
+            .select((articles::id, articles::user_id))
+            .first::<(i32, i32)>(&conn)
+            .optional()?;
+        let article_id = match article {
+            Some((_, author_id)) if viewer == Viewer::User(author_id) => return Ok(false),
+            Some((article_id, _)) => article_id,
+            None => return Ok(false),
+        };
+        let view = NewArticleView {
+            article_id,
+            viewer: viewer.key(),
+            viewed_hour: viewed_at.date().and_hms(viewed_at.hour(), 0, 0),
+        };
+        insert_or_ignore(&conn, &view).map(|inserted| inserted > 0)
+    })
+    .await
+}
+
+/// The article's views, favorites and comments in all, and on each day from `from` to `to`.
+pub async fn stats(
+    repo: Repo,
+    article_id: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<ArticleStats, RepoError> {
+    repo.run("views::stats", move |conn| {
+        let start = from.and_hms(0, 0, 0);
+        let mut days = Vec::new();
+        let mut date = from;
+        while date <= to {
+            days.push(DailyStats {
+                date,
+                views: 0,
+                favorites: 0,
+                comments: 0,
+            });
+            date = date.succ();
+        }
+
+        let hourly_views = article_views::table
+            .filter(article_views::article_id.eq(article_id))
+            .filter(article_views::viewed_hour.ge(start))
+            .group_by(article_views::viewed_hour)
+            .select((article_views::viewed_hour, count_star()))
+            .load::<(NaiveDateTime, i64)>(&conn)?;
+        for (hour, count) in hourly_views {
+            if let Some(day) = day_of(&mut days, from, hour) {
+                day.views += count;
+            }
+        }
+        let favorited_at = favorites::table
+            .filter(favorites::article_id.eq(article_id))
+            .filter(favorites::created_at.ge(start))
+            .select(favorites::created_at)
+            .load::<NaiveDateTime>(&conn)?;
+        for time in favorited_at {
+            if let Some(day) = day_of(&mut days, from, time) {
+                day.favorites += 1;
+            }
+        }
+        let commented_at = comments::table
+            .filter(comments::article_id.eq(article_id))
+            .filter(comments::created_at.ge(start))
+            .select(comments::created_at)
+            .load::<NaiveDateTime>(&conn)?;
+        for time in commented_at {
+            if let Some(day) = day_of(&mut days, from, time) {
+                day.comments += 1;
+            }
+        }
+
+        Ok(ArticleStats {
+            views: article_views::table
+                .filter(article_views::article_id.eq(article_id))
+                .count()
+                .get_result(&conn)?,
+            favorites: favorites::table
+                .filter(favorites::article_id.eq(article_id))
+                .count()
+                .get_result(&conn)?,
+            comments: comments::table
+                .filter(comments::article_id.eq(article_id))
+                .count()
+                .get_result(&conn)?,
+            days,
+        })
+    })
+    .await
+}
+
+/// The day `time` falls on, among `days` starting `from`.
+fn day_of(
+    days: &mut [DailyStats],
+    from: NaiveDate,
+    time: NaiveDateTime,
+) -> Option<&mut DailyStats> {
+    let index = (time.date() - from).num_days();
+    if index < 0 {
+        return None;
+    }
+    days.get_mut(index as usize)
+}
+
+#[cfg(feature = "postgres")]
+fn insert_or_ignore(conn: &DbConnection, view: &NewArticleView) -> QueryResult<usize> {
+    diesel::insert_into(article_views::table)
+        .values(view)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_or_ignore(conn: &DbConnection, view: &NewArticleView) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(article_views::table)
+        .values(view)
+        .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conduit::favorites;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_record_and_stats() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let reader = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+            let slug = || article.slug.clone();
+            let now = Utc::now().naive_utc();
+            let earlier = now - Duration::days(1);
+
+            let visitor = Viewer::visitor("127.0.0.1", "secret");
+            assert_eq!(visitor, Viewer::visitor("127.0.0.1", "secret"));
+            assert_ne!(visitor, Viewer::visitor("127.0.0.2", "secret"));
+            let view = |viewer: Viewer, at| record(repo.clone(), slug(), viewer, at);
+            assert!(view(Viewer::User(reader.id), now).await.unwrap());
+            // Within the same hour, it's the same view.
+            assert!(!view(Viewer::User(reader.id), now).await.unwrap());
+            assert!(view(Viewer::User(reader.id), earlier).await.unwrap());
+            assert!(view(visitor, now).await.unwrap());
+            assert!(!view(Viewer::User(author.id), now).await.unwrap());
+            let gone = record(
+                repo.clone(),
+                "no-such-article".into(),
+                Viewer::User(reader.id),
+                now,
+            );
+            assert!(!gone.await.unwrap());
+
+            favorites::favorite(repo.clone(), reader.id, article.id)
+                .await
+                .unwrap();
+            let today = now.date();
+            let stats = stats(repo.clone(), article.id, today, today).await.unwrap();
+            assert_eq!((stats.views, stats.favorites, stats.comments), (3, 1, 0));
+            assert_eq!(
+                stats.days,
+                vec![DailyStats {
+                    date: today,
+                    views: 2,
+                    favorites: 1,
+                    comments: 0,
+                }]
+            );
+        });
+    }
+}
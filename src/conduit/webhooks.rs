@@ -0,0 +1,134 @@
+use crate::auth::random_token;
+use crate::conduit;
+use crate::db::{DbConnection, RepoError};
+use crate::events::Event;
+use crate::jobs::{self, Job};
+use crate::models::{NewWebhook, Webhook};
+use crate::schema::webhooks;
+use crate::Repo;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::result::Error as dieselError;
+
+/// Register `url` to be sent `events`, with a new random secret to sign them with.
+pub async fn create(repo: Repo, url: String, events: Vec<String>) -> Result<Webhook, RepoError> {
+    repo.run("webhooks::create", move |conn| {
+        let new_webhook = NewWebhook {
+            url,
+            secret: random_token(),
+            events: events.join(","),
+        };
+        diesel::insert_into(webhooks::table)
+            .values(&new_webhook)
+            .execute(&conn)?;
+        webhooks::table
+            .filter(webhooks::secret.eq(&new_webhook.secret))
+            .first(&conn)
+    })
+    .await
+}
+
+/// Every webhook, oldest first.
+pub async fn list(repo: Repo) -> Result<Vec<Webhook>, RepoError> {
+    repo.run("webhooks::list", move |conn| {
+        webhooks::table.order(webhooks::id).load(&conn)
+    })
+    .await
+}
+
+/// The webhook with `id`, unless it has been deleted.
+pub async fn find(repo: Repo, id: i32) -> Result<Option<Webhook>, RepoError> {
+    repo.run("webhooks::find", move |conn| {
+        webhooks::table.find(id).first(&conn).optional()
+    })
+    .await
+}
+
+/// Stop sending events to a webhook. Deliveries already queued are dropped.
+/// Fails with `NotFound` if there's no webhook with that id.
+pub async fn delete(repo: Repo, id: i32) -> Result<(), RepoError> {
+    repo.run("webhooks::delete", move |conn| {
+        let deleted = diesel::delete(webhooks::table.find(id)).execute(&conn)?;
+        if deleted == 0 {
+            return Err(dieselError::NotFound);
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Queue a delivery of `event` to each webhook subscribed to it, so each is retried on its
+/// own should it fail. Returns how many there were.
+pub fn queue_deliveries(
+    conn: &DbConnection,
+    event: &Event,
+    occurred_at: NaiveDateTime,
+) -> QueryResult<usize> {
+    let payload = event.payload(occurred_at);
+    let subscribed: Vec<Webhook> = webhooks::table
+        .load::<Webhook>(conn)?
+        .into_iter()
+        .filter(|webhook| webhook.subscribes_to(event.name()))
+        .collect();
+    for webhook in &subscribed {
+        let job = Job::DeliverWebhook {
+            webhook_id: webhook.id,
+            event: event.name().to_string(),
+            payload: payload.clone(),
+        };
+        conduit::jobs::insert(conn, &jobs::new_job(&job))?;
+    }
+    Ok(subscribed.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::QueuedJob;
+    use crate::repo;
+    use crate::schema::jobs;
+    use crate::test_helpers::block_on;
+    use chrono::Utc;
+
+    #[test]
+    fn test_queue_deliveries() {
+        let repo = repo();
+        block_on(async move {
+            let url = "http://localhost:9000/hook".to_string();
+            let comments = create(repo.clone(), url.clone(), vec!["commentAdded".to_string()])
+                .await
+                .unwrap();
+            create(repo.clone(), url, vec!["userRegistered".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(list(repo.clone()).await.unwrap().len(), 2);
+
+            let event = Event::CommentAdded {
+                comment_id: 3,
+                article_id: 2,
+                author_id: 1,
+                article_author_id: 4,
+            };
+            let queued = repo
+                .transaction("test", move |conn| {
+                    queue_deliveries(conn, &event, Utc::now().naive_utc())
+                })
+                .await
+                .unwrap();
+            assert_eq!(queued, 1);
+            let job = repo
+                .run("test", |conn| jobs::table.first::<QueuedJob>(&conn))
+                .await
+                .unwrap();
+            match serde_json::from_str(&job.payload).unwrap() {
+                Job::DeliverWebhook { webhook_id, .. } => assert_eq!(webhook_id, comments.id),
+                other => panic!("Queued {:?}", other),
+            }
+
+            delete(repo.clone(), comments.id).await.unwrap();
+            assert!(find(repo.clone(), comments.id).await.unwrap().is_none());
+            assert!(delete(repo, comments.id).await.is_err());
+        });
+    }
+}
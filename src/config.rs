@@ -0,0 +1,552 @@
+use gotham::handler::HandlerFuture;
+use gotham::middleware::Middleware;
+use gotham::state::State;
+use gotham_derive::{NewMiddleware, StateData};
+use jsonwebtoken::Algorithm;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Application settings, read from the environment at startup.
+#[derive(Clone, Debug, StateData)]
+pub struct Config {
+    pub jwt: JwtConfig,
+    pub cors: CorsConfig,
+    pub login: LoginConfig,
+    /// Where the API is reachable from outside, for links in emails.
+    pub public_url: String,
+    /// How long password reset tokens can be used for.
+    pub password_reset_ttl: Duration,
+    pub mail: MailConfig,
+    pub oauth: OAuthConfig,
+    pub cache: CacheConfig,
+    pub storage: StorageConfig,
+    pub jobs: JobsConfig,
+    pub maintenance: MaintenanceConfig,
+    /// The address and port to listen for requests on.
+    pub listen_address: String,
+    /// How long to wait for requests in flight when shutting down.
+    pub shutdown_timeout: Duration,
+    /// Don't apply pending migrations at startup.
+    pub skip_migrations: bool,
+    /// The most results a `limit` query parameter can ask for.
+    pub max_page_size: i64,
+    /// The largest request body accepted, in bytes.
+    pub max_body_size: usize,
+    /// Refuse request bodies that aren't labelled `application/json`.
+    pub require_json_content_type: bool,
+    /// A built frontend to serve alongside the API, with an `index.html` for every path
+    /// that isn't one of its files or a route of the app's.
+    pub frontend_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JwtConfig {
+    pub secret: String,
+    /// How long issued tokens are valid for, in seconds.
+    pub ttl: u64,
+    pub algorithm: Algorithm,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to make cross-origin requests. `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+/// Limits on failed logins, to slow down password guessing.
+/// Once a limit is reached, logins are refused until enough failures fall out of the window.
+#[derive(Clone, Debug)]
+pub struct LoginConfig {
+    /// Failures allowed for one email or username within the window. 0 turns the limit off.
+    pub max_failures: u32,
+    /// Failures allowed from one IP address within the window, across all logins.
+    /// 0 turns the limit off.
+    pub max_failures_per_ip: u32,
+    pub window: Duration,
+    /// Refuse logins until the user has followed the link in their verification email.
+    pub require_verified_email: bool,
+}
+
+/// How emails to users are delivered.
+#[derive(Clone, Debug)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    /// The sender address.
+    pub from: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum MailTransport {
+    /// Print emails instead of sending them, for development.
+    Stdout,
+    Smtp(SmtpConfig),
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Connect with TLS, or upgrade to it with STARTTLS. Only worth turning off for local
+    /// test servers.
+    pub tls: bool,
+}
+
+impl MailConfig {
+    fn from_env() -> Result<MailConfig, ConfigError> {
+        let transport = match env::var("MAIL_TRANSPORT").unwrap_or_default().as_str() {
+            "" | "stdout" => MailTransport::Stdout,
+            "smtp" => MailTransport::Smtp(SmtpConfig {
+                host: required("SMTP_HOST")?,
+                port: parse_or("SMTP_PORT", 587)?,
+                username: optional("SMTP_USERNAME"),
+                password: optional("SMTP_PASSWORD"),
+                tls: parse_or("SMTP_TLS", true)?,
+            }),
+            other => return Err(ConfigError::Invalid("MAIL_TRANSPORT", other.to_string())),
+        };
+        Ok(MailConfig {
+            transport,
+            from: parse_or("MAIL_FROM", "conduit@localhost".to_string())?,
+        })
+    }
+}
+
+/// OAuth providers users can sign in with. Providers without credentials are turned off.
+#[derive(Clone, Debug, Default)]
+pub struct OAuthConfig {
+    pub github: Option<OAuthClient>,
+    pub google: Option<OAuthClient>,
+}
+
+/// The credentials of the app registered with a provider.
+#[derive(Clone, Debug)]
+pub struct OAuthClient {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl OAuthConfig {
+    fn from_env() -> Result<OAuthConfig, ConfigError> {
+        Ok(OAuthConfig {
+            github: oauth_client("GITHUB_CLIENT_ID", "GITHUB_CLIENT_SECRET")?,
+            google: oauth_client("GOOGLE_CLIENT_ID", "GOOGLE_CLIENT_SECRET")?,
+        })
+    }
+}
+
+/// Caching of responses that are the same for everyone, e.g. article lists for visitors.
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    /// Where Redis is. Nothing is cached without it.
+    pub redis_url: Option<String>,
+    /// How long responses are cached for. Changes made through the API clear the cache,
+    /// so this only bounds how stale other changes can get.
+    pub ttl: Duration,
+}
+
+/// Where uploaded files, e.g. avatars, are kept.
+#[derive(Clone, Debug)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// The largest file accepted, in bytes.
+    pub max_upload_size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum StorageBackend {
+    /// Files in this directory, served by the app under `/uploads`.
+    Local(PathBuf),
+    S3(S3Config),
+}
+
+/// A bucket on S3, or on a service with the same API, e.g. MinIO. Objects are expected to be
+/// publicly readable, through the bucket's policy or a CDN in front of it.
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Where to send requests instead of AWS, with the bucket in the path.
+    pub endpoint: Option<String>,
+    /// Where objects are read from, when it's not where they're uploaded to.
+    pub public_url: Option<String>,
+}
+
+impl StorageConfig {
+    fn from_env() -> Result<StorageConfig, ConfigError> {
+        let backend = match env::var("STORAGE_BACKEND").unwrap_or_default().as_str() {
+            "" | "local" => StorageBackend::Local(PathBuf::from(parse_or(
+                "UPLOADS_DIR",
+                "uploads".to_string(),
+            )?)),
+            "s3" => StorageBackend::S3(S3Config {
+                bucket: required("S3_BUCKET")?,
+                region: parse_or("S3_REGION", "us-east-1".to_string())?,
+                access_key_id: required("S3_ACCESS_KEY_ID")?,
+                secret_access_key: required("S3_SECRET_ACCESS_KEY")?,
+                endpoint: optional("S3_ENDPOINT"),
+                public_url: optional("S3_PUBLIC_URL"),
+            }),
+            other => return Err(ConfigError::Invalid("STORAGE_BACKEND", other.to_string())),
+        };
+        Ok(StorageConfig {
+            backend,
+            max_upload_size: parse_or("MAX_UPLOAD_SIZE", 2 * 1024 * 1024)?,
+        })
+    }
+}
+
+/// How the background worker works through the job queue.
+#[derive(Clone, Debug)]
+pub struct JobsConfig {
+    /// How long to wait before looking again when no job is due.
+    pub poll_interval: Duration,
+    /// How many times a job is tried before it's given up on.
+    pub max_attempts: i32,
+}
+
+/// How often the scheduler queues the jobs that clear out stale rows and publish scheduled
+/// articles. A zero interval turns a job off.
+#[derive(Clone, Debug)]
+pub struct MaintenanceConfig {
+    /// For purging expired revoked tokens and password reset tokens.
+    pub tokens_interval: Duration,
+    /// For purging deleted accounts.
+    pub deleted_users_interval: Duration,
+    /// How long deleted accounts are kept before they're purged.
+    pub deleted_users_retention: Duration,
+    /// For publishing drafts whose scheduled time has come.
+    pub scheduled_articles_interval: Duration,
+    /// For scoring the trending articles.
+    pub trending_interval: Duration,
+}
+
+impl CacheConfig {
+    fn from_env() -> Result<CacheConfig, ConfigError> {
+        let redis_url = optional("REDIS_URL");
+        if let Some(ref url) = redis_url {
+            if redis::Client::open(url.as_str()).is_err() {
+                return Err(ConfigError::Invalid("REDIS_URL", url.clone()));
+            }
+        }
+        let ttl = parse_or("CACHE_TTL_SECONDS", 30)?;
+        if ttl == 0 {
+            return Err(ConfigError::Invalid("CACHE_TTL_SECONDS", ttl.to_string()));
+        }
+        Ok(CacheConfig {
+            redis_url,
+            ttl: Duration::from_secs(ttl),
+        })
+    }
+}
+
+fn oauth_client(
+    id_name: &'static str,
+    secret_name: &'static str,
+) -> Result<Option<OAuthClient>, ConfigError> {
+    match optional(id_name) {
+        Some(client_id) => Ok(Some(OAuthClient {
+            client_id,
+            client_secret: required(secret_name)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Database connection settings. These are only needed at startup,
+/// so they're kept apart from the `Config` handlers see.
+#[derive(Clone, Debug)]
+pub struct DatabaseConfig {
+    pub url: String,
+    /// The maximum number of connections in the pool.
+    pub pool_size: u32,
+    /// How long to wait for the server when opening a connection.
+    pub connect_timeout: Duration,
+    /// How long a query waits for a free connection before giving up.
+    pub checkout_timeout: Duration,
+    /// How many more times to try building the pool at startup, backing off exponentially.
+    pub connect_retries: u32,
+    /// Queries that take longer are logged, with what they were for. Zero logs none.
+    pub slow_query_threshold: Duration,
+}
+
+impl DatabaseConfig {
+    /// Read the database settings from environment variables:
+    ///
+    /// - `DATABASE_URL`: required, the postgres connection url.
+    /// - `DATABASE_POOL_SIZE`: defaults to 10 connections.
+    /// - `DATABASE_CONNECT_TIMEOUT_SECONDS`: defaults to 5 seconds.
+    /// - `DATABASE_CHECKOUT_TIMEOUT_SECONDS`: defaults to 5 seconds.
+    /// - `DATABASE_CONNECT_RETRIES`: defaults to 5.
+    /// - `DATABASE_SLOW_QUERY_MS`: defaults to 500 milliseconds, 0 turns logging them off.
+    pub fn from_env() -> Result<DatabaseConfig, ConfigError> {
+        Ok(DatabaseConfig {
+            url: required("DATABASE_URL")?,
+            pool_size: parse_or("DATABASE_POOL_SIZE", 10)?,
+            connect_timeout: Duration::from_secs(parse_or("DATABASE_CONNECT_TIMEOUT_SECONDS", 5)?),
+            checkout_timeout: Duration::from_secs(parse_or(
+                "DATABASE_CHECKOUT_TIMEOUT_SECONDS",
+                5,
+            )?),
+            connect_retries: parse_or("DATABASE_CONNECT_RETRIES", 5)?,
+            slow_query_threshold: Duration::from_millis(parse_or("DATABASE_SLOW_QUERY_MS", 500)?),
+        })
+    }
+
+    /// The url with the connect timeout applied, unless the url already sets one.
+    /// Only Postgres urls take a timeout; SQLite paths and MySQL urls are returned as they are.
+    pub fn connection_url(&self) -> String {
+        if !self.url.starts_with("postgres") || self.url.contains("connect_timeout=") {
+            return self.url.clone();
+        }
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{}connect_timeout={}",
+            self.url,
+            separator,
+            self.connect_timeout.as_secs()
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Missing(&'static str),
+    Invalid(&'static str, String),
+}
+
+impl Config {
+    /// Read the configuration from environment variables:
+    ///
+    /// - `JWT_SECRET`: required, the key used to sign tokens.
+    /// - `JWT_TTL_SECONDS`: how long tokens are valid for, defaults to an hour.
+    /// - `JWT_ALGORITHM`: one of `HS256` (the default), `HS384` or `HS512`.
+    /// - `CORS_ALLOWED_ORIGINS`: comma separated origins allowed to call the API from a browser,
+    ///   or `*` for any. Cross-origin requests are refused when unset.
+    /// - `LOGIN_MAX_FAILURES`: failed logins allowed per email or username within the window,
+    ///   defaults to 5.
+    /// - `LOGIN_MAX_FAILURES_PER_IP`: failed logins allowed per IP address within the window,
+    ///   defaults to 50.
+    /// - `LOGIN_WINDOW_SECONDS`: how far back failures are counted, defaults to 15 minutes.
+    /// - `REQUIRE_VERIFIED_EMAIL`: `true` to refuse logins until the email is verified.
+    /// - `PUBLIC_URL`: the API's address as seen by users, for links in emails,
+    ///   defaults to `http://localhost:7878`.
+    /// - `PASSWORD_RESET_TTL_SECONDS`: how long emailed password reset tokens are valid for,
+    ///   defaults to an hour.
+    /// - `MAIL_TRANSPORT`: `stdout` (the default) to print emails, or `smtp` to send them
+    ///   through `SMTP_HOST`, `SMTP_PORT` (587), `SMTP_USERNAME`, `SMTP_PASSWORD`
+    ///   and `SMTP_TLS` (`true`).
+    /// - `MAIL_FROM`: the sender address, defaults to `conduit@localhost`.
+    /// - `GITHUB_CLIENT_ID` and `GITHUB_CLIENT_SECRET`: turn on signing in with GitHub.
+    /// - `GOOGLE_CLIENT_ID` and `GOOGLE_CLIENT_SECRET`: turn on signing in with Google.
+    /// - `REDIS_URL`: turns on caching of responses that are the same for everyone.
+    /// - `CACHE_TTL_SECONDS`: how long responses are cached for, defaults to 30 seconds.
+    /// - `STORAGE_BACKEND`: `local` (the default) to keep uploads in `UPLOADS_DIR` (`uploads`),
+    ///   or `s3` to put them in `S3_BUCKET` with `S3_REGION` (`us-east-1`), `S3_ACCESS_KEY_ID`
+    ///   and `S3_SECRET_ACCESS_KEY`. `S3_ENDPOINT` points at another S3 compatible service,
+    ///   and `S3_PUBLIC_URL` is where objects are read from, e.g. a CDN.
+    /// - `MAX_UPLOAD_SIZE`: the largest file accepted, in bytes, defaults to 2 MiB.
+    /// - `JOBS_POLL_INTERVAL_MS`: how often the worker checks for queued jobs when idle,
+    ///   defaults to a second.
+    /// - `JOBS_MAX_ATTEMPTS`: how many times a failing job is retried, defaults to 5.
+    /// - `PURGE_TOKENS_INTERVAL_SECONDS`: how often expired revoked and password reset tokens
+    ///   are deleted, defaults to an hour. 0 turns it off.
+    /// - `PURGE_DELETED_USERS_INTERVAL_SECONDS`: how often deleted accounts past their
+    ///   retention are purged, defaults to a day. 0 turns it off.
+    /// - `DELETED_USERS_RETENTION_DAYS`: how long deleted accounts are kept, defaults to 30.
+    /// - `PUBLISH_SCHEDULED_INTERVAL_SECONDS`: how often drafts due to be published are
+    ///   looked for, defaults to a minute. 0 turns it off.
+    /// - `LISTEN_ADDRESS`: where to listen for requests, defaults to `127.0.0.1:7878`.
+    /// - `SHUTDOWN_TIMEOUT_SECONDS`: how long to wait for requests in flight on shutdown,
+    ///   defaults to 30 seconds.
+    /// - `SKIP_MIGRATIONS`: `true` to not apply pending migrations at startup.
+    /// - `MAX_PAGE_SIZE`: the largest `limit` lists accept, defaults to 100.
+    /// - `MAX_BODY_SIZE`: the largest request body accepted, in bytes, defaults to 1 MiB.
+    /// - `REQUIRE_JSON_CONTENT_TYPE`: `false` to accept bodies without an `application/json`
+    ///   `Content-Type`, for older clients.
+    /// - `FRONTEND_DIR`: a directory with a built frontend to serve, with an `index.html`.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        let algorithm = parse_or("JWT_ALGORITHM", Algorithm::HS256)?;
+        match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => (),
+            _ => {
+                return Err(ConfigError::Invalid(
+                    "JWT_ALGORITHM",
+                    format!("{:?}", algorithm),
+                ))
+            }
+        }
+        let frontend_dir = optional("FRONTEND_DIR").map(PathBuf::from);
+        if let Some(ref directory) = frontend_dir {
+            if !directory.join("index.html").is_file() {
+                return Err(ConfigError::Invalid(
+                    "FRONTEND_DIR",
+                    directory.display().to_string(),
+                ));
+            }
+        }
+        Ok(Config {
+            jwt: JwtConfig {
+                secret: required("JWT_SECRET")?,
+                ttl: parse_or("JWT_TTL_SECONDS", 3600)?,
+                algorithm,
+            },
+            cors: CorsConfig {
+                allowed_origins: list("CORS_ALLOWED_ORIGINS"),
+            },
+            login: LoginConfig {
+                max_failures: parse_or("LOGIN_MAX_FAILURES", 5)?,
+                max_failures_per_ip: parse_or("LOGIN_MAX_FAILURES_PER_IP", 50)?,
+                window: Duration::from_secs(parse_or("LOGIN_WINDOW_SECONDS", 900)?),
+                require_verified_email: parse_or("REQUIRE_VERIFIED_EMAIL", false)?,
+            },
+            public_url: parse_or("PUBLIC_URL", "http://localhost:7878".to_string())?,
+            password_reset_ttl: Duration::from_secs(parse_or("PASSWORD_RESET_TTL_SECONDS", 3600)?),
+            mail: MailConfig::from_env()?,
+            oauth: OAuthConfig::from_env()?,
+            cache: CacheConfig::from_env()?,
+            storage: StorageConfig::from_env()?,
+            jobs: JobsConfig {
+                poll_interval: Duration::from_millis(parse_or("JOBS_POLL_INTERVAL_MS", 1000)?),
+                max_attempts: parse_or("JOBS_MAX_ATTEMPTS", 5)?,
+            },
+            maintenance: MaintenanceConfig {
+                tokens_interval: Duration::from_secs(parse_or(
+                    "PURGE_TOKENS_INTERVAL_SECONDS",
+                    3600,
+                )?),
+                deleted_users_interval: Duration::from_secs(parse_or(
+                    "PURGE_DELETED_USERS_INTERVAL_SECONDS",
+                    24 * 3600,
+                )?),
+                deleted_users_retention: Duration::from_secs(
+                    parse_or::<u64>("DELETED_USERS_RETENTION_DAYS", 30)? * 24 * 3600,
+                ),
+                scheduled_articles_interval: Duration::from_secs(parse_or(
+                    "PUBLISH_SCHEDULED_INTERVAL_SECONDS",
+                    60,
+                )?),
+                trending_interval: Duration::from_secs(parse_or("TRENDING_INTERVAL_SECONDS", 600)?),
+            },
+            listen_address: parse_or("LISTEN_ADDRESS", "127.0.0.1:7878".to_string())?,
+            shutdown_timeout: Duration::from_secs(parse_or("SHUTDOWN_TIMEOUT_SECONDS", 30)?),
+            skip_migrations: parse_or("SKIP_MIGRATIONS", false)?,
+            max_page_size: parse_or("MAX_PAGE_SIZE", 100)?,
+            max_body_size: parse_or("MAX_BODY_SIZE", 1024 * 1024)?,
+            require_json_content_type: parse_or("REQUIRE_JSON_CONTENT_TYPE", true)?,
+            frontend_dir,
+        })
+    }
+}
+
+fn required(name: &'static str) -> Result<String, ConfigError> {
+    match env::var(name) {
+        Ok(ref value) if value.is_empty() => Err(ConfigError::Missing(name)),
+        Ok(value) => Ok(value),
+        Err(_) => Err(ConfigError::Missing(name)),
+    }
+}
+
+fn optional(name: &'static str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn list(name: &'static str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn parse_or<T: FromStr>(name: &'static str, default: T) -> Result<T, ConfigError> {
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(|_| ConfigError::Invalid(name, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Missing(name) => write!(f, "{} must be set", name),
+            ConfigError::Invalid(name, value) => {
+                write!(f, "{} has invalid value {:?}", name, value)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Makes the `Config` available to handlers through `State`.
+#[derive(Clone, NewMiddleware)]
+pub struct ConfigMiddleware {
+    config: Config,
+}
+
+impl ConfigMiddleware {
+    pub fn new(config: Config) -> Self {
+        ConfigMiddleware { config }
+    }
+}
+
+impl Middleware for ConfigMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        state.put(self.config);
+        chain(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database_config(url: &str) -> DatabaseConfig {
+        DatabaseConfig {
+            url: url.to_string(),
+            pool_size: 10,
+            connect_timeout: Duration::from_secs(3),
+            checkout_timeout: Duration::from_secs(5),
+            connect_retries: 0,
+            slow_query_threshold: Duration::from_millis(500),
+        }
+    }
+
+    #[test]
+    fn test_connection_url() {
+        assert_eq!(
+            database_config("postgres://localhost/conduit").connection_url(),
+            "postgres://localhost/conduit?connect_timeout=3"
+        );
+        assert_eq!(
+            database_config("postgres://localhost/conduit?sslmode=require").connection_url(),
+            "postgres://localhost/conduit?sslmode=require&connect_timeout=3"
+        );
+        assert_eq!(
+            database_config("postgres://localhost/conduit?connect_timeout=10").connection_url(),
+            "postgres://localhost/conduit?connect_timeout=10"
+        );
+        assert_eq!(database_config("conduit.db").connection_url(), "conduit.db");
+    }
+}
@@ -0,0 +1,489 @@
+use diesel::connection::SimpleConnection;
+use diesel::migration::MigrationConnection;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel::result::Error as dieselError;
+use diesel::Connection;
+#[cfg(not(feature = "postgres"))]
+use diesel::{QueryResult, RunQueryDsl};
+use futures::compat::Future01CompatExt;
+use futures01::future::poll_fn;
+use futures01::Async;
+use gotham::state::StateData;
+use log::warn;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_threadpool::blocking;
+
+use crate::config::DatabaseConfig;
+
+/// How long to wait before the first retry when the database can't be reached at startup.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+#[cfg(any(
+    all(feature = "postgres", feature = "sqlite"),
+    all(feature = "postgres", feature = "mysql"),
+    all(feature = "sqlite", feature = "mysql"),
+))]
+compile_error!("Choose one database backend, e.g. `--no-default-features --features sqlite`");
+
+#[cfg(not(any(feature = "postgres", feature = "sqlite", feature = "mysql")))]
+compile_error!("A database backend feature is required: `postgres`, `sqlite` or `mysql`");
+
+/// The connection type for the backend chosen with the `postgres` (default),
+/// `sqlite` or `mysql` feature.
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel::pg::PgConnection;
+#[cfg(feature = "sqlite")]
+pub type DbConnection = diesel::sqlite::SqliteConnection;
+#[cfg(feature = "mysql")]
+pub type DbConnection = diesel::mysql::MysqlConnection;
+
+// Embeds the migrations for the chosen backend in the binary.
+#[cfg(feature = "postgres")]
+embed_migrations!("migrations");
+#[cfg(feature = "sqlite")]
+embed_migrations!("migrations_sqlite");
+#[cfg(feature = "mysql")]
+embed_migrations!("migrations_mysql");
+
+#[cfg(feature = "sqlite")]
+no_arg_sql_function!(
+    last_insert_rowid,
+    diesel::sql_types::BigInt,
+    "The rowid of the last row inserted on this connection."
+);
+
+#[cfg(feature = "mysql")]
+no_arg_sql_function!(
+    last_insert_id,
+    diesel::sql_types::Unsigned<diesel::sql_types::BigInt>,
+    "The id generated by the last insert on this connection."
+);
+
+/// The id of the row just inserted on `conn`,
+/// for backends that can't return the row with `RETURNING`.
+#[cfg(feature = "sqlite")]
+pub fn inserted_id(conn: &DbConnection) -> QueryResult<i32> {
+    let id: i64 = diesel::select(last_insert_rowid).get_result(conn)?;
+    Ok(id as i32)
+}
+
+#[cfg(feature = "mysql")]
+pub fn inserted_id(conn: &DbConnection) -> QueryResult<i32> {
+    let id: u64 = diesel::select(last_insert_id).get_result(conn)?;
+    Ok(id as i32)
+}
+
+/// A database connection pool, put into `State` by the `DieselMiddleware`.
+///
+/// Queries are run through `run`, which checks out a connection and
+/// executes the blocking Diesel call on the threadpool without stalling the event loop.
+pub struct Repo<T>
+where
+    T: Connection + Send + 'static,
+{
+    connection_pool: Pool<ConnectionManager<T>>,
+    /// Queries taking longer than this are logged. Zero logs none.
+    slow_query_threshold: Duration,
+}
+
+impl<T> Clone for Repo<T>
+where
+    T: Connection + Send + 'static,
+{
+    fn clone(&self) -> Repo<T> {
+        Repo {
+            connection_pool: self.connection_pool.clone(),
+            slow_query_threshold: self.slow_query_threshold,
+        }
+    }
+}
+
+impl<T> StateData for Repo<T> where T: Connection + Send + 'static {}
+
+/// Why running a query through the `Repo` failed.
+#[derive(Debug)]
+pub enum RepoError {
+    /// No connection could be checked out of the pool in time.
+    Pool(r2d2::Error),
+    /// The query itself failed.
+    Query(dieselError),
+}
+
+impl From<dieselError> for RepoError {
+    fn from(e: dieselError) -> Self {
+        RepoError::Query(e)
+    }
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepoError::Pool(e) => write!(f, "Could not check out a connection: {}", e),
+            RepoError::Query(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for RepoError {}
+
+/// The number of connections in a pool, and how many of them are idle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolState {
+    pub connections: u32,
+    pub idle_connections: u32,
+}
+
+impl PoolState {
+    pub fn in_use(&self) -> u32 {
+        self.connections - self.idle_connections
+    }
+}
+
+impl<T> Repo<T>
+where
+    T: Connection + Send + 'static,
+{
+    /// Build a connection pool.
+    /// When the database can't be reached, retry up to `connect_retries` times,
+    /// doubling the wait between attempts.
+    pub fn connect(config: &DatabaseConfig) -> Result<Self, r2d2::Error> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let manager = ConnectionManager::new(config.connection_url());
+            let result = Pool::builder()
+                .max_size(config.pool_size)
+                .connection_timeout(config.checkout_timeout)
+                .connection_customizer(Box::new(ConnectionSetup {
+                    test_transaction: false,
+                }))
+                .build(manager);
+            match result {
+                Ok(connection_pool) => {
+                    return Ok(Repo {
+                        connection_pool,
+                        slow_query_threshold: config.slow_query_threshold,
+                    })
+                }
+                Err(e) if attempt < config.connect_retries => {
+                    attempt += 1;
+                    warn!(
+                        "Could not connect to the database ({}), retrying in {}ms",
+                        e,
+                        backoff.as_secs() * 1000 + u64::from(backoff.subsec_millis())
+                    );
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// A pool with a single connection that runs everything in a transaction
+    /// which is never committed.
+    /// Each test builds its own, so tests don't see each other's rows and can run in parallel.
+    /// Benchmarks use one too, so the rows they insert are gone once they finish.
+    pub fn test(config: &DatabaseConfig) -> Result<Self, r2d2::Error> {
+        let manager = ConnectionManager::new(config.connection_url());
+        // Replacing the connection would silently discard the transaction.
+        let connection_pool = Pool::builder()
+            .max_size(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connection_timeout(config.checkout_timeout)
+            .connection_customizer(Box::new(ConnectionSetup {
+                test_transaction: true,
+            }))
+            .build(manager)?;
+        Ok(Repo {
+            connection_pool,
+            slow_query_threshold: config.slow_query_threshold,
+        })
+    }
+
+    /// A pool that doesn't connect until a query is run,
+    /// for tests where every query goes through a fake.
+    #[cfg(test)]
+    pub fn unconnected(url: &str) -> Self {
+        let manager = ConnectionManager::new(url);
+        let connection_pool = Pool::builder().min_idle(Some(0)).build_unchecked(manager);
+        Repo {
+            connection_pool,
+            slow_query_threshold: Duration::from_secs(0),
+        }
+    }
+
+    /// Run a blocking Diesel query with a connection from the pool.
+    ///
+    /// Should no connection become free within the checkout timeout,
+    /// it fails with `RepoError::Pool` rather than waiting indefinitely.
+    ///
+    /// `label` names what the query is for, e.g. `articles::list`, and is logged along with
+    /// how long it took when that's over the slow query threshold.
+    ///
+    /// `tokio_threadpool::blocking` is still futures 0.1 based, so it's wrapped in a
+    /// compatibility future here; callers just `.await` the result.
+    pub async fn run<F, R>(&self, label: &'static str, f: F) -> Result<R, RepoError>
+    where
+        F: FnOnce(PooledConnection<ConnectionManager<T>>) -> Result<R, dieselError>
+            + Send
+            + 'static,
+    {
+        let pool = self.connection_pool.clone();
+        let threshold = self.slow_query_threshold;
+        let mut f = Some(f);
+        let blocking_query = poll_fn(move || -> Result<Async<Result<R, RepoError>>, ()> {
+            blocking(|| match pool.get() {
+                Ok(conn) => {
+                    let started = Instant::now();
+                    let result = (f.take().expect("query already run"))(conn);
+                    log_if_slow(label, started.elapsed(), threshold);
+                    result.map_err(RepoError::Query)
+                }
+                Err(e) => Err(RepoError::Pool(e)),
+            })
+            .map_err(|_| panic!("the threadpool shut down"))
+        });
+        match blocking_query.compat().await {
+            Ok(result) => result,
+            Err(()) => unreachable!(),
+        }
+    }
+
+    /// Run several statements atomically with a connection from the pool.
+    /// The transaction is committed if `f` succeeds, and rolled back if it returns an error.
+    pub async fn transaction<F, R>(&self, label: &'static str, f: F) -> Result<R, RepoError>
+    where
+        F: FnOnce(&T) -> Result<R, dieselError> + Send + 'static,
+    {
+        self.run(label, move |conn| conn.transaction(|| f(&*conn)))
+            .await
+    }
+
+    /// Apply any migrations that haven't been run yet,
+    /// so deployments don't need a separate `diesel migration run` step.
+    pub fn run_pending_migrations(&self) -> Result<(), Box<dyn Error>>
+    where
+        T: MigrationConnection,
+    {
+        let conn = self.connection_pool.get()?;
+        embedded_migrations::run_with_output(&*conn, &mut io::stdout())?;
+        Ok(())
+    }
+
+    /// Connection counts for instrumentation.
+    pub fn pool_state(&self) -> PoolState {
+        let state = self.connection_pool.state();
+        PoolState {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+        }
+    }
+}
+
+/// Whether a query took long enough to be worth looking into.
+fn is_slow(elapsed: Duration, threshold: Duration) -> bool {
+    threshold > Duration::from_secs(0) && elapsed > threshold
+}
+
+fn log_if_slow(label: &str, elapsed: Duration, threshold: Duration) {
+    if is_slow(elapsed, threshold) {
+        warn!(
+            "Slow query {} took {}ms",
+            label,
+            elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+        );
+    }
+}
+
+/// Prepares each new connection in the pool.
+#[derive(Debug)]
+struct ConnectionSetup {
+    /// Open a test transaction, rolled back when the connection closes.
+    test_transaction: bool,
+}
+
+impl<T> r2d2::CustomizeConnection<T, diesel::r2d2::Error> for ConnectionSetup
+where
+    T: Connection,
+{
+    fn on_acquire(&self, conn: &mut T) -> Result<(), diesel::r2d2::Error> {
+        // SQLite only enforces foreign keys, and so cascading deletes, when asked to.
+        // This has no effect inside a transaction, so it has to come first.
+        if cfg!(feature = "sqlite") {
+            conn.batch_execute("PRAGMA foreign_keys = ON")
+                .map_err(diesel::r2d2::Error::QueryError)?;
+        }
+        if self.test_transaction {
+            conn.begin_test_transaction()
+                .map_err(diesel::r2d2::Error::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::schema::users;
+    use crate::test_helpers::{block_on, generate};
+    use diesel::prelude::*;
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let repo = repo();
+        block_on(async move {
+            let new_user = generate::new_user();
+            let email = new_user.email.clone();
+
+            let result = repo
+                .transaction("test", move |conn| {
+                    diesel::insert_into(users::table)
+                        .values(&new_user)
+                        .execute(conn)?;
+                    Err::<(), _>(dieselError::RollbackTransaction)
+                })
+                .await;
+            assert!(result.is_err());
+
+            let count = repo
+                .run("test", move |conn| {
+                    users::table
+                        .filter(users::email.eq(email))
+                        .count()
+                        .get_result::<i64>(&conn)
+                })
+                .await
+                .unwrap();
+            assert_eq!(count, 0);
+        });
+    }
+
+    #[test]
+    fn test_is_slow() {
+        let threshold = Duration::from_millis(500);
+        assert!(is_slow(Duration::from_millis(501), threshold));
+        assert!(!is_slow(Duration::from_millis(500), threshold));
+        // A zero threshold turns logging off.
+        assert!(!is_slow(Duration::from_secs(60), Duration::from_secs(0)));
+    }
+
+    /// Checks that the busiest lookups use an index. MySQL isn't checked: InnoDB indexes every
+    /// foreign key, which covers the same lookups.
+    #[cfg(not(feature = "mysql"))]
+    mod query_plans {
+        use super::*;
+        use diesel::deserialize::{self, QueryableByName};
+        use diesel::row::NamedRow;
+        use diesel::sql_types::Text;
+
+        type Backend = <DbConnection as Connection>::Backend;
+
+        /// A line of a query plan. The column it's in has a space in its name on Postgres, which
+        /// the derive can't express.
+        struct PlanLine(String);
+
+        #[cfg(feature = "postgres")]
+        const EXPLAIN: &str = "EXPLAIN";
+        #[cfg(feature = "postgres")]
+        const PLAN_COLUMN: &str = "QUERY PLAN";
+        #[cfg(feature = "sqlite")]
+        const EXPLAIN: &str = "EXPLAIN QUERY PLAN";
+        #[cfg(feature = "sqlite")]
+        const PLAN_COLUMN: &str = "detail";
+
+        impl QueryableByName<Backend> for PlanLine {
+            fn build<R: NamedRow<Backend>>(row: &R) -> deserialize::Result<Self> {
+                row.get::<Text, String>(PLAN_COLUMN).map(PlanLine)
+            }
+        }
+
+        /// Whether a plan reads the whole table, rather than looking rows up in an index.
+        fn is_full_scan(line: &str) -> bool {
+            line.contains("Seq Scan") || line.starts_with("SCAN ")
+        }
+
+        /// The lookups the busiest queries make, and the index each should use. Keys SQLite
+        /// names itself are left out, as long as some index is used.
+        #[test]
+        fn test_lookups_use_indexes() {
+            let cases = vec![
+                (
+                    "SELECT id FROM users WHERE lower(email) = 'someone@example.com'",
+                    Some("users_email_lower_key"),
+                ),
+                ("SELECT id FROM articles WHERE slug = 'a-slug'", None),
+                (
+                    "SELECT id FROM articles WHERE user_id = 1 ORDER BY created_at DESC",
+                    Some("articles_user_id_idx"),
+                ),
+                (
+                    "SELECT article_id FROM article_tags WHERE tag_id = 1",
+                    Some("article_tags_tag_id_idx"),
+                ),
+                (
+                    "SELECT 1 FROM favorites WHERE user_id = 1 AND article_id = 2",
+                    None,
+                ),
+                (
+                    "SELECT user_id FROM favorites WHERE article_id = 2",
+                    Some("favorites_article_id_idx"),
+                ),
+                (
+                    "SELECT 1 FROM followers WHERE follower_id = 1 AND followed_id = 2",
+                    None,
+                ),
+                (
+                    "SELECT follower_id FROM followers WHERE followed_id = 2",
+                    Some("followers_followed_id_idx"),
+                ),
+            ];
+            let repo = repo();
+            block_on(async move {
+                let plans = repo
+                    .run("test", move |conn| {
+                        // The tables are nearly empty, where reading them whole is cheapest.
+                        if cfg!(feature = "postgres") {
+                            conn.batch_execute("SET LOCAL enable_seqscan = off")?;
+                        }
+                        cases
+                            .into_iter()
+                            .map(|(query, index)| -> QueryResult<_> {
+                                let plan = diesel::sql_query(format!("{} {}", EXPLAIN, query))
+                                    .load::<PlanLine>(&conn)?;
+                                let lines: Vec<String> =
+                                    plan.into_iter().map(|PlanLine(line)| line).collect();
+                                Ok((query, index, lines))
+                            })
+                            .collect::<QueryResult<Vec<_>>>()
+                    })
+                    .await
+                    .unwrap();
+                for (query, index, lines) in plans {
+                    let plan = lines.join("\n");
+                    assert!(
+                        !lines.iter().any(|line| is_full_scan(line)),
+                        "{} scans the table:\n{}",
+                        query,
+                        plan
+                    );
+                    if let Some(index) = index {
+                        assert!(
+                            plan.contains(index),
+                            "{} doesn't use {}:\n{}",
+                            query,
+                            index,
+                            plan
+                        );
+                    }
+                }
+            });
+        }
+    }
+}
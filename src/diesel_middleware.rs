@@ -1,5 +1,5 @@
 use diesel::Connection;
-use futures::future::{self, Future};
+use futures01::future::{self, Future};
 use log::{error, trace};
 use std::io;
 use std::panic::{catch_unwind, AssertUnwindSafe};
@@ -13,14 +13,14 @@ use crate::db::Repo;
 
 pub struct DieselMiddleware<T>
 where
-    T: Connection + 'static,
+    T: Connection + Send + 'static,
 {
     repo: AssertUnwindSafe<Repo<T>>,
 }
 
 impl<T> DieselMiddleware<T>
 where
-    T: Connection,
+    T: Connection + Send + 'static,
 {
     pub fn new(repo: Repo<T>) -> Self {
         DieselMiddleware {
@@ -31,7 +31,7 @@ where
 
 impl<T> Clone for DieselMiddleware<T>
 where
-    T: Connection + 'static,
+    T: Connection + Send + 'static,
 {
     fn clone(&self) -> Self {
         match catch_unwind(|| self.repo.clone()) {
@@ -49,7 +49,7 @@ where
 
 impl<T> NewMiddleware for DieselMiddleware<T>
 where
-    T: Connection + 'static,
+    T: Connection + Send + 'static,
 {
     type Instance = DieselMiddleware<T>;
 
@@ -73,7 +73,7 @@ where
 
 impl<T> Middleware for DieselMiddleware<T>
 where
-    T: Connection + 'static,
+    T: Connection + Send + 'static,
 {
     fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
     where
@@ -0,0 +1,288 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use diesel::QueryResult;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::conduit;
+use crate::db::{DbConnection, RepoError};
+use crate::jobs::{self, Job};
+use crate::Repo;
+
+type Subscriber = UnboundedSender<(Event, NaiveDateTime)>;
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Subscribers> = Mutex::new(Subscribers::default());
+}
+
+/// Those listening for every event, and those only listening for the events notifying a
+/// user, by user.
+#[derive(Default)]
+struct Subscribers {
+    all: Vec<Subscriber>,
+    by_recipient: HashMap<i32, Vec<Subscriber>>,
+}
+
+/// Something that happened that integrations may want to hear about. The storage functions
+/// making the change publish it, and webhooks subscribed to it are sent it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", content = "data", rename_all = "camelCase")]
+pub enum Event {
+    #[serde(rename_all = "camelCase")]
+    UserRegistered { user_id: i32, username: String },
+    #[serde(rename_all = "camelCase")]
+    ArticlePublished {
+        article_id: i32,
+        slug: String,
+        author_id: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    CommentAdded {
+        comment_id: i32,
+        article_id: i32,
+        author_id: i32,
+        article_author_id: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    UserMentioned {
+        comment_id: i32,
+        article_id: i32,
+        author_id: i32,
+        user_id: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    UserFollowed { follower_id: i32, followed_id: i32 },
+    #[serde(rename_all = "camelCase")]
+    ArticleFavorited {
+        article_id: i32,
+        user_id: i32,
+        article_author_id: i32,
+    },
+}
+
+/// The names webhooks subscribe to events by.
+pub const EVENT_NAMES: &[&str] = &[
+    "userRegistered",
+    "articlePublished",
+    "commentAdded",
+    "userMentioned",
+    "userFollowed",
+    "articleFavorited",
+];
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::UserRegistered { .. } => "userRegistered",
+            Event::ArticlePublished { .. } => "articlePublished",
+            Event::CommentAdded { .. } => "commentAdded",
+            Event::UserMentioned { .. } => "userMentioned",
+            Event::UserFollowed { .. } => "userFollowed",
+            Event::ArticleFavorited { .. } => "articleFavorited",
+        }
+    }
+
+    /// The user to notify, if someone else did something to them or something of theirs.
+    pub fn recipient(&self) -> Option<i32> {
+        let (actor_id, owner_id) = match *self {
+            Event::CommentAdded {
+                author_id,
+                article_author_id,
+                ..
+            } => (author_id, article_author_id),
+            Event::UserMentioned {
+                author_id, user_id, ..
+            } => (author_id, user_id),
+            Event::UserFollowed {
+                follower_id,
+                followed_id,
+            } => (follower_id, followed_id),
+            Event::ArticleFavorited {
+                user_id,
+                article_author_id,
+                ..
+            } => (user_id, article_author_id),
+            _ => return None,
+        };
+        if actor_id == owner_id {
+            None
+        } else {
+            Some(owner_id)
+        }
+    }
+
+    /// The JSON webhooks are sent: `{"event": ..., "occurredAt": ..., "data": {...}}`.
+    pub fn payload(&self, occurred_at: NaiveDateTime) -> String {
+        let mut payload = serde_json::to_value(self).expect("Events serialize to JSON");
+        let occurred_at = DateTime::<Utc>::from_utc(occurred_at, Utc);
+        payload["occurredAt"] = occurred_at
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into();
+        payload.to_string()
+    }
+}
+
+/// What the worker does with a published event: notify its recipient, and queue its
+/// deliveries to webhooks. It's all one transaction, so retrying it doesn't do any twice.
+pub async fn handle(repo: Repo, event: Event, occurred_at: NaiveDateTime) -> Result<(), RepoError> {
+    repo.transaction("events::handle", move |conn| {
+        conduit::notifications::notify(conn, &event, occurred_at)?;
+        conduit::webhooks::queue_deliveries(conn, &event, occurred_at)?;
+        Ok(())
+    })
+    .await
+}
+
+/// Publish `event` on `conn`. It's queued as a job, so it's only published if the
+/// transaction making the change commits, and subscribers are told about it in the
+/// background rather than holding up the change.
+pub fn publish(conn: &DbConnection, event: Event) -> QueryResult<()> {
+    let job = Job::PublishEvent {
+        event,
+        occurred_at: Utc::now().naive_utc(),
+    };
+    conduit::jobs::insert(conn, &jobs::new_job(&job))
+}
+
+/// Hear about events, along with when they happened, as the worker in this process handles
+/// them. Stop by dropping the receiver.
+pub fn subscribe() -> UnboundedReceiver<(Event, NaiveDateTime)> {
+    let (sender, receiver) = mpsc::unbounded();
+    SUBSCRIBERS
+        .lock()
+        .expect("Subscribers lock poisoned")
+        .all
+        .push(sender);
+    receiver
+}
+
+/// Like `subscribe`, but only hear about the events whose `recipient` is `user_id`.
+pub fn subscribe_recipient(user_id: i32) -> UnboundedReceiver<(Event, NaiveDateTime)> {
+    let (sender, receiver) = mpsc::unbounded();
+    SUBSCRIBERS
+        .lock()
+        .expect("Subscribers lock poisoned")
+        .by_recipient
+        .entry(user_id)
+        .or_insert_with(Vec::new)
+        .push(sender);
+    receiver
+}
+
+/// Pass `event` on to the subscribers in this process, forgetting those that have gone.
+pub fn broadcast(event: &Event, occurred_at: NaiveDateTime) {
+    let send = |subscriber: &Subscriber| {
+        subscriber
+            .unbounded_send((event.clone(), occurred_at))
+            .is_ok()
+    };
+    let mut subscribers = SUBSCRIBERS.lock().expect("Subscribers lock poisoned");
+    subscribers.all.retain(send);
+    if let Some(user_id) = event.recipient() {
+        let remaining = match subscribers.by_recipient.get_mut(&user_id) {
+            Some(recipients) => {
+                recipients.retain(send);
+                recipients.len()
+            }
+            None => return,
+        };
+        if remaining == 0 {
+            subscribers.by_recipient.remove(&user_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::QueuedJob;
+    use crate::repo;
+    use crate::schema::jobs;
+    use crate::test_helpers::{block_on, generate};
+    use chrono::NaiveDate;
+    use diesel::prelude::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_changes_publish_events() {
+        let repo = repo();
+        let published = block_on(async move {
+            let user = generate::user().insert(repo.clone()).await;
+            let article = generate::article(user.id).insert(repo.clone()).await;
+            generate::comment(article.id, user.id)
+                .insert(repo.clone())
+                .await;
+            repo.run("test", |conn| {
+                jobs::table.order(jobs::id).load::<QueuedJob>(&conn)
+            })
+            .await
+            .unwrap()
+        });
+        let names: Vec<&str> = published
+            .iter()
+            .map(|job| match serde_json::from_str(&job.payload).unwrap() {
+                Job::PublishEvent { event, .. } => event.name(),
+                other => panic!("Queued {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec!["userRegistered", "articlePublished", "commentAdded"]
+        );
+    }
+
+    #[test]
+    fn test_payload() {
+        let event = Event::CommentAdded {
+            comment_id: 3,
+            article_id: 2,
+            author_id: 1,
+            article_author_id: 4,
+        };
+        let occurred_at = NaiveDate::from_ymd(2019, 10, 28).and_hms_milli(9, 30, 0, 250);
+        let payload: Value = serde_json::from_str(&event.payload(occurred_at)).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "event": "commentAdded",
+                "occurredAt": "2019-10-28T09:30:00.250Z",
+                "data": {"commentId": 3, "articleId": 2, "authorId": 1, "articleAuthorId": 4},
+            })
+        );
+        assert!(EVENT_NAMES.contains(&event.name()));
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let event = Event::UserRegistered {
+            user_id: -1,
+            username: "broadcast".to_string(),
+        };
+        let mut subscriber = subscribe();
+        broadcast(&event, Utc::now().naive_utc());
+        // Other tests may be broadcasting too.
+        let mut received = vec![];
+        while let Ok(Some((event, _))) = subscriber.try_next() {
+            received.push(event);
+        }
+        assert!(received.contains(&event));
+    }
+
+    #[test]
+    fn test_broadcast_to_recipient() {
+        let favorited = |user_id| Event::ArticleFavorited {
+            article_id: 2,
+            user_id,
+            article_author_id: -2,
+        };
+        let mut recipient = subscribe_recipient(-2);
+        broadcast(&favorited(-2), Utc::now().naive_utc());
+        broadcast(&favorited(-3), Utc::now().naive_utc());
+        let (event, _) = recipient.try_next().unwrap().unwrap();
+        assert_eq!(event, favorited(-3));
+        // Favoriting their own article doesn't notify them.
+        assert!(recipient.try_next().is_err());
+    }
+}
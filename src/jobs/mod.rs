@@ -0,0 +1,145 @@
+pub mod scheduler;
+pub mod worker;
+
+use chrono::{NaiveDateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
+use futures::TryFutureExt;
+use serde_derive::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::runtime::Runtime;
+
+use crate::conduit;
+use crate::conduit::views::Viewer;
+use crate::db::RepoError;
+use crate::events::Event;
+use crate::mail::{Email, MailError, Mailer};
+use crate::models::NewJob;
+use crate::Repo;
+
+/// Work that shouldn't hold up a request. Handlers `enqueue` it, and the `worker::Worker`
+/// started with the server does it, retrying failed jobs. It's stored as JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum Job {
+    /// Deliver an email with the configured transport.
+    SendEmail(Email),
+    /// Rebuild the full-text search index, to compact it after many edits.
+    ReindexSearch,
+    /// Forget revoked tokens that have expired since.
+    PurgeRevokedTokens,
+    /// Delete password reset tokens that expired unused.
+    PurgePasswordResets,
+    /// Delete accounts deleted longer ago than the retention, once nothing they wrote is left.
+    PurgeDeletedUsers { retention_seconds: u64 },
+    /// Publish the drafts whose scheduled time has come.
+    PublishScheduledArticles,
+    /// Tell the subscribers to an event about it.
+    PublishEvent {
+        event: Event,
+        occurred_at: NaiveDateTime,
+    },
+    /// Send a webhook an event's payload, signed with its secret.
+    DeliverWebhook {
+        webhook_id: i32,
+        event: String,
+        payload: String,
+    },
+    /// Score the recently published articles for the trending listing.
+    RefreshTrending,
+    /// Count a view of an article, unless the viewer viewed it within the same hour.
+    RecordView {
+        slug: String,
+        viewer: Viewer,
+        viewed_at: NaiveDateTime,
+    },
+}
+
+impl Job {
+    /// The name it's stored under, to tell jobs apart without reading their payload.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Job::SendEmail(_) => "SendEmail",
+            Job::ReindexSearch => "ReindexSearch",
+            Job::PurgeRevokedTokens => "PurgeRevokedTokens",
+            Job::PurgePasswordResets => "PurgePasswordResets",
+            Job::PurgeDeletedUsers { .. } => "PurgeDeletedUsers",
+            Job::PublishScheduledArticles => "PublishScheduledArticles",
+            Job::PublishEvent { .. } => "PublishEvent",
+            Job::DeliverWebhook { .. } => "DeliverWebhook",
+            Job::RefreshTrending => "RefreshTrending",
+            Job::RecordView { .. } => "RecordView",
+        }
+    }
+}
+
+/// Queue `job` to be done as soon as the worker gets to it.
+pub async fn enqueue(repo: Repo, job: Job) -> Result<(), RepoError> {
+    conduit::jobs::enqueue(repo, new_job(&job)).await
+}
+
+/// `job` as it's stored, due now.
+pub fn new_job(job: &Job) -> NewJob {
+    NewJob {
+        kind: job.kind().to_string(),
+        payload: serde_json::to_string(job).expect("Jobs serialize to JSON"),
+        run_at: Utc::now().naive_utc(),
+    }
+}
+
+/// Run `future` on `runtime`, waiting for it on this thread. `Repo` queries have to run on a
+/// threadpool, and webhook deliveries need a timer, so the worker and the scheduler need a
+/// runtime of their own.
+fn run_on<F, T>(runtime: &mut Runtime, future: F) -> T
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    runtime
+        .block_on(future.unit_error().boxed().compat())
+        .expect("Future failed")
+}
+
+/// Queues emails as jobs, so handlers don't wait on the mail server, and emails it can't
+/// take right away are retried.
+pub struct JobMailer {
+    repo: Repo,
+}
+
+impl JobMailer {
+    pub fn new(repo: Repo) -> Self {
+        JobMailer { repo }
+    }
+}
+
+impl Mailer for JobMailer {
+    fn send(&self, email: Email) -> BoxFuture<'static, Result<(), MailError>> {
+        queue_email(self.repo.clone(), email).boxed()
+    }
+}
+
+async fn queue_email(repo: Repo, email: Email) -> Result<(), MailError> {
+    enqueue(repo, Job::SendEmail(email))
+        .await
+        .map_err(|e| MailError(format!("could not queue it: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_is_tagged_with_kind() {
+        let job = Job::SendEmail(Email {
+            to: "jake@jake.jake".to_string(),
+            subject: "Hello".to_string(),
+            body: "Hi".to_string(),
+        });
+        let payload: serde_json::Value = serde_json::to_value(&job).unwrap();
+        assert_eq!(payload["kind"], job.kind());
+        assert_eq!(payload["to"], "jake@jake.jake");
+        assert_eq!(serde_json::from_value::<Job>(payload).unwrap(), job);
+
+        let payload = serde_json::to_value(&Job::ReindexSearch).unwrap();
+        assert_eq!(payload["kind"], Job::ReindexSearch.kind());
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::error;
+use tokio::runtime::Runtime;
+
+use crate::config::MaintenanceConfig;
+use crate::jobs::{enqueue, run_on, Job};
+use crate::Repo;
+
+/// The longest the scheduler sleeps at a time, so it notices when it's stopped.
+const TICK: Duration = Duration::from_secs(1);
+
+/// The housekeeping jobs to run, along with publishing scheduled articles and scoring the
+/// trending ones, each with how often to run it. Jobs with an interval of zero are turned
+/// off, and left out.
+pub fn maintenance_jobs(config: &MaintenanceConfig) -> Vec<(Job, Duration)> {
+    let jobs = vec![
+        (Job::PurgeRevokedTokens, config.tokens_interval),
+        (Job::PurgePasswordResets, config.tokens_interval),
+        (
+            Job::PurgeDeletedUsers {
+                retention_seconds: config.deleted_users_retention.as_secs(),
+            },
+            config.deleted_users_interval,
+        ),
+        (
+            Job::PublishScheduledArticles,
+            config.scheduled_articles_interval,
+        ),
+        (Job::RefreshTrending, config.trending_interval),
+    ];
+    jobs.into_iter()
+        .filter(|(_, interval)| *interval > Duration::from_secs(0))
+        .collect()
+}
+
+/// Queues the maintenance jobs for the worker, each once at startup and then every interval.
+pub struct Scheduler {
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Scheduler {
+    pub fn start(repo: Repo, config: MaintenanceConfig) -> Self {
+        let stopping = Arc::new(AtomicBool::new(false));
+        let stop = stopping.clone();
+        let thread = thread::Builder::new()
+            .name("scheduler".to_string())
+            .spawn(move || {
+                let mut runtime = Runtime::new().expect("Failed to start the scheduler runtime");
+                let now = Instant::now();
+                let mut schedule: Vec<(Job, Duration, Instant)> = maintenance_jobs(&config)
+                    .into_iter()
+                    .map(|(job, interval)| (job, interval, now))
+                    .collect();
+                while !stop.load(Ordering::SeqCst) {
+                    let now = Instant::now();
+                    for (job, interval, due) in schedule.iter_mut() {
+                        if *due > now {
+                            continue;
+                        }
+                        if let Err(e) = run_on(&mut runtime, enqueue(repo.clone(), job.clone())) {
+                            error!("Could not queue a {} job: {}", job.kind(), e);
+                        }
+                        *due = now + *interval;
+                    }
+                    thread::sleep(TICK);
+                }
+            })
+            .expect("Failed to start the scheduler thread");
+        Scheduler { stopping, thread }
+    }
+
+    pub fn stop(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        if self.thread.join().is_err() {
+            error!("The scheduler thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_jobs() {
+        let config = MaintenanceConfig {
+            tokens_interval: Duration::from_secs(60),
+            deleted_users_interval: Duration::from_secs(0),
+            deleted_users_retention: Duration::from_secs(3600),
+            scheduled_articles_interval: Duration::from_secs(0),
+            trending_interval: Duration::from_secs(0),
+        };
+        let jobs = maintenance_jobs(&config);
+        assert_eq!(
+            jobs,
+            vec![
+                (Job::PurgeRevokedTokens, Duration::from_secs(60)),
+                (Job::PurgePasswordResets, Duration::from_secs(60)),
+            ]
+        );
+
+        let config = MaintenanceConfig {
+            deleted_users_interval: Duration::from_secs(600),
+            ..config
+        };
+        let jobs = maintenance_jobs(&config);
+        assert_eq!(
+            jobs[2],
+            (
+                Job::PurgeDeletedUsers {
+                    retention_seconds: 3600
+                },
+                Duration::from_secs(600)
+            )
+        );
+
+        let config = MaintenanceConfig {
+            scheduled_articles_interval: Duration::from_secs(60),
+            ..config
+        };
+        let jobs = maintenance_jobs(&config);
+        assert_eq!(
+            jobs[3],
+            (Job::PublishScheduledArticles, Duration::from_secs(60))
+        );
+
+        let config = MaintenanceConfig {
+            trending_interval: Duration::from_secs(600),
+            ..config
+        };
+        let jobs = maintenance_jobs(&config);
+        assert_eq!(jobs[4], (Job::RefreshTrending, Duration::from_secs(600)));
+    }
+}
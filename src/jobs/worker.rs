@@ -0,0 +1,291 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use log::{error, info, warn};
+use tokio::runtime::Runtime;
+
+use crate::conduit;
+use crate::config::JobsConfig;
+use crate::db::RepoError;
+use crate::events;
+use crate::jobs::{run_on, Job};
+use crate::mail::Mailer;
+use crate::webhooks;
+use crate::Repo;
+
+/// How long a worker has to finish a job before it's taken to have died, and the job is
+/// started again.
+const LEASE: Duration = Duration::from_secs(5 * 60);
+/// How long to wait before retrying a failed job the first time. The wait doubles with each
+/// retry after that.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// The longest wait before a retry.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// What jobs need to do their work.
+#[derive(Clone)]
+pub struct Context {
+    pub repo: Repo,
+    /// Sends emails, rather than queueing them like the `JobMailer` handlers use.
+    pub mailer: Arc<dyn Mailer>,
+}
+
+/// Works through the job queue, one job at a time, on a thread of its own.
+pub struct Worker {
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Worker {
+    /// Start the thread. Jobs run on a threadpool of their own, as `Repo` queries block.
+    pub fn start(context: Context, config: JobsConfig) -> Self {
+        let stopping = Arc::new(AtomicBool::new(false));
+        let stop = stopping.clone();
+        let thread = thread::Builder::new()
+            .name("jobs".to_string())
+            .spawn(move || {
+                let mut runtime = Runtime::new().expect("Failed to start the jobs runtime");
+                while !stop.load(Ordering::SeqCst) {
+                    match run_on(&mut runtime, work_one(context.clone(), config.max_attempts)) {
+                        Ok(true) => (),
+                        Ok(false) => thread::sleep(config.poll_interval),
+                        Err(e) => {
+                            error!("Could not take a job off the queue: {}", e);
+                            thread::sleep(config.poll_interval);
+                        }
+                    }
+                }
+            })
+            .expect("Failed to start the jobs thread");
+        Worker { stopping, thread }
+    }
+
+    /// Stop once the job in hand, if there is one, is done.
+    pub fn stop(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        if self.thread.join().is_err() {
+            error!("The jobs thread panicked");
+        }
+    }
+}
+
+/// Do the job that has been due the longest, if any is. Returns whether there was one.
+///
+/// A failed job is retried later, unless it has been tried `max_attempts` times already, or
+/// its payload can't be read, in which case it's given up on.
+pub async fn work_one(context: Context, max_attempts: i32) -> Result<bool, RepoError> {
+    let lease_until = after(LEASE);
+    let job = match conduit::jobs::claim(context.repo.clone(), lease_until).await? {
+        Some(job) => job,
+        None => return Ok(false),
+    };
+    let result = match serde_json::from_str::<Job>(&job.payload) {
+        Ok(payload) => perform(&context, payload).await.map_err(|e| (e, true)),
+        Err(e) => Err((format!("Could not read the payload: {}", e), false)),
+    };
+    match result {
+        Ok(()) => conduit::jobs::complete(context.repo, job.id).await?,
+        Err((e, retryable)) => {
+            let retry_at = if retryable && job.attempts < max_attempts {
+                Some(after(retry_delay(job.attempts)))
+            } else {
+                None
+            };
+            match retry_at {
+                Some(retry_at) => warn!(
+                    "{} job {} failed, retrying at {}: {}",
+                    job.kind, job.id, retry_at, e
+                ),
+                None => error!("{} job {} failed, giving up: {}", job.kind, job.id, e),
+            }
+            conduit::jobs::fail(context.repo, job.id, e, retry_at).await?;
+        }
+    }
+    Ok(true)
+}
+
+async fn perform(context: &Context, job: Job) -> Result<(), String> {
+    match job {
+        Job::SendEmail(email) => context.mailer.send(email).await.map_err(|e| e.to_string()),
+        Job::ReindexSearch => conduit::search::reindex(context.repo.clone())
+            .await
+            .map_err(|e| e.to_string()),
+        Job::PurgeRevokedTokens => {
+            let purged = conduit::tokens::purge_expired(context.repo.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+            info!("Purged {} expired revoked tokens", purged);
+            Ok(())
+        }
+        Job::PurgePasswordResets => {
+            let purged = conduit::password_resets::purge_expired(context.repo.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+            info!("Purged {} expired password reset tokens", purged);
+            Ok(())
+        }
+        Job::PurgeDeletedUsers { retention_seconds } => {
+            let deleted_before = before(Duration::from_secs(retention_seconds));
+            let purged = conduit::users::purge_deleted(context.repo.clone(), deleted_before)
+                .await
+                .map_err(|e| e.to_string())?;
+            info!("Purged {} deleted users", purged);
+            Ok(())
+        }
+        Job::PublishScheduledArticles => {
+            let now = Utc::now().naive_utc();
+            let published = conduit::articles::publish_scheduled(context.repo.clone(), now)
+                .await
+                .map_err(|e| e.to_string())?;
+            info!("Published {} scheduled articles", published);
+            Ok(())
+        }
+        Job::PublishEvent { event, occurred_at } => {
+            events::handle(context.repo.clone(), event.clone(), occurred_at)
+                .await
+                .map_err(|e| e.to_string())?;
+            // Only once it's been handled, so a retry doesn't tell subscribers twice.
+            events::broadcast(&event, occurred_at);
+            Ok(())
+        }
+        Job::DeliverWebhook {
+            webhook_id,
+            event,
+            payload,
+        } => {
+            let webhook = conduit::webhooks::find(context.repo.clone(), webhook_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            match webhook {
+                Some(webhook) => webhooks::deliver(&webhook, &event, payload).await,
+                // It was deleted since.
+                None => Ok(()),
+            }
+        }
+        Job::RefreshTrending => {
+            let now = Utc::now().naive_utc();
+            let scored = conduit::trending::refresh(context.repo.clone(), now)
+                .await
+                .map_err(|e| e.to_string())?;
+            info!("Scored {} trending articles", scored);
+            Ok(())
+        }
+        Job::RecordView {
+            slug,
+            viewer,
+            viewed_at,
+        } => conduit::views::record(context.repo.clone(), slug, viewer, viewed_at)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// How long to wait before retrying a job that has failed `attempts` times.
+fn retry_delay(attempts: i32) -> Duration {
+    let doublings = (attempts - 1).max(0).min(16) as u32;
+    (FIRST_RETRY_DELAY * 2u32.pow(doublings)).min(MAX_RETRY_DELAY)
+}
+
+fn after(delay: Duration) -> NaiveDateTime {
+    Utc::now().naive_utc() + chrono::Duration::seconds(delay.as_secs() as i64)
+}
+
+fn before(delay: Duration) -> NaiveDateTime {
+    Utc::now().naive_utc() - chrono::Duration::seconds(delay.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::enqueue;
+    use crate::mail::{Email, MailError};
+    use crate::models::QueuedJob;
+    use crate::repo;
+    use crate::schema::jobs;
+    use crate::test_helpers::block_on;
+    use crate::test_helpers::fakes::CapturingMailer;
+    use diesel::prelude::*;
+    use futures::future::{self, BoxFuture, FutureExt};
+
+    struct FailingMailer;
+
+    impl Mailer for FailingMailer {
+        fn send(&self, _email: Email) -> BoxFuture<'static, Result<(), MailError>> {
+            future::err(MailError("the server is down".to_string())).boxed()
+        }
+    }
+
+    fn email() -> Email {
+        Email {
+            to: "jake@jake.jake".to_string(),
+            subject: "Hello".to_string(),
+            body: "Hi".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_secs(10));
+        assert_eq!(retry_delay(2), Duration::from_secs(20));
+        assert_eq!(retry_delay(3), Duration::from_secs(40));
+        assert_eq!(retry_delay(20), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_work_one() {
+        let repo = repo();
+        let mailer = Arc::new(CapturingMailer::default());
+        let context = Context {
+            repo: repo.clone(),
+            mailer: mailer.clone(),
+        };
+        block_on(async move {
+            assert!(!work_one(context.clone(), 3).await.unwrap());
+            enqueue(repo, Job::SendEmail(email())).await.unwrap();
+            assert!(work_one(context.clone(), 3).await.unwrap());
+            // Done, it's gone from the queue.
+            assert!(!work_one(context, 3).await.unwrap());
+        });
+        assert_eq!(mailer.sent(), vec![email()]);
+    }
+
+    #[test]
+    fn test_failed_jobs_are_retried_until_they_run_out_of_attempts() {
+        let repo = repo();
+        let context = Context {
+            repo: repo.clone(),
+            mailer: Arc::new(FailingMailer),
+        };
+        block_on(async move {
+            enqueue(repo.clone(), Job::SendEmail(email()))
+                .await
+                .unwrap();
+            assert!(work_one(context.clone(), 2).await.unwrap());
+            let job = repo
+                .run("test", |conn| jobs::table.first::<QueuedJob>(&conn))
+                .await
+                .unwrap();
+            assert_eq!(job.attempts, 1);
+            assert!(job.run_at > Utc::now().naive_utc());
+            assert!(job.failed_at.is_none());
+
+            // Due again, it fails for the last time.
+            let now = Utc::now().naive_utc();
+            conduit::jobs::fail(repo.clone(), job.id, "Failed".to_string(), Some(now))
+                .await
+                .unwrap();
+            assert!(work_one(context, 2).await.unwrap());
+            let job = repo
+                .run("test", |conn| jobs::table.first::<QueuedJob>(&conn))
+                .await
+                .unwrap();
+            assert_eq!(job.attempts, 2);
+            assert!(job.failed_at.is_some());
+            assert!(job.last_error.unwrap().contains("the server is down"));
+        });
+    }
+}
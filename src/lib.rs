@@ -0,0 +1,254 @@
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+
+pub mod atom;
+pub mod auth;
+pub mod cache;
+pub mod conduit;
+pub mod config;
+pub mod db;
+pub mod diesel_middleware;
+pub mod events;
+pub mod jobs;
+pub mod mail;
+pub mod markdown;
+pub mod mentions;
+pub mod middleware;
+pub mod models;
+pub mod oauth;
+pub mod reading;
+pub mod schema;
+pub mod server;
+pub mod slugs;
+pub mod storage;
+pub mod web;
+pub mod webhooks;
+
+#[cfg(test)]
+mod test_helpers;
+
+use gotham::handler::assets::FileOptions;
+use gotham::pipeline::new_pipeline;
+use gotham::pipeline::set::{finalize_pipeline_set, new_pipeline_set};
+use gotham::router::builder::*;
+use gotham::router::Router;
+use gotham::state::State;
+use std::sync::Arc;
+
+use crate::auth::middleware::{AuthMiddleware, RoleMiddleware};
+use crate::auth::Role;
+use crate::conduit::Repositories;
+use crate::config::{Config, ConfigMiddleware, DatabaseConfig, StorageBackend};
+use crate::diesel_middleware::DieselMiddleware;
+use crate::jobs::JobMailer;
+use crate::mail::Mailer;
+use crate::middleware::api_version::{ApiVersion, ApiVersionMiddleware};
+use crate::middleware::cache::CacheMiddleware;
+use crate::middleware::compression::CompressionMiddleware;
+use crate::middleware::cors::{self, CorsMiddleware};
+use crate::middleware::logging::RequestLogger;
+use crate::middleware::mailer::MailerMiddleware;
+use crate::middleware::metrics::MetricsMiddleware;
+use crate::middleware::repositories::RepositoriesMiddleware;
+use crate::middleware::request_id::RequestIdMiddleware;
+use crate::middleware::storage::StorageMiddleware;
+use crate::web::handler;
+use crate::web::routes::{Chain, Chains, Pipelines};
+
+const HELLO_ROUTER: &str = "Hello Router!";
+
+pub type Repo = db::Repo<db::DbConnection>;
+
+/// Where the API is served: under each version's prefix, and under `/api` for the
+/// unversioned alias. See `ApiVersion`.
+const API_PREFIXES: &[&str] = &["/api/v1", "/api"];
+
+/// Every path served under each of the `API_PREFIXES`.
+/// Static paths come before templated ones they overlap with, e.g. `/articles/feed`.
+const API_PATHS: &[&str] = &[
+    "/users",
+    "/users/login",
+    "/users/refresh",
+    "/users/logout",
+    "/users/verify/:token",
+    "/users/password/forgot",
+    "/users/password/reset",
+    "/users/oauth/:provider/authorize",
+    "/users/oauth/:provider/callback",
+    "/user",
+    "/user/password",
+    "/user/export",
+    "/user/image",
+    "/user/api-keys",
+    "/user/api-keys/:id",
+    "/profiles/:username",
+    "/profiles/:username/follow",
+    "/articles",
+    "/articles/feed",
+    "/articles/feed/stream",
+    "/articles/search",
+    "/articles/trending",
+    "/articles/:slug",
+    "/articles/:slug/favorite",
+    "/articles/:slug/publish",
+    "/articles/:slug/related",
+    "/articles/:slug/stats",
+    "/articles/:slug/revisions",
+    "/articles/:slug/revisions/:id/restore",
+    "/articles/:slug/comments",
+    "/articles/:slug/comments/:id",
+    "/tags",
+    "/tags/:tag",
+    "/tags/:tag/follow",
+    "/notifications",
+    "/notifications/unread-count",
+    "/notifications/:id/read",
+    "/graphql",
+    "/admin/users",
+    "/admin/users/:id",
+    "/admin/users/:id/suspend",
+    "/admin/comments/:id/revisions",
+    "/admin/maintenance",
+    "/admin/webhooks",
+    "/admin/webhooks/:id",
+    "/ws",
+    "/openapi.json",
+    "/docs",
+];
+
+pub fn say_hello(state: State) -> (State, &'static str) {
+    (state, HELLO_ROUTER)
+}
+
+/// The app's router. Emails are queued as jobs, for a `jobs::worker::Worker` to send.
+pub fn router(repo: Repo, config: Config) -> Router {
+    let repositories = Repositories::postgres(repo.clone());
+    let mailer = Arc::new(JobMailer::new(repo.clone()));
+    router_with_repositories(repo, repositories, mailer, config)
+}
+
+/// Build the router with the given `Repositories` and `Mailer`, so tests can substitute fakes.
+pub fn router_with_repositories(
+    repo: Repo,
+    repositories: Repositories,
+    mailer: Arc<dyn Mailer>,
+    config: Config,
+) -> Router {
+    let pipelines = new_pipeline_set();
+    let (pipelines, default) = pipelines.add(
+        new_pipeline()
+            .add(RequestLogger)
+            .add(RequestIdMiddleware)
+            .add(MetricsMiddleware::new(API_PREFIXES, API_PATHS))
+            .add(ApiVersionMiddleware)
+            .add(CompressionMiddleware)
+            .add(CorsMiddleware::new(config.cors.clone()))
+            .add(DieselMiddleware::new(repo))
+            .add(RepositoriesMiddleware::new(repositories))
+            .add(MailerMiddleware::new(mailer))
+            .add(CacheMiddleware::new(cache::from_config(&config.cache)))
+            .add(StorageMiddleware::new(storage::from_config(
+                &config.storage,
+                &config.public_url,
+            )))
+            .add(ConfigMiddleware::new(config.clone()))
+            .build(),
+    );
+    let (pipelines, authenticated) = pipelines.add(
+        new_pipeline()
+            .add(AuthMiddleware::required(config.jwt.clone()))
+            .build(),
+    );
+    let (pipelines, optionally_authenticated) = pipelines.add(
+        new_pipeline()
+            .add(AuthMiddleware::optional(config.jwt.clone()))
+            .build(),
+    );
+    let (pipelines, admin) = pipelines.add(
+        new_pipeline()
+            .add(AuthMiddleware::required(config.jwt.clone()))
+            .add(RoleMiddleware::new(Role::Admin))
+            .build(),
+    );
+    let pipeline_set = finalize_pipeline_set(pipelines);
+    let default_chain = (default, ());
+    let chains = Chains {
+        auth_required: (authenticated, default_chain),
+        auth_optional: (optionally_authenticated, default_chain),
+        admin: (admin, default_chain),
+    };
+
+    build_router(default_chain, pipeline_set, |route| {
+        if config.frontend_dir.is_some() {
+            // Only paths no other route matches reach the frontend.
+            route.get_or_head("/").to(handler(web::frontend::serve));
+            route.get_or_head("/*").to(handler(web::frontend::serve));
+        } else {
+            route.get("/").to(say_hello);
+        }
+        route.get("/healthz").to(web::health::healthz);
+        route.get("/readyz").to(handler(web::health::readyz));
+        route.get("/metrics").to(web::metrics::metrics);
+        web::feeds::register_routes(route, chains);
+        // Uploaded files never change, as each is stored under a new name.
+        if let StorageBackend::Local(ref directory) = config.storage.backend {
+            route.get(&format!("{}/*", storage::UPLOADS_PATH)).to_dir(
+                FileOptions::new(directory)
+                    .with_cache_control("public, max-age=31536000, immutable")
+                    .build(),
+            );
+        }
+        for version in ApiVersion::ALL {
+            route.scope(version.prefix(), |route| api_routes(route, chains));
+        }
+        route.scope("/api", |route| api_routes(route, chains));
+    })
+}
+
+/// Draw every API route, for mounting under each of the `API_PREFIXES`.
+fn api_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    // Gotham won't fall back to a catch-all route for a path that has other routes,
+    // so each path needs its own route for CORS preflight requests.
+    for path in API_PATHS {
+        route.options(path).to(cors::preflight);
+    }
+    web::users::register_routes(route, chains);
+    web::uploads::register_routes(route, chains);
+    web::oauth::register_routes(route, chains);
+    web::api_keys::register_routes(route, chains);
+    web::profiles::register_routes(route, chains);
+    web::articles::register_routes(route, chains);
+    web::comments::register_routes(route, chains);
+    web::tags::register_routes(route, chains);
+    web::notifications::register_routes(route, chains);
+    web::graphql::register_routes(route, chains);
+    web::admin::register_routes(route, chains);
+    web::webhooks::register_routes(route, chains);
+    web::ws::register_routes(route, chains);
+    web::openapi::register_routes(route, chains);
+}
+
+#[cfg(not(test))]
+pub fn repo() -> Repo {
+    let config =
+        DatabaseConfig::from_env().unwrap_or_else(|e| panic!("Invalid configuration: {}", e));
+    Repo::connect(&config).unwrap_or_else(|e| panic!("Could not connect to the database: {}", e))
+}
+
+/// In tests, every query runs in a transaction that's rolled back when the `Repo` is dropped.
+#[cfg(test)]
+pub fn repo() -> Repo {
+    let config =
+        DatabaseConfig::from_env().unwrap_or_else(|e| panic!("Invalid configuration: {}", e));
+    Repo::test(&config).unwrap_or_else(|e| panic!("Could not connect to the database: {}", e))
+}
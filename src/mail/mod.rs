@@ -0,0 +1,63 @@
+pub mod smtp;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use gotham_derive::StateData;
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::config::{MailConfig, MailTransport};
+use crate::mail::smtp::SmtpMailer;
+
+/// A plain text email.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to send email: {}", self.0)
+    }
+}
+
+impl Error for MailError {}
+
+/// Sends emails to users. Handlers find it in `State`, so tests can capture messages instead.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: Email) -> BoxFuture<'static, Result<(), MailError>>;
+}
+
+/// The `Mailer` handlers use, put into `State` by the `MailerMiddleware`.
+#[derive(Clone, StateData)]
+pub struct SharedMailer(pub Arc<dyn Mailer>);
+
+/// Prints emails to stdout rather than sending them, for development.
+pub struct StdoutMailer;
+
+impl Mailer for StdoutMailer {
+    fn send(&self, email: Email) -> BoxFuture<'static, Result<(), MailError>> {
+        println!(
+            "To: {}\nSubject: {}\n\n{}",
+            email.to, email.subject, email.body
+        );
+        future::ok(()).boxed()
+    }
+}
+
+/// The mailer for the configured transport. It sends emails as it's given them, so
+/// handlers queue emails with a `jobs::JobMailer` rather than wait on the mail server.
+pub fn from_config(config: &MailConfig) -> Arc<dyn Mailer> {
+    match config.transport {
+        MailTransport::Stdout => Arc::new(StdoutMailer),
+        MailTransport::Smtp(ref smtp) => {
+            Arc::new(SmtpMailer::new(smtp.clone(), config.from.clone()))
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use futures::future::{self, BoxFuture, FutureExt};
+use lettre::smtp::authentication::Credentials;
+use lettre::{ClientSecurity, ClientTlsParameters, SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+
+use crate::config::SmtpConfig;
+use crate::mail::{Email, MailError, Mailer};
+
+/// The port for SMTP over TLS. Other ports upgrade to TLS with STARTTLS.
+const SUBMISSIONS_PORT: u16 = 465;
+
+/// Delivers emails to an SMTP server, connecting for each one.
+/// Sending blocks until the server has accepted the message, so queue emails with a
+/// `jobs::JobMailer` and leave sending them to the jobs worker.
+pub struct SmtpMailer {
+    config: SmtpConfig,
+    from: String,
+}
+
+impl SmtpMailer {
+    pub fn new(config: SmtpConfig, from: String) -> Self {
+        SmtpMailer { config, from }
+    }
+
+    fn send_now(&self, email: Email) -> Result<(), MailError> {
+        let message = EmailBuilder::new()
+            .to(email.to)
+            .from(self.from.clone())
+            .subject(email.subject)
+            .text(email.body)
+            .build()
+            .map_err(|e| MailError(e.to_string()))?;
+        let address = (self.config.host.as_str(), self.config.port);
+        let mut client =
+            SmtpClient::new(address, self.security()?).map_err(|e| MailError(e.to_string()))?;
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            client = client.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        client
+            .transport()
+            .send(message.into())
+            .map(|_| ())
+            .map_err(|e| MailError(e.to_string()))
+    }
+
+    fn security(&self) -> Result<ClientSecurity, MailError> {
+        if !self.config.tls {
+            return Ok(ClientSecurity::None);
+        }
+        let connector = TlsConnector::new().map_err(|e| MailError(e.to_string()))?;
+        let parameters = ClientTlsParameters::new(self.config.host.clone(), connector);
+        if self.config.port == SUBMISSIONS_PORT {
+            Ok(ClientSecurity::Wrapper(parameters))
+        } else {
+            Ok(ClientSecurity::Required(parameters))
+        }
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: Email) -> BoxFuture<'static, Result<(), MailError>> {
+        future::ready(self.send_now(email)).boxed()
+    }
+}
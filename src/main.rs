@@ -1,71 +1,33 @@
-#[macro_use]
-extern crate diesel;
-
-mod auth;
-mod conduit;
-mod models;
-mod schema;
-mod web;
-
-#[cfg(test)]
-mod test_helpers;
-
-use std::env;
-
-use diesel::pg::PgConnection;
 use dotenv::dotenv;
-use gotham::pipeline::new_pipeline;
-use gotham::pipeline::set::{finalize_pipeline_set, new_pipeline_set};
-use gotham::router::builder::*;
-use gotham::router::Router;
-use gotham::state::State;
-use gotham_middleware_diesel::{self, DieselMiddleware};
-use gotham_middleware_jwt::JWTMiddleware;
-
-const HELLO_ROUTER: &str = "Hello Router!";
-
-pub type Repo = gotham_middleware_diesel::Repo<PgConnection>;
-
-pub fn say_hello(state: State) -> (State, &'static str) {
-    (state, HELLO_ROUTER)
-}
-
-pub fn router(repo: Repo) -> Router {
-    let pipelines = new_pipeline_set();
-    let (pipelines, default) =
-        pipelines.add(new_pipeline().add(DieselMiddleware::new(repo)).build());
-    let (pipelines, authenticated) = pipelines.add(
-        new_pipeline()
-            // Need to customize realm, as per Guardian.VerifyHeader
-            .add(JWTMiddleware::<auth::Claims>::new("secret".as_ref()))
-            .build(),
-    );
-    let pipeline_set = finalize_pipeline_set(pipelines);
-    let default_chain = (default, ());
-    let auth_chain = (authenticated, default_chain);
-
-    build_router(default_chain, pipeline_set, |route| {
-        route.get("/").to(say_hello);
-        route.scope("/api", |route| {
-            route.post("/users").to(web::users::register);
-            route.post("/users/login").to(web::users::login);
-            route.with_pipeline_chain(auth_chain, |route| {
-                route.get("/user").to(web::users::get_user);
-            });
-        })
-    })
-}
-
-pub fn repo() -> Repo {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    Repo::new(&database_url)
-}
+use log::info;
+use realworld_gotham::config::Config;
+use realworld_gotham::jobs::scheduler::Scheduler;
+use realworld_gotham::jobs::worker::{Context, Worker};
+use realworld_gotham::{mail, repo, router, server};
 
 pub fn main() {
     dotenv().ok();
     env_logger::init();
-    let addr = "127.0.0.1:7878";
+    let config = Config::from_env().unwrap_or_else(|e| panic!("Invalid configuration: {}", e));
+    let addr = config.listen_address.clone();
     println!("Listening for requests at http://{}", addr);
 
-    gotham::start(addr, router(repo()))
+    let repo = repo();
+    if !config.skip_migrations {
+        repo.run_pending_migrations()
+            .unwrap_or_else(|e| panic!("Failed to run migrations: {}", e));
+    }
+    let context = Context {
+        repo: repo.clone(),
+        mailer: mail::from_config(&config.mail),
+    };
+    let worker = Worker::start(context, config.jobs.clone());
+    let scheduler = Scheduler::start(repo.clone(), config.maintenance.clone());
+    let shutdown_timeout = config.shutdown_timeout;
+    server::serve(addr, router(repo.clone(), config), shutdown_timeout);
+    scheduler.stop();
+    worker.stop();
+    info!("Jobs worker stopped");
+    drop(repo);
+    info!("Database pool closed");
 }
@@ -0,0 +1,30 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// Render an article's Markdown body as HTML that's safe to put in a page as is. Raw HTML
+/// in the Markdown is kept, but only the tags and attributes `ammonia` allows by default:
+/// scripts, styles, event handlers and `javascript:` links are stripped out.
+pub fn to_html(body: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(body, options));
+    ammonia::clean(&unsafe_html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_html() {
+        let html = to_html("# Dragons\n\nHow to *train* them.");
+        assert!(html.contains("<h1>Dragons</h1>"));
+        assert!(html.contains("<p>How to <em>train</em> them.</p>"));
+    }
+
+    #[test]
+    fn test_to_html_strips_scripts() {
+        let html = to_html("Hi<script>alert(1)</script> [there](javascript:alert(1))");
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("javascript:"));
+    }
+}
@@ -0,0 +1,54 @@
+/// More mentions than this in one comment are ignored, so a comment can't notify everyone.
+pub const MAX_MENTIONS: usize = 20;
+
+/// The usernames mentioned in `body` as `@username`, each once, in the order they appear.
+/// An `@` right after a letter or digit is part of something else, like an email address,
+/// and a trailing `.` is taken to end the sentence rather than the username.
+pub fn usernames(body: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = Vec::new();
+    let mut previous = None;
+    for (i, c) in body.char_indices() {
+        if c == '@' && !previous.map_or(false, is_username_char) {
+            let rest = &body[i + 1..];
+            let end = rest
+                .find(|c| !is_username_char(c))
+                .unwrap_or_else(|| rest.len());
+            let username = rest[..end].trim_end_matches('.');
+            if !username.is_empty() && !usernames.iter().any(|u| u == username) {
+                usernames.push(username.to_string());
+                if usernames.len() == MAX_MENTIONS {
+                    break;
+                }
+            }
+        }
+        previous = Some(c);
+    }
+    usernames
+}
+
+/// The characters usernames are allowed to have.
+fn is_username_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usernames() {
+        assert_eq!(
+            usernames("Thanks @jake, and @jane_doe."),
+            vec!["jake", "jane_doe"]
+        );
+        assert_eq!(usernames("@jake.the-dog said so"), vec!["jake.the-dog"]);
+        assert_eq!(usernames("@jake @jake"), vec!["jake"]);
+        assert!(usernames("mail jake@example.com, or @ me").is_empty());
+    }
+
+    #[test]
+    fn test_usernames_are_capped() {
+        let body: Vec<String> = (0..30).map(|i| format!("@user{}", i)).collect();
+        assert_eq!(usernames(&body.join(" ")).len(), MAX_MENTIONS);
+    }
+}
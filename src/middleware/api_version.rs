@@ -0,0 +1,114 @@
+use gotham::handler::HandlerFuture;
+use gotham::middleware::Middleware;
+use gotham::state::{FromState, State};
+use gotham_derive::{NewMiddleware, StateData};
+use hyper::Uri;
+
+/// A version of the API, served under its own prefix, e.g. `/api/v1`.
+///
+/// Handlers check it to keep responding the way older clients expect after a breaking
+/// change, e.g. `if *ApiVersion::borrow_from(&state) >= ApiVersion::V2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, StateData)]
+pub enum ApiVersion {
+    V1,
+}
+
+impl ApiVersion {
+    /// Every version, oldest first.
+    pub const ALL: &'static [ApiVersion] = &[ApiVersion::V1];
+
+    /// The version `/api` serves without a version in the path. It stays v1, which clients
+    /// used before there were versions, as later versions are added.
+    pub const UNVERSIONED: ApiVersion = ApiVersion::V1;
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/api/v1",
+        }
+    }
+
+    /// The version a request for `path` is made to, if it's an API request at all.
+    pub fn of_path(path: &str) -> Option<ApiVersion> {
+        let under = |prefix: &str| {
+            path.starts_with(prefix)
+                && (path.len() == prefix.len() || path[prefix.len()..].starts_with('/'))
+        };
+        ApiVersion::ALL
+            .iter()
+            .cloned()
+            .find(|version| under(version.prefix()))
+            .or_else(|| {
+                if under("/api") {
+                    Some(ApiVersion::UNVERSIONED)
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+/// Puts the `ApiVersion` requested into `State`, going by the request path.
+#[derive(Clone, NewMiddleware)]
+pub struct ApiVersionMiddleware;
+
+impl Middleware for ApiVersionMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        if let Some(version) = ApiVersion::of_path(Uri::borrow_from(&state).path()) {
+            state.put(version);
+        }
+        chain(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::StdoutMailer;
+    use crate::router_with_repositories;
+    use crate::test_helpers::{self, block_on, generate};
+    use gotham::test::TestServer;
+    use std::sync::Arc;
+
+    #[test]
+    fn version_of_path() {
+        assert_eq!(
+            ApiVersion::of_path("/api/v1/articles"),
+            Some(ApiVersion::V1)
+        );
+        assert_eq!(
+            ApiVersion::of_path("/api/articles"),
+            Some(ApiVersion::UNVERSIONED)
+        );
+        assert_eq!(ApiVersion::of_path("/api/v1"), Some(ApiVersion::V1));
+        assert_eq!(ApiVersion::of_path("/apiary"), None);
+        assert_eq!(ApiVersion::of_path("/healthz"), None);
+    }
+
+    #[test]
+    fn unversioned_paths_are_aliases() {
+        let repositories = test_helpers::fakes::repositories();
+        let user = block_on(repositories.users.insert(generate::new_user())).unwrap();
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            repositories,
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        for prefix in &["/api/v1", "/api"] {
+            let res = server
+                .client()
+                .get(format!(
+                    "http://localhost{}/profiles/{}",
+                    prefix, user.username
+                ))
+                .perform()
+                .unwrap();
+            assert_eq!(res.status(), 200);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use gotham::handler::HandlerFuture;
+use gotham::middleware::{Middleware, NewMiddleware};
+use gotham::state::State;
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use crate::cache::{Cache, SharedCache};
+
+/// Puts the `Cache` into `State` for handlers to use.
+pub struct CacheMiddleware {
+    // Caches needn't be `RefUnwindSafe`, as Gotham requires of middleware.
+    cache: AssertUnwindSafe<Arc<dyn Cache>>,
+}
+
+impl CacheMiddleware {
+    pub fn new(cache: Arc<dyn Cache>) -> Self {
+        CacheMiddleware {
+            cache: AssertUnwindSafe(cache),
+        }
+    }
+}
+
+impl NewMiddleware for CacheMiddleware {
+    type Instance = CacheMiddleware;
+
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(CacheMiddleware::new(self.cache.0.clone()))
+    }
+}
+
+impl Middleware for CacheMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        state.put(SharedCache(self.cache.0));
+        chain(state)
+    }
+}
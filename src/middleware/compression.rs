@@ -0,0 +1,233 @@
+use futures01::future::{self, Either};
+use futures01::{Future, Stream};
+use gotham::handler::{HandlerFuture, IntoHandlerError};
+use gotham::middleware::Middleware;
+use gotham::state::{FromState, State};
+use gotham_derive::NewMiddleware;
+use hyper::header::{
+    HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use hyper::{Body, HeaderMap, Response};
+use std::io::{self, Write};
+
+/// Bodies smaller than this fit in a packet or two anyway, so aren't worth compressing.
+const MIN_LENGTH: usize = 1024;
+
+/// Brotli's quality ranges from 0 to 11. Higher levels compress JSON only a little better,
+/// and take a lot longer.
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+/// Compresses JSON responses with brotli or gzip, whichever the client prefers of those it
+/// accepts in `Accept-Encoding`, since pages of articles get large.
+#[derive(Clone, NewMiddleware)]
+pub struct CompressionMiddleware;
+
+impl Middleware for CompressionMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        let encoding = HeaderMap::borrow_from(&state)
+            .get(ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Encoding::negotiate);
+
+        let f = chain(state).and_then(move |(state, mut response)| {
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+            match encoding {
+                Some(encoding) if is_json(&response) => Either::A(
+                    compress(encoding, response).then(move |result| match result {
+                        Ok(response) => Ok((state, response)),
+                        Err(e) => Err((state, e.into_handler_error())),
+                    }),
+                ),
+                _ => Either::B(future::ok((state, response))),
+            }
+        });
+        Box::new(f)
+    }
+}
+
+/// Whether the response is JSON that hasn't been encoded already.
+fn is_json(response: &Response<Body>) -> bool {
+    let headers = response.headers();
+    !headers.contains_key(CONTENT_ENCODING)
+        && headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.starts_with("application/json"))
+}
+
+/// Read the whole body and compress it, unless it's too small to bother with.
+fn compress(
+    encoding: Encoding,
+    response: Response<Body>,
+) -> impl Future<Item = Response<Body>, Error = io::Error> {
+    let (mut parts, body) = response.into_parts();
+    body.concat2()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .and_then(move |body| {
+            if body.len() < MIN_LENGTH {
+                return Ok(Response::from_parts(parts, Body::from(body)));
+            }
+            let compressed = encoding.compress(&body)?;
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+            Ok(Response::from_parts(parts, Body::from(compressed)))
+        })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// The encoding to use for a client that sent `accept_encoding`, if it accepts any we
+    /// support. Brotli is preferred, since it makes JSON smaller than gzip does.
+    fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let accepted: Vec<String> = accept_encoding
+            .split(',')
+            .filter_map(|coding| {
+                let mut params = coding.split(';');
+                let name = params.next()?.trim().to_lowercase();
+                // `q=0` means the client doesn't want this encoding after all.
+                let refused = params.any(|param| {
+                    let param = param.trim();
+                    param.starts_with("q=") && param[2..].parse::<f32>().ok() == Some(0.0)
+                });
+                if refused {
+                    None
+                } else {
+                    Some(name)
+                }
+            })
+            .collect();
+        let accepts = |name: &str| {
+            accepted
+                .iter()
+                .any(|coding| coding == name || coding == "*")
+        };
+        if accepts("br") {
+            Some(Encoding::Brotli)
+        } else if accepts("gzip") {
+            Some(Encoding::Gzip)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the encoding in `Content-Encoding`.
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut writer =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(data)?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gotham::helpers::http::response::create_response;
+    use gotham::pipeline::new_pipeline;
+    use gotham::pipeline::single::single_pipeline;
+    use gotham::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use gotham::router::Router;
+    use gotham::test::TestServer;
+    use hyper::StatusCode;
+    use std::io::Read;
+
+    fn large(state: State) -> (State, Response<Body>) {
+        let body = format!("[{}]", vec!["\"article\""; 500].join(","));
+        let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
+        (state, res)
+    }
+
+    fn small(state: State) -> (State, Response<Body>) {
+        let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, "[]");
+        (state, res)
+    }
+
+    fn router() -> Router {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(CompressionMiddleware).build());
+        build_router(chain, pipelines, |route| {
+            route.get("/large").to(large);
+            route.get("/small").to(small);
+        })
+    }
+
+    fn get(path: &str, accept_encoding: &'static str) -> gotham::test::TestResponse {
+        let server = TestServer::new(router()).unwrap();
+        server
+            .client()
+            .get(format!("http://localhost{}", path))
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_static(accept_encoding))
+            .perform()
+            .unwrap()
+    }
+
+    #[test]
+    fn gzips_large_json() {
+        let res = get("/large", "gzip, deflate");
+        assert_eq!(res.headers()[CONTENT_ENCODING], "gzip");
+        let mut body = String::new();
+        flate2::read::GzDecoder::new(&res.read_body().unwrap()[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert!(body.starts_with("[\"article\","));
+    }
+
+    #[test]
+    fn prefers_brotli() {
+        let res = get("/large", "gzip, br");
+        assert_eq!(res.headers()[CONTENT_ENCODING], "br");
+    }
+
+    #[test]
+    fn leaves_small_and_unwanted_bodies() {
+        let res = get("/small", "gzip");
+        assert!(!res.headers().contains_key(CONTENT_ENCODING));
+        assert_eq!(res.read_utf8_body().unwrap(), "[]");
+
+        let res = get("/large", "br;q=0, identity");
+        assert!(!res.headers().contains_key(CONTENT_ENCODING));
+    }
+
+    #[test]
+    fn negotiates_encoding() {
+        assert_eq!(Encoding::negotiate("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(
+            Encoding::negotiate("br;q=0, gzip;q=0.5"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::negotiate("identity"), None);
+    }
+}
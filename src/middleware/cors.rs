@@ -0,0 +1,120 @@
+use futures01::Future;
+use gotham::handler::HandlerFuture;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::middleware::Middleware;
+use gotham::state::{FromState, State};
+use gotham_derive::NewMiddleware;
+use hyper::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ORIGIN,
+    VARY,
+};
+use hyper::{Body, HeaderMap, Method, Response, StatusCode};
+
+use crate::config::CorsConfig;
+
+const ALLOWED_METHODS: &str = "GET, POST, PUT, DELETE, OPTIONS";
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type, X-Api-Key, If-None-Match, If-Match";
+const EXPOSED_HEADERS: &str = "Authorization, Content-Type, X-Api-Key, ETag";
+const MAX_AGE_SECONDS: &str = "86400";
+
+/// Adds CORS headers to responses for requests from allowed origins,
+/// so browser frontends served from elsewhere can call the API.
+#[derive(Clone, NewMiddleware)]
+pub struct CorsMiddleware {
+    config: CorsConfig,
+}
+
+impl CorsMiddleware {
+    pub fn new(config: CorsConfig) -> Self {
+        CorsMiddleware { config }
+    }
+}
+
+impl Middleware for CorsMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        let origin = HeaderMap::borrow_from(&state)
+            .get(ORIGIN)
+            .and_then(|origin| origin.to_str().ok())
+            .filter(|origin| self.config.allows(origin))
+            .and_then(|origin| HeaderValue::from_str(origin).ok());
+        let preflight = Method::borrow_from(&state) == Method::OPTIONS;
+
+        let f = chain(state).map(move |(state, mut response)| {
+            if let Some(origin) = origin {
+                let headers = response.headers_mut();
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+                headers.append(VARY, HeaderValue::from_static("Origin"));
+                headers.insert(
+                    ACCESS_CONTROL_EXPOSE_HEADERS,
+                    HeaderValue::from_static(EXPOSED_HEADERS),
+                );
+                if preflight {
+                    headers.insert(
+                        ACCESS_CONTROL_ALLOW_METHODS,
+                        HeaderValue::from_static(ALLOWED_METHODS),
+                    );
+                    headers.insert(
+                        ACCESS_CONTROL_ALLOW_HEADERS,
+                        HeaderValue::from_static(ALLOWED_HEADERS),
+                    );
+                    headers.insert(
+                        ACCESS_CONTROL_MAX_AGE,
+                        HeaderValue::from_static(MAX_AGE_SECONDS),
+                    );
+                }
+            }
+            (state, response)
+        });
+        Box::new(f)
+    }
+}
+
+/// Answers preflight OPTIONS requests. The `CorsMiddleware` adds the headers.
+pub fn preflight(state: State) -> (State, Response<Body>) {
+    let res = create_empty_response(&state, StatusCode::NO_CONTENT);
+    (state, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{self, fakes};
+    use crate::{repo, router};
+    use gotham::test::TestServer;
+    use hyper::header::{ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN};
+    use hyper::{Method, StatusCode};
+
+    #[test]
+    fn preflight_from_allowed_origin() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .build_request(Method::OPTIONS, "http://localhost/api/articles/some-slug")
+            .with_header(ORIGIN, "http://localhost:4100".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:4100"
+        );
+        assert!(res.headers().contains_key(ACCESS_CONTROL_ALLOW_METHODS));
+    }
+
+    #[test]
+    fn request_from_other_origin() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/api/tags")
+            .with_header(ORIGIN, "http://evil.example.com".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+use futures01::Future;
+use gotham::handler::HandlerFuture;
+use gotham::middleware::Middleware;
+use gotham::state::{request_id, FromState, State};
+use gotham_derive::NewMiddleware;
+use hyper::{Method, Uri};
+use log::{error, info};
+
+use crate::auth::middleware::CurrentUser;
+
+/// Logs a line for every request with its method, path, response status and latency,
+/// tagged with the request id, plus the id of the signed in user when there is one.
+#[derive(Clone, NewMiddleware)]
+pub struct RequestLogger;
+
+impl Middleware for RequestLogger {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        let started = Instant::now();
+        let method = Method::borrow_from(&state).clone();
+        let path = Uri::borrow_from(&state).path().to_string();
+
+        let f = chain(state).then(move |result| {
+            let elapsed = started.elapsed();
+            let millis = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+            match result {
+                Ok((ref state, ref response)) => info!(
+                    "[{}] {} {} {} {}ms user={}",
+                    request_id(state),
+                    method,
+                    path,
+                    response.status().as_u16(),
+                    millis,
+                    user_id(state),
+                ),
+                Err((ref state, ref e)) => error!(
+                    "[{}] {} {} failed after {}ms user={}: {:?}",
+                    request_id(state),
+                    method,
+                    path,
+                    millis,
+                    user_id(state),
+                    e,
+                ),
+            }
+            result
+        });
+        Box::new(f)
+    }
+}
+
+fn user_id(state: &State) -> String {
+    CurrentUser::try_borrow_from(state)
+        .map(|CurrentUser(claims)| claims.user_id().to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
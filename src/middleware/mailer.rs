@@ -0,0 +1,41 @@
+use gotham::handler::HandlerFuture;
+use gotham::middleware::{Middleware, NewMiddleware};
+use gotham::state::State;
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use crate::mail::{Mailer, SharedMailer};
+
+/// Puts the `Mailer` into `State` for handlers to use.
+pub struct MailerMiddleware {
+    // Mailers needn't be `RefUnwindSafe`, as Gotham requires of middleware.
+    mailer: AssertUnwindSafe<Arc<dyn Mailer>>,
+}
+
+impl MailerMiddleware {
+    pub fn new(mailer: Arc<dyn Mailer>) -> Self {
+        MailerMiddleware {
+            mailer: AssertUnwindSafe(mailer),
+        }
+    }
+}
+
+impl NewMiddleware for MailerMiddleware {
+    type Instance = MailerMiddleware;
+
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(MailerMiddleware::new(self.mailer.0.clone()))
+    }
+}
+
+impl Middleware for MailerMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        state.put(SharedMailer(self.mailer.0));
+        chain(state)
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::Instant;
+
+use futures01::Future;
+use gotham::handler::HandlerFuture;
+use gotham::middleware::Middleware;
+use gotham::state::{FromState, State};
+use gotham_derive::NewMiddleware;
+use hyper::{Method, Uri};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, HistogramVec,
+    IntCounterVec, IntGaugeVec,
+};
+
+use crate::Repo;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests, by route and response status class.",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latencies in seconds.",
+        &["method", "route"]
+    )
+    .unwrap();
+    static ref DB_POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "db_pool_connections",
+        "Database pool connections, by whether they are in use or idle.",
+        &["state"]
+    )
+    .unwrap();
+}
+
+/// Counts requests and records their latency, labelled by route.
+///
+/// Routes are labelled by their path template, e.g. `/api/articles/:slug`,
+/// rather than the requested path, to keep the number of series bounded.
+#[derive(Clone, NewMiddleware)]
+pub struct MetricsMiddleware {
+    prefixes: &'static [&'static str],
+    routes: &'static [&'static str],
+}
+
+impl MetricsMiddleware {
+    /// `routes` are the path templates served under each of `prefixes`.
+    pub fn new(prefixes: &'static [&'static str], routes: &'static [&'static str]) -> Self {
+        MetricsMiddleware { prefixes, routes }
+    }
+
+    fn route_label(&self, path: &str) -> String {
+        for prefix in self.prefixes {
+            if path.starts_with(prefix) {
+                let rest = &path[prefix.len()..];
+                if let Some(route) = self.routes.iter().find(|route| matches(route, rest)) {
+                    return format!("{}{}", prefix, route);
+                }
+            }
+        }
+        path.to_string()
+    }
+}
+
+impl Middleware for MetricsMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        let started = Instant::now();
+        let method = Method::borrow_from(&state).to_string();
+        let route = self.route_label(Uri::borrow_from(&state).path());
+
+        let f = chain(state).then(move |result| {
+            let elapsed = started.elapsed();
+            let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            let status = match result {
+                Ok((_, ref response)) => status_class(response.status().as_u16()),
+                Err(_) => "5xx",
+            };
+            HTTP_REQUESTS
+                .with_label_values(&[&method, &route, status])
+                .inc();
+            HTTP_REQUEST_DURATION
+                .with_label_values(&[&method, &route])
+                .observe(seconds);
+            result
+        });
+        Box::new(f)
+    }
+}
+
+/// Update the pool gauges, just before metrics are gathered.
+pub fn record_pool_state(repo: &Repo) {
+    let pool = repo.pool_state();
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(i64::from(pool.in_use()));
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(i64::from(pool.idle_connections));
+}
+
+/// Whether a path matches a route template, where `:name` segments match anything.
+fn matches(route: &str, path: &str) -> bool {
+    let route_segments: Vec<&str> = route.split('/').collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+    route_segments.len() == path_segments.len()
+        && route_segments
+            .iter()
+            .zip(path_segments.iter())
+            .all(|(r, p)| r.starts_with(':') || r == p)
+}
+
+fn status_class(status: u16) -> &'static str {
+    match status {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTES: &[&str] = &["/articles", "/articles/feed", "/articles/:slug"];
+
+    #[test]
+    fn test_route_label() {
+        let middleware = MetricsMiddleware::new(&["/api", "/api/v1"], ROUTES);
+        assert_eq!(middleware.route_label("/api/articles"), "/api/articles");
+        assert_eq!(
+            middleware.route_label("/api/articles/feed"),
+            "/api/articles/feed"
+        );
+        assert_eq!(
+            middleware.route_label("/api/articles/how-to-train-your-dragon"),
+            "/api/articles/:slug"
+        );
+        assert_eq!(
+            middleware.route_label("/api/v1/articles/how-to-train-your-dragon"),
+            "/api/v1/articles/:slug"
+        );
+        assert_eq!(middleware.route_label("/healthz"), "/healthz");
+    }
+
+    #[test]
+    fn test_status_class() {
+        assert_eq!(status_class(204), "2xx");
+        assert_eq!(status_class(404), "4xx");
+        assert_eq!(status_class(503), "5xx");
+    }
+}
@@ -0,0 +1,10 @@
+pub mod api_version;
+pub mod cache;
+pub mod compression;
+pub mod cors;
+pub mod logging;
+pub mod mailer;
+pub mod metrics;
+pub mod repositories;
+pub mod request_id;
+pub mod storage;
@@ -0,0 +1,40 @@
+use gotham::handler::HandlerFuture;
+use gotham::middleware::{Middleware, NewMiddleware};
+use gotham::state::State;
+use std::io;
+use std::panic::AssertUnwindSafe;
+
+use crate::conduit::Repositories;
+
+/// Puts the `Repositories` into `State` for handlers to use.
+pub struct RepositoriesMiddleware {
+    // Connection pools aren't `RefUnwindSafe`, as Gotham requires of middleware.
+    repositories: AssertUnwindSafe<Repositories>,
+}
+
+impl RepositoriesMiddleware {
+    pub fn new(repositories: Repositories) -> Self {
+        RepositoriesMiddleware {
+            repositories: AssertUnwindSafe(repositories),
+        }
+    }
+}
+
+impl NewMiddleware for RepositoriesMiddleware {
+    type Instance = RepositoriesMiddleware;
+
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(RepositoriesMiddleware::new(self.repositories.clone()))
+    }
+}
+
+impl Middleware for RepositoriesMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        state.put(self.repositories.0);
+        chain(state)
+    }
+}
@@ -0,0 +1,69 @@
+use futures01::Future;
+use gotham::handler::HandlerFuture;
+use gotham::middleware::Middleware;
+use gotham::state::{request_id, State};
+use gotham_derive::NewMiddleware;
+use hyper::header::HeaderValue;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Echoes the request id back in an `X-Request-Id` response header,
+/// so failures can be correlated with the server logs.
+///
+/// Gotham assigns every request an id before routing, taken from the
+/// `X-Request-Id` request header when a caller sends one or a new UUID otherwise,
+/// and keeps it in `State` where `gotham::state::request_id` finds it.
+#[derive(Clone, NewMiddleware)]
+pub struct RequestIdMiddleware;
+
+impl Middleware for RequestIdMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        let f = chain(state).map(|(state, mut response)| {
+            if let Ok(id) = HeaderValue::from_str(request_id(&state)) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, id);
+            }
+            (state, response)
+        });
+        Box::new(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers;
+    use crate::{repo, router};
+    use gotham::test::TestServer;
+    use hyper::StatusCode;
+
+    #[test]
+    fn generates_request_id() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/api/tags")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers()[REQUEST_ID_HEADER].is_empty());
+    }
+
+    #[test]
+    fn echoes_request_id() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/api/articles/no-such-article")
+            .with_header(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"))
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[REQUEST_ID_HEADER], "abc-123");
+        let body = res.read_utf8_body().unwrap();
+        assert!(body.contains(r#""requestId":"abc-123""#));
+    }
+}
@@ -0,0 +1,41 @@
+use gotham::handler::HandlerFuture;
+use gotham::middleware::{Middleware, NewMiddleware};
+use gotham::state::State;
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use crate::storage::{SharedStorage, Storage};
+
+/// Puts the `Storage` into `State` for handlers to use.
+pub struct StorageMiddleware {
+    // Storage backends needn't be `RefUnwindSafe`, as Gotham requires of middleware.
+    storage: AssertUnwindSafe<Arc<dyn Storage>>,
+}
+
+impl StorageMiddleware {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        StorageMiddleware {
+            storage: AssertUnwindSafe(storage),
+        }
+    }
+}
+
+impl NewMiddleware for StorageMiddleware {
+    type Instance = StorageMiddleware;
+
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(StorageMiddleware::new(self.storage.0.clone()))
+    }
+}
+
+impl Middleware for StorageMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        state.put(SharedStorage(self.storage.0));
+        chain(state)
+    }
+}
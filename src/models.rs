@@ -1,5 +1,25 @@
+use crate::schema::api_keys;
+use crate::schema::article_revisions;
+use crate::schema::article_tags;
+use crate::schema::article_views;
 use crate::schema::articles;
+use crate::schema::comment_revisions;
+use crate::schema::comments;
+use crate::schema::email_verifications;
+use crate::schema::favorites;
+use crate::schema::followed_tags;
+use crate::schema::followers;
+use crate::schema::identities;
+use crate::schema::jobs;
+use crate::schema::login_attempts;
+use crate::schema::mentions;
+use crate::schema::notifications;
+use crate::schema::password_resets;
+use crate::schema::revoked_tokens;
+use crate::schema::tags;
+use crate::schema::trending_articles;
 use crate::schema::users;
+use crate::schema::webhooks;
 use chrono::NaiveDateTime;
 use diesel::{Insertable, Queryable};
 use serde_derive::{Deserialize, Serialize};
@@ -13,16 +33,62 @@ pub struct NewUser {
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: i32,
     pub username: String,
     pub email: String,
+    /// The password hash, which must never be sent to clients.
+    #[serde(skip_serializing)]
     pub password: String,
     pub bio: Option<String>,
     pub image: Option<String>,
     pub token: Option<String>,
+    #[serde(with = "iso8601")]
     pub created_at: NaiveDateTime,
+    #[serde(with = "iso8601")]
     pub updated_at: NaiveDateTime,
+    /// Tokens issued before this are no longer accepted.
+    #[serde(skip)]
+    pub password_changed_at: Option<NaiveDateTime>,
+    /// Whether the user has followed the link in their verification email.
+    #[serde(skip)]
+    pub verified: bool,
+    /// The name of the user's `Role`.
+    #[serde(skip)]
+    pub role: String,
+    /// When an admin suspended the user, if they're suspended.
+    #[serde(skip)]
+    pub suspended_at: Option<NaiveDateTime>,
+    /// When the user deleted their account, which has been anonymized since.
+    #[serde(skip)]
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub username: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub following: bool,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "iso8601")]
+    pub updated_at: NaiveDateTime,
+}
+
+impl Profile {
+    pub fn from_user(user: User, following: bool) -> Self {
+        Profile {
+            username: user.username,
+            bio: user.bio,
+            image: user.image,
+            following,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, AsChangeset, Default, Clone)]
@@ -33,9 +99,12 @@ pub struct UpdateUser {
     pub password: Option<String>,
     pub image: Option<String>,
     pub bio: Option<String>,
+    #[serde(skip_deserializing)]
+    pub password_changed_at: Option<NaiveDateTime>,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Article {
     pub id: i32,
     pub title: String,
@@ -43,8 +112,60 @@ pub struct Article {
     pub description: String,
     pub body: String,
     pub user_id: i32,
+    #[serde(with = "iso8601")]
     pub created_at: NaiveDateTime,
+    #[serde(with = "iso8601")]
     pub updated_at: NaiveDateTime,
+    /// Bumped on every edit, to detect edits based on an outdated copy.
+    #[serde(skip)]
+    pub version: i32,
+    /// How many users favorited it, sent as `favoritesCount` along with whether the reader
+    /// favorited it.
+    #[serde(skip)]
+    pub favorites_count: i32,
+    /// The name of the article's `Status`.
+    pub status: String,
+    /// When the scheduler is to publish it, if it's a draft that's been scheduled.
+    #[serde(
+        serialize_with = "iso8601::serialize_option",
+        deserialize_with = "iso8601::deserialize_option"
+    )]
+    pub publish_at: Option<NaiveDateTime>,
+    /// The name of the article's `Visibility`.
+    pub visibility: String,
+    /// How many words the body has.
+    pub word_count: i32,
+    /// How many minutes the body takes to read.
+    pub reading_time: i32,
+    /// The URL of an image to show with the article.
+    pub cover_image: Option<String>,
+    /// Where the article was first published, if it's cross-posted.
+    pub canonical_url: Option<String>,
+}
+
+/// What an article was at `version`, before an edit replaced it at `created_at`.
+#[derive(Queryable, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleRevision {
+    pub id: i32,
+    #[serde(skip)]
+    pub article_id: i32,
+    pub version: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "article_revisions"]
+pub struct NewArticleRevision {
+    pub article_id: i32,
+    pub version: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
 }
 
 #[derive(Insertable, Deserialize, Debug, Clone)]
@@ -55,4 +176,392 @@ pub struct NewArticle {
     pub description: String,
     pub body: String,
     pub user_id: i32,
+    pub status: String,
+    pub publish_at: Option<NaiveDateTime>,
+    pub visibility: String,
+    pub cover_image: Option<String>,
+    pub canonical_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, AsChangeset, Default, Clone)]
+#[table_name = "articles"]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateArticle {
+    pub title: Option<String>,
+    #[serde(skip_deserializing)]
+    pub slug: Option<String>,
+    pub description: Option<String>,
+    pub body: Option<String>,
+    /// Only drafts can be scheduled.
+    #[serde(default, deserialize_with = "iso8601::deserialize_option")]
+    pub publish_at: Option<NaiveDateTime>,
+    /// The name of a `Visibility`, checked when the request is validated.
+    pub visibility: Option<String>,
+    /// Worked out from the body when it changes.
+    #[serde(skip_deserializing)]
+    pub word_count: Option<i32>,
+    #[serde(skip_deserializing)]
+    pub reading_time: Option<i32>,
+    pub cover_image: Option<String>,
+    pub canonical_url: Option<String>,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub id: i32,
+    pub body: String,
+    pub article_id: i32,
+    pub user_id: i32,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "iso8601")]
+    pub updated_at: NaiveDateTime,
+    /// The comment this replies to, on the same article, if it's a reply.
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "comments"]
+pub struct NewComment {
+    pub body: String,
+    pub article_id: i32,
+    pub user_id: i32,
+    pub parent_id: Option<i32>,
+}
+
+/// What a comment said before an edit replaced it, at `created_at`.
+#[derive(Queryable, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentRevision {
+    pub id: i32,
+    pub comment_id: i32,
+    pub body: String,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "comment_revisions"]
+pub struct NewCommentRevision {
+    pub comment_id: i32,
+    pub body: String,
+}
+
+/// A view of an article, by one viewer in the hour it was in.
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "article_views"]
+pub struct NewArticleView {
+    pub article_id: i32,
+    pub viewer: String,
+    pub viewed_hour: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "favorites"]
+pub struct NewFavorite {
+    pub user_id: i32,
+    pub article_id: i32,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "followed_tags"]
+pub struct NewFollowedTag {
+    pub user_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "followers"]
+pub struct NewFollower {
+    pub follower_id: i32,
+    pub followed_id: i32,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "tags"]
+pub struct NewTag {
+    pub tag: String,
+}
+
+/// How much an article is trending, as of when it was computed.
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "trending_articles"]
+pub struct NewTrendingArticle {
+    pub article_id: i32,
+    pub score: f64,
+    pub computed_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "article_tags"]
+pub struct NewArticleTag {
+    pub article_id: i32,
+    pub tag_id: i32,
+}
+
+/// A key for scripts and bots to authenticate with. The key itself is only shown once,
+/// when it's created; after that only its prefix is known.
+#[derive(Queryable, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub name: String,
+    /// The start of the key, so users can tell their keys apart.
+    pub prefix: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+    #[serde(serialize_with = "iso8601::serialize_option")]
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "api_keys"]
+pub struct NewApiKey {
+    pub user_id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "email_verifications"]
+pub struct NewEmailVerification {
+    pub token: String,
+    pub user_id: i32,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "identities"]
+pub struct NewIdentity {
+    pub user_id: i32,
+    pub provider: String,
+    pub subject: String,
+}
+
+/// A user as described by an OAuth provider.
+#[derive(Debug, Clone)]
+pub struct ExternalProfile {
+    pub provider: String,
+    /// The provider's id for the user.
+    pub subject: String,
+    /// A suggested username, valid but not necessarily available.
+    pub username: String,
+    /// The user's email, if the provider has verified it.
+    pub email: Option<String>,
+}
+
+/// A job in the queue, with its arguments as JSON. See `jobs::Job`.
+#[derive(Queryable, Debug, Clone)]
+pub struct QueuedJob {
+    pub id: i32,
+    pub kind: String,
+    pub payload: String,
+    /// How many times it has been started.
+    pub attempts: i32,
+    /// When it's next due. A worker running it pushes this back, so it isn't run twice.
+    pub run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    /// When it was given up on. It's kept, but not run again.
+    pub failed_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "jobs"]
+pub struct NewJob {
+    pub kind: String,
+    pub payload: String,
+    pub run_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "login_attempts"]
+pub struct NewLoginAttempt {
+    pub login: String,
+    pub ip: Option<String>,
+    pub attempted_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "mentions"]
+pub struct NewMention {
+    pub comment_id: i32,
+    pub user_id: i32,
+}
+
+/// Something another user did that concerns `user_id`. See `conduit::notifications`.
+/// The actor and article are sent as a profile and a link, so their ids are left out.
+#[derive(Queryable, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    /// `follow`, `comment`, `favorite` or `mention`.
+    pub kind: String,
+    /// Who followed, commented, favorited or mentioned.
+    #[serde(skip)]
+    pub actor_id: i32,
+    #[serde(skip)]
+    pub article_id: Option<i32>,
+    pub comment_id: Option<i32>,
+    #[serde(serialize_with = "iso8601::serialize_option")]
+    pub read_at: Option<NaiveDateTime>,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "notifications"]
+pub struct NewNotification {
+    pub user_id: i32,
+    pub kind: String,
+    pub actor_id: i32,
+    pub article_id: Option<i32>,
+    pub comment_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "password_resets"]
+pub struct NewPasswordReset {
+    pub token: String,
+    pub user_id: i32,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "revoked_tokens"]
+pub struct NewRevokedToken {
+    pub jti: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// A URL sent the events it subscribes to. Its secret signs the payloads, so it's only shown
+/// when the webhook is created.
+#[derive(Queryable, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: String,
+    /// The names of the events it's sent, comma separated. See `events::Event`.
+    #[serde(serialize_with = "comma_separated")]
+    pub events: String,
+    #[serde(with = "iso8601")]
+    pub created_at: NaiveDateTime,
+}
+
+impl Webhook {
+    pub fn subscribes_to(&self, event_name: &str) -> bool {
+        self.events.split(',').any(|name| name == event_name)
+    }
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "webhooks"]
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: String,
+    pub events: String,
+}
+
+/// Serialize a comma separated list as an array.
+fn comma_separated<S>(list: &str, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(list.split(',').filter(|item| !item.is_empty()))
+}
+
+/// Timestamps are stored in UTC without a time zone.
+/// The API sends them in ISO 8601 with millisecond precision, e.g. `2016-02-18T03:22:56.637Z`.
+pub(crate) mod iso8601 {
+    use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::Serializer;
+
+    pub fn serialize<S>(timestamp: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let timestamp = DateTime::<Utc>::from_utc(*timestamp, Utc);
+        serializer.serialize_str(&timestamp.to_rfc3339_opts(SecondsFormat::Millis, true))
+    }
+
+    /// Like `serialize`, with `null` for `None`.
+    pub fn serialize_option<S>(
+        timestamp: &Option<NaiveDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match timestamp {
+            Some(timestamp) => serialize(timestamp, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&s)
+            .map(|timestamp| timestamp.naive_utc())
+            .map_err(de::Error::custom)
+    }
+
+    /// Like `deserialize`, with `None` for `null`.
+    pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => DateTime::parse_from_rfc3339(&s)
+                .map(|timestamp| Some(timestamp.naive_utc()))
+                .map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    #[test]
+    fn test_comment_json() {
+        let created_at = NaiveDate::from_ymd(2016, 2, 18).and_hms_milli(3, 22, 56, 637);
+        let comment = Comment {
+            id: 1,
+            body: "It takes a Jacobian".to_string(),
+            article_id: 2,
+            user_id: 3,
+            created_at,
+            updated_at: created_at,
+            parent_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&comment).unwrap(),
+            json!({
+                "id": 1,
+                "body": "It takes a Jacobian",
+                "articleId": 2,
+                "userId": 3,
+                "createdAt": "2016-02-18T03:22:56.637Z",
+                "updatedAt": "2016-02-18T03:22:56.637Z",
+                "parentId": null,
+            })
+        );
+    }
 }
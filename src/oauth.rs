@@ -0,0 +1,329 @@
+use futures::compat::Future01CompatExt;
+use futures01::Stream as Stream01;
+use hyper::client::HttpConnector;
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use jsonwebtoken::{decode, encode, Header, Validation};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use url::form_urlencoded;
+
+use crate::auth::seconds_from_now;
+use crate::config::{JwtConfig, OAuthClient, OAuthConfig};
+use crate::models::ExternalProfile;
+use crate::web::validation::MAX_USERNAME_LENGTH;
+
+/// How long a user has to get through the provider's consent page.
+const STATE_TTL_SECONDS: u64 = 600;
+
+lazy_static! {
+    static ref CLIENT: Client<HttpsConnector<HttpConnector>> =
+        Client::builder().build(HttpsConnector::new(4).expect("TLS is unavailable"));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Provider {
+    GitHub,
+    Google,
+}
+
+impl Provider {
+    /// The provider named in a route, e.g. `github`.
+    pub fn from_name(name: &str) -> Option<Provider> {
+        match name {
+            "github" => Some(Provider::GitHub),
+            "google" => Some(Provider::Google),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::GitHub => "github",
+            Provider::Google => "google",
+        }
+    }
+
+    /// The app's credentials with the provider, if signing in with it is turned on.
+    pub fn client(self, config: &OAuthConfig) -> Option<&OAuthClient> {
+        match self {
+            Provider::GitHub => config.github.as_ref(),
+            Provider::Google => config.google.as_ref(),
+        }
+    }
+
+    fn authorize_endpoint(self) -> &'static str {
+        match self {
+            Provider::GitHub => "https://github.com/login/oauth/authorize",
+            Provider::Google => "https://accounts.google.com/o/oauth2/v2/auth",
+        }
+    }
+
+    fn token_endpoint(self) -> &'static str {
+        match self {
+            Provider::GitHub => "https://github.com/login/oauth/access_token",
+            Provider::Google => "https://oauth2.googleapis.com/token",
+        }
+    }
+
+    fn scope(self) -> &'static str {
+        match self {
+            Provider::GitHub => "read:user user:email",
+            Provider::Google => "openid email profile",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OAuthError {
+    /// The provider turned down the code or token, e.g. because it was used already.
+    Rejected(String),
+    /// The provider couldn't be reached or answered with something unexpected.
+    Unavailable(String),
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OAuthError::Rejected(e) => write!(f, "OAuth provider rejected the request: {}", e),
+            OAuthError::Unavailable(e) => write!(f, "OAuth provider unavailable: {}", e),
+        }
+    }
+}
+
+fn unavailable<E: fmt::Display>(e: E) -> OAuthError {
+    OAuthError::Unavailable(e.to_string())
+}
+
+/// Where to send the user to sign in with the provider and grant access to their profile.
+pub fn authorize_url(
+    provider: Provider,
+    client: &OAuthClient,
+    redirect_uri: &str,
+    state: &str,
+) -> String {
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("client_id", &client.client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("response_type", "code")
+        .append_pair("scope", provider.scope())
+        .append_pair("state", state)
+        .finish();
+    format!("{}?{}", provider.authorize_endpoint(), query)
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateClaims {
+    provider: String,
+    exp: u64,
+}
+
+/// The `state` passed through the provider, signed so callbacks can't be forged.
+pub fn encode_state(config: &JwtConfig, provider: Provider) -> String {
+    let claims = StateClaims {
+        provider: provider.name().to_string(),
+        exp: seconds_from_now(STATE_TTL_SECONDS),
+    };
+    encode(
+        &Header::new(config.algorithm),
+        &claims,
+        config.secret.as_ref(),
+    )
+    .unwrap()
+}
+
+/// Whether `state` came from `encode_state` for this provider, and hasn't expired.
+pub fn is_valid_state(config: &JwtConfig, provider: Provider, state: &str) -> bool {
+    decode::<StateClaims>(
+        state,
+        config.secret.as_ref(),
+        &Validation::new(config.algorithm),
+    )
+    .map(|data| data.claims.provider == provider.name())
+    .unwrap_or(false)
+}
+
+/// Exchange the code the provider sent the user back with for their profile.
+pub async fn fetch_profile(
+    provider: Provider,
+    client: &OAuthClient,
+    redirect_uri: &str,
+    code: &str,
+) -> Result<ExternalProfile, OAuthError> {
+    let token = exchange_code(provider, client, redirect_uri, code).await?;
+    match provider {
+        Provider::GitHub => github_profile(&token).await,
+        Provider::Google => google_profile(&token).await,
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+}
+
+async fn exchange_code(
+    provider: Provider,
+    client: &OAuthClient,
+    redirect_uri: &str,
+    code: &str,
+) -> Result<String, OAuthError> {
+    let form = form_urlencoded::Serializer::new(String::new())
+        .append_pair("client_id", &client.client_id)
+        .append_pair("client_secret", &client.client_secret)
+        .append_pair("code", code)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("grant_type", "authorization_code")
+        .finish();
+    let request = Request::post(provider.token_endpoint())
+        .header(ACCEPT, "application/json")
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .map_err(unavailable)?;
+    // GitHub reports a bad code with a 200 and an `error`.
+    let response: TokenResponse = send(request).await?;
+    match response.access_token {
+        Some(token) => Ok(token),
+        None => Err(OAuthError::Rejected(
+            response
+                .error
+                .unwrap_or_else(|| "no access token".to_string()),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    id: i64,
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+async fn github_profile(token: &str) -> Result<ExternalProfile, OAuthError> {
+    let user: GitHubUser = send(api_request("https://api.github.com/user", token)?).await?;
+    // The email on the profile is only the public one, and may not be verified.
+    let emails: Vec<GitHubEmail> =
+        send(api_request("https://api.github.com/user/emails", token)?).await?;
+    let email = emails
+        .into_iter()
+        .find(|email| email.primary && email.verified)
+        .map(|email| email.email);
+    Ok(ExternalProfile {
+        provider: Provider::GitHub.name().to_string(),
+        subject: user.id.to_string(),
+        username: username_from(&user.login),
+        email,
+    })
+}
+
+#[derive(Deserialize)]
+struct GoogleUser {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    name: Option<String>,
+}
+
+async fn google_profile(token: &str) -> Result<ExternalProfile, OAuthError> {
+    let url = "https://openidconnect.googleapis.com/v1/userinfo";
+    let user: GoogleUser = send(api_request(url, token)?).await?;
+    let email = if user.email_verified {
+        user.email
+    } else {
+        None
+    };
+    let name = match (&user.name, &email) {
+        (Some(name), _) => name.clone(),
+        (None, Some(email)) => email.split('@').next().unwrap_or_default().to_string(),
+        (None, None) => String::new(),
+    };
+    Ok(ExternalProfile {
+        provider: Provider::Google.name().to_string(),
+        subject: user.sub,
+        username: username_from(&name),
+        email,
+    })
+}
+
+fn api_request(url: &str, token: &str) -> Result<Request<Body>, OAuthError> {
+    Request::get(url)
+        .header(ACCEPT, "application/json")
+        .header(AUTHORIZATION, format!("Bearer {}", token))
+        // GitHub refuses requests without one.
+        .header(USER_AGENT, "realworld-gotham")
+        .body(Body::empty())
+        .map_err(unavailable)
+}
+
+async fn send<T: DeserializeOwned>(request: Request<Body>) -> Result<T, OAuthError> {
+    let response = CLIENT
+        .request(request)
+        .compat()
+        .await
+        .map_err(unavailable)?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .concat2()
+        .compat()
+        .await
+        .map_err(unavailable)?;
+    if status.is_client_error() {
+        return Err(OAuthError::Rejected(status.to_string()));
+    }
+    if !status.is_success() {
+        return Err(OAuthError::Unavailable(status.to_string()));
+    }
+    serde_json::from_slice(&body).map_err(unavailable)
+}
+
+/// Turn a provider's name for the user into a valid username, leaving room for a suffix
+/// in case it's taken.
+fn username_from(name: &str) -> String {
+    let mut username = name
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("_")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-' || *c == '.')
+        .take(MAX_USERNAME_LENGTH - 10)
+        .collect::<String>();
+    if username.is_empty() {
+        username = "user".to_string();
+    }
+    username
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers;
+
+    #[test]
+    fn test_state() {
+        let config = test_helpers::config().jwt;
+        let state = encode_state(&config, Provider::GitHub);
+        assert!(is_valid_state(&config, Provider::GitHub, &state));
+        assert!(!is_valid_state(&config, Provider::Google, &state));
+        assert!(!is_valid_state(&config, Provider::GitHub, "forged"));
+    }
+
+    #[test]
+    fn test_username_from() {
+        assert_eq!(username_from("octocat"), "octocat");
+        assert_eq!(username_from("Jane Q. Public"), "Jane_Q._Public");
+        assert_eq!(username_from("Zoë"), "Zo");
+        assert_eq!(username_from("李"), "user");
+    }
+}
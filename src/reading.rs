@@ -0,0 +1,33 @@
+/// Words read per minute, a typical rate for reading on a screen.
+const WORDS_PER_MINUTE: i32 = 200;
+
+/// How many words `body` has. Markdown symbols standing on their own, like a `-` starting a
+/// list item, count as words too, which makes little difference to the reading time.
+pub fn word_count(body: &str) -> i32 {
+    body.split_whitespace().count() as i32
+}
+
+/// How many minutes it takes to read `word_count` words, rounded up so that anything with
+/// words in it takes at least a minute.
+pub fn reading_time(word_count: i32) -> i32 {
+    (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_count() {
+        assert_eq!(word_count("How to  train\nyour dragon"), 5);
+        assert_eq!(word_count("   "), 0);
+    }
+
+    #[test]
+    fn test_reading_time() {
+        assert_eq!(reading_time(0), 0);
+        assert_eq!(reading_time(1), 1);
+        assert_eq!(reading_time(200), 1);
+        assert_eq!(reading_time(201), 2);
+    }
+}
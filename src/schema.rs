@@ -1,3 +1,42 @@
+table! {
+    api_keys (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        prefix -> Varchar,
+        key_hash -> Varchar,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    article_revisions (id) {
+        id -> Int4,
+        article_id -> Int4,
+        version -> Int4,
+        title -> Varchar,
+        description -> Varchar,
+        body -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    article_views (article_id, viewer, viewed_hour) {
+        article_id -> Int4,
+        viewer -> Varchar,
+        viewed_hour -> Timestamp,
+    }
+}
+
+table! {
+    article_tags (article_id, tag_id) {
+        article_id -> Int4,
+        tag_id -> Int4,
+    }
+}
+
 table! {
     articles (id) {
         id -> Int4,
@@ -8,6 +47,150 @@ table! {
         user_id -> Int4,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        version -> Int4,
+        favorites_count -> Int4,
+        status -> Varchar,
+        publish_at -> Nullable<Timestamp>,
+        visibility -> Varchar,
+        word_count -> Int4,
+        reading_time -> Int4,
+        cover_image -> Nullable<Varchar>,
+        canonical_url -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    comment_revisions (id) {
+        id -> Int4,
+        comment_id -> Int4,
+        body -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    comments (id) {
+        id -> Int4,
+        body -> Text,
+        article_id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        parent_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    email_verifications (token) {
+        token -> Varchar,
+        user_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    favorites (user_id, article_id) {
+        user_id -> Int4,
+        article_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    followed_tags (user_id, tag_id) {
+        user_id -> Int4,
+        tag_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    followers (follower_id, followed_id) {
+        follower_id -> Int4,
+        followed_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    identities (id) {
+        id -> Int4,
+        user_id -> Int4,
+        provider -> Varchar,
+        subject -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    jobs (id) {
+        id -> Int4,
+        kind -> Varchar,
+        payload -> Text,
+        attempts -> Int4,
+        run_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        failed_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    login_attempts (id) {
+        id -> Int4,
+        login -> Varchar,
+        ip -> Nullable<Varchar>,
+        attempted_at -> Timestamp,
+    }
+}
+
+table! {
+    mentions (comment_id, user_id) {
+        comment_id -> Int4,
+        user_id -> Int4,
+    }
+}
+
+table! {
+    notifications (id) {
+        id -> Int4,
+        user_id -> Int4,
+        kind -> Varchar,
+        actor_id -> Int4,
+        article_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        read_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    password_resets (token) {
+        token -> Varchar,
+        user_id -> Int4,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    revoked_tokens (jti) {
+        jti -> Varchar,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Int4,
+        tag -> Varchar,
+    }
+}
+
+table! {
+    trending_articles (article_id) {
+        article_id -> Int4,
+        score -> Float8,
+        computed_at -> Timestamp,
     }
 }
 
@@ -22,12 +205,67 @@ table! {
         token -> Nullable<Varchar>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        password_changed_at -> Nullable<Timestamp>,
+        verified -> Bool,
+        role -> Varchar,
+        suspended_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Int4,
+        url -> Varchar,
+        secret -> Varchar,
+        events -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
+joinable!(api_keys -> users (user_id));
+joinable!(article_revisions -> articles (article_id));
+joinable!(article_tags -> articles (article_id));
+joinable!(article_views -> articles (article_id));
+joinable!(article_tags -> tags (tag_id));
 joinable!(articles -> users (user_id));
+joinable!(comment_revisions -> comments (comment_id));
+joinable!(comments -> articles (article_id));
+joinable!(comments -> users (user_id));
+joinable!(email_verifications -> users (user_id));
+joinable!(favorites -> articles (article_id));
+joinable!(favorites -> users (user_id));
+joinable!(followed_tags -> tags (tag_id));
+joinable!(followed_tags -> users (user_id));
+joinable!(identities -> users (user_id));
+joinable!(mentions -> comments (comment_id));
+joinable!(mentions -> users (user_id));
+joinable!(notifications -> articles (article_id));
+joinable!(notifications -> comments (comment_id));
+joinable!(password_resets -> users (user_id));
+joinable!(trending_articles -> articles (article_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_keys,
+    article_revisions,
+    article_tags,
+    article_views,
     articles,
+    comment_revisions,
+    comments,
+    email_verifications,
+    favorites,
+    followed_tags,
+    followers,
+    identities,
+    jobs,
+    login_attempts,
+    mentions,
+    notifications,
+    password_resets,
+    revoked_tokens,
+    tags,
+    trending_articles,
     users,
+    webhooks,
 );
@@ -0,0 +1,60 @@
+use std::net::ToSocketAddrs;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use futures01::{Future, Stream};
+use gotham::handler::NewHandler;
+use log::{error, info, warn};
+use tokio::runtime::Runtime;
+use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+/// Serve requests until the process receives SIGTERM or SIGINT.
+///
+/// On a signal the listener is closed so no new connections are accepted,
+/// then requests already in flight, and any queries they're waiting on, are given
+/// up to `shutdown_timeout` to finish before returning.
+pub fn serve<NH, A>(addr: A, new_handler: NH, shutdown_timeout: Duration)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let mut runtime = Runtime::new().expect("Failed to create the runtime");
+    // Whichever finishes first, dropping the server future closes the listener.
+    let server = gotham::init_server(addr, new_handler)
+        .select2(shutdown_signal())
+        .map(|_| ())
+        .map_err(|_| ());
+    if runtime.block_on(server).is_err() {
+        error!("Server failed");
+    }
+
+    info!(
+        "Shutting down, waiting up to {}s for requests in flight",
+        shutdown_timeout.as_secs()
+    );
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = runtime.shutdown_on_idle().wait();
+        let _ = done.send(());
+    });
+    match finished.recv_timeout(shutdown_timeout) {
+        Ok(()) => info!("All requests finished"),
+        Err(_) => warn!("Shutdown timed out with requests still in flight"),
+    }
+}
+
+/// Resolves when the first SIGTERM or SIGINT arrives.
+fn shutdown_signal() -> impl Future<Item = (), Error = ()> {
+    let sigterm = Signal::new(SIGTERM).flatten_stream();
+    let sigint = Signal::new(SIGINT).flatten_stream();
+    sigterm
+        .select(sigint)
+        .into_future()
+        .map(|(signal, _)| {
+            if let Some(signal) = signal {
+                info!("Received signal {}", signal);
+            }
+        })
+        .map_err(|(e, _)| error!("Failed to listen for signals: {}", e))
+}
@@ -0,0 +1,130 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+/// Leaves room for a suffix within the 255 characters of the slug column.
+const MAX_LENGTH: usize = 200;
+const SUFFIX_LENGTH: usize = 6;
+
+/// Derive a URL-safe slug from an article title.
+/// Anything that isn't an ascii letter or digit is treated as a word separator,
+/// and a title without any usable characters gets a random slug.
+pub fn slugify(title: &str) -> String {
+    let mut slug = title
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    slug.truncate(MAX_LENGTH);
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        random_suffix()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Make a slug distinct from `slug` by appending a random suffix, used when a slug is already taken.
+pub fn with_suffix(slug: &str) -> String {
+    format!("{}-{}", slug, random_suffix())
+}
+
+fn random_suffix() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SUFFIX_LENGTH)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(
+            slugify("How to train your dragon"),
+            "how-to-train-your-dragon"
+        );
+        assert_eq!(slugify("  What's new?! "), "what-s-new");
+    }
+
+    #[test]
+    fn test_slugify_without_usable_characters() {
+        let slug = slugify("?!");
+        assert_eq!(slug.len(), SUFFIX_LENGTH);
+        assert!(slug.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_slugify_long_title() {
+        let title = "word ".repeat(100);
+        let slug = slugify(&title);
+        assert!(slug.len() <= MAX_LENGTH);
+        assert!(!slug.ends_with('-'));
+    }
+
+    #[test]
+    fn test_with_suffix() {
+        let slug = with_suffix("dragons");
+        assert!(slug.starts_with("dragons-"));
+        assert_eq!(slug.len(), "dragons-".len() + SUFFIX_LENGTH);
+        assert_ne!(with_suffix("dragons"), with_suffix("dragons"));
+    }
+
+    fn is_url_safe(slug: &str) -> bool {
+        !slug.is_empty()
+            && slug.len() <= MAX_LENGTH
+            && !slug.starts_with('-')
+            && !slug.ends_with('-')
+            && !slug.contains("--")
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    }
+
+    /// Titles made of ascii words, separated by punctuation, whitespace and non-ascii letters.
+    fn titles() -> impl Strategy<Value = (Vec<String>, String)> {
+        let separator =
+            prop::sample::select(vec![" ", "  ", "-", "_", "?! ", "\t", "ü", "日本", " — "]);
+        prop::collection::vec(("[a-zA-Z0-9]{1,12}", separator), 1..10).prop_map(|parts| {
+            let words: Vec<String> = parts.iter().map(|(word, _)| word.to_lowercase()).collect();
+            let title: String = parts
+                .iter()
+                .map(|(word, separator)| format!("{}{}", word, separator))
+                .collect();
+            (words, title)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn slugs_are_url_safe(title in "\\PC*") {
+            prop_assert!(is_url_safe(&slugify(&title)), "{:?}", slugify(&title));
+        }
+
+        #[test]
+        fn slugs_keep_the_words_of_the_title((words, title) in titles()) {
+            prop_assert_eq!(slugify(&title), words.join("-"));
+        }
+
+        #[test]
+        fn titles_with_different_words_get_different_slugs(
+            (words, title) in titles(),
+            (other_words, other_title) in titles()
+        ) {
+            prop_assert_eq!(slugify(&title) == slugify(&other_title), words == other_words);
+        }
+
+        #[test]
+        fn suffixed_slugs_are_url_safe_and_distinct(title in "\\PC*") {
+            let slug = slugify(&title);
+            let suffixed = with_suffix(&slug);
+            prop_assert!(suffixed.starts_with(&format!("{}-", slug)));
+            prop_assert!(suffixed.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+            prop_assert_ne!(suffixed, slug);
+        }
+    }
+}
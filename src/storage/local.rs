@@ -0,0 +1,50 @@
+use futures::compat::Future01CompatExt;
+use futures::future::{BoxFuture, FutureExt};
+use std::path::PathBuf;
+
+use crate::storage::{Storage, StorageError};
+
+/// Files on the app's own disk. They're only shared between instances of the app if the
+/// directory is.
+pub struct LocalStorage {
+    directory: PathBuf,
+    /// Where the directory is served, see `UPLOADS_PATH`.
+    base_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(directory: PathBuf, base_url: String) -> Self {
+        LocalStorage {
+            directory,
+            base_url,
+        }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        data: Vec<u8>,
+    ) -> BoxFuture<'static, Result<String, StorageError>> {
+        let path = self.directory.join(key);
+        let url = format!("{}/{}", self.base_url, key);
+        write(path, data).map(|result| result.map(|()| url)).boxed()
+    }
+}
+
+/// Write the file, making the directories it goes in first.
+async fn write(path: PathBuf, data: Vec<u8>) -> Result<(), StorageError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent.to_path_buf())
+            .compat()
+            .await
+            .map_err(|e| StorageError(e.to_string()))?;
+    }
+    tokio::fs::write(path, data)
+        .compat()
+        .await
+        .map(|_| ())
+        .map_err(|e| StorageError(e.to_string()))
+}
@@ -0,0 +1,52 @@
+pub mod local;
+pub mod s3;
+
+use futures::future::BoxFuture;
+use gotham_derive::StateData;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::config::{StorageBackend, StorageConfig};
+use crate::storage::local::LocalStorage;
+use crate::storage::s3::S3Storage;
+
+/// Where the app serves files kept by `LocalStorage`.
+pub const UPLOADS_PATH: &str = "/uploads";
+
+#[derive(Debug)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to store file: {}", self.0)
+    }
+}
+
+impl Error for StorageError {}
+
+/// Keeps uploaded files, e.g. avatars, somewhere they can be read from by anyone.
+pub trait Storage: Send + Sync {
+    /// Store `data` under `key`, a relative path, and return the URL it can be read from.
+    fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> BoxFuture<'static, Result<String, StorageError>>;
+}
+
+/// The `Storage` handlers use, put into `State` by the `StorageMiddleware`.
+#[derive(Clone, StateData)]
+pub struct SharedStorage(pub Arc<dyn Storage>);
+
+/// The storage for the configured backend. Local files get URLs under `public_url`.
+pub fn from_config(config: &StorageConfig, public_url: &str) -> Arc<dyn Storage> {
+    match config.backend {
+        StorageBackend::Local(ref directory) => {
+            let base_url = format!("{}{}", public_url.trim_end_matches('/'), UPLOADS_PATH);
+            Arc::new(LocalStorage::new(directory.clone(), base_url))
+        }
+        StorageBackend::S3(ref s3) => Arc::new(S3Storage::new(s3.clone())),
+    }
+}
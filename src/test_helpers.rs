@@ -1,23 +1,1003 @@
-/// Functions for generating test data
+use crate::config::{
+    CacheConfig, Config, CorsConfig, JobsConfig, JwtConfig, LoginConfig, MailConfig, MailTransport,
+    MaintenanceConfig, OAuthClient, OAuthConfig, StorageBackend, StorageConfig,
+};
+use crate::Repo;
+use futures::{FutureExt, TryFutureExt};
+use futures01::Future as Future01;
+use gotham::test::{TestResponse, TestServer};
+use jsonwebtoken::Algorithm;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::time::Duration;
+use tokio_threadpool::ThreadPool;
+
+/// Configuration for tests, independent of the environment.
+pub fn config() -> Config {
+    Config {
+        jwt: JwtConfig {
+            secret: "secret".to_string(),
+            ttl: 3600,
+            algorithm: Algorithm::HS256,
+        },
+        cors: CorsConfig {
+            allowed_origins: vec!["http://localhost:4100".to_string()],
+        },
+        login: LoginConfig {
+            max_failures: 5,
+            max_failures_per_ip: 50,
+            window: Duration::from_secs(900),
+            require_verified_email: false,
+        },
+        public_url: "http://localhost:7878".to_string(),
+        password_reset_ttl: Duration::from_secs(3600),
+        mail: MailConfig {
+            transport: MailTransport::Stdout,
+            from: "conduit@localhost".to_string(),
+        },
+        oauth: OAuthConfig {
+            github: Some(OAuthClient {
+                client_id: "github-client".to_string(),
+                client_secret: "github-secret".to_string(),
+            }),
+            google: None,
+        },
+        cache: CacheConfig {
+            redis_url: None,
+            ttl: Duration::from_secs(30),
+        },
+        storage: StorageConfig {
+            backend: StorageBackend::Local(std::env::temp_dir().join("conduit-test-uploads")),
+            max_upload_size: 64 * 1024,
+        },
+        jobs: JobsConfig {
+            poll_interval: Duration::from_millis(10),
+            max_attempts: 3,
+        },
+        maintenance: MaintenanceConfig {
+            tokens_interval: Duration::from_secs(3600),
+            deleted_users_interval: Duration::from_secs(24 * 3600),
+            deleted_users_retention: Duration::from_secs(30 * 24 * 3600),
+            scheduled_articles_interval: Duration::from_secs(60),
+            trending_interval: Duration::from_secs(600),
+        },
+        listen_address: "127.0.0.1:7878".to_string(),
+        shutdown_timeout: Duration::from_secs(1),
+        skip_migrations: true,
+        max_page_size: 100,
+        max_body_size: 64 * 1024,
+        require_json_content_type: true,
+        frontend_dir: None,
+    }
+}
+
+/// Run a future to completion. `Repo` queries block, so they need to run on a threadpool.
+/// Data generated in the future comes from the calling test's generator.
+pub fn block_on<F, T>(future: F) -> T
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let future = generate::within(generate::current(), future.boxed());
+    ThreadPool::new()
+        .spawn_handle(future.unit_error().boxed().compat())
+        .wait()
+        .expect("Future failed")
+}
+
+/// A `Repo` that never connects, for router tests that only use fake repositories.
+pub fn unconnected_repo() -> Repo {
+    Repo::unconnected("postgres://localhost/unused")
+}
+
+/// The body of a response, parsed as JSON.
+pub fn response_json(res: TestResponse) -> Value {
+    let body = res.read_body().unwrap();
+    serde_json::from_slice(&body).expect("Could not parse body.")
+}
+
+/// Register a new user through the API, returning the token they're signed in with.
+pub fn sign_up(server: &TestServer) -> String {
+    let user = generate::new_user();
+    let res = server
+        .client()
+        .post(
+            "http://localhost/api/users",
+            json!({
+                "user": {
+                    "email": user.email,
+                    "password": user.password,
+                    "username": user.username,
+                }
+            })
+            .to_string(),
+            mime::APPLICATION_JSON,
+        )
+        .perform()
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    response_json(res)["user"]["token"]
+        .as_str()
+        .expect("No token in the response")
+        .to_string()
+}
+
+/// Log in through the API with an email or username.
+pub fn login(server: &TestServer, email: &str, password: &str) -> TestResponse {
+    server
+        .client()
+        .post(
+            "http://localhost/api/users/login",
+            json!({ "user": { "email": email, "password": password } }).to_string(),
+            mime::APPLICATION_JSON,
+        )
+        .perform()
+        .unwrap()
+}
+
+/// In-memory stand-ins for the repositories, so handlers can be tested without a database.
+pub mod fakes {
+    use chrono::{NaiveDateTime, Utc};
+    use diesel::result::{DatabaseErrorKind, Error as dieselError};
+    use futures::future::{self, BoxFuture, FutureExt};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use gotham::router::Router;
+
+    use super::unconnected_repo;
+    use crate::auth::{random_token, Role};
+    use crate::cache::Cache;
+    use crate::conduit::api_keys::{generate_key, hash_key, visible_prefix, ApiKeysRepository};
+    use crate::conduit::identities::IdentitiesRepository;
+    use crate::conduit::login_attempts::LoginAttemptsRepository;
+    use crate::conduit::password_resets::PasswordResetsRepository;
+    use crate::conduit::tokens::TokensRepository;
+    use crate::conduit::users::{anonymous_username, normalize_email, ListParams, UsersRepository};
+    use crate::conduit::verifications::VerificationsRepository;
+    use crate::conduit::Repositories;
+    use crate::config::Config;
+    use crate::db::RepoError;
+    use crate::mail::{Email, MailError, Mailer, StdoutMailer};
+    use crate::models::{ApiKey, ExternalProfile, NewUser, UpdateUser, User};
+    use crate::router_with_repositories;
+
+    /// The app's router over `repositories()`, printing emails, for handler tests that run
+    /// without Postgres or migrations. Handlers that query through `Repo` directly, e.g. for
+    /// articles, get a 503 as the database can't be reached.
+    pub fn router(config: Config) -> Router {
+        router_with_repositories(
+            unconnected_repo(),
+            repositories(),
+            Arc::new(StdoutMailer),
+            config,
+        )
+    }
+
+    /// Repositories with every store held in memory.
+    pub fn repositories() -> Repositories {
+        let users = Arc::new(InMemoryUsers::default());
+        Repositories {
+            users: users.clone(),
+            tokens: Arc::new(InMemoryTokens::default()),
+            login_attempts: Arc::new(InMemoryLoginAttempts::default()),
+            verifications: Arc::new(InMemoryVerifications::new(users.clone())),
+            password_resets: Arc::new(InMemoryPasswordResets::new(users.clone())),
+            identities: Arc::new(InMemoryIdentities::new(users)),
+            api_keys: Arc::new(InMemoryApiKeys::default()),
+        }
+    }
+
+    /// Users kept in a `Vec`. Passwords are stored as given rather than hashed.
+    #[derive(Default)]
+    pub struct InMemoryUsers {
+        users: Mutex<Vec<User>>,
+    }
+
+    impl InMemoryUsers {
+        pub fn set_role(&self, user_id: i32, role: Role) {
+            let mut users = self.users.lock().unwrap();
+            if let Some(user) = users.iter_mut().find(|user| user.id == user_id) {
+                user.role = role.name().to_string();
+            }
+        }
+
+        fn find_by<P>(&self, predicate: P) -> BoxFuture<'static, Result<User, RepoError>>
+        where
+            P: Fn(&User) -> bool,
+        {
+            let users = self.users.lock().unwrap();
+            let result = users
+                .iter()
+                .find(|user| predicate(user))
+                .cloned()
+                .ok_or(RepoError::Query(dieselError::NotFound));
+            future::ready(result).boxed()
+        }
+    }
+
+    impl UsersRepository for InMemoryUsers {
+        fn insert(&self, user: NewUser) -> BoxFuture<'static, Result<User, RepoError>> {
+            let mut users = self.users.lock().unwrap();
+            let email = normalize_email(&user.email);
+            let taken = if users.iter().any(|existing| existing.email == email) {
+                Some("users.email")
+            } else if users
+                .iter()
+                .any(|existing| existing.username == user.username)
+            {
+                Some("users.username")
+            } else {
+                None
+            };
+            if let Some(column) = taken {
+                let e = dieselError::DatabaseError(
+                    DatabaseErrorKind::UniqueViolation,
+                    Box::new(format!("UNIQUE constraint failed: {}", column)),
+                );
+                return future::err(RepoError::Query(e)).boxed();
+            }
+            let now = Utc::now().naive_utc();
+            let user = User {
+                id: users.last().map_or(1, |last| last.id + 1),
+                username: user.username,
+                email,
+                password: user.password,
+                bio: None,
+                image: None,
+                token: None,
+                created_at: now,
+                updated_at: now,
+                password_changed_at: None,
+                verified: false,
+                role: Role::User.name().to_string(),
+                suspended_at: None,
+                deleted_at: None,
+            };
+            users.push(user.clone());
+            future::ok(user).boxed()
+        }
+
+        fn find(&self, user_id: i32) -> BoxFuture<'static, Result<User, RepoError>> {
+            self.find_by(|user| user.id == user_id)
+        }
+
+        fn find_by_username(
+            &self,
+            username: String,
+        ) -> BoxFuture<'static, Result<User, RepoError>> {
+            self.find_by(|user| user.username == username)
+        }
+
+        fn update(
+            &self,
+            user_id: i32,
+            changes: UpdateUser,
+        ) -> BoxFuture<'static, Result<User, RepoError>> {
+            let mut users = self.users.lock().unwrap();
+            let result = match users.iter_mut().find(|user| user.id == user_id) {
+                Some(user) => {
+                    if let Some(email) = changes.email {
+                        user.email = normalize_email(&email);
+                    }
+                    if let Some(username) = changes.username {
+                        user.username = username;
+                    }
+                    if changes.image.is_some() {
+                        user.image = changes.image;
+                    }
+                    if changes.bio.is_some() {
+                        user.bio = changes.bio;
+                    }
+                    user.updated_at = Utc::now().naive_utc();
+                    Ok(user.clone())
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+
+        fn find_by_login(
+            &self,
+            login: String,
+            password: String,
+        ) -> BoxFuture<'static, Result<User, RepoError>> {
+            self.find_by(|user| {
+                let matches = if login.contains('@') {
+                    user.email == normalize_email(&login)
+                } else {
+                    user.username == login
+                };
+                matches && user.password == password
+            })
+        }
+
+        fn change_password(
+            &self,
+            user_id: i32,
+            current_password: String,
+            new_password: String,
+        ) -> BoxFuture<'static, Result<User, RepoError>> {
+            let mut users = self.users.lock().unwrap();
+            let result = match users
+                .iter_mut()
+                .find(|user| user.id == user_id && user.password == current_password)
+            {
+                Some(user) => {
+                    user.password = new_password;
+                    user.password_changed_at = Some(Utc::now().naive_utc());
+                    Ok(user.clone())
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+
+        fn list(
+            &self,
+            params: ListParams,
+        ) -> BoxFuture<'static, Result<(Vec<User>, i64), RepoError>> {
+            let users = self.users.lock().unwrap();
+            let email = params.email.map(|email| normalize_email(&email));
+            let username = params.username.map(|username| username.to_lowercase());
+            let matching: Vec<User> = users
+                .iter()
+                .filter(|user| {
+                    email
+                        .as_ref()
+                        .map_or(true, |email| user.email.contains(email))
+                })
+                .filter(|user| {
+                    username.as_ref().map_or(true, |username| {
+                        user.username.to_lowercase().contains(username)
+                    })
+                })
+                .cloned()
+                .collect();
+            let count = matching.len() as i64;
+            let page = matching
+                .into_iter()
+                .skip(params.offset as usize)
+                .take(params.limit as usize)
+                .collect();
+            future::ok((page, count)).boxed()
+        }
+
+        fn set_suspended(
+            &self,
+            user_id: i32,
+            suspended: bool,
+        ) -> BoxFuture<'static, Result<User, RepoError>> {
+            let mut users = self.users.lock().unwrap();
+            let result = match users.iter_mut().find(|user| user.id == user_id) {
+                Some(user) => {
+                    user.suspended_at = if suspended {
+                        Some(Utc::now().naive_utc())
+                    } else {
+                        None
+                    };
+                    Ok(user.clone())
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+
+        fn delete(&self, user_id: i32) -> BoxFuture<'static, Result<(), RepoError>> {
+            let mut users = self.users.lock().unwrap();
+            let count = users.len();
+            users.retain(|user| user.id != user_id);
+            if users.len() == count {
+                return future::err(RepoError::Query(dieselError::NotFound)).boxed();
+            }
+            future::ok(()).boxed()
+        }
+
+        fn anonymize(&self, user_id: i32) -> BoxFuture<'static, Result<(), RepoError>> {
+            let mut users = self.users.lock().unwrap();
+            let result = match users.iter_mut().find(|user| user.id == user_id) {
+                Some(user) => {
+                    let now = Utc::now().naive_utc();
+                    user.username = anonymous_username(user_id);
+                    user.email = format!("{}@deleted.invalid", user.username);
+                    user.password = random_token();
+                    user.bio = None;
+                    user.image = None;
+                    user.role = Role::User.name().to_string();
+                    user.password_changed_at = Some(now);
+                    user.deleted_at = Some(now);
+                    Ok(())
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+    }
+
+    /// Revoked token ids with their expiry times.
+    #[derive(Default)]
+    pub struct InMemoryTokens {
+        revoked: Mutex<HashMap<String, NaiveDateTime>>,
+    }
+
+    impl TokensRepository for InMemoryTokens {
+        fn revoke(
+            &self,
+            jti: String,
+            expires_at: NaiveDateTime,
+        ) -> BoxFuture<'static, Result<(), RepoError>> {
+            self.revoked.lock().unwrap().insert(jti, expires_at);
+            future::ok(()).boxed()
+        }
+
+        fn is_revoked(&self, jti: String) -> BoxFuture<'static, Result<bool, RepoError>> {
+            let revoked = self.revoked.lock().unwrap().contains_key(&jti);
+            future::ok(revoked).boxed()
+        }
+    }
+
+    /// Failed logins as (login, ip, time) tuples, oldest first.
+    #[derive(Default)]
+    pub struct InMemoryLoginAttempts {
+        failures: Mutex<Vec<(String, Option<String>, NaiveDateTime)>>,
+    }
+
+    impl InMemoryLoginAttempts {
+        fn latest<P>(
+            &self,
+            since: NaiveDateTime,
+            limit: u32,
+            predicate: P,
+        ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>>
+        where
+            P: Fn(&str, &Option<String>) -> bool,
+        {
+            let failures = self.failures.lock().unwrap();
+            let times = failures
+                .iter()
+                .rev()
+                .filter(|(login, ip, time)| *time > since && predicate(login, ip))
+                .map(|(_, _, time)| *time)
+                .take(limit as usize)
+                .collect();
+            future::ok(times).boxed()
+        }
+    }
+
+    impl LoginAttemptsRepository for InMemoryLoginAttempts {
+        fn record_failure(
+            &self,
+            login: String,
+            ip: Option<String>,
+        ) -> BoxFuture<'static, Result<(), RepoError>> {
+            let now = Utc::now().naive_utc();
+            self.failures.lock().unwrap().push((login, ip, now));
+            future::ok(()).boxed()
+        }
+
+        fn clear(&self, login: String) -> BoxFuture<'static, Result<(), RepoError>> {
+            self.failures
+                .lock()
+                .unwrap()
+                .retain(|(failed, _, _)| *failed != login);
+            future::ok(()).boxed()
+        }
+
+        fn failures_for_login(
+            &self,
+            login: String,
+            since: NaiveDateTime,
+            limit: u32,
+        ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>> {
+            self.latest(since, limit, |failed, _| failed == login)
+        }
+
+        fn failures_from_ip(
+            &self,
+            ip: String,
+            since: NaiveDateTime,
+            limit: u32,
+        ) -> BoxFuture<'static, Result<Vec<NaiveDateTime>, RepoError>> {
+            self.latest(since, limit, |_, from| from.as_ref() == Some(&ip))
+        }
+    }
+
+    /// Verification tokens mapped to user ids, verifying users held by an `InMemoryUsers`.
+    pub struct InMemoryVerifications {
+        users: Arc<InMemoryUsers>,
+        tokens: Mutex<HashMap<String, i32>>,
+    }
+
+    impl InMemoryVerifications {
+        pub fn new(users: Arc<InMemoryUsers>) -> Self {
+            InMemoryVerifications {
+                users,
+                tokens: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl VerificationsRepository for InMemoryVerifications {
+        fn create(&self, user_id: i32) -> BoxFuture<'static, Result<String, RepoError>> {
+            let token = random_token();
+            self.tokens.lock().unwrap().insert(token.clone(), user_id);
+            future::ok(token).boxed()
+        }
+
+        fn verify(&self, token: String) -> BoxFuture<'static, Result<User, RepoError>> {
+            let user_id = match self.tokens.lock().unwrap().remove(&token) {
+                Some(user_id) => user_id,
+                None => return future::err(RepoError::Query(dieselError::NotFound)).boxed(),
+            };
+            let mut users = self.users.users.lock().unwrap();
+            let result = match users.iter_mut().find(|user| user.id == user_id) {
+                Some(user) => {
+                    user.verified = true;
+                    Ok(user.clone())
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+    }
+
+    /// Reset tokens mapped to user ids and expiry times, resetting passwords of users held
+    /// by an `InMemoryUsers`.
+    pub struct InMemoryPasswordResets {
+        users: Arc<InMemoryUsers>,
+        tokens: Mutex<HashMap<String, (i32, NaiveDateTime)>>,
+    }
+
+    impl InMemoryPasswordResets {
+        pub fn new(users: Arc<InMemoryUsers>) -> Self {
+            InMemoryPasswordResets {
+                users,
+                tokens: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl PasswordResetsRepository for InMemoryPasswordResets {
+        fn create(
+            &self,
+            email: String,
+            expires_at: NaiveDateTime,
+        ) -> BoxFuture<'static, Result<(User, String), RepoError>> {
+            let users = self.users.users.lock().unwrap();
+            let email = normalize_email(&email);
+            let result = match users.iter().find(|user| user.email == email) {
+                Some(user) => {
+                    let token = random_token();
+                    let mut tokens = self.tokens.lock().unwrap();
+                    tokens.insert(token.clone(), (user.id, expires_at));
+                    Ok((user.clone(), token))
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+
+        fn reset(
+            &self,
+            token: String,
+            new_password: String,
+        ) -> BoxFuture<'static, Result<User, RepoError>> {
+            let mut tokens = self.tokens.lock().unwrap();
+            let user_id = match tokens.remove(&token) {
+                Some((user_id, expires_at)) if expires_at > Utc::now().naive_utc() => user_id,
+                _ => return future::err(RepoError::Query(dieselError::NotFound)).boxed(),
+            };
+            tokens.retain(|_, (id, _)| *id != user_id);
+            let mut users = self.users.users.lock().unwrap();
+            let result = match users.iter_mut().find(|user| user.id == user_id) {
+                Some(user) => {
+                    user.password = new_password;
+                    user.password_changed_at = Some(Utc::now().naive_utc());
+                    Ok(user.clone())
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+    }
+
+    /// Identities as (provider, subject) pairs mapped to user ids, signing in users held by
+    /// an `InMemoryUsers`.
+    pub struct InMemoryIdentities {
+        users: Arc<InMemoryUsers>,
+        linked: Mutex<HashMap<(String, String), i32>>,
+    }
+
+    impl InMemoryIdentities {
+        pub fn new(users: Arc<InMemoryUsers>) -> Self {
+            InMemoryIdentities {
+                users,
+                linked: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl IdentitiesRepository for InMemoryIdentities {
+        fn sign_in(&self, profile: ExternalProfile) -> BoxFuture<'static, Result<User, RepoError>> {
+            let mut linked = self.linked.lock().unwrap();
+            let mut users = self.users.users.lock().unwrap();
+            let key = (profile.provider, profile.subject);
+            if let Some(user_id) = linked.get(&key) {
+                let user = users.iter().find(|user| user.id == *user_id).cloned();
+                let result = user.ok_or(RepoError::Query(dieselError::NotFound));
+                return future::ready(result).boxed();
+            }
+            let email = match profile.email {
+                Some(email) => normalize_email(&email),
+                None => return future::err(RepoError::Query(dieselError::NotFound)).boxed(),
+            };
+            if !users.iter().any(|user| user.email == email) {
+                let now = Utc::now().naive_utc();
+                let id = users.last().map_or(1, |last| last.id + 1);
+                users.push(User {
+                    id,
+                    username: profile.username,
+                    email: email.clone(),
+                    password: random_token(),
+                    bio: None,
+                    image: None,
+                    token: None,
+                    created_at: now,
+                    updated_at: now,
+                    password_changed_at: None,
+                    verified: false,
+                    role: Role::User.name().to_string(),
+                    suspended_at: None,
+                    deleted_at: None,
+                });
+            }
+            let user = users.iter_mut().find(|user| user.email == email).unwrap();
+            user.verified = true;
+            linked.insert(key, user.id);
+            future::ok(user.clone()).boxed()
+        }
+    }
+
+    /// API keys kept in a `Vec`, hashed as they are in the database.
+    #[derive(Default)]
+    pub struct InMemoryApiKeys {
+        keys: Mutex<Vec<ApiKey>>,
+    }
+
+    impl ApiKeysRepository for InMemoryApiKeys {
+        fn create(
+            &self,
+            user_id: i32,
+            name: String,
+        ) -> BoxFuture<'static, Result<(ApiKey, String), RepoError>> {
+            let mut keys = self.keys.lock().unwrap();
+            let key = generate_key();
+            let api_key = ApiKey {
+                id: keys.last().map_or(1, |last| last.id + 1),
+                user_id,
+                name,
+                prefix: visible_prefix(&key),
+                key_hash: hash_key(&key),
+                created_at: Utc::now().naive_utc(),
+                last_used_at: None,
+            };
+            keys.push(api_key.clone());
+            future::ok((api_key, key)).boxed()
+        }
+
+        fn list(&self, user_id: i32) -> BoxFuture<'static, Result<Vec<ApiKey>, RepoError>> {
+            let keys = self.keys.lock().unwrap();
+            let owned = keys
+                .iter()
+                .filter(|key| key.user_id == user_id)
+                .cloned()
+                .collect();
+            future::ok(owned).boxed()
+        }
+
+        fn delete(&self, user_id: i32, id: i32) -> BoxFuture<'static, Result<(), RepoError>> {
+            let mut keys = self.keys.lock().unwrap();
+            let count = keys.len();
+            keys.retain(|key| !(key.id == id && key.user_id == user_id));
+            if keys.len() == count {
+                return future::err(RepoError::Query(dieselError::NotFound)).boxed();
+            }
+            future::ok(()).boxed()
+        }
+
+        fn authenticate(&self, key: String) -> BoxFuture<'static, Result<i32, RepoError>> {
+            let mut keys = self.keys.lock().unwrap();
+            let key_hash = hash_key(&key);
+            let result = match keys.iter_mut().find(|key| key.key_hash == key_hash) {
+                Some(api_key) => {
+                    api_key.last_used_at = Some(Utc::now().naive_utc());
+                    Ok(api_key.user_id)
+                }
+                None => Err(RepoError::Query(dieselError::NotFound)),
+            };
+            future::ready(result).boxed()
+        }
+    }
+
+    /// A cache in a `HashMap`, whose entries never expire.
+    #[derive(Default)]
+    pub struct InMemoryCache {
+        entries: Mutex<HashMap<String, String>>,
+    }
+
+    impl Cache for InMemoryCache {
+        fn get(&self, key: &str) -> BoxFuture<'static, Option<String>> {
+            future::ready(self.entries.lock().unwrap().get(key).cloned()).boxed()
+        }
+
+        fn set(&self, key: &str, value: String) -> BoxFuture<'static, ()> {
+            self.entries.lock().unwrap().insert(key.to_string(), value);
+            future::ready(()).boxed()
+        }
+
+        fn invalidate(&self, prefix: &str) -> BoxFuture<'static, ()> {
+            self.entries
+                .lock()
+                .unwrap()
+                .retain(|key, _| !key.starts_with(prefix));
+            future::ready(()).boxed()
+        }
+    }
+
+    /// Keeps emails instead of sending them, so tests can read them.
+    #[derive(Default)]
+    pub struct CapturingMailer {
+        sent: Mutex<Vec<Email>>,
+    }
+
+    impl CapturingMailer {
+        pub fn sent(&self) -> Vec<Email> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    impl Mailer for CapturingMailer {
+        fn send(&self, email: Email) -> BoxFuture<'static, Result<(), MailError>> {
+            self.sent.lock().unwrap().push(email);
+            future::ok(()).boxed()
+        }
+    }
+}
+
+/// Functions for generating test data.
+///
+/// Values are drawn from a random generator seeded with the test's name and `TEST_SEED`
+/// (0 when unset), so a test gets the same data on every run. The builders override just the
+/// fields a test cares about, e.g. `generate::article(user.id).title("Dragons").insert(repo)`.
 pub mod generate {
-    use crate::models::{NewArticle, NewUser};
-    use fake::fake;
+    use crate::conduit::{articles, comments, favorites, followers, users};
+    use crate::models::{Article, Comment, NewArticle, NewComment, NewUser, User};
+    use crate::slugs;
+    use crate::Repo;
+    use futures::future::{self, BoxFuture};
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::cell::RefCell;
+    use std::collections::hash_map::DefaultHasher;
+    use std::env;
+    use std::future::Future;
+    use std::hash::{Hash, Hasher};
+    use std::sync::{Arc, Mutex};
+    use std::task::Poll;
+    use std::thread;
+
+    const WORDS: &[&str] = &[
+        "anchor", "badger", "beacon", "canyon", "cipher", "comet", "dragon", "ember", "falcon",
+        "fjord", "garnet", "glacier", "harbor", "hazel", "island", "jasper", "juniper", "kestrel",
+        "lantern", "lichen", "marble", "meadow", "nebula", "orchid", "otter", "pebble", "quartz",
+        "raven", "saffron", "sparrow", "thistle", "tundra", "umber", "velvet", "willow", "zephyr",
+    ];
+
+    pub struct Generator {
+        rng: StdRng,
+        sequence: u32,
+    }
+
+    impl Generator {
+        fn new() -> Generator {
+            let mut hasher = DefaultHasher::new();
+            env::var("TEST_SEED")
+                .unwrap_or_else(|_| "0".to_string())
+                .hash(&mut hasher);
+            thread::current().name().hash(&mut hasher);
+            Generator {
+                rng: StdRng::seed_from_u64(hasher.finish()),
+                sequence: 0,
+            }
+        }
+    }
+
+    thread_local! {
+        static CURRENT: RefCell<Option<Arc<Mutex<Generator>>>> = RefCell::new(None);
+    }
+
+    /// The generator for the test running on this thread.
+    pub fn current() -> Arc<Mutex<Generator>> {
+        CURRENT.with(|current| {
+            current
+                .borrow_mut()
+                .get_or_insert_with(|| Arc::new(Mutex::new(Generator::new())))
+                .clone()
+        })
+    }
+
+    /// Run `future` with `generator`, whichever thread polls it, so data generated in a
+    /// future run on a threadpool comes from the test's generator.
+    pub fn within<T>(
+        generator: Arc<Mutex<Generator>>,
+        mut future: BoxFuture<'static, T>,
+    ) -> impl Future<Output = T> {
+        future::poll_fn(move |cx| -> Poll<T> {
+            let previous = CURRENT.with(|current| current.replace(Some(generator.clone())));
+            let poll = future.as_mut().poll(cx);
+            CURRENT.with(|current| current.replace(previous));
+            poll
+        })
+    }
+
+    fn with<T>(f: impl FnOnce(&mut Generator) -> T) -> T {
+        let generator = current();
+        let mut generator = generator.lock().unwrap();
+        f(&mut generator)
+    }
+
+    fn word() -> &'static str {
+        with(|generator| *WORDS.choose(&mut generator.rng).unwrap())
+    }
+
+    /// A number no earlier call in the test returned, to keep unique columns unique.
+    fn sequence() -> u32 {
+        with(|generator| {
+            generator.sequence += 1;
+            generator.sequence
+        })
+    }
+
+    fn sentence(min_words: usize, max_words: usize) -> String {
+        let count = with(|generator| generator.rng.gen_range(min_words, max_words + 1));
+        let words: Vec<&str> = (0..count).map(|_| word()).collect();
+        let sentence = words.join(" ");
+        format!("{}{}.", sentence[..1].to_uppercase(), &sentence[1..])
+    }
+
+    fn paragraph(sentences: usize) -> String {
+        let sentences: Vec<String> = (0..sentences).map(|_| sentence(4, 12)).collect();
+        sentences.join(" ")
+    }
 
     pub fn new_user() -> NewUser {
+        let username = format!("{}_{}{}", word(), word(), sequence());
         NewUser {
-            username: fake!(Internet.user_name).to_string(),
-            email: fake!(Internet.free_email).to_string(),
-            password: fake!(Lorem.word).to_string(),
+            email: format!("{}@example.com", username),
+            password: format!("{}-{}-{}", word(), word(), sequence()),
+            username,
         }
     }
 
     pub fn new_article(user_id: i32) -> NewArticle {
+        let title = format!("{} {}", sentence(3, 8), sequence());
         NewArticle {
-            title: fake!(Lorem.sentence(4, 10)).to_string(),
-            slug: format!("{}{}", fake!(Lorem.word).to_string(), user_id),
-            description: fake!(Lorem.paragraph(3, 10)),
-            body: fake!(Lorem.paragraph(10, 5)),
+            slug: slugs::slugify(&title),
+            title,
+            description: sentence(6, 15),
+            body: paragraph(5),
             user_id: user_id,
+            status: articles::Status::Published.name().to_string(),
+            publish_at: None,
+            visibility: articles::Visibility::Public.name().to_string(),
+            cover_image: None,
+            canonical_url: None,
+        }
+    }
+
+    pub fn new_comment(article_id: i32, user_id: i32) -> NewComment {
+        NewComment {
+            body: sentence(3, 20),
+            article_id,
+            user_id,
+            parent_id: None,
+        }
+    }
+
+    /// A user to register, with `new_user()`'s values for any fields not overridden.
+    pub fn user() -> UserBuilder {
+        UserBuilder(new_user())
+    }
+
+    pub struct UserBuilder(NewUser);
+
+    impl UserBuilder {
+        pub fn email(mut self, email: &str) -> UserBuilder {
+            self.0.email = email.to_string();
+            self
+        }
+
+        pub fn build(self) -> NewUser {
+            self.0
+        }
+
+        pub async fn insert(self, repo: Repo) -> User {
+            users::insert(repo, self.0).await.unwrap()
+        }
+    }
+
+    /// An article by `user_id`, with `new_article()`'s values and no tags unless overridden.
+    pub fn article(user_id: i32) -> ArticleBuilder {
+        ArticleBuilder {
+            article: new_article(user_id),
+            tags: vec![],
         }
     }
+
+    pub struct ArticleBuilder {
+        article: NewArticle,
+        tags: Vec<String>,
+    }
+
+    impl ArticleBuilder {
+        /// Set the title, and the slug to match it.
+        pub fn title(mut self, title: &str) -> ArticleBuilder {
+            self.article.title = title.to_string();
+            self.article.slug = slugs::slugify(title);
+            self
+        }
+
+        pub fn body(mut self, body: &str) -> ArticleBuilder {
+            self.article.body = body.to_string();
+            self
+        }
+
+        pub fn tags(mut self, tags: &[&str]) -> ArticleBuilder {
+            self.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            self
+        }
+
+        pub async fn insert(self, repo: Repo) -> Article {
+            let (article, _) = articles::insert_with_tags(repo, self.article, self.tags)
+                .await
+                .unwrap();
+            article
+        }
+    }
+
+    /// A comment by `user_id` on `article_id`, with `new_comment()`'s body unless overridden.
+    pub fn comment(article_id: i32, user_id: i32) -> CommentBuilder {
+        CommentBuilder(new_comment(article_id, user_id))
+    }
+
+    pub struct CommentBuilder(NewComment);
+
+    impl CommentBuilder {
+        pub fn body(mut self, body: &str) -> CommentBuilder {
+            self.0.body = body.to_string();
+            self
+        }
+
+        pub fn reply_to(mut self, parent_id: i32) -> CommentBuilder {
+            self.0.parent_id = Some(parent_id);
+            self
+        }
+
+        pub async fn insert(self, repo: Repo) -> Comment {
+            comments::insert(repo, self.0).await.unwrap()
+        }
+    }
+
+    pub async fn follow(repo: Repo, follower: &User, followed: &User) {
+        followers::follow(repo, follower.id, followed.id)
+            .await
+            .unwrap();
+    }
+
+    pub async fn favorite(repo: Repo, user: &User, article: &Article) {
+        favorites::favorite(repo, user.id, article.id)
+            .await
+            .unwrap();
+    }
 }
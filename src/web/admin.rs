@@ -0,0 +1,362 @@
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::SharedCache;
+use crate::conduit::users::ListParams;
+use crate::conduit::Repositories;
+use crate::config::Config;
+use crate::jobs::enqueue;
+use crate::jobs::scheduler::maintenance_jobs;
+use crate::models::User;
+use crate::web::articles::invalidate_articles_and_tags;
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::{clamp_page, current_user_id, handler, json_response};
+use crate::Repo;
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct UsersQuery {
+    email: Option<String>,
+    username: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl From<UsersQuery> for ListParams {
+    fn from(query: UsersQuery) -> Self {
+        let defaults = ListParams::default();
+        ListParams {
+            email: query.email,
+            username: query.username,
+            limit: query.limit.unwrap_or(defaults.limit),
+            offset: query.offset.unwrap_or(defaults.offset),
+        }
+    }
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct UserPath {
+    id: i32,
+}
+
+/// A user as admins see them, with the details hidden from everyone else.
+#[derive(Serialize)]
+pub struct AdminUserJson {
+    #[serde(flatten)]
+    user: User,
+    role: String,
+    verified: bool,
+    suspended: bool,
+    deleted: bool,
+}
+
+impl From<User> for AdminUserJson {
+    fn from(user: User) -> Self {
+        AdminUserJson {
+            role: user.role.clone(),
+            verified: user.verified,
+            suspended: user.suspended_at.is_some(),
+            deleted: user.deleted_at.is_some(),
+            user,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AdminUserResponse {
+    user: AdminUserJson,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUsersResponse {
+    users: Vec<AdminUserJson>,
+    users_count: i64,
+}
+
+/// The kinds of the maintenance jobs that were queued.
+#[derive(Serialize)]
+pub struct MaintenanceResponse {
+    jobs: Vec<&'static str>,
+}
+
+/// Draw the routes admins manage users with.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.admin, |route| {
+        route
+            .get("/admin/users")
+            .with_query_string_extractor::<UsersQuery>()
+            .to(handler(list_users));
+        route
+            .delete("/admin/users/:id")
+            .with_path_extractor::<UserPath>()
+            .to(handler(delete_user));
+        route
+            .post("/admin/users/:id/suspend")
+            .with_path_extractor::<UserPath>()
+            .to(handler(suspend));
+        route
+            .delete("/admin/users/:id/suspend")
+            .with_path_extractor::<UserPath>()
+            .to(handler(unsuspend));
+        route
+            .post("/admin/maintenance")
+            .to(handler(run_maintenance));
+    });
+}
+
+/// A page of users, optionally only those whose email or username contains the
+/// `email` or `username` in the query.
+pub async fn list_users(mut state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let mut params = ListParams::from(UsersQuery::take_from(&mut state));
+    let (limit, offset) = clamp_page(Config::borrow_from(&state), params.limit, params.offset);
+    params.limit = limit;
+    params.offset = offset;
+    let res = match users.list(params).await {
+        Ok((users, users_count)) => {
+            let response = AdminUsersResponse {
+                users: users.into_iter().map(AdminUserJson::from).collect(),
+                users_count,
+            };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Stop a user from logging in or using the tokens and API keys they have.
+pub async fn suspend(state: State) -> (State, Response<Body>) {
+    set_suspended(state, true).await
+}
+
+/// Let a suspended user back in.
+pub async fn unsuspend(state: State) -> (State, Response<Body>) {
+    set_suspended(state, false).await
+}
+
+async fn set_suspended(mut state: State, suspended: bool) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let admin_id = current_user_id(&state);
+    let path = UserPath::take_from(&mut state);
+    let result = match not_self(admin_id, path.id) {
+        Ok(()) => users
+            .set_suspended(path.id, suspended)
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = AdminUserResponse { user: user.into() };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Delete a user, along with their articles, comments and everything else of theirs.
+pub async fn delete_user(mut state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let admin_id = current_user_id(&state);
+    let path = UserPath::take_from(&mut state);
+    let result = match not_self(admin_id, path.id) {
+        Ok(()) => users.delete(path.id).await.map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    if result.is_ok() {
+        invalidate_articles_and_tags(cache).await;
+    }
+    let res = match result {
+        Ok(()) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Queue every maintenance job now, rather than waiting for the scheduler to. Jobs turned
+/// off in the configuration are left out.
+pub async fn run_maintenance(state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let jobs = maintenance_jobs(&Config::borrow_from(&state).maintenance);
+    let mut queued = Vec::new();
+    let mut result = Ok(());
+    for (job, _) in jobs {
+        let kind = job.kind();
+        if let Err(e) = enqueue(repo.clone(), job).await {
+            result = Err(e);
+            break;
+        }
+        queued.push(kind);
+    }
+    let res = match result {
+        Ok(()) => {
+            let response = MaintenanceResponse { jobs: queued };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Admins can't suspend or delete themselves, so there's always one left to undo it.
+fn not_self(admin_id: i32, user_id: i32) -> Result<(), ApiError> {
+    if admin_id == user_id {
+        return Err(ApiError::unprocessable_entity(
+            "user",
+            "can't be your own account",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::{encode_token, Role};
+    use crate::conduit::users::UsersRepository;
+    use crate::conduit::Repositories;
+    use crate::mail::StdoutMailer;
+    use crate::router_with_repositories;
+    use crate::test_helpers::fakes::InMemoryUsers;
+    use crate::test_helpers::{self, block_on, generate, login, response_json};
+    use gotham::test::TestServer;
+    use hyper::header::HeaderValue;
+    use std::sync::Arc;
+
+    fn server(repositories: Repositories) -> TestServer {
+        TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            repositories,
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap()
+    }
+
+    fn authorization(user_id: i32) -> HeaderValue {
+        let token = encode_token(&test_helpers::config().jwt, user_id, Role::User);
+        HeaderValue::from_str(&format!("Token {}", token)).unwrap()
+    }
+
+    #[test]
+    fn admin_endpoints_require_admin_role() {
+        let repositories = test_helpers::fakes::repositories();
+        let user = block_on(repositories.users.insert(generate::new_user())).unwrap();
+        let server = server(repositories);
+
+        let res = server
+            .client()
+            .get("http://localhost/api/admin/users")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+        let res = server
+            .client()
+            .get("http://localhost/api/admin/users")
+            .with_header("Authorization", authorization(user.id))
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 403);
+    }
+
+    #[test]
+    fn suspend_and_delete_users() {
+        let users = Arc::new(InMemoryUsers::default());
+        let admin = block_on(users.insert(generate::new_user())).unwrap();
+        users.set_role(admin.id, Role::Admin);
+        let new_user = generate::new_user();
+        let user = block_on(users.insert(new_user.clone())).unwrap();
+        let server = server(Repositories {
+            users,
+            ..test_helpers::fakes::repositories()
+        });
+        let admin_auth = authorization(admin.id);
+
+        let res = server
+            .client()
+            .get(format!(
+                "http://localhost/api/admin/users?username={}",
+                user.username.to_uppercase()
+            ))
+            .with_header("Authorization", admin_auth.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let listed = response_json(res);
+        assert_eq!(listed["usersCount"], 1);
+        assert_eq!(listed["users"][0]["email"], user.email);
+        assert_eq!(listed["users"][0]["suspended"], false);
+
+        let res = server
+            .client()
+            .get("http://localhost/api/admin/users?limit=1000&offset=-5")
+            .with_header("Authorization", admin_auth.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let listed = response_json(res);
+        assert_eq!(listed["usersCount"], 2);
+        assert_eq!(listed["users"].as_array().unwrap().len(), 2);
+
+        let suspend_url = format!("http://localhost/api/admin/users/{}/suspend", user.id);
+        let res = server
+            .client()
+            .post(suspend_url.clone(), "", mime::APPLICATION_JSON)
+            .with_header("Authorization", admin_auth.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(response_json(res)["user"]["suspended"], true);
+        let res = login(&server, &new_user.email, &new_user.password);
+        assert_eq!(res.status(), 403);
+        let res = server
+            .client()
+            .get("http://localhost/api/user")
+            .with_header("Authorization", authorization(user.id))
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+
+        let res = server
+            .client()
+            .delete(suspend_url)
+            .with_header("Authorization", admin_auth.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let res = login(&server, &new_user.email, &new_user.password);
+        assert_eq!(res.status(), 200);
+
+        let res = server
+            .client()
+            .delete(format!("http://localhost/api/admin/users/{}", admin.id))
+            .with_header("Authorization", admin_auth.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 422);
+        let res = server
+            .client()
+            .delete(format!("http://localhost/api/admin/users/{}", user.id))
+            .with_header("Authorization", admin_auth)
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let res = login(&server, &new_user.email, &new_user.password);
+        assert_eq!(res.status(), 401);
+    }
+}
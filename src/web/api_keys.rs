@@ -0,0 +1,228 @@
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::conduit::Repositories;
+use crate::models::ApiKey;
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::validation::{is_blank, Validate, Validator};
+use crate::web::{current_user_id, extract_valid_json, handler, json_response};
+
+pub const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct ApiKeyPath {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewApiKeyRequest {
+    api_key: NewApiKeyData,
+}
+
+#[derive(Deserialize)]
+pub struct NewApiKeyData {
+    name: String,
+}
+
+impl Validate for NewApiKeyRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        let name = &self.api_key.name;
+        Validator::default()
+            .check(!is_blank(name), "name", "can't be blank")
+            .check(
+                name.chars().count() <= MAX_NAME_LENGTH,
+                "name",
+                &format!("is too long (maximum is {} characters)", MAX_NAME_LENGTH),
+            )
+            .finish()
+    }
+}
+
+/// A new key, with the key itself as the only time it's shown.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiKeyResponse {
+    api_key: CreatedApiKey,
+}
+
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    api_key: ApiKey,
+    key: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeysResponse {
+    api_keys: Vec<ApiKey>,
+}
+
+/// Draw the routes for managing the current user's API keys.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route.post("/user/api-keys").to(handler(create));
+        route.get("/user/api-keys").to(handler(list));
+        route
+            .delete("/user/api-keys/:id")
+            .with_path_extractor::<ApiKeyPath>()
+            .to(handler(delete));
+    });
+}
+
+/// Create an API key for the current user. The response is the only place the key appears;
+/// only a hash of it is stored.
+pub async fn create(mut state: State) -> (State, Response<Body>) {
+    let api_keys = Repositories::borrow_from(&state).api_keys.clone();
+    let user_id = current_user_id(&state);
+    let result = match extract_valid_json::<NewApiKeyRequest>(&mut state).await {
+        Ok(body) => api_keys
+            .create(user_id, body.api_key.name.trim().to_string())
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok((api_key, key)) => {
+            let response = CreatedApiKeyResponse {
+                api_key: CreatedApiKey { api_key, key },
+            };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// The current user's API keys, without the keys themselves.
+pub async fn list(state: State) -> (State, Response<Body>) {
+    let api_keys = Repositories::borrow_from(&state).api_keys.clone();
+    let user_id = current_user_id(&state);
+    let res = match api_keys.list(user_id).await {
+        Ok(api_keys) => json_response(&state, StatusCode::OK, &ApiKeysResponse { api_keys }),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Revoke one of the current user's API keys. Other users' keys are reported as not found.
+pub async fn delete(mut state: State) -> (State, Response<Body>) {
+    let api_keys = Repositories::borrow_from(&state).api_keys.clone();
+    let user_id = current_user_id(&state);
+    let path = ApiKeyPath::take_from(&mut state);
+    let res = match api_keys.delete(user_id, path.id).await {
+        Ok(()) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{self, fakes, response_json, sign_up};
+    use gotham::test::{TestResponse, TestServer};
+    use hyper::header::HeaderValue;
+    use serde_json::json;
+
+    fn authorization(token: &str) -> HeaderValue {
+        HeaderValue::from_str(&format!("Token {}", token)).unwrap()
+    }
+
+    fn get_user_with_key(server: &TestServer, key: &str) -> TestResponse {
+        server
+            .client()
+            .get("http://localhost/api/user")
+            .with_header("X-Api-Key", HeaderValue::from_str(key).unwrap())
+            .perform()
+            .unwrap()
+    }
+
+    #[test]
+    fn api_key_authenticates() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let token = sign_up(&server);
+
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/user/api-keys",
+                json!({ "apiKey": { "name": "deploy bot" } }).to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .with_header("Authorization", authorization(&token))
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let created = response_json(res);
+        let key = created["apiKey"]["key"].as_str().unwrap().to_string();
+        assert!(key.starts_with(created["apiKey"]["prefix"].as_str().unwrap()));
+
+        let res = get_user_with_key(&server, &key);
+        assert_eq!(res.status(), 200);
+
+        let res = server
+            .client()
+            .get("http://localhost/api/user/api-keys")
+            .with_header("X-Api-Key", HeaderValue::from_str(&key).unwrap())
+            .perform()
+            .unwrap();
+        let listed = response_json(res);
+        assert_eq!(listed["apiKeys"][0]["name"], "deploy bot");
+        assert!(listed["apiKeys"][0]["lastUsedAt"].is_string());
+        assert!(!listed.to_string().contains(&key));
+
+        let id = created["apiKey"]["id"].as_i64().unwrap();
+        let res = server
+            .client()
+            .delete(format!("http://localhost/api/user/api-keys/{}", id))
+            .with_header("Authorization", authorization(&token))
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let res = get_user_with_key(&server, &key);
+        assert_eq!(res.status(), 401);
+    }
+
+    #[test]
+    fn api_keys_belong_to_their_user() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let owner = sign_up(&server);
+        let other = sign_up(&server);
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/user/api-keys",
+                json!({ "apiKey": { "name": "ci" } }).to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .with_header("Authorization", authorization(&owner))
+            .perform()
+            .unwrap();
+        let id = response_json(res)["apiKey"]["id"].as_i64().unwrap();
+
+        let res = server
+            .client()
+            .delete(format!("http://localhost/api/user/api-keys/{}", id))
+            .with_header("Authorization", authorization(&other))
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 404);
+        let res = get_user_with_key(&server, "rwg_unknown");
+        assert_eq!(res.status(), 401);
+    }
+}
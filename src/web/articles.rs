@@ -0,0 +1,1131 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::future;
+use futures::stream::StreamExt;
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::{create_empty_response, create_response};
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::router::response::extender::StaticResponseExtender;
+use gotham::state::{client_addr, FromState, State};
+use gotham_derive::StateData;
+use hyper::header::IF_MATCH;
+use hyper::{Body, HeaderMap, Response, StatusCode, Uri};
+use log::error;
+use mime;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::cache::{Cache, SharedCache};
+use crate::conduit::articles::{
+    self, ArticleDto, Cursor, Direction, ListParams, Sort, Status, Visibility,
+};
+use crate::conduit::favorites;
+use crate::conduit::followers;
+use crate::conduit::search;
+use crate::conduit::trending;
+use crate::conduit::views::{self, ArticleStats, Viewer};
+use crate::config::Config;
+use crate::db::RepoError;
+use crate::events::{self, Event};
+use crate::jobs::{self, Job};
+use crate::markdown;
+use crate::models::{iso8601, Article, ArticleRevision, NewArticle, Profile, UpdateArticle};
+use crate::slugs;
+use crate::web::errors::ApiError;
+use crate::web::extractors::{RevisionIdPath, SlugPath};
+use crate::web::query::{self, check_page, take_valid_query};
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::sse;
+use crate::web::tags::CACHE_KEY as TAGS_CACHE_KEY;
+use crate::web::validation::{is_blank, is_http_url, Validate, Validator};
+use crate::web::{
+    cached_json, clamp_page, current_user_id, etag, extract_valid_json, handler,
+    json_body_response, json_response, lists_etag, optional_user_id, tagged_json_response, to_json,
+    with_etag,
+};
+use crate::Repo;
+
+/// Where articles are cached, for visitors who aren't signed in.
+pub const CACHE_PREFIX: &str = "articles:";
+/// Where rendered bodies are cached. Each is keyed by the version of the article it was
+/// rendered from, so edits don't need to clear them.
+const HTML_CACHE_PREFIX: &str = "article-html:";
+/// How many days of stats are shown, unless asked for more or fewer.
+const DEFAULT_STATS_DAYS: i64 = 30;
+/// The most days of stats that can be asked for.
+const MAX_STATS_DAYS: i64 = 365;
+/// How many related articles are suggested, unless asked for more or fewer.
+const DEFAULT_RELATED_LIMIT: i64 = 5;
+
+#[derive(Deserialize, StateData)]
+pub struct ArticlesQuery {
+    pub tag: Option<String>,
+    /// A username, or `me` for the signed in user's own articles.
+    pub author: Option<String>,
+    pub favorited: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// The `nextCursor` of the previous page, to page by position instead of `offset`.
+    pub after: Option<String>,
+    /// `created` (the default), `updated` or `favorites`. Anything else is a 422.
+    pub sort: Option<Sort>,
+    /// `asc` or `desc` (the default).
+    pub direction: Option<Direction>,
+    /// `published` (the default), or `draft` for the signed in user's drafts.
+    pub status: Option<Status>,
+}
+
+impl StaticResponseExtender for ArticlesQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for ArticlesQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        check_page(Validator::default(), self.limit, self.offset).finish()
+    }
+}
+
+impl ArticlesQuery {
+    /// The filters and page asked for, with the page clamped to the configured size.
+    /// Fails if `after` isn't a cursor from an earlier page, or if `user_id` is needed to
+    /// tell whose articles are `me`'s or whose drafts to list, but nobody is signed in.
+    pub fn into_params(
+        self,
+        config: &Config,
+        user_id: Option<i32>,
+    ) -> Result<ListParams, ApiError> {
+        let defaults = ListParams::default();
+        let mine = self.author.as_ref().map_or(false, |author| author == "me");
+        let status = self.status.unwrap_or(defaults.status);
+        if user_id.is_none() && (mine || status == Status::Draft) {
+            return Err(ApiError::unauthorized());
+        }
+        let sort = self.sort.unwrap_or(defaults.sort);
+        let after = match self.after {
+            Some(_) if sort != Sort::Created => {
+                return Err(ApiError::unprocessable_entity(
+                    "after",
+                    "can only be used when sorting by created",
+                ))
+            }
+            Some(ref value) => match Cursor::decode(value) {
+                Some(cursor) => Some(cursor),
+                None => return Err(ApiError::unprocessable_entity("after", "is invalid")),
+            },
+            None => None,
+        };
+        let (limit, offset) = clamp_page(
+            config,
+            self.limit.unwrap_or(defaults.limit),
+            self.offset.unwrap_or(defaults.offset),
+        );
+        Ok(ListParams {
+            tag: self.tag,
+            author: if mine { None } else { self.author },
+            favorited: self.favorited,
+            limit,
+            offset,
+            after,
+            sort,
+            direction: self.direction.unwrap_or(defaults.direction),
+            status,
+            mine,
+        })
+    }
+}
+
+#[derive(Deserialize, StateData)]
+pub struct ArticleQuery {
+    /// `html` to add the body rendered from Markdown as `bodyHtml`.
+    pub render: Option<Render>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Render {
+    Html,
+}
+
+impl StaticResponseExtender for ArticleQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for ArticleQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, StateData)]
+pub struct FeedQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// `true` to add articles with the tags the user follows. Only `/articles/feed` reads it.
+    pub tags: Option<bool>,
+}
+
+impl StaticResponseExtender for FeedQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for FeedQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        check_page(Validator::default(), self.limit, self.offset).finish()
+    }
+}
+
+impl FeedQuery {
+    /// The `limit` and `offset` asked for, clamped to the configured page size.
+    pub fn page(&self, config: &Config) -> (i64, i64) {
+        let defaults = ListParams::default();
+        clamp_page(
+            config,
+            self.limit.unwrap_or(defaults.limit),
+            self.offset.unwrap_or(defaults.offset),
+        )
+    }
+}
+
+#[derive(Deserialize, StateData)]
+pub struct SearchQuery {
+    /// Optional only so a missing `q` is reported like a blank one.
+    q: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl StaticResponseExtender for SearchQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for SearchQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        let q = self.q.as_ref().map_or("", String::as_str);
+        check_page(Validator::default(), self.limit, self.offset)
+            .check(!is_blank(q), "q", "can't be blank")
+            .finish()
+    }
+}
+
+#[derive(Deserialize, StateData)]
+pub struct RelatedQuery {
+    pub limit: Option<i64>,
+}
+
+impl StaticResponseExtender for RelatedQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for RelatedQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        check_page(Validator::default(), self.limit, None).finish()
+    }
+}
+
+#[derive(Deserialize, StateData)]
+pub struct StatsQuery {
+    /// How many days back, including today, to break the stats down for.
+    pub days: Option<i64>,
+}
+
+impl StaticResponseExtender for StatsQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for StatsQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(
+                self.days
+                    .map_or(true, |days| days >= 1 && days <= MAX_STATS_DAYS),
+                "days",
+                "must be between 1 and 365",
+            )
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewArticleRequest {
+    article: NewArticleData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewArticleData {
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    #[serde(default)]
+    pub tag_list: Vec<String>,
+    /// `published` (the default), or `draft` to keep it to the author until it's published.
+    #[serde(default)]
+    pub status: Status,
+    /// When to publish it. It's a draft until then.
+    #[serde(default, deserialize_with = "iso8601::deserialize_option")]
+    pub publish_at: Option<NaiveDateTime>,
+    /// `public` (the default), `unlisted` or `private`.
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub cover_image: Option<String>,
+    /// Where it was first published, if it's cross-posted.
+    #[serde(default)]
+    pub canonical_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateArticleRequest {
+    article: UpdateArticle,
+}
+
+impl Validate for NewArticleRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        self.article.validate()
+    }
+}
+
+impl Validate for NewArticleData {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.title), "title", "can't be blank")
+            .check(
+                !is_blank(&self.description),
+                "description",
+                "can't be blank",
+            )
+            .check(!is_blank(&self.body), "body", "can't be blank")
+            .check(
+                self.cover_image
+                    .as_ref()
+                    .map_or(true, |url| is_http_url(url)),
+                "coverImage",
+                "must be an http or https URL",
+            )
+            .check(
+                self.canonical_url
+                    .as_ref()
+                    .map_or(true, |url| is_http_url(url)),
+                "canonicalUrl",
+                "must be an http or https URL",
+            )
+            .finish()
+    }
+}
+
+impl Validate for UpdateArticleRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        self.article.validate()
+    }
+}
+
+/// An article along with its author, tags and details that depend on who is asking.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleJson {
+    #[serde(flatten)]
+    pub article: Article,
+    pub author: Profile,
+    pub tag_list: Vec<String>,
+    pub favorited: bool,
+    pub favorites_count: i64,
+    pub comments_count: i64,
+    /// The body as sanitized HTML, when asked for with `render=html`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
+}
+
+impl From<ArticleDto> for ArticleJson {
+    fn from(dto: ArticleDto) -> Self {
+        ArticleJson {
+            article: dto.article,
+            author: dto.author,
+            tag_list: dto.tag_list,
+            favorited: dto.favorite.favorited,
+            favorites_count: dto.favorite.count,
+            comments_count: dto.comments_count,
+            body_html: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ArticleResponse {
+    article: ArticleJson,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticlesResponse {
+    pub articles: Vec<ArticleJson>,
+    /// How many articles there are in all, across every page.
+    pub articles_count: i64,
+    /// Where the next page starts, to pass as `after`. Only set when this page is full.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ArticleStatsResponse {
+    stats: ArticleStats,
+}
+
+#[derive(Serialize)]
+pub struct ArticleRevisionsResponse {
+    revisions: Vec<ArticleRevision>,
+}
+
+/// An article found by a search, with where it matched.
+#[derive(Serialize)]
+pub struct SearchResultJson {
+    #[serde(flatten)]
+    article: ArticleJson,
+    snippet: String,
+    rank: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResultsResponse {
+    articles: Vec<SearchResultJson>,
+    articles_count: i64,
+}
+
+/// Draw the routes for listing, searching, writing and favoriting articles.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_optional, |route| {
+        route
+            .get("/articles")
+            .with_query_string_extractor::<ArticlesQuery>()
+            .to(handler(list));
+        route
+            .get("/articles/search")
+            .with_query_string_extractor::<SearchQuery>()
+            .to(handler(search));
+        route
+            .get("/articles/trending")
+            .with_query_string_extractor::<FeedQuery>()
+            .to(handler(trending));
+        route
+            .get("/articles/:slug")
+            .with_path_extractor::<SlugPath>()
+            .with_query_string_extractor::<ArticleQuery>()
+            .to(handler(get_article));
+        route
+            .get("/articles/:slug/related")
+            .with_path_extractor::<SlugPath>()
+            .with_query_string_extractor::<RelatedQuery>()
+            .to(handler(related));
+    });
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route
+            .get("/articles/feed")
+            .with_query_string_extractor::<FeedQuery>()
+            .to(handler(feed));
+        route.get("/articles/feed/stream").to(handler(feed_stream));
+        route.post("/articles").to(handler(create));
+        route
+            .put("/articles/:slug")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(update));
+        route
+            .delete("/articles/:slug")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(delete));
+        route
+            .post("/articles/:slug/publish")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(publish));
+        route
+            .get("/articles/:slug/stats")
+            .with_path_extractor::<SlugPath>()
+            .with_query_string_extractor::<StatsQuery>()
+            .to(handler(stats));
+        route
+            .get("/articles/:slug/revisions")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(revisions));
+        route
+            .post("/articles/:slug/revisions/:id/restore")
+            .with_path_extractor::<RevisionIdPath>()
+            .to(handler(restore));
+        route
+            .post("/articles/:slug/favorite")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(favorite));
+        route
+            .delete("/articles/:slug/favorite")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(unfavorite));
+    });
+}
+
+pub async fn list(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    // Signed in users see which articles they favorited, so only visitors share responses.
+    let key = match user_id {
+        Some(_) => None,
+        None => {
+            let query = Uri::borrow_from(&state).query().unwrap_or("");
+            Some(format!("{}list:{}", CACHE_PREFIX, query))
+        }
+    };
+    let params = take_valid_query::<ArticlesQuery>(&mut state)
+        .and_then(|query| query.into_params(Config::borrow_from(&state), user_id));
+    let result = match params {
+        Ok(params) => cached_json(cache, key, list_page(repo, user_id, params)).await,
+        Err(e) => Err(e),
+    };
+    json_body_response(state, result)
+}
+
+/// A page of articles, with a cursor for the next one when this one is full and sorted by
+/// creation time.
+pub async fn list_page(
+    repo: Repo,
+    user_id: Option<i32>,
+    params: ListParams,
+) -> Result<ArticlesResponse, ApiError> {
+    let limit = params.limit;
+    let sort = params.sort;
+    let (articles, articles_count) = articles::list(repo, user_id, params).await?;
+    let next_cursor = match articles.last() {
+        Some(last) if sort == Sort::Created && articles.len() as i64 == limit => {
+            Some(Cursor::after(&last.article).encode())
+        }
+        _ => None,
+    };
+    Ok(ArticlesResponse {
+        articles: articles.into_iter().map(ArticleJson::from).collect(),
+        articles_count,
+        next_cursor,
+    })
+}
+
+pub async fn feed(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let query = match take_valid_query::<FeedQuery>(&mut state) {
+        Ok(query) => query,
+        Err(e) => return articles_response(state, Err(e)),
+    };
+    let (limit, offset) = query.page(Config::borrow_from(&state));
+    let with_followed_tags = query.tags.unwrap_or(false);
+    let result = feed_page(repo, user_id, with_followed_tags, limit, offset).await;
+    articles_response(state, result)
+}
+
+/// Hold the connection open, and send an `articlePublished` event whenever an author the
+/// user follows publishes an article, for clients to show there are new posts in the feed.
+pub async fn feed_stream(state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let published = events::subscribe()
+        .filter_map(move |(event, occurred_at)| {
+            let repo = repo.clone();
+            async move {
+                let author_id = match event {
+                    Event::ArticlePublished { author_id, .. } => author_id,
+                    _ => return None,
+                };
+                match followers::is_following(repo, user_id, author_id).await {
+                    Ok(true) => Some(sse::message(event.name(), &event.payload(occurred_at))),
+                    Ok(false) => None,
+                    Err(e) => {
+                        error!("Could not check who user {} follows: {}", user_id, e);
+                        None
+                    }
+                }
+            }
+        })
+        .boxed();
+    let res = sse::response(&state, published);
+    (state, res)
+}
+
+/// A page of articles by the users `user_id` follows, and with `with_followed_tags`, other
+/// articles with the tags they follow.
+pub async fn feed_page(
+    repo: Repo,
+    user_id: i32,
+    with_followed_tags: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<ArticlesResponse, ApiError> {
+    let (articles, articles_count) =
+        articles::feed(repo, user_id, with_followed_tags, limit, offset).await?;
+    Ok(ArticlesResponse {
+        articles: articles.into_iter().map(ArticleJson::from).collect(),
+        articles_count,
+        next_cursor: None,
+    })
+}
+
+/// The recently published articles that are being read, favorited and commented on the
+/// most, as last scored by the `RefreshTrending` job.
+pub async fn trending(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let key = match user_id {
+        Some(_) => None,
+        None => {
+            let query = Uri::borrow_from(&state).query().unwrap_or("");
+            Some(format!("{}trending:{}", CACHE_PREFIX, query))
+        }
+    };
+    let result = match take_valid_query::<FeedQuery>(&mut state) {
+        Ok(query) => {
+            let (limit, offset) = query.page(Config::borrow_from(&state));
+            cached_json(cache, key, trending_page(repo, user_id, limit, offset)).await
+        }
+        Err(e) => Err(e),
+    };
+    json_body_response(state, result)
+}
+
+pub async fn trending_page(
+    repo: Repo,
+    user_id: Option<i32>,
+    limit: i64,
+    offset: i64,
+) -> Result<ArticlesResponse, ApiError> {
+    let (articles, articles_count) = trending::list(repo.clone(), limit, offset).await?;
+    Ok(ArticlesResponse {
+        articles: articles_json(repo, user_id, articles).await?,
+        articles_count,
+        next_cursor: None,
+    })
+}
+
+/// Articles containing the words in `q`, best match first.
+pub async fn search(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let result = match take_valid_query::<SearchQuery>(&mut state) {
+        Ok(query) => {
+            let defaults = ListParams::default();
+            let (limit, offset) = clamp_page(
+                Config::borrow_from(&state),
+                query.limit.unwrap_or(defaults.limit),
+                query.offset.unwrap_or(defaults.offset),
+            );
+            let q = query.q.unwrap_or_default();
+            search_results(repo, user_id, q, limit, offset).await
+        }
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(response) => json_response(&state, StatusCode::OK, &response),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+async fn search_results(
+    repo: Repo,
+    user_id: Option<i32>,
+    q: String,
+    limit: i64,
+    offset: i64,
+) -> Result<SearchResultsResponse, ApiError> {
+    let (results, articles_count) = search::search(repo.clone(), q, limit, offset).await?;
+    let mut matches: HashMap<i32, (String, f32)> = HashMap::new();
+    let mut articles = Vec::with_capacity(results.len());
+    for found in results {
+        matches.insert(found.article.id, (found.snippet, found.rank));
+        articles.push(found.article);
+    }
+    // Matched back up by id, so each result keeps its own snippet and rank.
+    let articles = articles_json(repo, user_id, articles)
+        .await?
+        .into_iter()
+        .filter_map(|article| {
+            let (snippet, rank) = matches.remove(&article.article.id)?;
+            Some(SearchResultJson {
+                article,
+                snippet,
+                rank,
+            })
+        })
+        .collect();
+    Ok(SearchResultsResponse {
+        articles,
+        articles_count,
+    })
+}
+
+/// Read an article. Each read is counted as a view, once the worker gets to it.
+pub async fn get_article(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let viewer = match user_id {
+        Some(user_id) => Some(Viewer::User(user_id)),
+        None => client_addr(&state).map(|addr| {
+            let secret = &Config::borrow_from(&state).jwt.secret;
+            Viewer::visitor(&addr.ip().to_string(), secret)
+        }),
+    };
+    let path = SlugPath::take_from(&mut state);
+    let slug = path.slug.clone();
+    let views_repo = repo.clone();
+    let render = match take_valid_query::<ArticleQuery>(&mut state) {
+        Ok(query) => query.render,
+        Err(e) => {
+            let res = e.into_response(&state);
+            return (state, res);
+        }
+    };
+    let key = match (user_id, render) {
+        (Some(_), _) => None,
+        (None, Some(Render::Html)) => Some(format!("{}slug:{}:html", CACHE_PREFIX, path.slug)),
+        (None, None) => Some(format!("{}slug:{}", CACHE_PREFIX, path.slug)),
+    };
+    let html_cache = cache.clone();
+    let article = async move {
+        let article = articles::find_visible(repo.clone(), path.slug, user_id).await?;
+        let mut article = article_json(repo, user_id, article).await?;
+        if render == Some(Render::Html) {
+            article.body_html = Some(body_html(html_cache, &article.article).await);
+        }
+        Ok::<_, ApiError>(ArticleResponse { article })
+    };
+    let res = match cached_json(cache, key, article).await {
+        Ok(body) => {
+            if let Some(viewer) = viewer {
+                record_view(views_repo, slug, viewer).await;
+            }
+            tagged_json_response(&state, body)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+pub async fn create(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let result = match extract_valid_json::<NewArticleRequest>(&mut state).await {
+        Ok(request) => insert_article(repo, user_id, request.article).await,
+        Err(e) => Err(e),
+    };
+    if result.is_ok() {
+        invalidate_articles_and_tags(cache).await;
+    }
+    article_response(state, result)
+}
+
+pub async fn update(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let if_match = HeaderMap::borrow_from(&state)
+        .get(IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let result = match extract_valid_json::<UpdateArticleRequest>(&mut state).await {
+        Ok(request) => {
+            update_own_article(repo, user_id, path.slug, if_match, request.article).await
+        }
+        Err(e) => Err(e),
+    };
+    if result.is_ok() {
+        invalidate_articles_and_tags(cache).await;
+    }
+    // The new ETag lets the editor make another edit without fetching the article again.
+    let res = match result.and_then(|article| to_json(&ArticleResponse { article })) {
+        Ok(body) => {
+            let tag = etag(body.as_bytes());
+            let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
+            with_etag(res, &tag)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+pub async fn delete(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let result = match find_own_article(repo.clone(), user_id, path.slug).await {
+        Ok(article) => articles::delete(repo, article.id)
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    if result.is_ok() {
+        invalidate_articles_and_tags(cache).await;
+    }
+    let res = match result {
+        Ok(_) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Publish one of the current user's drafts.
+pub async fn publish(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let result = publish_own_article(repo, user_id, path.slug).await;
+    if result.is_ok() {
+        invalidate_articles_and_tags(cache).await;
+    }
+    article_response(state, result)
+}
+
+/// What one of the current user's articles was before each of its edits.
+pub async fn revisions(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let path = SlugPath::take_from(&mut state);
+    let result = match find_own_article(repo.clone(), user_id, path.slug).await {
+        Ok(article) => articles::revisions(repo, article.id)
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(revisions) => {
+            let response = ArticleRevisionsResponse { revisions };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Articles to read after this one, sharing its tags or its author.
+pub async fn related(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let key = match user_id {
+        Some(_) => None,
+        None => {
+            let query = Uri::borrow_from(&state).query().unwrap_or("");
+            Some(format!("{}related:{}:{}", CACHE_PREFIX, path.slug, query))
+        }
+    };
+    let result = match take_valid_query::<RelatedQuery>(&mut state) {
+        Ok(query) => {
+            let limit = query.limit.unwrap_or(DEFAULT_RELATED_LIMIT);
+            let (limit, _) = clamp_page(Config::borrow_from(&state), limit, 0);
+            cached_json(cache, key, related_page(repo, user_id, path.slug, limit)).await
+        }
+        Err(e) => Err(e),
+    };
+    json_body_response(state, result)
+}
+
+pub async fn related_page(
+    repo: Repo,
+    user_id: Option<i32>,
+    slug: String,
+    limit: i64,
+) -> Result<ArticlesResponse, ApiError> {
+    let article = articles::find_visible(repo.clone(), slug, user_id).await?;
+    let articles = articles::related(repo, user_id, article, limit).await?;
+    Ok(ArticlesResponse {
+        articles_count: articles.len() as i64,
+        articles: articles.into_iter().map(ArticleJson::from).collect(),
+        next_cursor: None,
+    })
+}
+
+/// How one of the current user's articles has been viewed, favorited and commented on, in
+/// all and for each of the last `days` days.
+pub async fn stats(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let path = SlugPath::take_from(&mut state);
+    let days = match take_valid_query::<StatsQuery>(&mut state) {
+        Ok(query) => query.days.unwrap_or(DEFAULT_STATS_DAYS),
+        Err(e) => {
+            let res = e.into_response(&state);
+            return (state, res);
+        }
+    };
+    let to = Utc::now().naive_utc().date();
+    let from = to - Duration::days(days - 1);
+    let result = match find_own_article(repo.clone(), user_id, path.slug).await {
+        Ok(article) => views::stats(repo, article.id, from, to)
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(stats) => json_response(&state, StatusCode::OK, &ArticleStatsResponse { stats }),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Roll one of the current user's articles back to one of its revisions.
+pub async fn restore(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = RevisionIdPath::take_from(&mut state);
+    let result = restore_own_article(repo, user_id, path.slug, path.id).await;
+    if result.is_ok() {
+        invalidate_articles_and_tags(cache).await;
+    }
+    article_response(state, result)
+}
+
+pub async fn favorite(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let result = favorite_by_slug(repo, user_id, path.slug, true).await;
+    if result.is_ok() {
+        // Favorites counts are part of every cached article.
+        cache.invalidate(CACHE_PREFIX).await;
+    }
+    article_response(state, result.map_err(ApiError::from))
+}
+
+pub async fn unfavorite(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let result = favorite_by_slug(repo, user_id, path.slug, false).await;
+    if result.is_ok() {
+        // Favorites counts are part of every cached article.
+        cache.invalidate(CACHE_PREFIX).await;
+    }
+    article_response(state, result.map_err(ApiError::from))
+}
+
+/// Write a new article by the current user.
+pub async fn insert_article(
+    repo: Repo,
+    user_id: i32,
+    article: NewArticleData,
+) -> Result<ArticleJson, ApiError> {
+    let tag_list = article.tag_list;
+    let status = match article.publish_at {
+        Some(_) => Status::Draft,
+        None => article.status,
+    };
+    let new_article = NewArticle {
+        slug: slugs::slugify(&article.title),
+        title: article.title,
+        description: article.description,
+        body: article.body,
+        user_id,
+        status: status.name().to_string(),
+        publish_at: article.publish_at,
+        visibility: article.visibility.name().to_string(),
+        cover_image: article.cover_image,
+        canonical_url: article.canonical_url,
+    };
+    let (article, _) = articles::insert_with_tags(repo.clone(), new_article, tag_list).await?;
+    Ok(article_json(repo, Some(user_id), article).await?)
+}
+
+/// Favorite or unfavorite an article, returning it as the user now sees it.
+pub async fn favorite_by_slug(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+    favorite: bool,
+) -> Result<ArticleJson, RepoError> {
+    let article = articles::find_visible(repo.clone(), slug, Some(user_id)).await?;
+    if favorite {
+        favorites::favorite(repo.clone(), user_id, article.id).await?;
+    } else {
+        favorites::unfavorite(repo.clone(), user_id, article.id).await?;
+    }
+    article_json(repo, Some(user_id), article).await
+}
+
+/// The article's body rendered from Markdown, from the cache if it's been rendered since
+/// it was last edited.
+pub async fn body_html(cache: Arc<dyn Cache>, article: &Article) -> String {
+    let key = format!("{}{}:{}", HTML_CACHE_PREFIX, article.id, article.version);
+    if let Some(html) = cache.get(&key).await {
+        return html;
+    }
+    let html = markdown::to_html(&article.body);
+    cache.set(&key, html.clone()).await;
+    html
+}
+
+/// Queue counting a view of the article. Reading it doesn't fail if the view can't be.
+async fn record_view(repo: Repo, slug: String, viewer: Viewer) {
+    let job = Job::RecordView {
+        slug,
+        viewer,
+        viewed_at: Utc::now().naive_utc(),
+    };
+    if let Err(e) = jobs::enqueue(repo, job).await {
+        error!("Could not queue a view of an article: {}", e);
+    }
+}
+
+/// Clear the cached articles and tags after articles are added, edited or deleted.
+pub async fn invalidate_articles_and_tags(cache: Arc<dyn Cache>) {
+    future::join(
+        cache.invalidate(CACHE_PREFIX),
+        cache.invalidate(TAGS_CACHE_KEY),
+    )
+    .await;
+}
+
+/// Find an article that the current user is allowed to modify. Someone else's draft or
+/// private article is a 404, as it would be to read.
+pub async fn find_own_article(repo: Repo, user_id: i32, slug: String) -> Result<Article, ApiError> {
+    let article = articles::find_visible(repo, slug, Some(user_id)).await?;
+    if article.user_id == user_id {
+        Ok(article)
+    } else {
+        Err(ApiError::forbidden())
+    }
+}
+
+/// Edit an article of the current user's. With `if_match`, the edit is only made if the
+/// article's ETag is still the one given, i.e. the editor has seen its latest version.
+pub async fn update_own_article(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+    if_match: Option<String>,
+    changes: UpdateArticle,
+) -> Result<ArticleJson, ApiError> {
+    let article = find_own_article(repo.clone(), user_id, slug).await?;
+    if changes.publish_at.is_some() && article.status != Status::Draft.name() {
+        return Err(ApiError::unprocessable_entity(
+            "publishAt",
+            "can only be set on drafts",
+        ));
+    }
+    let (article_id, version) = (article.id, article.version);
+    if let Some(if_match) = if_match {
+        let current = article_json(repo.clone(), Some(user_id), article).await?;
+        let body = to_json(&ArticleResponse { article: current })?;
+        if !lists_etag(&if_match, &etag(body.as_bytes())) {
+            return Err(ApiError::precondition_failed());
+        }
+    }
+    // The version check catches edits made since the article was read above.
+    match articles::update(repo.clone(), article_id, version, changes).await? {
+        Some(article) => Ok(article_json(repo, Some(user_id), article).await?),
+        None => Err(ApiError::precondition_failed()),
+    }
+}
+
+/// Publish a draft of the current user's.
+pub async fn publish_own_article(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+) -> Result<ArticleJson, ApiError> {
+    let article = find_own_article(repo.clone(), user_id, slug).await?;
+    let article = articles::publish(repo.clone(), article.id).await?;
+    Ok(article_json(repo, Some(user_id), article).await?)
+}
+
+/// Restore an article of the current user's to what it was in revision `revision_id`.
+pub async fn restore_own_article(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+    revision_id: i32,
+) -> Result<ArticleJson, ApiError> {
+    let article = find_own_article(repo.clone(), user_id, slug).await?;
+    let article = articles::restore(repo.clone(), article.id, revision_id).await?;
+    Ok(article_json(repo, Some(user_id), article).await?)
+}
+
+pub async fn article_json(
+    repo: Repo,
+    user_id: Option<i32>,
+    article: Article,
+) -> Result<ArticleJson, RepoError> {
+    let mut articles = articles_json(repo, user_id, vec![article]).await?;
+    articles
+        .pop()
+        .ok_or(RepoError::Query(diesel::result::Error::NotFound))
+}
+
+/// Look up the authors, tags and favorites for a page of articles in batches, rather than
+/// per article.
+pub async fn articles_json(
+    repo: Repo,
+    user_id: Option<i32>,
+    articles: Vec<Article>,
+) -> Result<Vec<ArticleJson>, RepoError> {
+    let articles = articles::details(repo, user_id, articles).await?;
+    Ok(articles.into_iter().map(ArticleJson::from).collect())
+}
+
+fn article_response(
+    state: State,
+    result: Result<ArticleJson, ApiError>,
+) -> (State, Response<Body>) {
+    let res = match result {
+        Ok(article) => {
+            let response = ArticleResponse { article };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+fn articles_response(
+    state: State,
+    result: Result<ArticlesResponse, ApiError>,
+) -> (State, Response<Body>) {
+    let res = match result {
+        Ok(response) => json_response(&state, StatusCode::OK, &response),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
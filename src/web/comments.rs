@@ -0,0 +1,446 @@
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::router::response::extender::StaticResponseExtender;
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::SharedCache;
+use crate::conduit::articles;
+use crate::conduit::comments::{self, Order};
+use crate::config::Config;
+use crate::db::RepoError;
+use crate::models::{Comment, CommentRevision, NewComment};
+use crate::web::articles::CACHE_PREFIX;
+use crate::web::errors::ApiError;
+use crate::web::extractors::{CommentIdPath, SlugPath};
+use crate::web::query::{self, check_page, take_valid_query};
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::validation::{is_blank, Validate, Validator};
+use crate::web::{
+    clamp_page, current_user_id, extract_valid_json, handler, json_response, optional_user_id,
+};
+use crate::Repo;
+
+#[derive(Deserialize, StateData)]
+pub struct CommentsQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// `oldest` (the default) or `newest`. Anything else is a 422.
+    pub order: Option<Order>,
+}
+
+impl StaticResponseExtender for CommentsQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for CommentsQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        check_page(Validator::default(), self.limit, self.offset).finish()
+    }
+}
+
+impl CommentsQuery {
+    /// The `limit` and `offset` asked for, clamped to the configured page size. Without a
+    /// `limit`, a page is as long as it's allowed to be.
+    pub fn page(&self, config: &Config) -> (i64, i64) {
+        clamp_page(
+            config,
+            self.limit.unwrap_or(config.max_page_size),
+            self.offset.unwrap_or(0),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewCommentRequest {
+    comment: NewCommentData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewCommentData {
+    pub body: String,
+    /// The comment on the same article this replies to.
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+}
+
+impl Validate for NewCommentRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        self.comment.validate()
+    }
+}
+
+impl Validate for NewCommentData {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.body), "body", "can't be blank")
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCommentRequest {
+    comment: UpdateCommentData,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCommentData {
+    pub body: String,
+}
+
+impl Validate for UpdateCommentRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        self.comment.validate()
+    }
+}
+
+impl Validate for UpdateCommentData {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.body), "body", "can't be blank")
+            .finish()
+    }
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct CommentPath {
+    id: i32,
+}
+
+/// A comment along with the usernames it mentions.
+#[derive(Serialize)]
+pub struct CommentJson {
+    #[serde(flatten)]
+    comment: Comment,
+    mentions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct CommentResponse {
+    comment: CommentJson,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentsResponse {
+    comments: Vec<CommentJson>,
+    /// How many comments there are in all, across every page.
+    comments_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct CommentRevisionsResponse {
+    revisions: Vec<CommentRevision>,
+}
+
+/// Draw the routes for an article's comments.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_optional, |route| {
+        route
+            .get("/articles/:slug/comments")
+            .with_path_extractor::<SlugPath>()
+            .with_query_string_extractor::<CommentsQuery>()
+            .to(handler(list));
+    });
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route
+            .post("/articles/:slug/comments")
+            .with_path_extractor::<SlugPath>()
+            .to(handler(create));
+        route
+            .put("/articles/:slug/comments/:id")
+            .with_path_extractor::<CommentIdPath>()
+            .to(handler(update));
+        route
+            .delete("/articles/:slug/comments/:id")
+            .with_path_extractor::<CommentIdPath>()
+            .to(handler(delete));
+    });
+    route.with_pipeline_chain(chains.admin, |route| {
+        route
+            .get("/admin/comments/:id/revisions")
+            .with_path_extractor::<CommentPath>()
+            .to(handler(revisions));
+    });
+}
+
+/// A page of an article's comments, oldest first unless asked for the newest.
+pub async fn list(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let path = SlugPath::take_from(&mut state);
+    let query = match take_valid_query::<CommentsQuery>(&mut state) {
+        Ok(query) => query,
+        Err(e) => {
+            let res = e.into_response(&state);
+            return (state, res);
+        }
+    };
+    let (limit, offset) = query.page(Config::borrow_from(&state));
+    let order = query.order.unwrap_or(Order::Oldest);
+    let res = match list_page(repo, user_id, path.slug, order, limit, offset).await {
+        Ok(response) => json_response(&state, StatusCode::OK, &response),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+async fn list_page(
+    repo: Repo,
+    user_id: Option<i32>,
+    slug: String,
+    order: Order,
+    limit: i64,
+    offset: i64,
+) -> Result<CommentsResponse, RepoError> {
+    let article = articles::find_visible(repo.clone(), slug, user_id).await?;
+    let (page, count) = comments::list(repo.clone(), article.id, order, limit, offset).await?;
+    Ok(CommentsResponse {
+        comments: with_mentions(repo, page).await?,
+        comments_count: count,
+    })
+}
+
+pub async fn create(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = SlugPath::take_from(&mut state);
+    let result = match extract_valid_json::<NewCommentRequest>(&mut state).await {
+        Ok(request) => {
+            let NewCommentData { body, parent_id } = request.comment;
+            insert_comment(repo.clone(), user_id, path.slug, body, parent_id).await
+        }
+        Err(e) => Err(e),
+    };
+    if result.is_ok() {
+        // Comment counts are part of every cached article.
+        cache.invalidate(CACHE_PREFIX).await;
+    }
+    let result = match result {
+        Ok(comment) => with_mentions(repo, vec![comment])
+            .await
+            .map(|mut comments| comments.remove(0))
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(comment) => {
+            let response = CommentResponse { comment };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Edit a comment. Only the comment's author may do this.
+pub async fn update(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let path = CommentIdPath::take_from(&mut state);
+    let result = match extract_valid_json::<UpdateCommentRequest>(&mut state).await {
+        Ok(request) => {
+            let body = request.comment.body;
+            update_own_comment(repo.clone(), user_id, path.slug, path.id, body).await
+        }
+        Err(e) => Err(e),
+    };
+    let result = match result {
+        Ok(comment) => with_mentions(repo, vec![comment])
+            .await
+            .map(|mut comments| comments.remove(0))
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(comment) => {
+            let response = CommentResponse { comment };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// What a comment said before each time it was edited, for moderators.
+pub async fn revisions(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let path = CommentPath::take_from(&mut state);
+    let res = match comments::revisions(repo, path.id).await {
+        Ok(revisions) => {
+            let response = CommentRevisionsResponse { revisions };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Delete a comment. Only the comment's author may do this.
+pub async fn delete(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = CommentIdPath::take_from(&mut state);
+    let res = match delete_own_comment(repo, user_id, path.slug, path.id).await {
+        Ok(_) => {
+            cache.invalidate(CACHE_PREFIX).await;
+            create_empty_response(&state, StatusCode::OK)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Add the usernames each comment mentions, keeping their order.
+async fn with_mentions(repo: Repo, comments: Vec<Comment>) -> Result<Vec<CommentJson>, RepoError> {
+    let ids = comments.iter().map(|comment| comment.id).collect();
+    let mut mentioned = comments::mentioned(repo, ids).await?;
+    Ok(comments
+        .into_iter()
+        .map(|comment| CommentJson {
+            mentions: mentioned.remove(&comment.id).unwrap_or_default(),
+            comment,
+        })
+        .collect())
+}
+
+/// Comment on the article with `slug`, or reply to one of its comments.
+pub async fn insert_comment(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+    body: String,
+    parent_id: Option<i32>,
+) -> Result<Comment, ApiError> {
+    let article = articles::find_visible(repo.clone(), slug, Some(user_id)).await?;
+    if let Some(parent_id) = parent_id {
+        match comments::find(repo.clone(), article.id, parent_id).await {
+            Ok(_) => (),
+            Err(RepoError::Query(diesel::result::Error::NotFound)) => {
+                return Err(ApiError::unprocessable_entity(
+                    "parentId",
+                    "must be a comment on the same article",
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let new_comment = NewComment {
+        body,
+        article_id: article.id,
+        user_id,
+        parent_id,
+    };
+    Ok(comments::insert(repo, new_comment).await?)
+}
+
+/// Edit a comment on the article with `slug`, as long as `user_id` wrote it.
+pub async fn update_own_comment(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+    comment_id: i32,
+    body: String,
+) -> Result<Comment, ApiError> {
+    let article = articles::find_visible(repo.clone(), slug, Some(user_id)).await?;
+    let comment = comments::find(repo.clone(), article.id, comment_id).await?;
+    if comment.user_id != user_id {
+        return Err(ApiError::forbidden());
+    }
+    Ok(comments::update(repo, comment.id, body).await?)
+}
+
+pub async fn delete_own_comment(
+    repo: Repo,
+    user_id: i32,
+    slug: String,
+    comment_id: i32,
+) -> Result<(), ApiError> {
+    let article = articles::find_visible(repo.clone(), slug, Some(user_id)).await?;
+    let comment = comments::find(repo.clone(), article.id, comment_id).await?;
+    if comment.user_id != user_id {
+        return Err(ApiError::forbidden());
+    }
+    comments::delete(repo, comment.id).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_reply() {
+        let repo = repo();
+        block_on(async move {
+            let user = generate::user().insert(repo.clone()).await;
+            let article = generate::article(user.id).insert(repo.clone()).await;
+            let other = generate::article(user.id).insert(repo.clone()).await;
+            let parent = generate::comment(article.id, user.id)
+                .insert(repo.clone())
+                .await;
+            let elsewhere = generate::comment(other.id, user.id)
+                .insert(repo.clone())
+                .await;
+
+            let slug = article.slug.clone();
+            let body = "Indeed".to_string();
+            let reply = insert_comment(repo.clone(), user.id, slug, body, Some(parent.id))
+                .await
+                .unwrap();
+            assert_eq!(reply.parent_id, Some(parent.id));
+
+            let body = "Wrong thread".to_string();
+            let e = insert_comment(repo, user.id, article.slug, body, Some(elsewhere.id))
+                .await
+                .unwrap_err();
+            assert_eq!(e.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        });
+    }
+
+    #[test]
+    fn test_update_own_comment() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let other = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+            let comment = generate::comment(article.id, author.id)
+                .insert(repo.clone())
+                .await;
+
+            let slug = article.slug.clone();
+            let body = "Edited".to_string();
+            let e = update_own_comment(repo.clone(), other.id, slug, comment.id, body)
+                .await
+                .unwrap_err();
+            assert_eq!(e.status(), StatusCode::FORBIDDEN);
+
+            let body = "Edited".to_string();
+            let updated = update_own_comment(repo, author.id, article.slug, comment.id, body)
+                .await
+                .unwrap();
+            assert_eq!(updated.body, "Edited");
+        });
+    }
+}
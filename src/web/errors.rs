@@ -0,0 +1,333 @@
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind};
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_response;
+use gotham::state::{request_id, State};
+use hyper::header::{HeaderValue, RETRY_AFTER};
+use hyper::{Body, Response, StatusCode};
+use juniper::{FieldError, IntoFieldError, Object, Value};
+use log::error;
+use mime;
+use serde_derive::Serialize;
+use serde_json;
+use std::collections::BTreeMap;
+
+use crate::db::RepoError;
+use std::fmt;
+
+/// How long clients are asked to wait before retrying when the service is overloaded.
+const RETRY_AFTER_SECONDS: u64 = 1;
+
+/// Unique constraints a client can run into, the column they cover,
+/// and the field to report them against.
+/// Postgres names the violated constraint, while SQLite only names the column in its message
+/// and MySQL the constraint.
+const UNIQUE_FIELDS: &[(&str, &str, &str)] = &[
+    ("users_email_key", "users.email", "email"),
+    ("users_email_lower_key", "users.email", "email"),
+    ("users_username_key", "users.username", "username"),
+];
+
+/// A failed request, rendered in the RealWorld error format:
+/// `{"errors": {"body": ["can't be empty"]}}`
+/// When rendered, the request id is added as `requestId` so a failure can be found in the logs.
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    /// What went wrong internally, logged rather than shown to the client.
+    #[serde(skip)]
+    cause: Option<String>,
+    /// Sent as a `Retry-After` header, in seconds.
+    #[serde(skip)]
+    retry_after: Option<u64>,
+    errors: BTreeMap<String, Vec<String>>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, field: &str, message: &str) -> Self {
+        ApiError {
+            status,
+            cause: None,
+            retry_after: None,
+            errors: BTreeMap::new(),
+            request_id: None,
+        }
+        .and(field, message)
+    }
+
+    /// A validation failure, which the frontend displays next to the form.
+    pub fn unprocessable_entity(field: &str, message: &str) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, field, message)
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "body", message)
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "body", "unauthorized")
+    }
+
+    pub fn forbidden() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "body", "forbidden")
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "body", "not found")
+    }
+
+    /// The resource changed since the version the client sent in `If-Match`.
+    pub fn precondition_failed() -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            "body",
+            "has been changed by someone else, reload it and try again",
+        )
+    }
+
+    /// The request body is over the `max_bytes` the server accepts.
+    pub fn payload_too_large(max_bytes: usize) -> Self {
+        Self::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "body",
+            &format!("is too large, the limit is {} bytes", max_bytes),
+        )
+    }
+
+    pub fn unsupported_media_type() -> Self {
+        Self::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "body",
+            "must be sent as application/json",
+        )
+    }
+
+    pub fn internal_server_error() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "body",
+            "internal server error",
+        )
+    }
+
+    /// The database is overloaded or unreachable. Clients can retry shortly.
+    pub fn service_unavailable() -> Self {
+        let mut e = Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "body",
+            "service temporarily unavailable",
+        );
+        e.retry_after = Some(RETRY_AFTER_SECONDS);
+        e
+    }
+
+    /// The client has to wait `retry_after` seconds before trying again.
+    pub fn too_many_requests(retry_after: u64) -> Self {
+        let mut e = Self::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "body",
+            "too many failed attempts, try again later",
+        );
+        e.retry_after = Some(retry_after);
+        e
+    }
+
+    /// Add another message, so several problems can be reported at once.
+    pub fn and(mut self, field: &str, message: &str) -> Self {
+        self.errors
+            .entry(field.to_string())
+            .or_insert_with(Vec::new)
+            .push(message.to_string());
+        self
+    }
+
+    /// Record the underlying error, to be logged along with the request id.
+    pub fn caused_by<E: fmt::Display>(mut self, cause: E) -> Self {
+        self.cause = Some(cause.to_string());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {:?}", self.status, self.errors)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => ApiError::not_found(),
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                unique_violation(info.as_ref())
+            }
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UnableToSendCommand, info) => {
+                ApiError::service_unavailable().caused_by(info.message())
+            }
+            e => ApiError::internal_server_error().caused_by(format!("Database error: {}", e)),
+        }
+    }
+}
+
+impl From<RepoError> for ApiError {
+    fn from(e: RepoError) -> Self {
+        match e {
+            RepoError::Pool(e) => ApiError::service_unavailable().caused_by(e),
+            RepoError::Query(e) => ApiError::from(e),
+        }
+    }
+}
+
+fn unique_violation(info: &dyn DatabaseErrorInformation) -> ApiError {
+    let field = UNIQUE_FIELDS
+        .iter()
+        .find(|(constraint, column, _)| match info.constraint_name() {
+            Some(name) => name == *constraint,
+            None => info.message().ends_with(column) || info.message().contains(constraint),
+        })
+        .map(|(_, _, field)| *field)
+        .unwrap_or("body");
+    ApiError::unprocessable_entity(field, "has already been taken")
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(mut self, state: &State) -> Response<Body> {
+        let id = request_id(state);
+        if let Some(ref cause) = self.cause {
+            error!("[{}] {}", id, cause);
+        }
+        self.request_id = Some(id.to_string());
+        let body = serde_json::to_string(&self).unwrap_or_else(|e| {
+            error!("[{}] Failed to serialize errors: {}", id, e);
+            r#"{"errors":{"body":["internal server error"]}}"#.to_string()
+        });
+        let mut res = create_response(state, self.status, mime::APPLICATION_JSON, body);
+        if let Some(seconds) = self.retry_after {
+            res.headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        res
+    }
+}
+
+/// In a GraphQL response, the messages are joined into the error's message, and the status
+/// and messages by field that REST would have sent are in its `extensions`.
+impl IntoFieldError for ApiError {
+    fn into_field_error(self) -> FieldError {
+        if let Some(ref cause) = self.cause {
+            error!("{}", cause);
+        }
+        let message = self
+            .errors
+            .iter()
+            .map(|(field, messages)| format!("{} {}", field, messages.join(", ")))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut errors = Object::with_capacity(self.errors.len());
+        for (field, messages) in self.errors {
+            let messages = messages.into_iter().map(Value::scalar).collect();
+            errors.add_field(field, Value::list(messages));
+        }
+        let mut extensions = Object::with_capacity(2);
+        extensions.add_field("status", Value::scalar(i32::from(self.status.as_u16())));
+        extensions.add_field("errors", Value::object(errors));
+        FieldError::new(message, Value::object(extensions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_error_envelope() {
+        let e = ApiError::unprocessable_entity("email", "can't be blank")
+            .and("email", "is invalid")
+            .and("password", "is too short");
+        assert_eq!(e.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::to_value(&e).unwrap(),
+            json!({
+                "errors": {
+                    "email": ["can't be blank", "is invalid"],
+                    "password": ["is too short"],
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_diesel_error() {
+        let e = ApiError::from(diesel::result::Error::NotFound);
+        assert_eq!(e.status(), StatusCode::NOT_FOUND);
+        let e = ApiError::from(diesel::result::Error::RollbackTransaction);
+        assert_eq!(e.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    struct UniqueViolation(&'static str);
+
+    impl DatabaseErrorInformation for UniqueViolation {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint"
+        }
+        fn details(&self) -> Option<&str> {
+            None
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            None
+        }
+        fn column_name(&self) -> Option<&str> {
+            None
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn test_from_connection_failure() {
+        let e = ApiError::from(diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::UnableToSendCommand,
+            Box::new("timed out".to_string()),
+        ));
+        assert_eq!(e.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(e.retry_after, Some(RETRY_AFTER_SECONDS));
+    }
+
+    #[test]
+    fn test_from_unnamed_unique_violation() {
+        let e = ApiError::from(diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new("UNIQUE constraint failed: users.username".to_string()),
+        ));
+        assert_eq!(
+            serde_json::to_value(&e).unwrap(),
+            json!({"errors": {"username": ["has already been taken"]}})
+        );
+    }
+
+    #[test]
+    fn test_from_unique_violation() {
+        let e = ApiError::from(diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new(UniqueViolation("users_email_key")),
+        ));
+        assert_eq!(e.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::to_value(&e).unwrap(),
+            json!({"errors": {"email": ["has already been taken"]}})
+        );
+    }
+}
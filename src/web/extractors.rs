@@ -0,0 +1,85 @@
+use gotham::handler::IntoResponse;
+use gotham::router::response::extender::StaticResponseExtender;
+use gotham::state::State;
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response};
+use serde_derive::Deserialize;
+
+use crate::web::errors::ApiError;
+
+/// The `:slug` of an article route.
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct SlugPath {
+    pub slug: String,
+}
+
+/// The `:username` of a profile route.
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct UsernamePath {
+    pub username: String,
+}
+
+/// The `:tag` of a tag route.
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct TagPath {
+    pub tag: String,
+}
+
+/// The `:slug` of an article and the `:id` of one of its comments.
+#[derive(Deserialize, StateData)]
+pub struct CommentIdPath {
+    pub slug: String,
+    pub id: i32,
+}
+
+/// An id that isn't a number can't be any comment's, so it's a 404 like any other missing one.
+impl StaticResponseExtender for CommentIdPath {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        *res = ApiError::not_found().into_response(state);
+    }
+}
+
+/// The `:slug` of an article and the `:id` of one of its revisions.
+#[derive(Deserialize, StateData)]
+pub struct RevisionIdPath {
+    pub slug: String,
+    pub id: i32,
+}
+
+/// Like comments, an id that isn't a number can't be any revision's.
+impl StaticResponseExtender for RevisionIdPath {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        *res = ApiError::not_found().into_response(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mail::StdoutMailer;
+    use crate::router_with_repositories;
+    use crate::test_helpers;
+    use gotham::test::TestServer;
+    use hyper::StatusCode;
+    use std::sync::Arc;
+
+    #[test]
+    fn comment_id_must_be_a_number() {
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let res = server
+            .client()
+            .delete("http://localhost/api/articles/some-slug/comments/first")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
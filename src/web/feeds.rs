@@ -0,0 +1,215 @@
+use chrono::Utc;
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::Deserialize;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use crate::atom::{Entry, Feed};
+use crate::cache::SharedCache;
+use crate::conduit::articles::{self, ListParams};
+use crate::config::Config;
+use crate::web::articles::CACHE_PREFIX;
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::{cached_text, handler};
+use crate::Repo;
+
+/// How many of the latest articles a feed has.
+const FEED_LENGTH: i64 = 20;
+
+/// The `:file` of a feed route, e.g. `jake.xml`.
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct FeedPath {
+    file: String,
+}
+
+impl FeedPath {
+    /// The author or tag the feed is for, without the `.xml`.
+    fn name(&self) -> Option<String> {
+        if !self.file.ends_with(".xml") {
+            return None;
+        }
+        let name = &self.file[..self.file.len() - ".xml".len()];
+        if name.is_empty() {
+            return None;
+        }
+        Some(name.to_string())
+    }
+}
+
+/// Which articles a feed follows.
+enum Source {
+    All,
+    Author(String),
+    Tag(String),
+}
+
+impl Source {
+    /// Where the feed is served, with the name encoded.
+    fn path(&self) -> String {
+        let encode = |name: &str| utf8_percent_encode(name, PATH_SEGMENT_ENCODE_SET).to_string();
+        match self {
+            Source::All => "/feeds/articles.xml".to_string(),
+            Source::Author(username) => format!("/feeds/authors/{}.xml", encode(username)),
+            Source::Tag(tag) => format!("/feeds/tags/{}.xml", encode(tag)),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Source::All => "Conduit".to_string(),
+            Source::Author(username) => format!("Conduit: articles by {}", username),
+            Source::Tag(tag) => format!("Conduit: articles tagged {}", tag),
+        }
+    }
+
+    /// The latest articles, as visitors see them.
+    fn into_params(self) -> ListParams {
+        let (author, tag) = match self {
+            Source::All => (None, None),
+            Source::Author(username) => (Some(username), None),
+            Source::Tag(tag) => (None, Some(tag)),
+        };
+        ListParams {
+            author,
+            tag,
+            limit: FEED_LENGTH,
+            ..ListParams::default()
+        }
+    }
+}
+
+/// Draw the Atom feed routes. They're served outside `/api`, for feed readers.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, _chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.get("/feeds/articles.xml").to(handler(latest));
+    route
+        .get("/feeds/authors/:file")
+        .with_path_extractor::<FeedPath>()
+        .to(handler(author));
+    route
+        .get("/feeds/tags/:file")
+        .with_path_extractor::<FeedPath>()
+        .to(handler(tag));
+}
+
+pub async fn latest(state: State) -> (State, Response<Body>) {
+    feed_response(state, Some(Source::All)).await
+}
+
+pub async fn author(mut state: State) -> (State, Response<Body>) {
+    let source = FeedPath::take_from(&mut state).name().map(Source::Author);
+    feed_response(state, source).await
+}
+
+pub async fn tag(mut state: State) -> (State, Response<Body>) {
+    let source = FeedPath::take_from(&mut state).name().map(Source::Tag);
+    feed_response(state, source).await
+}
+
+/// Respond with the feed, from the cache when it's there. It's cleared with the cached
+/// articles, whenever they change.
+async fn feed_response(state: State, source: Option<Source>) -> (State, Response<Body>) {
+    let source = match source {
+        Some(source) => source,
+        None => {
+            let res = ApiError::not_found().into_response(&state);
+            return (state, res);
+        }
+    };
+    let repo = Repo::borrow_from(&state).clone();
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let public_url = Config::borrow_from(&state).public_url.clone();
+    let key = format!("{}feed:{}", CACHE_PREFIX, source.path());
+    let result = cached_text(cache, Some(key), build_feed(repo, public_url, source)).await;
+    let res = match result {
+        Ok(xml) => {
+            let atom = "application/atom+xml; charset=utf-8".parse::<mime::Mime>();
+            create_response(
+                &state,
+                StatusCode::OK,
+                atom.expect("the media type is valid"),
+                xml,
+            )
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// The feed's latest articles as Atom, linking to where the frontend shows them.
+async fn build_feed(repo: Repo, public_url: String, source: Source) -> Result<String, ApiError> {
+    let public_url = public_url.trim_end_matches('/');
+    let url = format!("{}{}", public_url, source.path());
+    let title = source.title();
+    let (articles, _) = articles::list(repo, None, source.into_params()).await?;
+    let entries = articles
+        .into_iter()
+        .map(|dto| Entry {
+            url: format!("{}/article/{}", public_url, dto.article.slug),
+            title: dto.article.title,
+            summary: dto.article.description,
+            author: dto.author.username,
+            categories: dto.tag_list,
+            published: dto.article.created_at,
+            updated: dto.article.updated_at,
+        })
+        .collect();
+    let feed = Feed {
+        url,
+        title,
+        updated: Utc::now().naive_utc(),
+        entries,
+    };
+    Ok(feed.to_xml())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo;
+    use crate::test_helpers::{block_on, generate};
+
+    #[test]
+    fn test_feed_name() {
+        let path = |file: &str| FeedPath {
+            file: file.to_string(),
+        };
+        assert_eq!(path("jake.xml").name(), Some("jake".to_string()));
+        assert_eq!(path("jake").name(), None);
+        assert_eq!(path(".xml").name(), None);
+        assert_eq!(
+            Source::Tag("train your dragon".to_string()).path(),
+            "/feeds/tags/train%20your%20dragon.xml"
+        );
+    }
+
+    #[test]
+    fn test_author_feed() {
+        let repo = repo();
+        block_on(async move {
+            let author = generate::user().insert(repo.clone()).await;
+            let article = generate::article(author.id).insert(repo.clone()).await;
+            let other = generate::user().insert(repo.clone()).await;
+            generate::article(other.id).insert(repo.clone()).await;
+
+            let public_url = "http://localhost:7878/".to_string();
+            let source = Source::Author(author.username.clone());
+            let xml = build_feed(repo, public_url, source).await.unwrap();
+            let link = format!("http://localhost:7878/article/{}", article.slug);
+            assert!(xml.contains(&format!("<id>{}</id>", link)));
+            assert_eq!(xml.matches("<entry>").count(), 1);
+        });
+    }
+}
@@ -0,0 +1,170 @@
+use futures::compat::Future01CompatExt;
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_response;
+use gotham::state::{FromState, State};
+use hyper::header::{HeaderValue, CACHE_CONTROL};
+use hyper::{Body, Response, StatusCode, Uri};
+use std::path::{Path, PathBuf};
+use url::percent_encoding::percent_decode;
+
+use crate::config::Config;
+use crate::web::errors::ApiError;
+
+/// The page served for paths that aren't files, for the frontend's own router to handle.
+const INDEX: &str = "index.html";
+
+/// Serve a file of the configured frontend, or its `index.html` when there's no such file.
+/// Paths under `/api` that no route matched are a 404 as usual.
+pub async fn serve(state: State) -> (State, Response<Body>) {
+    let directory = Config::borrow_from(&state).frontend_dir.clone();
+    let path = Uri::borrow_from(&state).path().to_string();
+    let directory = match directory {
+        Some(ref directory) if !is_api(&path) => directory,
+        _ => {
+            let res = ApiError::not_found().into_response(&state);
+            return (state, res);
+        }
+    };
+    let file = match file_path(directory, &path) {
+        Some(file) => read(&file).await.map(|body| (file, body)),
+        None => None,
+    };
+    let index = directory.join(INDEX);
+    let (file, body) = match file {
+        Some(found) => found,
+        None => match read(&index).await {
+            Some(body) => (index, body),
+            None => {
+                let res = ApiError::not_found().into_response(&state);
+                return (state, res);
+            }
+        },
+    };
+    let mut res = create_response(&state, StatusCode::OK, content_type(&file), body);
+    // The index links to the other files, so it's checked for changes on every visit.
+    let cache_control = if file.ends_with(INDEX) {
+        "no-cache"
+    } else {
+        "public, max-age=3600"
+    };
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    (state, res)
+}
+
+fn is_api(path: &str) -> bool {
+    path == "/api" || path.starts_with("/api/")
+}
+
+/// The file a request path names within `directory`, unless it tries to leave it.
+fn file_path(directory: &Path, path: &str) -> Option<PathBuf> {
+    let mut file = directory.to_path_buf();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let segment = percent_decode(segment.as_bytes()).decode_utf8().ok()?;
+        if segment == "." || segment == ".." || segment.contains(|c| c == '/' || c == '\\') {
+            return None;
+        }
+        file.push(segment.as_ref());
+    }
+    if file == directory {
+        return None;
+    }
+    Some(file)
+}
+
+/// The file's contents, if it's a file that can be read.
+async fn read(file: &Path) -> Option<Vec<u8>> {
+    tokio::fs::read(file.to_path_buf()).compat().await.ok()
+}
+
+/// The media type of the files frontends are built into, by extension.
+fn content_type(file: &Path) -> mime::Mime {
+    let extension = file.extension().and_then(|extension| extension.to_str());
+    let media_type = match extension.unwrap_or("") {
+        "html" => "text/html; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    };
+    media_type.parse().expect("the media types are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{self, fakes};
+    use gotham::test::TestServer;
+    use hyper::header::CONTENT_TYPE;
+    use std::fs;
+
+    #[test]
+    fn test_file_path() {
+        let directory = Path::new("/srv/frontend");
+        assert_eq!(
+            file_path(directory, "/assets/app.js"),
+            Some(directory.join("assets").join("app.js"))
+        );
+        assert_eq!(
+            file_path(directory, "/my%20article"),
+            Some(directory.join("my article"))
+        );
+        assert_eq!(file_path(directory, "/"), None);
+        assert_eq!(file_path(directory, "/../secrets"), None);
+        assert_eq!(file_path(directory, "/%2e%2e/secrets"), None);
+        assert_eq!(file_path(directory, "/a%2f..%2f..%2fsecrets"), None);
+    }
+
+    #[test]
+    fn test_serve_frontend() {
+        let directory = std::env::temp_dir().join("conduit-test-frontend");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join(INDEX), "<html></html>").unwrap();
+        fs::write(directory.join("app.js"), "conduit();").unwrap();
+        let mut config = test_helpers::config();
+        config.frontend_dir = Some(directory);
+        let server = TestServer::new(fakes::router(config)).unwrap();
+
+        let res = server
+            .client()
+            .get("http://localhost/app.js")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[CONTENT_TYPE],
+            "application/javascript; charset=utf-8"
+        );
+        assert_eq!(res.read_body().unwrap(), b"conduit();");
+
+        for path in &["/", "/article/how-to-train-your-dragon", "/settings"] {
+            let url = format!("http://localhost{}", path);
+            let res = server.client().get(url).perform().unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers()[CACHE_CONTROL], "no-cache");
+            assert_eq!(res.read_body().unwrap(), b"<html></html>");
+        }
+
+        let res = server
+            .client()
+            .get("http://localhost/api/no-such-route")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = server
+            .client()
+            .get("http://localhost/healthz")
+            .perform()
+            .unwrap();
+        assert_eq!(res.read_body().unwrap(), br#"{"status":"ok"}"#);
+    }
+}
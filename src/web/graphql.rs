@@ -0,0 +1,632 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::compat::Future01CompatExt;
+use futures::executor::block_on;
+use futures01::future::poll_fn;
+use futures01::Async;
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use hyper::{Body, Response, StatusCode};
+use juniper::http::GraphQLRequest;
+use juniper::{GraphQLInputObject, GraphQLObject, RootNode};
+use lazy_static::lazy_static;
+use mime;
+use std::sync::Arc;
+use tokio_threadpool::blocking;
+
+use crate::cache::{Cache, SharedCache};
+use crate::conduit::articles::{Status, Visibility};
+use crate::conduit::comments::Order;
+use crate::conduit::{articles, comments, followers, tags, Repositories};
+use crate::config::Config;
+use crate::models::{self, UpdateArticle};
+use crate::web::articles::{
+    article_json, favorite_by_slug, feed_page, find_own_article, insert_article,
+    invalidate_articles_and_tags, list_page, publish_own_article, update_own_article, ArticleJson,
+    ArticlesQuery, ArticlesResponse, FeedQuery, NewArticleData, CACHE_PREFIX,
+};
+use crate::web::comments::{
+    delete_own_comment, insert_comment, update_own_comment, CommentsQuery, NewCommentData,
+    UpdateCommentData,
+};
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::validation::Validate;
+use crate::web::{extract_json, handler, optional_user_id, to_json};
+use crate::Repo;
+
+pub type Schema = RootNode<'static, Query, Mutation>;
+
+lazy_static! {
+    static ref SCHEMA: Schema = Schema::new(Query, Mutation);
+}
+
+/// What resolvers run with: the same storage as the REST handlers, and who is asking.
+pub struct Context {
+    repo: Repo,
+    repositories: Repositories,
+    cache: Arc<dyn Cache>,
+    config: Config,
+    user_id: Option<i32>,
+}
+
+impl juniper::Context for Context {}
+
+impl Context {
+    /// The signed in user's id, for the fields only they can use.
+    fn current_user_id(&self) -> Result<i32, ApiError> {
+        self.user_id.ok_or_else(ApiError::unauthorized)
+    }
+}
+
+fn utc(timestamp: NaiveDateTime) -> DateTime<Utc> {
+    DateTime::from_utc(timestamp, Utc)
+}
+
+/// The signed in user.
+#[derive(GraphQLObject)]
+pub struct User {
+    username: String,
+    email: String,
+    bio: Option<String>,
+    image: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<models::User> for User {
+    fn from(user: models::User) -> Self {
+        User {
+            username: user.username,
+            email: user.email,
+            bio: user.bio,
+            image: user.image,
+            created_at: utc(user.created_at),
+            updated_at: utc(user.updated_at),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct Profile {
+    username: String,
+    bio: Option<String>,
+    image: Option<String>,
+    /// Whether the signed in user follows them.
+    following: bool,
+}
+
+impl From<models::Profile> for Profile {
+    fn from(profile: models::Profile) -> Self {
+        Profile {
+            username: profile.username,
+            bio: profile.bio,
+            image: profile.image,
+            following: profile.following,
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct Article {
+    slug: String,
+    title: String,
+    description: String,
+    body: String,
+    tag_list: Vec<String>,
+    /// The id of the user who wrote it.
+    user_id: i32,
+    author: Profile,
+    /// Whether the signed in user favorited it.
+    favorited: bool,
+    favorites_count: i32,
+    comments_count: i32,
+    /// `draft` or `published`.
+    status: String,
+    /// When a scheduled draft is to be published.
+    publish_at: Option<DateTime<Utc>>,
+    /// `public`, `unlisted` or `private`.
+    visibility: String,
+    word_count: i32,
+    /// In minutes.
+    reading_time: i32,
+    cover_image: Option<String>,
+    /// Where it was first published, if it's cross-posted.
+    canonical_url: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<ArticleJson> for Article {
+    fn from(json: ArticleJson) -> Self {
+        let article = json.article;
+        Article {
+            slug: article.slug,
+            title: article.title,
+            description: article.description,
+            body: article.body,
+            tag_list: json.tag_list,
+            user_id: article.user_id,
+            author: json.author.into(),
+            favorited: json.favorited,
+            favorites_count: json.favorites_count as i32,
+            comments_count: json.comments_count as i32,
+            status: article.status,
+            publish_at: article.publish_at.map(utc),
+            visibility: article.visibility,
+            word_count: article.word_count,
+            reading_time: article.reading_time,
+            cover_image: article.cover_image,
+            canonical_url: article.canonical_url,
+            created_at: utc(article.created_at),
+            updated_at: utc(article.updated_at),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct ArticlesPage {
+    articles: Vec<Article>,
+    /// How many articles there are in all, across every page.
+    articles_count: i32,
+    /// Where the next page starts, to pass as `after`. Only set when this page is full.
+    next_cursor: Option<String>,
+}
+
+impl From<ArticlesResponse> for ArticlesPage {
+    fn from(response: ArticlesResponse) -> Self {
+        ArticlesPage {
+            articles: response.articles.into_iter().map(Article::from).collect(),
+            articles_count: response.articles_count as i32,
+            next_cursor: response.next_cursor,
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct Comment {
+    id: i32,
+    body: String,
+    /// The id of the user who wrote it.
+    user_id: i32,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// The id of the comment it replies to, if it's a reply.
+    parent_id: Option<i32>,
+}
+
+impl From<models::Comment> for Comment {
+    fn from(comment: models::Comment) -> Self {
+        Comment {
+            id: comment.id,
+            body: comment.body,
+            user_id: comment.user_id,
+            created_at: utc(comment.created_at),
+            updated_at: utc(comment.updated_at),
+            parent_id: comment.parent_id,
+        }
+    }
+}
+
+#[derive(GraphQLInputObject)]
+pub struct NewArticleInput {
+    title: String,
+    description: String,
+    body: String,
+    tag_list: Option<Vec<String>>,
+    /// Keep it to the author until it's published.
+    draft: Option<bool>,
+    /// When to publish it. It's a draft until then.
+    publish_at: Option<DateTime<Utc>>,
+    /// `public` (the default), `unlisted` or `private`.
+    visibility: Option<String>,
+    cover_image: Option<String>,
+    canonical_url: Option<String>,
+}
+
+/// The fields to change. Those left out stay as they are.
+#[derive(GraphQLInputObject)]
+pub struct UpdateArticleInput {
+    title: Option<String>,
+    description: Option<String>,
+    body: Option<String>,
+    /// Reschedule a draft.
+    publish_at: Option<DateTime<Utc>>,
+    /// `public`, `unlisted` or `private`.
+    visibility: Option<String>,
+    cover_image: Option<String>,
+    canonical_url: Option<String>,
+}
+
+/// Resolvers run in the threadpool's blocking section, see `execute`, so they wait on the
+/// storage with `block_on`.
+pub struct Query;
+
+#[juniper::object(Context = Context)]
+impl Query {
+    /// The signed in user.
+    fn me(context: &Context) -> Result<User, ApiError> {
+        let user_id = context.current_user_id()?;
+        let user = block_on(context.repositories.users.find(user_id))?;
+        Ok(user.into())
+    }
+
+    fn profile(context: &Context, username: String) -> Result<Profile, ApiError> {
+        let user = block_on(context.repositories.users.find_by_username(username))?;
+        let following = match context.user_id {
+            Some(viewer_id) => block_on(followers::is_following(
+                context.repo.clone(),
+                viewer_id,
+                user.id,
+            ))?,
+            None => false,
+        };
+        Ok(models::Profile::from_user(user, following).into())
+    }
+
+    /// Articles, newest first, filtered like `GET /api/articles`.
+    fn articles(
+        context: &Context,
+        tag: Option<String>,
+        author: Option<String>,
+        favorited: Option<String>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        after: Option<String>,
+    ) -> Result<ArticlesPage, ApiError> {
+        let query = ArticlesQuery {
+            tag,
+            author,
+            favorited,
+            limit: limit.map(i64::from),
+            offset: offset.map(i64::from),
+            after,
+            sort: None,
+            direction: None,
+            status: None,
+        };
+        query.validate()?;
+        let params = query.into_params(&context.config, context.user_id)?;
+        let page = block_on(list_page(context.repo.clone(), context.user_id, params))?;
+        Ok(page.into())
+    }
+
+    /// Articles by the users the signed in user follows.
+    fn feed(
+        context: &Context,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<ArticlesPage, ApiError> {
+        let user_id = context.current_user_id()?;
+        let query = FeedQuery {
+            limit: limit.map(i64::from),
+            offset: offset.map(i64::from),
+            tags: None,
+        };
+        query.validate()?;
+        let (limit, offset) = query.page(&context.config);
+        let page = block_on(feed_page(
+            context.repo.clone(),
+            user_id,
+            false,
+            limit,
+            offset,
+        ))?;
+        Ok(page.into())
+    }
+
+    fn article(context: &Context, slug: String) -> Result<Article, ApiError> {
+        let repo = context.repo.clone();
+        let article = block_on(articles::find_visible(repo.clone(), slug, context.user_id))?;
+        Ok(block_on(article_json(repo, context.user_id, article))?.into())
+    }
+
+    /// A page of an article's comments, oldest first, or newest first with `newestFirst`.
+    fn comments(
+        context: &Context,
+        slug: String,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        newest_first: Option<bool>,
+    ) -> Result<Vec<Comment>, ApiError> {
+        let query = CommentsQuery {
+            limit: limit.map(i64::from),
+            offset: offset.map(i64::from),
+            order: None,
+        };
+        query.validate()?;
+        let (limit, offset) = query.page(&context.config);
+        let order = if newest_first.unwrap_or(false) {
+            Order::Newest
+        } else {
+            Order::Oldest
+        };
+        let repo = context.repo.clone();
+        let article = block_on(articles::find_visible(repo.clone(), slug, context.user_id))?;
+        let (comments, _) = block_on(comments::list(repo, article.id, order, limit, offset))?;
+        Ok(comments.into_iter().map(Comment::from).collect())
+    }
+
+    fn tags(context: &Context) -> Result<Vec<String>, ApiError> {
+        Ok(block_on(tags::list(context.repo.clone()))?)
+    }
+}
+
+/// Everything here needs a signed in user.
+pub struct Mutation;
+
+#[juniper::object(Context = Context)]
+impl Mutation {
+    fn create_article(context: &Context, article: NewArticleInput) -> Result<Article, ApiError> {
+        let user_id = context.current_user_id()?;
+        let article = NewArticleData {
+            title: article.title,
+            description: article.description,
+            body: article.body,
+            tag_list: article.tag_list.unwrap_or_default(),
+            status: if article.draft.unwrap_or(false) {
+                Status::Draft
+            } else {
+                Status::Published
+            },
+            publish_at: article.publish_at.map(|publish_at| publish_at.naive_utc()),
+            visibility: match article.visibility {
+                Some(ref name) => Visibility::from_name(name).ok_or_else(|| {
+                    ApiError::unprocessable_entity(
+                        "visibility",
+                        "must be public, unlisted or private",
+                    )
+                })?,
+                None => Visibility::Public,
+            },
+            cover_image: article.cover_image,
+            canonical_url: article.canonical_url,
+        };
+        article.validate()?;
+        let created = block_on(insert_article(context.repo.clone(), user_id, article))?;
+        block_on(invalidate_articles_and_tags(context.cache.clone()));
+        Ok(created.into())
+    }
+
+    /// Edit one of the signed in user's articles.
+    fn update_article(
+        context: &Context,
+        slug: String,
+        article: UpdateArticleInput,
+    ) -> Result<Article, ApiError> {
+        let user_id = context.current_user_id()?;
+        let changes = UpdateArticle {
+            title: article.title,
+            description: article.description,
+            body: article.body,
+            publish_at: article.publish_at.map(|publish_at| publish_at.naive_utc()),
+            visibility: article.visibility,
+            cover_image: article.cover_image,
+            canonical_url: article.canonical_url,
+            ..UpdateArticle::default()
+        };
+        changes.validate()?;
+        let repo = context.repo.clone();
+        let updated = block_on(update_own_article(repo, user_id, slug, None, changes))?;
+        block_on(invalidate_articles_and_tags(context.cache.clone()));
+        Ok(updated.into())
+    }
+
+    /// Publish one of the signed in user's drafts.
+    fn publish_article(context: &Context, slug: String) -> Result<Article, ApiError> {
+        let user_id = context.current_user_id()?;
+        let article = block_on(publish_own_article(context.repo.clone(), user_id, slug))?;
+        block_on(invalidate_articles_and_tags(context.cache.clone()));
+        Ok(article.into())
+    }
+
+    /// Delete one of the signed in user's articles.
+    fn delete_article(context: &Context, slug: String) -> Result<bool, ApiError> {
+        let user_id = context.current_user_id()?;
+        let repo = context.repo.clone();
+        let article = block_on(find_own_article(repo.clone(), user_id, slug))?;
+        block_on(articles::delete(repo, article.id))?;
+        block_on(invalidate_articles_and_tags(context.cache.clone()));
+        Ok(true)
+    }
+
+    fn favorite_article(context: &Context, slug: String) -> Result<Article, ApiError> {
+        let user_id = context.current_user_id()?;
+        let article = block_on(favorite_by_slug(context.repo.clone(), user_id, slug, true))?;
+        block_on(context.cache.invalidate(CACHE_PREFIX));
+        Ok(article.into())
+    }
+
+    fn unfavorite_article(context: &Context, slug: String) -> Result<Article, ApiError> {
+        let user_id = context.current_user_id()?;
+        let article = block_on(favorite_by_slug(context.repo.clone(), user_id, slug, false))?;
+        block_on(context.cache.invalidate(CACHE_PREFIX));
+        Ok(article.into())
+    }
+
+    /// Comment on an article, or reply to one of its comments with `parentId`.
+    fn add_comment(
+        context: &Context,
+        slug: String,
+        body: String,
+        parent_id: Option<i32>,
+    ) -> Result<Comment, ApiError> {
+        let user_id = context.current_user_id()?;
+        let comment = NewCommentData { body, parent_id };
+        comment.validate()?;
+        let repo = context.repo.clone();
+        let comment = insert_comment(repo, user_id, slug, comment.body, comment.parent_id);
+        let comment = block_on(comment)?;
+        // Comment counts are part of every cached article.
+        block_on(context.cache.invalidate(CACHE_PREFIX));
+        Ok(comment.into())
+    }
+
+    /// Edit one of the signed in user's comments.
+    fn edit_comment(
+        context: &Context,
+        slug: String,
+        id: i32,
+        body: String,
+    ) -> Result<Comment, ApiError> {
+        let user_id = context.current_user_id()?;
+        let comment = UpdateCommentData { body };
+        comment.validate()?;
+        let repo = context.repo.clone();
+        Ok(block_on(update_own_comment(repo, user_id, slug, id, comment.body))?.into())
+    }
+
+    /// Delete one of the signed in user's comments.
+    fn delete_comment(context: &Context, slug: String, id: i32) -> Result<bool, ApiError> {
+        let user_id = context.current_user_id()?;
+        block_on(delete_own_comment(context.repo.clone(), user_id, slug, id))?;
+        block_on(context.cache.invalidate(CACHE_PREFIX));
+        Ok(true)
+    }
+
+    fn follow(context: &Context, username: String) -> Result<Profile, ApiError> {
+        let user_id = context.current_user_id()?;
+        let user = block_on(context.repositories.users.find_by_username(username))?;
+        block_on(followers::follow(context.repo.clone(), user_id, user.id))?;
+        Ok(models::Profile::from_user(user, true).into())
+    }
+
+    fn unfollow(context: &Context, username: String) -> Result<Profile, ApiError> {
+        let user_id = context.current_user_id()?;
+        let user = block_on(context.repositories.users.find_by_username(username))?;
+        block_on(followers::unfollow(context.repo.clone(), user_id, user.id))?;
+        Ok(models::Profile::from_user(user, false).into())
+    }
+}
+
+/// Draw the GraphQL endpoint.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_optional, |route| {
+        route.post("/graphql").to(handler(graphql));
+    });
+}
+
+pub async fn graphql(mut state: State) -> (State, Response<Body>) {
+    let context = Context {
+        repo: Repo::borrow_from(&state).clone(),
+        repositories: Repositories::borrow_from(&state).clone(),
+        cache: SharedCache::borrow_from(&state).0.clone(),
+        config: Config::borrow_from(&state).clone(),
+        user_id: optional_user_id(&state),
+    };
+    let result = match extract_json::<GraphQLRequest>(&mut state).await {
+        Ok(request) => execute(request, context).await,
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok((status, body)) => create_response(&state, status, mime::APPLICATION_JSON, body),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// The status and body to respond with.
+type Executed = Result<(StatusCode, String), ApiError>;
+
+/// Run a request against the schema. juniper resolves fields synchronously, so it runs in
+/// the threadpool's blocking section, as Diesel queries do.
+///
+/// Errors in fields are reported in the body alongside the data resolved, and only a request
+/// that isn't valid GraphQL is a 400.
+async fn execute(request: GraphQLRequest, context: Context) -> Executed {
+    let mut request = Some((request, context));
+    let execution = poll_fn(move || -> Result<Async<Executed>, ()> {
+        blocking(|| {
+            let (request, context) = request.take().expect("request already run");
+            let response = request.execute(&SCHEMA, &context);
+            let status = if response.is_ok() {
+                StatusCode::OK
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            to_json(&response).map(|body| (status, body))
+        })
+        .map_err(|_| panic!("the threadpool shut down"))
+    });
+    match execution.compat().await {
+        Ok(result) => result,
+        Err(()) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mail::StdoutMailer;
+    use crate::router_with_repositories;
+    use crate::test_helpers::{self, block_on, generate};
+    use gotham::test::TestServer;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    fn post(server: &TestServer, query: &str) -> (u16, Value) {
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/graphql",
+                json!({ "query": query }).to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .perform()
+            .unwrap();
+        let status = res.status().as_u16();
+        (
+            status,
+            serde_json::from_slice(&res.read_body().unwrap()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn profile_query() {
+        let repositories = test_helpers::fakes::repositories();
+        let user = block_on(repositories.users.insert(generate::new_user())).unwrap();
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            repositories,
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let query = format!(
+            r#"{{ profile(username: "{}") {{ username following }} }}"#,
+            user.username
+        );
+        let (status, body) = post(&server, &query);
+        assert_eq!(status, 200);
+        assert_eq!(body["data"]["profile"]["username"], user.username.as_str());
+        assert_eq!(body["data"]["profile"]["following"], false);
+    }
+
+    #[test]
+    fn errors_carry_their_status() {
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let mutation = r#"mutation { follow(username: "jake") { username } }"#;
+        let (status, body) = post(&server, mutation);
+        assert_eq!(status, 200);
+        assert_eq!(body["errors"][0]["extensions"]["status"], 401);
+        assert_eq!(body["errors"][0]["path"][0], "follow");
+
+        let (status, body) = post(&server, "{ articles { nope } }");
+        assert_eq!(status, 400);
+        assert!(body["errors"][0]["message"].is_string());
+    }
+}
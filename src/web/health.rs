@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use futures::compat::Future01CompatExt;
+use futures::{FutureExt, TryFutureExt};
+use gotham::state::{request_id, FromState, State};
+use hyper::{Body, Response, StatusCode};
+use log::error;
+use serde_derive::Serialize;
+use tokio::timer::Timeout;
+
+use crate::conduit::health;
+use crate::web::json_response;
+use crate::Repo;
+
+/// How long the readiness check waits for the database,
+/// so probes fail instead of hanging when the pool is exhausted.
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    status: &'static str,
+}
+
+/// Liveness: the process is up and serving requests.
+pub fn healthz(state: State) -> (State, Response<Body>) {
+    let res = health_response(&state, StatusCode::OK, "ok");
+    (state, res)
+}
+
+/// Readiness: the database can be reached through the connection pool.
+pub async fn readyz(state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    // Tokio's timer still speaks futures 0.1, so the ping is bridged in and back out.
+    let ping = health::ping(repo).boxed().compat();
+    let res = match Timeout::new(ping, READY_TIMEOUT).compat().await {
+        Ok(()) => health_response(&state, StatusCode::OK, "ok"),
+        Err(e) => {
+            if e.is_elapsed() {
+                error!("[{}] Readiness check timed out", request_id(&state));
+            } else if let Some(e) = e.into_inner() {
+                error!("[{}] Readiness check failed: {}", request_id(&state), e);
+            }
+            health_response(&state, StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+        }
+    };
+    (state, res)
+}
+
+fn health_response(state: &State, status: StatusCode, message: &'static str) -> Response<Body> {
+    json_response(state, status, &HealthResponse { status: message })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers;
+    use crate::{repo, router};
+    use gotham::test::TestServer;
+    use hyper::StatusCode;
+
+    #[test]
+    fn healthz() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/healthz")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn readyz() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/readyz")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.read_utf8_body().unwrap(), r#"{"status":"ok"}"#);
+    }
+}
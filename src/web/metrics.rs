@@ -0,0 +1,45 @@
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_response;
+use gotham::state::{FromState, State};
+use hyper::{Body, Response, StatusCode};
+use mime;
+use prometheus::{self, Encoder, TextEncoder};
+
+use crate::middleware::metrics::record_pool_state;
+use crate::web::errors::ApiError;
+use crate::Repo;
+
+/// Metrics in the Prometheus text format.
+pub fn metrics(state: State) -> (State, Response<Body>) {
+    record_pool_state(Repo::borrow_from(&state));
+    let mut buffer = Vec::new();
+    let res = match TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, buffer),
+        Err(e) => ApiError::internal_server_error()
+            .caused_by(e)
+            .into_response(&state),
+    };
+    (state, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers;
+    use crate::{repo, router};
+    use gotham::test::TestServer;
+    use hyper::StatusCode;
+
+    #[test]
+    fn metrics() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let client = server.client();
+        client.get("http://localhost/api/tags").perform().unwrap();
+        let res = client.get("http://localhost/metrics").perform().unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.read_utf8_body().unwrap();
+        assert!(
+            body.contains(r#"http_requests_total{method="GET",route="/api/tags",status="2xx"}"#)
+        );
+        assert!(body.contains("db_pool_connections"));
+    }
+}
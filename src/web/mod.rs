@@ -1 +1,317 @@
-pub mod users;
\ No newline at end of file
+pub mod admin;
+pub mod api_keys;
+pub mod articles;
+pub mod comments;
+pub mod errors;
+pub mod extractors;
+pub mod feeds;
+pub mod frontend;
+pub mod graphql;
+pub mod health;
+pub mod metrics;
+pub mod notifications;
+pub mod oauth;
+pub mod openapi;
+pub mod profiles;
+pub mod query;
+pub mod routes;
+pub mod sse;
+pub mod tags;
+pub mod uploads;
+pub mod users;
+pub mod validation;
+pub mod webhooks;
+pub mod ws;
+
+use futures::compat::Future01CompatExt;
+use futures::{FutureExt, TryFutureExt};
+use futures01::Stream as Stream01;
+use gotham::handler::{HandlerError, HandlerFuture, IntoResponse};
+use gotham::helpers::http::response::{create_empty_response, create_response};
+use gotham::state::{FromState, State};
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use hyper::{Body, HeaderMap, Response, StatusCode};
+use mime;
+use serde::Serialize;
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use crate::auth::middleware::CurrentUser;
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::web::errors::ApiError;
+use crate::web::validation::Validate;
+
+/// Adapt an `async fn` handler to Gotham, which still expects a futures 0.1 `HandlerFuture`.
+///
+/// Routes are registered with `.to(handler(users::register))`.
+pub fn handler<F, Fut>(
+    f: F,
+) -> impl Fn(State) -> Box<HandlerFuture> + Copy + Send + Sync + RefUnwindSafe + 'static
+where
+    F: Fn(State) -> Fut + Copy + Send + Sync + RefUnwindSafe + 'static,
+    Fut: Future<Output = (State, Response<Body>)> + Send + 'static,
+{
+    move |state| {
+        let f = f(state)
+            .map(Ok::<_, (State, HandlerError)>)
+            .boxed()
+            .compat();
+        Box::new(f)
+    }
+}
+
+/// Read and deserialize a JSON request body.
+/// A body that doesn't match the expected shape is reported as a validation failure.
+/// Bodies over the configured `max_body_size` are refused, without reading the rest,
+/// and so are bodies that aren't labelled as JSON, unless the config allows them.
+pub async fn extract_json<T>(state: &mut State) -> Result<T, ApiError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let config = Config::borrow_from(state);
+    let (limit, require_json) = (config.max_body_size, config.require_json_content_type);
+    let headers = HeaderMap::borrow_from(state);
+    let labelled_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, is_json);
+    if require_json && !labelled_json {
+        return Err(ApiError::unsupported_media_type());
+    }
+    let body = read_body(state, limit).await?;
+    let s = from_utf8(&body).map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    serde_json::from_str::<T>(s).map_err(|e| ApiError::unprocessable_entity("body", &e.to_string()))
+}
+
+/// Read the whole request body, refusing it without reading the rest once it's over `limit`
+/// bytes.
+pub async fn read_body(state: &mut State, limit: usize) -> Result<Vec<u8>, ApiError> {
+    let declared = HeaderMap::borrow_from(state)
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.map_or(false, |length| length > limit) {
+        return Err(ApiError::payload_too_large(limit));
+    }
+    Body::take_from(state)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))
+        .fold(Vec::new(), move |mut body, chunk| {
+            if body.len() + chunk.len() > limit {
+                return Err(ApiError::payload_too_large(limit));
+            }
+            body.extend_from_slice(&chunk);
+            Ok(body)
+        })
+        .compat()
+        .await
+}
+
+/// Whether a `Content-Type` is JSON, e.g. `application/json; charset=utf-8`
+/// or `application/vnd.api+json`.
+fn is_json(content_type: &str) -> bool {
+    match content_type.parse::<mime::Mime>() {
+        Ok(media_type) => {
+            media_type.type_() == mime::APPLICATION
+                && (media_type.subtype() == mime::JSON || media_type.suffix() == Some(mime::JSON))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Like `extract_json`, but also rejects payloads that fail validation.
+pub async fn extract_valid_json<T>(state: &mut State) -> Result<T, ApiError>
+where
+    T: serde::de::DeserializeOwned + Validate,
+{
+    let payload = extract_json::<T>(state).await?;
+    payload.validate()?;
+    Ok(payload)
+}
+
+/// Serialize a response body as JSON.
+/// Should serialization fail, a 500 in the error envelope is returned rather than panicking.
+pub fn json_response<T>(state: &State, status: StatusCode, body: &T) -> Response<Body>
+where
+    T: Serialize,
+{
+    match serde_json::to_vec(body) {
+        Ok(body) => create_response(state, status, mime::APPLICATION_JSON, body),
+        Err(e) => ApiError::internal_server_error()
+            .caused_by(format!("Failed to serialize response: {}", e))
+            .into_response(state),
+    }
+}
+
+/// Serialize a response body as JSON, for responses built from the text, e.g. cached ones.
+pub fn to_json<T>(body: &T) -> Result<String, ApiError>
+where
+    T: Serialize,
+{
+    serde_json::to_string(body).map_err(|e| {
+        ApiError::internal_server_error().caused_by(format!("Failed to serialize response: {}", e))
+    })
+}
+
+/// The JSON body cached under `key`, or else the one `build` makes, which is cached for
+/// the next request. Without a key, e.g. for a response that depends on who's asking,
+/// the cache isn't used. Errors are never cached.
+pub async fn cached_json<T, Fut>(
+    cache: Arc<dyn Cache>,
+    key: Option<String>,
+    build: Fut,
+) -> Result<String, ApiError>
+where
+    T: Serialize,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    cached_text(cache, key, async move { to_json(&build.await?) }).await
+}
+
+/// Like `cached_json`, for bodies that are built as text already, e.g. XML.
+pub async fn cached_text<Fut>(
+    cache: Arc<dyn Cache>,
+    key: Option<String>,
+    build: Fut,
+) -> Result<String, ApiError>
+where
+    Fut: Future<Output = Result<String, ApiError>>,
+{
+    if let Some(ref key) = key {
+        if let Some(body) = cache.get(key).await {
+            return Ok(body);
+        }
+    }
+    let body = build.await?;
+    if let Some(ref key) = key {
+        cache.set(key, body.clone()).await;
+    }
+    Ok(body)
+}
+
+/// Respond with a body that's already JSON, e.g. from `cached_json`, or with the error.
+pub fn json_body_response(
+    state: State,
+    result: Result<String, ApiError>,
+) -> (State, Response<Body>) {
+    let res = match result {
+        Ok(body) => create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// A weak ETag for a response body: the start of its SHA-256, which is plenty to tell
+/// versions of one resource apart.
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest
+        .iter()
+        .take(16)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("W/\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` or `If-Match` header lists `etag`. Tags are compared weakly,
+/// since all of ours are weak.
+pub fn lists_etag(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    header.trim() == "*" || header.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+/// A 200 with a body that's already JSON, tagged with its ETag. When the client says in
+/// `If-None-Match` that it has this version already, it gets a 304 without the body instead.
+pub fn tagged_json_response(state: &State, body: String) -> Response<Body> {
+    let tag = etag(body.as_bytes());
+    let not_modified = HeaderMap::borrow_from(state)
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| lists_etag(value, &tag));
+    let res = if not_modified {
+        create_empty_response(state, StatusCode::NOT_MODIFIED)
+    } else {
+        create_response(state, StatusCode::OK, mime::APPLICATION_JSON, body)
+    };
+    with_etag(res, &tag)
+}
+
+/// Add an `ETag` header, so the client can use it in a later `If-Match` or `If-None-Match`.
+pub fn with_etag(mut res: Response<Body>, etag: &str) -> Response<Body> {
+    res.headers_mut()
+        .insert(ETAG, HeaderValue::from_str(etag).unwrap());
+    res
+}
+
+/// The id of the authenticated user. Only valid for routes that require authentication.
+pub fn current_user_id(state: &State) -> i32 {
+    CurrentUser::borrow_from(state).0.user_id()
+}
+
+/// The id of the signed in user, if any, for routes where authentication is optional.
+pub fn optional_user_id(state: &State) -> Option<i32> {
+    CurrentUser::try_borrow_from(state).map(|user| user.0.user_id())
+}
+
+/// `limit` and `offset` as given in a query, kept to a page of at most `max_page_size`
+/// results. Negative values count as 0.
+pub fn clamp_page(config: &Config, limit: i64, offset: i64) -> (i64, i64) {
+    (limit.max(0).min(config.max_page_size), offset.max(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::block_on;
+    use crate::test_helpers::fakes::InMemoryCache;
+    use futures::future;
+
+    #[test]
+    fn cached_json_builds_once() {
+        let cache: Arc<dyn Cache> = Arc::new(InMemoryCache::default());
+        let key = Some("answer".to_string());
+        let built = block_on(cached_json(cache.clone(), key.clone(), future::ok(42))).unwrap();
+        assert_eq!(built, "42");
+        let cached = block_on(cached_json(cache.clone(), key.clone(), future::ok(7))).unwrap();
+        assert_eq!(cached, "42");
+        let uncached = block_on(cached_json(cache.clone(), None, future::ok(7))).unwrap();
+        assert_eq!(uncached, "7");
+
+        block_on(cache.invalidate("ans"));
+        let failed = block_on(cached_json(
+            cache.clone(),
+            key.clone(),
+            future::err::<i32, _>(ApiError::not_found()),
+        ));
+        assert!(failed.is_err());
+        let rebuilt = block_on(cached_json(cache, key, future::ok(7))).unwrap();
+        assert_eq!(rebuilt, "7");
+    }
+
+    #[test]
+    fn json_content_types() {
+        assert!(is_json("application/json"));
+        assert!(is_json("application/json; charset=utf-8"));
+        assert!(is_json("application/vnd.api+json"));
+        assert!(!is_json("text/plain"));
+        assert!(!is_json("application/x-www-form-urlencoded"));
+    }
+
+    #[test]
+    fn etags_match_weakly() {
+        let tag = etag(b"{}");
+        assert!(tag.starts_with("W/\""));
+        assert_ne!(tag, etag(b"[]"));
+        assert!(lists_etag(&tag, &tag));
+        assert!(lists_etag(
+            &format!("\"other\", {}", tag.trim_start_matches("W/")),
+            &tag
+        ));
+        assert!(lists_etag("*", &tag));
+        assert!(!lists_etag("W/\"other\"", &tag));
+    }
+}
@@ -0,0 +1,153 @@
+use gotham::handler::IntoResponse;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::conduit::notifications::{self, NotificationDto};
+use crate::config::Config;
+use crate::models::{Notification, Profile};
+use crate::web::articles::FeedQuery;
+use crate::web::errors::ApiError;
+use crate::web::query::take_valid_query;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::{current_user_id, handler, json_response};
+use crate::Repo;
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct NotificationPath {
+    id: i32,
+}
+
+/// A notification with who caused it and, unless it's a follow, the article it's about.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationJson {
+    #[serde(flatten)]
+    notification: Notification,
+    actor: Profile,
+    article: Option<ArticleLinkJson>,
+    read: bool,
+}
+
+#[derive(Serialize)]
+pub struct ArticleLinkJson {
+    slug: String,
+    title: String,
+}
+
+impl From<NotificationDto> for NotificationJson {
+    fn from(dto: NotificationDto) -> Self {
+        NotificationJson {
+            read: dto.notification.read_at.is_some(),
+            notification: dto.notification,
+            actor: dto.actor,
+            article: dto.article.map(|link| ArticleLinkJson {
+                slug: link.slug,
+                title: link.title,
+            }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct NotificationResponse {
+    notification: NotificationJson,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationsResponse {
+    notifications: Vec<NotificationJson>,
+    /// How many notifications there are in all, across every page.
+    notifications_count: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadCountResponse {
+    unread_count: i64,
+}
+
+/// Draw the routes users read their notifications with.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route
+            .get("/notifications")
+            .with_query_string_extractor::<FeedQuery>()
+            .to(handler(list));
+        route
+            .get("/notifications/unread-count")
+            .to(handler(unread_count));
+        route
+            .post("/notifications/:id/read")
+            .with_path_extractor::<NotificationPath>()
+            .to(handler(mark_read));
+    });
+}
+
+/// A page of the user's notifications, newest first.
+pub async fn list(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let query = match take_valid_query::<FeedQuery>(&mut state) {
+        Ok(query) => query,
+        Err(e) => {
+            let res = e.into_response(&state);
+            return (state, res);
+        }
+    };
+    let (limit, offset) = query.page(Config::borrow_from(&state));
+    let res = match notifications::list(repo, user_id, limit, offset).await {
+        Ok((page, count)) => {
+            let response = NotificationsResponse {
+                notifications: page.into_iter().map(NotificationJson::from).collect(),
+                notifications_count: count,
+            };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// How many of the user's notifications are unread, for a badge on a notification bell.
+pub async fn unread_count(state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let res = match notifications::unread_count(repo, user_id).await {
+        Ok(unread_count) => json_response(
+            &state,
+            StatusCode::OK,
+            &UnreadCountResponse { unread_count },
+        ),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Mark one of the user's notifications read. Someone else's is not found.
+pub async fn mark_read(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let path = NotificationPath::take_from(&mut state);
+    let res = match notifications::mark_read(repo, user_id, path.id).await {
+        Ok(dto) => {
+            let response = NotificationResponse {
+                notification: dto.into(),
+            };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
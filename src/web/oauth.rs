@@ -0,0 +1,216 @@
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::header::{HeaderValue, LOCATION};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::Deserialize;
+
+use crate::conduit::Repositories;
+use crate::config::{Config, OAuthClient};
+use crate::db::RepoError;
+use crate::models::User;
+use crate::oauth::{self, OAuthError, Provider};
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::users::{not_suspended, UserResponse};
+use crate::web::{handler, json_response};
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct ProviderPath {
+    provider: String,
+}
+
+/// What the provider sends the user back with: a code to exchange, or an error if they
+/// didn't grant access.
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Draw the routes for signing in with an OAuth provider.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, _chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route
+        .get("/users/oauth/:provider/authorize")
+        .with_path_extractor::<ProviderPath>()
+        .to(authorize);
+    route
+        .get("/users/oauth/:provider/callback")
+        .with_path_extractor::<ProviderPath>()
+        .with_query_string_extractor::<CallbackQuery>()
+        .to(handler(callback));
+}
+
+/// Redirect to the provider's sign in page, which sends the user back to the callback.
+pub fn authorize(mut state: State) -> (State, Response<Body>) {
+    let path = ProviderPath::take_from(&mut state);
+    let config = Config::borrow_from(&state);
+    let res = match configured_provider(config, &path.provider) {
+        Ok((provider, client)) => {
+            let url = oauth::authorize_url(
+                provider,
+                client,
+                &redirect_uri(config, provider),
+                &oauth::encode_state(&config.jwt, provider),
+            );
+            let mut res = create_empty_response(&state, StatusCode::FOUND);
+            res.headers_mut()
+                .insert(LOCATION, HeaderValue::from_str(&url).unwrap());
+            res
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Sign in the user the provider sent back, with an account created or linked by email
+/// the first time, and respond as a login does.
+pub async fn callback(mut state: State) -> (State, Response<Body>) {
+    let path = ProviderPath::take_from(&mut state);
+    let query = CallbackQuery::take_from(&mut state);
+    let repositories = Repositories::borrow_from(&state).clone();
+    let config = Config::borrow_from(&state).clone();
+    let res = match sign_in(&repositories, &config, &path.provider, query).await {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+async fn sign_in(
+    repositories: &Repositories,
+    config: &Config,
+    provider_name: &str,
+    query: CallbackQuery,
+) -> Result<User, ApiError> {
+    let (provider, client) = configured_provider(config, provider_name)?;
+    if let Some(error) = query.error {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            provider.name(),
+            &error,
+        ));
+    }
+    let valid_state = match query.state {
+        Some(ref value) => oauth::is_valid_state(&config.jwt, provider, value),
+        None => false,
+    };
+    if !valid_state {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "state",
+            "is invalid",
+        ));
+    }
+    let code = match query.code {
+        Some(code) => code,
+        None => return Err(ApiError::bad_request("code is missing")),
+    };
+    let profile = oauth::fetch_profile(provider, client, &redirect_uri(config, provider), &code)
+        .await
+        .map_err(|e| match e {
+            OAuthError::Rejected(_) => {
+                ApiError::new(StatusCode::UNAUTHORIZED, "code", "is invalid").caused_by(e)
+            }
+            OAuthError::Unavailable(_) => {
+                ApiError::new(StatusCode::BAD_GATEWAY, provider.name(), "is unavailable")
+                    .caused_by(e)
+            }
+        })?;
+    let user = repositories
+        .identities
+        .sign_in(profile)
+        .await
+        .map_err(|e| match e {
+            RepoError::Query(diesel::result::Error::NotFound) => {
+                ApiError::unprocessable_entity("email", "must be verified with the provider")
+            }
+            e => ApiError::from(e),
+        })?;
+    not_suspended(&user)?;
+    Ok(user)
+}
+
+/// The provider named in the path, if it's known and has credentials configured.
+fn configured_provider<'a>(
+    config: &'a Config,
+    name: &str,
+) -> Result<(Provider, &'a OAuthClient), ApiError> {
+    Provider::from_name(name)
+        .and_then(|provider| {
+            provider
+                .client(&config.oauth)
+                .map(|client| (provider, client))
+        })
+        .ok_or_else(ApiError::not_found)
+}
+
+fn redirect_uri(config: &Config, provider: Provider) -> String {
+    format!(
+        "{}/api/users/oauth/{}/callback",
+        config.public_url.trim_end_matches('/'),
+        provider.name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{self, fakes};
+    use gotham::test::TestServer;
+
+    #[test]
+    fn authorize_redirects_to_provider() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/api/users/oauth/github/authorize")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 302);
+        let location = res.headers()["Location"].to_str().unwrap();
+        assert!(location.starts_with("https://github.com/login/oauth/authorize?"));
+        assert!(location.contains("client_id=github-client"));
+        assert!(location.contains("state="));
+    }
+
+    #[test]
+    fn unconfigured_provider_is_not_found() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        for provider in &["google", "myspace"] {
+            let res = server
+                .client()
+                .get(format!(
+                    "http://localhost/api/users/oauth/{}/authorize",
+                    provider
+                ))
+                .perform()
+                .unwrap();
+            assert_eq!(res.status(), 404);
+        }
+    }
+
+    #[test]
+    fn callback_rejects_forged_state() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .get("http://localhost/api/users/oauth/github/callback?code=abc&state=forged")
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+    }
+}
@@ -0,0 +1,1188 @@
+use gotham::helpers::http::response::create_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::State;
+use hyper::{Body, Response, StatusCode};
+use mime;
+use serde_json::{json, Map, Value};
+
+use crate::middleware::api_version::ApiVersion;
+use crate::web::json_response;
+use crate::web::routes::{Chain, Chains, Pipelines};
+
+/// Who can call an operation.
+#[derive(Clone, Copy)]
+enum Auth {
+    Anyone,
+    /// Anyone, with extra details for signed in users, e.g. whether they follow an author.
+    Optional,
+    Required,
+    Admin,
+}
+
+/// One route, as documented in the OpenAPI document.
+struct Operation {
+    method: &'static str,
+    /// The route's path under `/api`, as the router has it, e.g. `/articles/:slug`.
+    path: &'static str,
+    summary: &'static str,
+    auth: Auth,
+    /// Query parameters, with their JSON schema type.
+    query: &'static [(&'static str, &'static str)],
+    /// The component schema of the request body.
+    request: Option<&'static str>,
+    /// The component schema of the response body, if there is one.
+    response: Option<&'static str>,
+}
+
+const PAGE: &[(&str, &str)] = &[("limit", "integer"), ("offset", "integer")];
+
+/// Request bodies sent as `multipart/form-data` rather than JSON.
+const FORM_REQUESTS: &[&str] = &["ImageUpload"];
+
+/// Every API route. `every_route_is_documented` keeps this in step with the router.
+const OPERATIONS: &[Operation] = &[
+    Operation {
+        method: "post",
+        path: "/users",
+        summary: "Register",
+        auth: Auth::Anyone,
+        query: &[],
+        request: Some("NewUserRequest"),
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/users/login",
+        summary: "Sign in with an email or username and password",
+        auth: Auth::Anyone,
+        query: &[],
+        request: Some("LoginRequest"),
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/users/refresh",
+        summary: "Exchange a token for a new one",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/users/logout",
+        summary: "Revoke the token used",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/users/verify/:token",
+        summary: "Verify an email address with the token emailed to it",
+        auth: Auth::Anyone,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "post",
+        path: "/users/password/forgot",
+        summary: "Email a password reset token",
+        auth: Auth::Anyone,
+        query: &[],
+        request: Some("ForgotPasswordRequest"),
+        response: None,
+    },
+    Operation {
+        method: "post",
+        path: "/users/password/reset",
+        summary: "Set a new password with a reset token",
+        auth: Auth::Anyone,
+        query: &[],
+        request: Some("ResetPasswordRequest"),
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/users/oauth/:provider/authorize",
+        summary: "Redirect to an OAuth provider to sign in",
+        auth: Auth::Anyone,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/users/oauth/:provider/callback",
+        summary: "Sign in with the code an OAuth provider redirected back with",
+        auth: Auth::Anyone,
+        query: &[("code", "string"), ("state", "string"), ("error", "string")],
+        request: None,
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/user",
+        summary: "The current user",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "put",
+        path: "/user",
+        summary: "Update the current user",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("UpdateUserRequest"),
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/user",
+        summary: "Delete the current user's account",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "put",
+        path: "/user/password",
+        summary: "Change the current user's password",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("PasswordChange"),
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/user/export",
+        summary: "Download everything stored about the current user",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "post",
+        path: "/user/image",
+        summary: "Upload a new image for the current user",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("ImageUpload"),
+        response: Some("UserResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/user/api-keys",
+        summary: "Create an API key",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("NewApiKeyRequest"),
+        response: Some("CreatedApiKeyResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/user/api-keys",
+        summary: "The current user's API keys",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ApiKeysResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/user/api-keys/:id",
+        summary: "Revoke an API key",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/profiles/:username",
+        summary: "A user's profile",
+        auth: Auth::Optional,
+        query: &[],
+        request: None,
+        response: Some("ProfileResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/profiles/:username/follow",
+        summary: "Follow a user",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ProfileResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/profiles/:username/follow",
+        summary: "Unfollow a user",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ProfileResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles",
+        summary: "List articles, newest first by default",
+        auth: Auth::Optional,
+        query: &[
+            ("tag", "string"),
+            ("author", "string"),
+            ("favorited", "string"),
+            ("limit", "integer"),
+            ("offset", "integer"),
+            ("after", "string"),
+            ("sort", "string"),
+            ("direction", "string"),
+            ("status", "string"),
+        ],
+        request: None,
+        response: Some("ArticlesResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/articles",
+        summary: "Write an article",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("NewArticleRequest"),
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/feed",
+        summary: "Articles by the users the current user follows, or with the tags they follow",
+        auth: Auth::Required,
+        query: &[
+            ("limit", "integer"),
+            ("offset", "integer"),
+            ("tags", "boolean"),
+        ],
+        request: None,
+        response: Some("ArticlesResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/feed/stream",
+        summary: "A text/event-stream of articlePublished events by followed users",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/articles/search",
+        summary: "Search articles, best match first",
+        auth: Auth::Optional,
+        query: &[("q", "string"), ("limit", "integer"), ("offset", "integer")],
+        request: None,
+        response: Some("SearchResultsResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/trending",
+        summary: "Recently published articles, most read, favorited and commented on first",
+        auth: Auth::Optional,
+        query: PAGE,
+        request: None,
+        response: Some("ArticlesResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/:slug",
+        summary: "An article",
+        auth: Auth::Optional,
+        query: &[("render", "string")],
+        request: None,
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "put",
+        path: "/articles/:slug",
+        summary: "Edit one of the current user's articles",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("UpdateArticleRequest"),
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/articles/:slug",
+        summary: "Delete one of the current user's articles",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "post",
+        path: "/articles/:slug/favorite",
+        summary: "Favorite an article",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/articles/:slug/favorite",
+        summary: "Unfavorite an article",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/:slug/comments",
+        summary: "A page of an article's comments, oldest first unless the order is newest",
+        auth: Auth::Optional,
+        query: &[
+            ("limit", "integer"),
+            ("offset", "integer"),
+            ("order", "string"),
+        ],
+        request: None,
+        response: Some("CommentsResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/articles/:slug/publish",
+        summary: "Publish one of the current user's drafts",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/:slug/related",
+        summary: "Articles sharing an article's tags or author, most related first",
+        auth: Auth::Optional,
+        query: &[("limit", "integer")],
+        request: None,
+        response: Some("ArticlesResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/:slug/stats",
+        summary: "Views, favorites and comments of one of the current user's articles, by day",
+        auth: Auth::Required,
+        query: &[("days", "integer")],
+        request: None,
+        response: Some("ArticleStatsResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/articles/:slug/revisions",
+        summary: "What one of the current user's articles was before each of its edits",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ArticleRevisionsResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/articles/:slug/revisions/:id/restore",
+        summary: "Edit one of the current user's articles back to one of its revisions",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("ArticleResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/articles/:slug/comments",
+        summary: "Comment on an article",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("NewCommentRequest"),
+        response: Some("CommentResponse"),
+    },
+    Operation {
+        method: "put",
+        path: "/articles/:slug/comments/:id",
+        summary: "Edit one of the current user's comments",
+        auth: Auth::Required,
+        query: &[],
+        request: Some("UpdateCommentRequest"),
+        response: Some("CommentResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/articles/:slug/comments/:id",
+        summary: "Delete one of the current user's comments",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/tags",
+        summary: "The tags of public articles, optionally with article counts or most used first",
+        auth: Auth::Anyone,
+        query: &[
+            ("counts", "boolean"),
+            ("order", "string"),
+            ("limit", "integer"),
+        ],
+        request: None,
+        response: Some("TagsResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/tags/:tag",
+        summary: "A tag with its article count, and a page of its articles, newest first",
+        auth: Auth::Optional,
+        query: PAGE,
+        request: None,
+        response: Some("TagResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/tags/:tag/follow",
+        summary: "Follow a tag, for its articles to be in the feed with tags=true",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("FollowedTagResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/tags/:tag/follow",
+        summary: "Stop following a tag",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("FollowedTagResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/notifications",
+        summary: "The current user's notifications, newest first",
+        auth: Auth::Required,
+        query: PAGE,
+        request: None,
+        response: Some("NotificationsResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/notifications/unread-count",
+        summary: "How many of the current user's notifications are unread",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("UnreadCountResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/notifications/:id/read",
+        summary: "Mark one of the current user's notifications read",
+        auth: Auth::Required,
+        query: &[],
+        request: None,
+        response: Some("NotificationResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/graphql",
+        summary: "Run a GraphQL query or mutation",
+        auth: Auth::Optional,
+        query: &[],
+        request: Some("GraphQLRequest"),
+        response: Some("GraphQLResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/admin/users",
+        summary: "List users, filtered by part of their email or username",
+        auth: Auth::Admin,
+        query: &[
+            ("email", "string"),
+            ("username", "string"),
+            ("limit", "integer"),
+            ("offset", "integer"),
+        ],
+        request: None,
+        response: Some("AdminUsersResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/admin/users/:id",
+        summary: "Delete a user and everything they wrote",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "post",
+        path: "/admin/users/:id/suspend",
+        summary: "Suspend a user",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: Some("AdminUserResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/admin/users/:id/suspend",
+        summary: "Lift a user's suspension",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: Some("AdminUserResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/admin/comments/:id/revisions",
+        summary: "What a comment said before each of its edits",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: Some("CommentRevisionsResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/admin/maintenance",
+        summary: "Queue the jobs that purge expired tokens and deleted users",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: Some("MaintenanceResponse"),
+    },
+    Operation {
+        method: "post",
+        path: "/admin/webhooks",
+        summary: "Register a URL to be sent signed events",
+        auth: Auth::Admin,
+        query: &[],
+        request: Some("NewWebhookRequest"),
+        response: Some("CreatedWebhookResponse"),
+    },
+    Operation {
+        method: "get",
+        path: "/admin/webhooks",
+        summary: "List webhooks",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: Some("WebhooksResponse"),
+    },
+    Operation {
+        method: "delete",
+        path: "/admin/webhooks/:id",
+        summary: "Stop sending events to a webhook",
+        auth: Auth::Admin,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/ws",
+        summary: "A WebSocket notifying the user of new followers, comments and favorites",
+        auth: Auth::Optional,
+        query: &[("token", "string")],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/openapi.json",
+        summary: "This document",
+        auth: Auth::Anyone,
+        query: &[],
+        request: None,
+        response: None,
+    },
+    Operation {
+        method: "get",
+        path: "/docs",
+        summary: "Swagger UI for this document",
+        auth: Auth::Anyone,
+        query: &[],
+        request: None,
+        response: None,
+    },
+];
+
+/// Draw the routes serving the OpenAPI document and Swagger UI.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, _chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.get("/openapi.json").to(openapi_json);
+    route.get("/docs").to(docs);
+}
+
+pub fn openapi_json(state: State) -> (State, Response<Body>) {
+    let res = json_response(&state, StatusCode::OK, &document());
+    (state, res)
+}
+
+/// Swagger UI, loaded from a CDN, showing the document next to it.
+pub fn docs(state: State) -> (State, Response<Body>) {
+    let res = create_response(&state, StatusCode::OK, mime::TEXT_HTML_UTF_8, DOCS_PAGE);
+    (state, res)
+}
+
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Conduit API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@3/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@3/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({url: "openapi.json", dom_id: "#swagger-ui"});</script>
+</body>
+</html>
+"##;
+
+/// The OpenAPI 3 document describing the API.
+pub fn document() -> Value {
+    let mut paths = Map::new();
+    for operation in OPERATIONS {
+        let path = openapi_path(operation.path);
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        item[operation.method] = describe(operation);
+    }
+    let servers: Vec<Value> = ApiVersion::ALL
+        .iter()
+        .map(|version| json!({"url": version.prefix()}))
+        .collect();
+    json!({
+        "openapi": "3.0.2",
+        "info": {
+            "title": "Conduit API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": servers,
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "token": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "Authorization",
+                    "description": "`Token <jwt>`, with the token from signing in.",
+                },
+                "apiKey": {"type": "apiKey", "in": "header", "name": "X-Api-Key"},
+            },
+        },
+    })
+}
+
+/// A router path in OpenAPI's form, e.g. `/articles/{slug}`.
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.starts_with(':') {
+                format!("{{{}}}", &segment[1..])
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn describe(operation: &Operation) -> Value {
+    let path_params = operation
+        .path
+        .split('/')
+        .filter(|segment| segment.starts_with(':'))
+        .map(|segment| {
+            let schema_type = if segment == ":id" {
+                "integer"
+            } else {
+                "string"
+            };
+            json!({
+                "name": &segment[1..],
+                "in": "path",
+                "required": true,
+                "schema": {"type": schema_type},
+            })
+        });
+    let query_params = operation.query.iter().map(
+        |(name, schema_type)| json!({"name": name, "in": "query", "schema": {"type": schema_type}}),
+    );
+    let parameters: Vec<Value> = path_params.chain(query_params).collect();
+
+    let success = match operation.response {
+        Some(schema) => json!({
+            "description": "OK",
+            "content": {"application/json": {"schema": reference(schema)}},
+        }),
+        None => json!({"description": "OK"}),
+    };
+    let error = json!({
+        "description": "The request failed, with a message for each field at fault",
+        "content": {"application/json": {"schema": reference("GenericErrorModel")}},
+    });
+    let mut described = json!({
+        "summary": operation.summary,
+        "parameters": parameters,
+        "responses": {"200": success, "default": error},
+    });
+    if let Some(schema) = operation.request {
+        let media_type = if FORM_REQUESTS.contains(&schema) {
+            "multipart/form-data"
+        } else {
+            "application/json"
+        };
+        described["requestBody"] = json!({
+            "required": true,
+            "content": {media_type: {"schema": reference(schema)}},
+        });
+    }
+    let security = match operation.auth {
+        Auth::Anyone => None,
+        // An empty requirement means the operation works without credentials too.
+        Auth::Optional => Some(json!([{"token": []}, {"apiKey": []}, {}])),
+        Auth::Required | Auth::Admin => Some(json!([{"token": []}, {"apiKey": []}])),
+    };
+    if let Some(security) = security {
+        described["security"] = security;
+    }
+    described
+}
+
+fn reference(schema: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", schema)})
+}
+
+/// An object schema, with every property required.
+fn object(properties: &[(&str, Value)]) -> Value {
+    object_with_optional(properties, &[])
+}
+
+/// An object schema, with the properties in `optional` not required.
+fn object_with_optional(properties: &[(&str, Value)], optional: &[&str]) -> Value {
+    let required: Vec<&str> = properties
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !optional.contains(name))
+        .collect();
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+    json!({"type": "object", "properties": properties, "required": required})
+}
+
+/// An object with a single property, as request and response bodies wrap their content.
+fn wrapper(name: &str, schema: &str) -> Value {
+    object(&[(name, reference(schema))])
+}
+
+fn list_of(schema: &str) -> Value {
+    json!({"type": "array", "items": reference(schema)})
+}
+
+fn string() -> Value {
+    json!({"type": "string"})
+}
+
+fn nullable_string() -> Value {
+    json!({"type": "string", "nullable": true})
+}
+
+fn uri() -> Value {
+    json!({"type": "string", "format": "uri"})
+}
+
+fn integer() -> Value {
+    json!({"type": "integer"})
+}
+
+fn boolean() -> Value {
+    json!({"type": "boolean"})
+}
+
+fn timestamp() -> Value {
+    json!({"type": "string", "format": "date-time"})
+}
+
+fn date() -> Value {
+    json!({"type": "string", "format": "date"})
+}
+
+/// The request and response bodies, as the types in `models` and the handlers serialize them.
+fn schemas() -> Value {
+    let article_fields = vec![
+        ("id", integer()),
+        ("title", string()),
+        ("slug", string()),
+        ("description", string()),
+        ("body", string()),
+        ("userId", integer()),
+        ("author", reference("Profile")),
+        ("createdAt", timestamp()),
+        ("updatedAt", timestamp()),
+        ("tagList", json!({"type": "array", "items": string()})),
+        ("favorited", boolean()),
+        ("favoritesCount", integer()),
+        ("commentsCount", integer()),
+        ("status", string()),
+        (
+            "publishAt",
+            json!({"type": "string", "format": "date-time", "nullable": true}),
+        ),
+        ("visibility", string()),
+        ("wordCount", integer()),
+        ("readingTime", integer()),
+        ("coverImage", nullable_string()),
+        ("canonicalUrl", nullable_string()),
+        ("bodyHtml", string()),
+    ];
+    let mut search_result_fields = article_fields.clone();
+    search_result_fields.push(("snippet", string()));
+    search_result_fields.push(("rank", json!({"type": "number"})));
+    json!({
+        "User": object(&[
+            ("id", integer()),
+            ("username", string()),
+            ("email", string()),
+            ("bio", nullable_string()),
+            ("image", nullable_string()),
+            ("token", nullable_string()),
+            ("createdAt", timestamp()),
+            ("updatedAt", timestamp()),
+        ]),
+        "UserResponse": wrapper("user", "User"),
+        "NewUserRequest": object(&[(
+            "user",
+            object(&[("username", string()), ("email", string()), ("password", string())]),
+        )]),
+        "LoginRequest": object(&[(
+            "user",
+            object(&[("email", string()), ("password", string())]),
+        )]),
+        "UpdateUserRequest": object(&[(
+            "user",
+            object_with_optional(
+                &[
+                    ("email", string()),
+                    ("username", string()),
+                    ("image", string()),
+                    ("bio", string()),
+                ],
+                &["email", "username", "image", "bio"],
+            ),
+        )]),
+        "ImageUpload": object(&[("image", json!({"type": "string", "format": "binary"}))]),
+        "PasswordChange": object(&[
+            ("current_password", string()),
+            ("new_password", string()),
+        ]),
+        "ForgotPasswordRequest": object(&[("user", object(&[("email", string())]))]),
+        "ResetPasswordRequest": object(&[(
+            "user",
+            object(&[("token", string()), ("password", string())]),
+        )]),
+        "ApiKey": object(&[
+            ("id", integer()),
+            ("name", string()),
+            ("prefix", string()),
+            ("createdAt", timestamp()),
+            ("lastUsedAt", json!({"type": "string", "format": "date-time", "nullable": true})),
+        ]),
+        "NewApiKeyRequest": object(&[("apiKey", object(&[("name", string())]))]),
+        "CreatedApiKeyResponse": object(&[(
+            "apiKey",
+            json!({"allOf": [reference("ApiKey"), object(&[("key", string())])]}),
+        )]),
+        "ApiKeysResponse": object(&[("apiKeys", list_of("ApiKey"))]),
+        "Profile": object(&[
+            ("username", string()),
+            ("bio", nullable_string()),
+            ("image", nullable_string()),
+            ("following", boolean()),
+            ("createdAt", timestamp()),
+            ("updatedAt", timestamp()),
+        ]),
+        "ProfileResponse": wrapper("profile", "Profile"),
+        "Article": object_with_optional(&article_fields, &["bodyHtml"]),
+        "ArticleResponse": wrapper("article", "Article"),
+        "ArticlesResponse": object_with_optional(
+            &[
+                ("articles", list_of("Article")),
+                ("articlesCount", integer()),
+                ("nextCursor", string()),
+            ],
+            &["nextCursor"],
+        ),
+        "ArticleRevision": object(&[
+            ("id", integer()),
+            ("version", integer()),
+            ("title", string()),
+            ("description", string()),
+            ("body", string()),
+            ("createdAt", timestamp()),
+        ]),
+        "ArticleRevisionsResponse": object(&[("revisions", list_of("ArticleRevision"))]),
+        "DailyStats": object(&[
+            ("date", date()),
+            ("views", integer()),
+            ("favorites", integer()),
+            ("comments", integer()),
+        ]),
+        "ArticleStats": object(&[
+            ("views", integer()),
+            ("favorites", integer()),
+            ("comments", integer()),
+            ("days", list_of("DailyStats")),
+        ]),
+        "ArticleStatsResponse": wrapper("stats", "ArticleStats"),
+        "SearchResult": object_with_optional(&search_result_fields, &["bodyHtml"]),
+        "SearchResultsResponse": object(&[
+            ("articles", list_of("SearchResult")),
+            ("articlesCount", integer()),
+        ]),
+        "NewArticleRequest": object(&[(
+            "article",
+            object_with_optional(
+                &[
+                    ("title", string()),
+                    ("description", string()),
+                    ("body", string()),
+                    ("tagList", json!({"type": "array", "items": string()})),
+                    ("status", string()),
+                    ("publishAt", timestamp()),
+                    ("visibility", string()),
+                    ("coverImage", uri()),
+                    ("canonicalUrl", uri()),
+                ],
+                &[
+                    "tagList",
+                    "status",
+                    "publishAt",
+                    "visibility",
+                    "coverImage",
+                    "canonicalUrl",
+                ],
+            ),
+        )]),
+        "UpdateArticleRequest": object(&[(
+            "article",
+            object_with_optional(
+                &[
+                    ("title", string()),
+                    ("description", string()),
+                    ("body", string()),
+                    ("publishAt", timestamp()),
+                    ("visibility", string()),
+                    ("coverImage", uri()),
+                    ("canonicalUrl", uri()),
+                ],
+                &[
+                    "title",
+                    "description",
+                    "body",
+                    "publishAt",
+                    "visibility",
+                    "coverImage",
+                    "canonicalUrl",
+                ],
+            ),
+        )]),
+        "Comment": object(&[
+            ("id", integer()),
+            ("body", string()),
+            ("articleId", integer()),
+            ("userId", integer()),
+            ("createdAt", timestamp()),
+            ("updatedAt", timestamp()),
+            ("parentId", json!({"type": "integer", "nullable": true})),
+            ("mentions", json!({"type": "array", "items": string()})),
+        ]),
+        "CommentResponse": wrapper("comment", "Comment"),
+        "CommentsResponse": object(&[
+            ("comments", list_of("Comment")),
+            ("commentsCount", integer()),
+        ]),
+        "NewCommentRequest": object(&[(
+            "comment",
+            object_with_optional(
+                &[("body", string()), ("parentId", integer())],
+                &["parentId"],
+            ),
+        )]),
+        "UpdateCommentRequest": object(&[("comment", object(&[("body", string())]))]),
+        "CommentRevision": object(&[
+            ("id", integer()),
+            ("commentId", integer()),
+            ("body", string()),
+            ("createdAt", timestamp()),
+        ]),
+        "CommentRevisionsResponse": object(&[("revisions", list_of("CommentRevision"))]),
+        "TagCount": object(&[("tag", string()), ("articlesCount", integer())]),
+        "TagsResponse": object_with_optional(
+            &[
+                ("tags", json!({"type": "array", "items": string()})),
+                ("counts", list_of("TagCount")),
+            ],
+            &["counts"],
+        ),
+        "Tag": object(&[
+            ("tag", string()),
+            ("articlesCount", integer()),
+            ("following", boolean()),
+        ]),
+        "FollowedTagResponse": wrapper("tag", "Tag"),
+        "TagResponse": object(&[
+            ("tag", reference("Tag")),
+            ("articles", list_of("Article")),
+            ("articlesCount", integer()),
+        ]),
+        "Notification": object(&[
+            ("id", integer()),
+            (
+                "kind",
+                json!({"type": "string", "enum": ["follow", "comment", "favorite", "mention"]}),
+            ),
+            ("actor", reference("Profile")),
+            (
+                "article",
+                json!({
+                    "allOf": [object(&[("slug", string()), ("title", string())])],
+                    "nullable": true,
+                }),
+            ),
+            ("commentId", json!({"type": "integer", "nullable": true})),
+            ("read", boolean()),
+            ("readAt", json!({"type": "string", "format": "date-time", "nullable": true})),
+            ("createdAt", timestamp()),
+        ]),
+        "NotificationResponse": wrapper("notification", "Notification"),
+        "NotificationsResponse": object(&[
+            ("notifications", list_of("Notification")),
+            ("notificationsCount", integer()),
+        ]),
+        "UnreadCountResponse": object(&[("unreadCount", integer())]),
+        "AdminUser": json!({"allOf": [
+            reference("User"),
+            object(&[
+                ("role", string()),
+                ("verified", boolean()),
+                ("suspended", boolean()),
+                ("deleted", boolean()),
+            ]),
+        ]}),
+        "AdminUserResponse": wrapper("user", "AdminUser"),
+        "AdminUsersResponse": object(&[
+            ("users", list_of("AdminUser")),
+            ("usersCount", integer()),
+        ]),
+        "MaintenanceResponse": object(&[("jobs", json!({"type": "array", "items": string()}))]),
+        "Webhook": object(&[
+            ("id", integer()),
+            ("url", string()),
+            ("events", json!({"type": "array", "items": string()})),
+            ("createdAt", timestamp()),
+        ]),
+        "NewWebhookRequest": object(&[(
+            "webhook",
+            object(&[
+                ("url", string()),
+                ("events", json!({"type": "array", "items": string()})),
+            ]),
+        )]),
+        "CreatedWebhookResponse": object(&[(
+            "webhook",
+            json!({"allOf": [reference("Webhook"), object(&[("secret", string())])]}),
+        )]),
+        "WebhooksResponse": object(&[("webhooks", list_of("Webhook"))]),
+        "GraphQLRequest": object_with_optional(
+            &[
+                ("query", string()),
+                ("operationName", string()),
+                ("variables", json!({"type": "object"})),
+            ],
+            &["operationName", "variables"],
+        ),
+        "GraphQLResponse": object_with_optional(
+            &[
+                ("data", json!({"type": "object", "nullable": true})),
+                ("errors", json!({"type": "array", "items": {"type": "object"}})),
+            ],
+            &["data", "errors"],
+        ),
+        "GenericErrorModel": object_with_optional(
+            &[
+                (
+                    "errors",
+                    json!({
+                        "type": "object",
+                        "additionalProperties": {"type": "array", "items": string()},
+                    }),
+                ),
+                ("requestId", string()),
+            ],
+            &["requestId"],
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::API_PATHS;
+
+    #[test]
+    fn every_route_is_documented() {
+        for path in API_PATHS {
+            assert!(
+                OPERATIONS.iter().any(|operation| operation.path == *path),
+                "{} isn't documented",
+                path
+            );
+        }
+        for operation in OPERATIONS {
+            assert!(
+                API_PATHS.contains(&operation.path),
+                "{} isn't in API_PATHS",
+                operation.path
+            );
+        }
+    }
+
+    #[test]
+    fn every_schema_referenced_is_defined() {
+        let document = document();
+        let schemas = &document["components"]["schemas"];
+        let text = document.to_string();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.get(name).is_some(), "{} isn't defined", name);
+        }
+        assert_eq!(
+            document["paths"]["/articles/{slug}"]["put"]["requestBody"]["content"]
+                ["application/json"]["schema"]["$ref"],
+            "#/components/schemas/UpdateArticleRequest"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use gotham::handler::IntoResponse;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::Serialize;
+
+use crate::conduit::followers;
+use crate::conduit::Repositories;
+use crate::db::RepoError;
+use crate::models::Profile;
+use crate::web::errors::ApiError;
+use crate::web::extractors::UsernamePath;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::{
+    current_user_id, handler, json_response, optional_user_id, tagged_json_response, to_json,
+};
+use crate::Repo;
+
+#[derive(Serialize)]
+pub struct ProfileResponse {
+    profile: Profile,
+}
+
+/// Draw the routes for viewing and following profiles.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_optional, |route| {
+        route
+            .get("/profiles/:username")
+            .with_path_extractor::<UsernamePath>()
+            .to(handler(get_profile));
+    });
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route
+            .post("/profiles/:username/follow")
+            .with_path_extractor::<UsernamePath>()
+            .to(handler(follow));
+        route
+            .delete("/profiles/:username/follow")
+            .with_path_extractor::<UsernamePath>()
+            .to(handler(unfollow));
+    });
+}
+
+pub async fn get_profile(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let users = Repositories::borrow_from(&state).users.clone();
+    let viewer_id = optional_user_id(&state);
+    let path = UsernamePath::take_from(&mut state);
+    let result = match users.find_by_username(path.username).await {
+        Ok(user) => {
+            let following = match viewer_id {
+                Some(viewer_id) => followers::is_following(repo, viewer_id, user.id).await,
+                None => Ok(false),
+            };
+            following.map(move |following| Profile::from_user(user, following))
+        }
+        Err(e) => Err(e),
+    };
+    let body = result
+        .map_err(ApiError::from)
+        .and_then(|profile| to_json(&ProfileResponse { profile }));
+    let res = match body {
+        Ok(body) => tagged_json_response(&state, body),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+pub async fn follow(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let users = Repositories::borrow_from(&state).users.clone();
+    let follower_id = current_user_id(&state);
+    let path = UsernamePath::take_from(&mut state);
+    let result = match users.find_by_username(path.username).await {
+        Ok(user) => followers::follow(repo, follower_id, user.id)
+            .await
+            .map(move |_| Profile::from_user(user, true)),
+        Err(e) => Err(e),
+    };
+    profile_response(state, result)
+}
+
+pub async fn unfollow(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let users = Repositories::borrow_from(&state).users.clone();
+    let follower_id = current_user_id(&state);
+    let path = UsernamePath::take_from(&mut state);
+    let result = match users.find_by_username(path.username).await {
+        Ok(user) => followers::unfollow(repo, follower_id, user.id)
+            .await
+            .map(move |_| Profile::from_user(user, false)),
+        Err(e) => Err(e),
+    };
+    profile_response(state, result)
+}
+
+fn profile_response(state: State, result: Result<Profile, RepoError>) -> (State, Response<Body>) {
+    let res = match result {
+        Ok(profile) => {
+            let response = ProfileResponse { profile };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conduit::users::UsersRepository;
+    use crate::mail::StdoutMailer;
+    use crate::router_with_repositories;
+    use crate::test_helpers::{self, block_on, generate};
+    use gotham::test::TestServer;
+    use hyper::header::{ETAG, IF_NONE_MATCH};
+    use std::sync::Arc;
+
+    #[test]
+    fn unchanged_profile_is_not_modified() {
+        let repositories = test_helpers::fakes::repositories();
+        let user = block_on(repositories.users.insert(generate::new_user())).unwrap();
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            repositories,
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let url = format!("http://localhost/api/profiles/{}", user.username);
+
+        let res = server.client().get(url.clone()).perform().unwrap();
+        assert_eq!(res.status(), 200);
+        let etag = res.headers()[ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with("W/"));
+
+        let res = server
+            .client()
+            .get(url.clone())
+            .with_header(IF_NONE_MATCH, etag.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 304);
+        assert_eq!(res.headers()[ETAG], etag);
+        assert!(res.read_body().unwrap().is_empty());
+
+        let res = server
+            .client()
+            .get(url)
+            .with_header(IF_NONE_MATCH, "W/\"stale\"".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+    }
+}
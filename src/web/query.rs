@@ -0,0 +1,122 @@
+use gotham::handler::IntoResponse;
+use gotham::state::{FromState, State, StateData};
+use hyper::{Body, Response, Uri};
+use serde::de::DeserializeOwned;
+use url::form_urlencoded;
+
+use crate::web::errors::ApiError;
+use crate::web::validation::{Validate, Validator};
+
+/// Answer a query string that Gotham's `QueryStringExtractor` couldn't deserialize as `T`
+/// with a 422 naming the parameters at fault, instead of Gotham's empty 400.
+///
+/// Meant to be called from `StaticResponseExtender::extend` with the response Gotham made.
+/// Each parameter is deserialized on its own to find the bad ones, so all the fields of `T`
+/// have to be optional.
+pub fn reject<T>(state: &mut State, res: &mut Response<Body>)
+where
+    T: DeserializeOwned,
+{
+    let query = Uri::borrow_from(state).query().unwrap_or("").to_string();
+    *res = invalid_params::<T>(&query).into_response(state);
+}
+
+fn invalid_params<T>(query: &str) -> ApiError
+where
+    T: DeserializeOwned,
+{
+    let mut error: Option<ApiError> = None;
+    for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+        let param = form_urlencoded::Serializer::new(String::new())
+            .append_pair(&name, &value)
+            .finish();
+        if let Err(e) = serde_urlencoded::from_str::<T>(&param) {
+            let message = e.to_string();
+            error = Some(match error {
+                Some(error) => error.and(&name, &message),
+                None => ApiError::unprocessable_entity(&name, &message),
+            });
+        }
+    }
+    error.unwrap_or_else(|| ApiError::unprocessable_entity("query", "is invalid"))
+}
+
+/// Take the query string extracted for the route, failing with a 422 if it's invalid.
+pub fn take_valid_query<T>(state: &mut State) -> Result<T, ApiError>
+where
+    T: StateData + Validate,
+{
+    let query = T::take_from(state);
+    query.validate()?;
+    Ok(query)
+}
+
+/// Check that `limit` and `offset`, where given, make a page. A `limit` over the configured
+/// maximum is lowered to it by `clamp_page` instead of being refused.
+pub fn check_page(validator: Validator, limit: Option<i64>, offset: Option<i64>) -> Validator {
+    validator
+        .check(
+            limit.map_or(true, |limit| limit >= 0),
+            "limit",
+            "can't be negative",
+        )
+        .check(
+            offset.map_or(true, |offset| offset >= 0),
+            "offset",
+            "can't be negative",
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mail::StdoutMailer;
+    use crate::router_with_repositories;
+    use crate::test_helpers;
+    use gotham::test::TestServer;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    fn get(path: &str) -> (u16, Value) {
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let res = server
+            .client()
+            .get(format!("http://localhost{}", path))
+            .perform()
+            .unwrap();
+        let status = res.status().as_u16();
+        (
+            status,
+            serde_json::from_slice(&res.read_body().unwrap()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn malformed_params_are_named() {
+        let (status, body) = get("/api/articles?limit=ten&sort=best&tag=dragons");
+        assert_eq!(status, 422);
+        assert!(body["errors"]["limit"][0].is_string());
+        assert!(body["errors"]["sort"][0]
+            .as_str()
+            .unwrap()
+            .contains("unknown variant"));
+        assert!(body["errors"].get("tag").is_none());
+    }
+
+    #[test]
+    fn out_of_range_params_are_refused() {
+        let (status, body) = get("/api/articles?limit=-1&offset=-5");
+        assert_eq!(status, 422);
+        assert_eq!(body["errors"]["limit"][0], "can't be negative");
+        assert_eq!(body["errors"]["offset"][0], "can't be negative");
+
+        let (status, body) = get("/api/articles/search?q=%20");
+        assert_eq!(status, 422);
+        assert_eq!(body["errors"]["q"][0], "can't be blank");
+    }
+}
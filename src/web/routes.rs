@@ -0,0 +1,24 @@
+use gotham::pipeline::chain::PipelineHandleChain;
+use std::panic::RefUnwindSafe;
+
+/// A pipeline chain that routes can be drawn with.
+pub trait Chain<P>: PipelineHandleChain<P> + Copy + Send + Sync + 'static {}
+
+impl<T, P> Chain<P> for T where T: PipelineHandleChain<P> + Copy + Send + Sync + 'static {}
+
+/// A finalized set of pipelines, as chains refer to them.
+pub trait Pipelines: RefUnwindSafe + Send + Sync + 'static {}
+
+impl<T> Pipelines for T where T: RefUnwindSafe + Send + Sync + 'static {}
+
+/// The pipeline chains, besides the default one, that each resource's `register_routes`
+/// draws its routes with.
+#[derive(Clone, Copy)]
+pub struct Chains<R, O, A> {
+    /// Only signed in users get through, and `CurrentUser` is in `State`.
+    pub auth_required: R,
+    /// Everyone gets through, and `CurrentUser` is in `State` for signed in users.
+    pub auth_optional: O,
+    /// Only admins get through.
+    pub admin: A,
+}
@@ -0,0 +1,71 @@
+use futures::compat::Stream01CompatExt;
+use futures::future;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use gotham::helpers::http::response::create_response;
+use gotham::state::State;
+use hyper::header::{HeaderValue, CACHE_CONTROL};
+use hyper::{Body, Chunk, Response, StatusCode};
+use std::io;
+use std::time::Duration;
+use tokio::timer::Interval;
+
+/// How often to send a comment down an idle stream, so proxies don't close it.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// How long browsers wait before reconnecting a dropped stream, in milliseconds.
+const RETRY_MS: u64 = 5000;
+
+/// A Server-Sent Events message with the given event name and data.
+pub fn message(event: &str, data: &str) -> String {
+    let mut message = format!("event: {}\n", event);
+    for line in data.lines() {
+        message.push_str("data: ");
+        message.push_str(line);
+        message.push('\n');
+    }
+    message.push('\n');
+    message
+}
+
+/// A `text/event-stream` response sending `messages` as they come, which stays open until
+/// the client goes away.
+pub fn response<S>(state: &State, messages: S) -> Response<Body>
+where
+    S: Stream<Item = String> + Send + 'static,
+{
+    let keep_alive = Interval::new_interval(KEEP_ALIVE)
+        .compat()
+        .filter_map(|tick| future::ready(tick.ok().map(|_| ": keep-alive\n\n".to_string())));
+    let stream = stream::once(future::ready(format!("retry: {}\n\n", RETRY_MS)))
+        .chain(stream::select(messages, keep_alive))
+        .map(|message| Ok::<_, io::Error>(Chunk::from(message)))
+        .boxed()
+        .compat();
+    let mut res = create_response(
+        state,
+        StatusCode::OK,
+        mime::TEXT_EVENT_STREAM,
+        Body::wrap_stream(stream),
+    );
+    let headers = res.headers_mut();
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    // Stop nginx from buffering the stream.
+    headers.insert("X-Accel-Buffering", HeaderValue::from_static("no"));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message("articlePublished", "{\"articleId\":1}"),
+            "event: articlePublished\ndata: {\"articleId\":1}\n\n"
+        );
+        assert_eq!(
+            message("note", "one\ntwo"),
+            "event: note\ndata: one\ndata: two\n\n"
+        );
+    }
+}
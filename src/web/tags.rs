@@ -0,0 +1,238 @@
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::router::response::extender::StaticResponseExtender;
+use gotham::state::{FromState, State};
+use gotham_derive::StateData;
+use hyper::{Body, Response, Uri};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::SharedCache;
+use crate::conduit::articles::{self, ListParams};
+use crate::conduit::tags::{self, Order, TagCount};
+use crate::config::Config;
+use crate::web::articles::{ArticleJson, FeedQuery, CACHE_PREFIX};
+use crate::web::errors::ApiError;
+use crate::web::extractors::TagPath;
+use crate::web::query::{self, check_page, take_valid_query};
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::validation::{Validate, Validator};
+use crate::web::{
+    cached_json, clamp_page, current_user_id, handler, json_body_response, optional_user_id,
+    to_json,
+};
+use crate::Repo;
+
+/// Where the list of tags is cached. It's cleared when articles change.
+pub const CACHE_KEY: &str = "tags";
+
+#[derive(Deserialize, StateData)]
+pub struct TagsQuery {
+    /// `true` to add how many articles have each tag.
+    pub counts: Option<bool>,
+    /// `name` (the default) or `popular`. Anything else is a 422.
+    pub order: Option<Order>,
+    pub limit: Option<i64>,
+}
+
+impl StaticResponseExtender for TagsQuery {
+    type ResBody = Body;
+
+    fn extend(state: &mut State, res: &mut Response<Body>) {
+        query::reject::<Self>(state, res)
+    }
+}
+
+impl Validate for TagsQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        check_page(Validator::default(), self.limit, None).finish()
+    }
+}
+
+#[derive(Serialize)]
+pub struct TagsResponse {
+    tags: Vec<String>,
+    /// The tags with their article counts, in the same order, when asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counts: Option<Vec<TagCount>>,
+}
+
+/// A tag as the API shows it: with its article count, and whether the user follows it.
+#[derive(Serialize)]
+pub struct TagJson {
+    #[serde(flatten)]
+    tag: TagCount,
+    following: bool,
+}
+
+#[derive(Serialize)]
+pub struct FollowedTagResponse {
+    tag: TagJson,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagResponse {
+    tag: TagJson,
+    articles: Vec<ArticleJson>,
+    articles_count: i64,
+}
+
+/// Draw the routes listing tags and their articles.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route
+        .get("/tags")
+        .with_query_string_extractor::<TagsQuery>()
+        .to(handler(list));
+    route.with_pipeline_chain(chains.auth_optional, |route| {
+        route
+            .get("/tags/:tag")
+            .with_path_extractor::<TagPath>()
+            .with_query_string_extractor::<FeedQuery>()
+            .to(handler(get_tag));
+    });
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route
+            .post("/tags/:tag/follow")
+            .with_path_extractor::<TagPath>()
+            .to(handler(follow));
+        route
+            .delete("/tags/:tag/follow")
+            .with_path_extractor::<TagPath>()
+            .to(handler(unfollow));
+    });
+}
+
+/// The tags of the articles anyone can read, alphabetically unless `order` says otherwise,
+/// with their article counts when asked for.
+pub async fn list(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let key = match Uri::borrow_from(&state).query() {
+        Some(query) => format!("{}?{}", CACHE_KEY, query),
+        None => CACHE_KEY.to_string(),
+    };
+    let result = match take_valid_query::<TagsQuery>(&mut state) {
+        Ok(query) => {
+            let limit = query
+                .limit
+                .map(|limit| clamp_page(Config::borrow_from(&state), limit, 0).0);
+            cached_json(cache, Some(key), tags_response(repo, query, limit)).await
+        }
+        Err(e) => Err(e),
+    };
+    json_body_response(state, result)
+}
+
+async fn tags_response(
+    repo: Repo,
+    query: TagsQuery,
+    limit: Option<i64>,
+) -> Result<TagsResponse, ApiError> {
+    let with_counts = query.counts.unwrap_or(false);
+    if !with_counts && query.order.is_none() && limit.is_none() {
+        let tags = tags::list(repo).await?;
+        return Ok(TagsResponse { tags, counts: None });
+    }
+    let order = query.order.unwrap_or(Order::Name);
+    let counts = tags::counts(repo, order, limit).await?;
+    Ok(TagsResponse {
+        tags: counts.iter().map(|counted| counted.tag.clone()).collect(),
+        counts: if with_counts { Some(counts) } else { None },
+    })
+}
+
+/// A tag, with a page of the articles that have it, newest first.
+pub async fn get_tag(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = optional_user_id(&state);
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let path = TagPath::take_from(&mut state);
+    // Cached with the articles, since the page shows how they're favorited.
+    let key = match user_id {
+        Some(_) => None,
+        None => {
+            let query = Uri::borrow_from(&state).query().unwrap_or("");
+            Some(format!("{}tag:{}:{}", CACHE_PREFIX, path.tag, query))
+        }
+    };
+    let result = match take_valid_query::<FeedQuery>(&mut state) {
+        Ok(query) => {
+            let (limit, offset) = query.page(Config::borrow_from(&state));
+            let page = tag_page(repo, user_id, path.tag, limit, offset);
+            cached_json(cache, key, page).await
+        }
+        Err(e) => Err(e),
+    };
+    json_body_response(state, result)
+}
+
+async fn tag_page(
+    repo: Repo,
+    user_id: Option<i32>,
+    tag: String,
+    limit: i64,
+    offset: i64,
+) -> Result<TagResponse, ApiError> {
+    let tag = tags::find(repo.clone(), tag).await?;
+    let following = match user_id {
+        Some(user_id) => tags::is_following(repo.clone(), user_id, tag.tag.clone()).await?,
+        None => false,
+    };
+    let params = ListParams {
+        tag: Some(tag.tag.clone()),
+        limit,
+        offset,
+        ..ListParams::default()
+    };
+    let (articles, articles_count) = articles::list(repo, user_id, params).await?;
+    Ok(TagResponse {
+        tag: TagJson { tag, following },
+        articles: articles.into_iter().map(ArticleJson::from).collect(),
+        articles_count,
+    })
+}
+
+/// Follow a tag, so `/articles/feed?tags=true` has other users' articles with it.
+pub async fn follow(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let path = TagPath::take_from(&mut state);
+    let result = set_following(repo, user_id, path.tag, true)
+        .await
+        .and_then(|response| to_json(&response));
+    json_body_response(state, result)
+}
+
+pub async fn unfollow(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let path = TagPath::take_from(&mut state);
+    let result = set_following(repo, user_id, path.tag, false)
+        .await
+        .and_then(|response| to_json(&response));
+    json_body_response(state, result)
+}
+
+async fn set_following(
+    repo: Repo,
+    user_id: i32,
+    tag: String,
+    following: bool,
+) -> Result<FollowedTagResponse, ApiError> {
+    if following {
+        tags::follow(repo.clone(), user_id, tag.clone()).await?;
+    } else {
+        tags::unfollow(repo.clone(), user_id, tag.clone()).await?;
+    }
+    let tag = tags::find(repo, tag).await?;
+    Ok(FollowedTagResponse {
+        tag: TagJson { tag, following },
+    })
+}
@@ -0,0 +1,238 @@
+use gotham::handler::IntoResponse;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, HeaderMap, Response, StatusCode};
+use multipart::server::Multipart;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+use crate::auth::random_token;
+use crate::conduit::users::UsersRepository;
+use crate::conduit::Repositories;
+use crate::config::Config;
+use crate::models::{UpdateUser, User};
+use crate::storage::{SharedStorage, Storage};
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::users::UserResponse;
+use crate::web::{current_user_id, handler, json_response, read_body};
+
+/// The form field the image is sent in.
+const IMAGE_FIELD: &str = "image";
+/// Room in the body for the multipart boundaries and headers around the image.
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+/// Draw the routes for uploading files.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route.post("/user/image").to(handler(upload_image));
+    });
+}
+
+/// Replace the current user's `image` with one uploaded as the `image` field of a
+/// `multipart/form-data` body.
+pub async fn upload_image(mut state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let storage = SharedStorage::borrow_from(&state).0.clone();
+    let config = Config::borrow_from(&state).clone();
+    let max_size = config.storage.max_upload_size;
+    let user_id = current_user_id(&state);
+    let result = match extract_image(&mut state, max_size).await {
+        Ok(image) => set_image(users, storage, user_id, image).await,
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Store the image and point the user's profile at it.
+async fn set_image(
+    users: Arc<dyn UsersRepository>,
+    storage: Arc<dyn Storage>,
+    user_id: i32,
+    image: Vec<u8>,
+) -> Result<User, ApiError> {
+    let (extension, content_type) = image_type(&image).ok_or_else(|| {
+        ApiError::unprocessable_entity(IMAGE_FIELD, "must be a PNG, JPEG, GIF or WebP image")
+    })?;
+    let key = format!("avatars/{}-{}.{}", user_id, random_token(), extension);
+    let url = storage
+        .put(&key, content_type, image)
+        .await
+        .map_err(|e| ApiError::internal_server_error().caused_by(e))?;
+    let changes = UpdateUser {
+        image: Some(url),
+        ..UpdateUser::default()
+    };
+    Ok(users.update(user_id, changes).await?)
+}
+
+/// Read the image out of the request body, refusing ones over `max_size` bytes.
+async fn extract_image(state: &mut State, max_size: usize) -> Result<Vec<u8>, ApiError> {
+    let boundary = HeaderMap::borrow_from(state)
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(form_data_boundary)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "body",
+                "must be sent as multipart/form-data",
+            )
+        })?;
+    let body = read_body(state, max_size + MULTIPART_OVERHEAD).await?;
+    let mut form = Multipart::with_body(Cursor::new(body), boundary);
+    while let Some(mut field) = form
+        .read_entry()
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?
+    {
+        if &*field.headers.name != IMAGE_FIELD {
+            continue;
+        }
+        let mut image = Vec::new();
+        field
+            .data
+            .by_ref()
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut image)
+            .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+        if image.len() > max_size {
+            return Err(ApiError::payload_too_large(max_size));
+        }
+        return Ok(image);
+    }
+    Err(ApiError::unprocessable_entity(
+        IMAGE_FIELD,
+        "can't be blank",
+    ))
+}
+
+/// The boundary of a `multipart/form-data` `Content-Type`.
+fn form_data_boundary(content_type: &str) -> Option<String> {
+    let media_type = content_type.parse::<mime::Mime>().ok()?;
+    if media_type.essence_str() != mime::MULTIPART_FORM_DATA.essence_str() {
+        return None;
+    }
+    media_type
+        .get_param(mime::BOUNDARY)
+        .map(|boundary| boundary.as_str().to_string())
+}
+
+/// The extension and media type of an image, told from its first bytes rather than what the
+/// client says it is.
+fn image_type(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(("png", "image/png"))
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some(("jpg", "image/jpeg"))
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some(("gif", "image/gif"))
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some(("webp", "image/webp"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{self, fakes, response_json, sign_up};
+    use gotham::test::TestServer;
+    use serde_json::Value;
+
+    const BOUNDARY: &str = "conduit-test-boundary";
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn form(field: &str, data: &[u8]) -> Vec<u8> {
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"avatar\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            BOUNDARY, field
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+        body
+    }
+
+    fn upload(server: &TestServer, token: &str, body: Vec<u8>) -> (StatusCode, Value) {
+        let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/user/image",
+                body,
+                content_type.parse::<mime::Mime>().unwrap(),
+            )
+            .with_header("Authorization", format!("Token {}", token).parse().unwrap())
+            .perform()
+            .unwrap();
+        (res.status(), response_json(res))
+    }
+
+    #[test]
+    fn test_upload_image() {
+        let config = test_helpers::config();
+        let server = TestServer::new(fakes::router(config)).unwrap();
+        let token = sign_up(&server);
+
+        let (status, body) = upload(&server, &token, form("image", PNG));
+        assert_eq!(status, StatusCode::OK);
+        let image = body["user"]["image"].as_str().unwrap();
+        assert!(image.starts_with("http://localhost:7878/uploads/avatars/"));
+        assert!(image.ends_with(".png"));
+
+        let res = server.client().get(image).perform().unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.read_body().unwrap(), PNG);
+    }
+
+    #[test]
+    fn test_upload_invalid_image() {
+        let config = test_helpers::config();
+        let max_size = config.storage.max_upload_size;
+        let server = TestServer::new(fakes::router(config)).unwrap();
+        let token = sign_up(&server);
+
+        let (status, body) = upload(&server, &token, form("image", b"<svg></svg>"));
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["errors"]["image"].is_array());
+
+        let (status, _) = upload(&server, &token, form("avatar", PNG));
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let mut too_large = PNG.to_vec();
+        too_large.resize(max_size + 1, 0);
+        let (status, _) = upload(&server, &token, form("image", &too_large));
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_image_type() {
+        assert_eq!(image_type(PNG), Some(("png", "image/png")));
+        assert_eq!(image_type(b"\xff\xd8\xff\xe0"), Some(("jpg", "image/jpeg")));
+        assert_eq!(image_type(b"GIF89a"), Some(("gif", "image/gif")));
+        assert_eq!(
+            image_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(("webp", "image/webp"))
+        );
+        assert_eq!(image_type(b"RIFF"), None);
+        assert_eq!(image_type(b"<svg></svg>"), None);
+    }
+}
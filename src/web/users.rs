@@ -1,17 +1,32 @@
-use futures::{future, Future, Stream};
-use gotham::handler::{HandlerError, HandlerFuture, IntoHandlerError};
-use gotham::helpers::http::response::{create_empty_response, create_response};
-use gotham::state::{FromState, State};
-use gotham_middleware_jwt::AuthorizationToken;
-use hyper::{Body, StatusCode};
-use mime;
+use chrono::{Duration, NaiveDateTime, Utc};
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{client_addr, FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::header::{HeaderValue, CONTENT_DISPOSITION};
+use hyper::{Body, Response, StatusCode};
+use log::error;
 use serde_derive::{Deserialize, Serialize};
-use serde_json;
-use std::str::from_utf8;
 
-use crate::auth::{encode_token, Claims};
-use crate::conduit::users;
-use crate::models::{NewUser, User};
+use crate::auth::middleware::CurrentUser;
+use crate::auth::{encode_token, Role};
+use crate::cache::SharedCache;
+use crate::conduit::exports;
+use crate::conduit::login_attempts::LoginAttemptsRepository;
+use crate::conduit::users::normalize_email;
+use crate::conduit::Repositories;
+use crate::config::{Config, JwtConfig, LoginConfig};
+use crate::db::RepoError;
+use crate::mail::{Email, Mailer, SharedMailer};
+use crate::models::{NewUser, UpdateUser, User};
+use crate::web::articles;
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::validation::{
+    is_blank, password_too_short, Validate, Validator, MIN_PASSWORD_LENGTH,
+};
+use crate::web::{current_user_id, extract_valid_json, handler, json_response};
 use crate::Repo;
 
 #[derive(Deserialize, Debug)]
@@ -21,7 +36,24 @@ pub struct Registration {
 
 #[derive(Serialize)]
 pub struct UserResponse {
-    user: User,
+    pub user: User,
+}
+
+impl UserResponse {
+    /// The user along with a new token for them.
+    pub fn signed_in(user: User, config: &JwtConfig) -> Self {
+        UserResponse {
+            user: User {
+                token: Some(encode_token(config, user.id, Role::from_name(&user.role))),
+                ..user
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRequest {
+    user: UpdateUser,
 }
 
 #[derive(Deserialize)]
@@ -31,125 +63,1021 @@ pub struct AuthRequest {
 
 #[derive(Deserialize)]
 pub struct AuthUser {
+    /// Either an email or a username. Frontends send it as `email`.
     email: String,
     password: String,
 }
 
-fn bad_request<E>(e: E) -> HandlerError
-where
-    E: std::error::Error + Send + 'static,
-{
-    e.into_handler_error().with_status(StatusCode::BAD_REQUEST)
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct VerifyPath {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+impl Validate for PasswordChange {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(
+                !is_blank(&self.current_password),
+                "current_password",
+                "can't be blank",
+            )
+            .check(
+                self.new_password.chars().count() >= MIN_PASSWORD_LENGTH,
+                "new_password",
+                &password_too_short(),
+            )
+            .check(
+                self.new_password != self.current_password,
+                "new_password",
+                "must be different from the current password",
+            )
+            .finish()
+    }
+}
+
+impl Validate for Registration {
+    fn validate(&self) -> Result<(), ApiError> {
+        self.user.validate()
+    }
+}
+
+impl Validate for UpdateRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        self.user.validate()
+    }
+}
+
+impl Validate for AuthRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.user.email), "email", "can't be blank")
+            .check(!is_blank(&self.user.password), "password", "can't be blank")
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    user: ForgotPassword,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPassword {
+    email: String,
+}
+
+impl Validate for ForgotPasswordRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.user.email), "email", "can't be blank")
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    user: PasswordReset,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordReset {
+    token: String,
+    password: String,
+}
+
+impl Validate for ResetPasswordRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.user.token), "token", "can't be blank")
+            .check(
+                self.user.password.chars().count() >= MIN_PASSWORD_LENGTH,
+                "password",
+                &password_too_short(),
+            )
+            .finish()
+    }
 }
 
-fn extract_json<T>(state: &mut State) -> impl Future<Item = T, Error = HandlerError>
+/// Draw the routes for registering, signing in and managing one's own account.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
 where
-    T: serde::de::DeserializeOwned,
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
 {
-    Body::take_from(state)
-        .concat2()
-        .map_err(bad_request)
-        .and_then(|body| {
-            let b = body.to_vec();
-            from_utf8(&b)
-                .map_err(bad_request)
-                .and_then(|s| serde_json::from_str::<T>(s).map_err(bad_request))
-        })
-}
-
-pub fn register(mut state: State) -> Box<HandlerFuture> {
-    let repo = Repo::borrow_from(&state).clone();
-    let f = extract_json::<Registration>(&mut state)
-        .and_then(|registration| {
-            users::insert(repo, registration.user).map_err(|e| e.into_handler_error())
-        })
-        .then(|result| match result {
-            Ok(user) => {
-                let body = serde_json::to_string(&user).expect("Failed to serialize user.");
-                let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
-                future::ok((state, res))
-            }
-            Err(e) => future::err((state, e.into_handler_error())),
-        });
-    Box::new(f)
+    route.post("/users").to(handler(register));
+    route.post("/users/login").to(handler(login));
+    route
+        .get("/users/verify/:token")
+        .with_path_extractor::<VerifyPath>()
+        .to(handler(verify));
+    route
+        .post("/users/password/forgot")
+        .to(handler(forgot_password));
+    route
+        .post("/users/password/reset")
+        .to(handler(reset_password));
+    route.with_pipeline_chain(chains.auth_required, |route| {
+        route.post("/users/refresh").to(handler(refresh));
+        route.post("/users/logout").to(handler(logout));
+        route.get("/user").to(handler(get_user));
+        route.put("/user").to(handler(update));
+        route.delete("/user").to(handler(delete_account));
+        route.get("/user/export").to(handler(export));
+        route.put("/user/password").to(handler(change_password));
+    });
 }
 
-pub fn login(mut state: State) -> Box<HandlerFuture> {
-    let repo = Repo::borrow_from(&state).clone();
-    let f = extract_json::<AuthRequest>(&mut state)
-        .and_then(move |body| {
-            let user = body.user;
-            users::find_by_email_password(repo, user.email, user.password).map_err(|e| match e {
-                diesel::result::Error::NotFound => {
-                    e.into_handler_error().with_status(StatusCode::UNAUTHORIZED)
+/// Register a user, and email them a link to verify their address with. They're signed in
+/// straight away, unless they have to verify it before they can log in.
+pub async fn register(mut state: State) -> (State, Response<Body>) {
+    let repositories = Repositories::borrow_from(&state).clone();
+    let mailer = SharedMailer::borrow_from(&state).0.clone();
+    let config = Config::borrow_from(&state).clone();
+    let result = match extract_valid_json::<Registration>(&mut state).await {
+        Ok(registration) => {
+            create_account(&repositories, mailer.as_ref(), &config, registration.user).await
+        }
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = if config.login.require_verified_email {
+                UserResponse { user }
+            } else {
+                UserResponse::signed_in(user, &config.jwt)
+            };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+async fn create_account(
+    repositories: &Repositories,
+    mailer: &dyn Mailer,
+    config: &Config,
+    new_user: NewUser,
+) -> Result<User, ApiError> {
+    let user = repositories.users.insert(new_user).await?;
+    let token = repositories.verifications.create(user.id).await?;
+    let email = verification_email(&user, &config.public_url, &token);
+    if let Err(e) = mailer.send(email).await {
+        // The account exists either way, so the registration still succeeds.
+        error!("Verification email for user {} not sent: {}", user.id, e);
+    }
+    Ok(user)
+}
+
+fn verification_email(user: &User, public_url: &str, token: &str) -> Email {
+    Email {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nFollow this link to verify your email address:\n{}/api/users/verify/{}\n",
+            user.username,
+            public_url.trim_end_matches('/'),
+            token
+        ),
+    }
+}
+
+/// Mark an account as verified, from the link emailed at registration.
+pub async fn verify(mut state: State) -> (State, Response<Body>) {
+    let verifications = Repositories::borrow_from(&state).verifications.clone();
+    let path = VerifyPath::take_from(&mut state);
+    let res = match verifications.verify(path.token).await {
+        Ok(_) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Email a password reset token. The response is the same whether or not the email belongs
+/// to an account, so this can't be used to find out who is registered.
+pub async fn forgot_password(mut state: State) -> (State, Response<Body>) {
+    let repositories = Repositories::borrow_from(&state).clone();
+    let mailer = SharedMailer::borrow_from(&state).0.clone();
+    let config = Config::borrow_from(&state).clone();
+    let result = match extract_valid_json::<ForgotPasswordRequest>(&mut state).await {
+        Ok(body) => {
+            send_reset_email(&repositories, mailer.as_ref(), &config, body.user.email).await
+        }
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(()) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+async fn send_reset_email(
+    repositories: &Repositories,
+    mailer: &dyn Mailer,
+    config: &Config,
+    email: String,
+) -> Result<(), ApiError> {
+    let ttl = Duration::seconds(config.password_reset_ttl.as_secs() as i64);
+    let expires_at = Utc::now().naive_utc() + ttl;
+    let (user, token) = match repositories.password_resets.create(email, expires_at).await {
+        Ok(created) => created,
+        Err(RepoError::Query(diesel::result::Error::NotFound)) => return Ok(()),
+        Err(e) => return Err(ApiError::from(e)),
+    };
+    if let Err(e) = mailer.send(password_reset_email(&user, &token, ttl)).await {
+        error!("Password reset email for user {} not sent: {}", user.id, e);
+    }
+    Ok(())
+}
+
+fn password_reset_email(user: &User, token: &str, ttl: Duration) -> Email {
+    Email {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nUse this token to reset your password within the next {} minutes:\n{}\n\n\
+             If you didn't ask to reset your password, you can ignore this email.\n",
+            user.username,
+            ttl.num_minutes(),
+            token
+        ),
+    }
+}
+
+/// Set a new password with a token from a password reset email, and sign in with it.
+/// Tokens issued before the reset are no longer accepted.
+pub async fn reset_password(mut state: State) -> (State, Response<Body>) {
+    let resets = Repositories::borrow_from(&state).password_resets.clone();
+    let config = Config::borrow_from(&state).clone();
+    let result = match extract_valid_json::<ResetPasswordRequest>(&mut state).await {
+        Ok(body) => resets
+            .reset(body.user.token, body.user.password)
+            .await
+            .map_err(|e| match e {
+                RepoError::Query(diesel::result::Error::NotFound) => {
+                    ApiError::unprocessable_entity("token", "is invalid or has expired")
                 }
-                e => e.into_handler_error(),
-            })
-        })
-        .then(|result| match result {
-            Ok(user) => {
-                let response = UserResponse {
-                    user: User {
-                        token: Some(encode_token(user.id)),
-                        ..user
-                    },
-                };
-                let body = serde_json::to_string(&response).expect("Failed to serialize user.");
-                let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
-                future::ok((state, res))
+                e => ApiError::from(e),
+            }),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Log in with an email or username. Repeated failures for the same login or from the same
+/// address lock it out for a while, answered with a 429 and a `Retry-After` header.
+pub async fn login(mut state: State) -> (State, Response<Body>) {
+    let repositories = Repositories::borrow_from(&state).clone();
+    let config = Config::borrow_from(&state).clone();
+    let ip = client_addr(&state).map(|addr| addr.ip().to_string());
+    let result = match extract_valid_json::<AuthRequest>(&mut state).await {
+        Ok(body) => authenticate(&repositories, &config.login, body.user, ip).await,
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// The current user, with a token as the spec's `UserResponse` has.
+pub async fn get_user(state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let config = Config::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let res = match users.find(user_id).await {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        // The token is valid, but for a user that no longer exists.
+        Err(RepoError::Query(diesel::result::Error::NotFound)) => {
+            ApiError::unauthorized().into_response(&state)
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+async fn authenticate(
+    repositories: &Repositories,
+    limits: &LoginConfig,
+    credentials: AuthUser,
+    ip: Option<String>,
+) -> Result<User, ApiError> {
+    let attempts = &repositories.login_attempts;
+    let login = login_key(&credentials.email);
+    if let Some(seconds) = lockout(attempts.as_ref(), limits, &login, ip.clone()).await? {
+        return Err(ApiError::too_many_requests(seconds));
+    }
+    match repositories
+        .users
+        .find_by_login(credentials.email, credentials.password)
+        .await
+    {
+        Ok(user) => {
+            attempts.clear(login).await?;
+            not_suspended(&user)?;
+            if limits.require_verified_email && !user.verified {
+                return Err(ApiError::new(
+                    StatusCode::FORBIDDEN,
+                    "email",
+                    "is not verified",
+                ));
             }
-            Err(e) => future::err((state, e)),
-        });
-    Box::new(f)
+            Ok(user)
+        }
+        Err(RepoError::Query(diesel::result::Error::NotFound)) => {
+            attempts.record_failure(login, ip).await?;
+            Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "email or password",
+                "is invalid",
+            ))
+        }
+        Err(e) => Err(ApiError::from(e)),
+    }
 }
 
-pub fn get_user(state: State) -> Box<HandlerFuture> {
-    let repo = Repo::borrow_from(&state).clone();
-    let token = AuthorizationToken::<Claims>::borrow_from(&state);
-    let results = users::find(repo.clone(), token.0.claims.user_id()).then(|result| match result {
+/// Turn away users an admin has suspended, wherever they sign in.
+pub fn not_suspended(user: &User) -> Result<(), ApiError> {
+    if user.suspended_at.is_some() {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "account",
+            "is suspended",
+        ));
+    }
+    Ok(())
+}
+
+/// Failures are counted per email the way it's stored, or per username as given.
+fn login_key(login: &str) -> String {
+    if login.contains('@') {
+        normalize_email(login)
+    } else {
+        login.to_string()
+    }
+}
+
+/// How many seconds until `login` may try again from `ip`, if it has failed too often lately.
+async fn lockout(
+    attempts: &dyn LoginAttemptsRepository,
+    limits: &LoginConfig,
+    login: &str,
+    ip: Option<String>,
+) -> Result<Option<u64>, RepoError> {
+    let now = Utc::now().naive_utc();
+    let window = Duration::seconds(limits.window.as_secs() as i64);
+    let since = now - window;
+    let failures = attempts
+        .failures_for_login(login.to_string(), since, limits.max_failures)
+        .await?;
+    let mut unlocked_at = unlock_time(&failures, limits.max_failures, window);
+    if let Some(ip) = ip {
+        let failures = attempts
+            .failures_from_ip(ip, since, limits.max_failures_per_ip)
+            .await?;
+        unlocked_at = unlocked_at.max(unlock_time(&failures, limits.max_failures_per_ip, window));
+    }
+    Ok(unlocked_at.map(|time| {
+        let millis = (time - now).num_milliseconds().max(1);
+        ((millis + 999) / 1000) as u64
+    }))
+}
+
+/// Given the latest failures, newest first, when the limit stops applying:
+/// once `limit` of them are in the window, logins wait for the oldest of those to leave it.
+fn unlock_time(failures: &[NaiveDateTime], limit: u32, window: Duration) -> Option<NaiveDateTime> {
+    if limit == 0 || failures.len() < limit as usize {
+        return None;
+    }
+    Some(failures[limit as usize - 1] + window)
+}
+
+/// Exchange a valid token for a new one, so signed in users don't have to log in again
+/// when their token expires.
+pub async fn refresh(state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let config = Config::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let res = match users.find(user_id).await {
         Ok(user) => {
-            let response = UserResponse { user };
-            let body = serde_json::to_string(&response).expect("Failed to serialize user.");
-            let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
-            future::ok((state, res))
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
         }
-        Err(diesel::result::Error::NotFound) => {
-            let res = create_empty_response(&state, StatusCode::UNAUTHORIZED);
-            future::ok((state, res))
+        Err(RepoError::Query(diesel::result::Error::NotFound)) => {
+            ApiError::unauthorized().into_response(&state)
         }
-        Err(e) => future::err((state, e.into_handler_error())),
-    });
-    Box::new(results)
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Revoke the token the request was made with, so it can't be used again even before it expires.
+pub async fn logout(state: State) -> (State, Response<Body>) {
+    let tokens = Repositories::borrow_from(&state).tokens.clone();
+    let claims = CurrentUser::borrow_from(&state).0.clone();
+    let result = match claims.token_id() {
+        Some(jti) => {
+            let expires_at = NaiveDateTime::from_timestamp(claims.expires_at() as i64, 0);
+            tokens.revoke(jti.to_string(), expires_at).await
+        }
+        // Tokens without an id can't be revoked, and stay valid until they expire.
+        None => Ok(()),
+    };
+    let res = match result {
+        Ok(()) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Edit the current user's profile. A fresh token is returned, since changing the password
+/// revokes the ones issued before.
+pub async fn update(mut state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let config = Config::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let result = match extract_valid_json::<UpdateRequest>(&mut state).await {
+        Ok(body) => users
+            .update(user_id, body.user)
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Download everything stored about the current user, as a JSON file.
+pub async fn export(state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let res = match exports::export(repo, user_id).await {
+        Ok(export) => {
+            let mut res = json_response(&state, StatusCode::OK, &export);
+            res.headers_mut().insert(
+                CONTENT_DISPOSITION,
+                HeaderValue::from_static("attachment; filename=\"conduit-export.json\""),
+            );
+            res
+        }
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Delete the current user's account. Their articles and comments stay, credited to an
+/// anonymous username, and every token and API key they have stops working.
+pub async fn delete_account(state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let cache = SharedCache::borrow_from(&state).0.clone();
+    let user_id = current_user_id(&state);
+    let result = users.anonymize(user_id).await;
+    if result.is_ok() {
+        // Their favorites are gone, so cached favorites counts are out of date.
+        cache.invalidate(articles::CACHE_PREFIX).await;
+    }
+    let res = match result {
+        Ok(()) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Change the password, given the current one.
+/// Tokens issued before the change stop working, so a fresh one is returned.
+pub async fn change_password(mut state: State) -> (State, Response<Body>) {
+    let users = Repositories::borrow_from(&state).users.clone();
+    let config = Config::borrow_from(&state).clone();
+    let user_id = current_user_id(&state);
+    let result = match extract_valid_json::<PasswordChange>(&mut state).await {
+        Ok(body) => users
+            .change_password(user_id, body.current_password, body.new_password)
+            .await
+            .map_err(|e| match e {
+                RepoError::Query(diesel::result::Error::NotFound) => {
+                    ApiError::unprocessable_entity("current_password", "is invalid")
+                }
+                e => ApiError::from(e),
+            }),
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(user) => {
+            let response = UserResponse::signed_in(user, &config.jwt);
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::mail::StdoutMailer;
     use crate::models::NewUser;
-    use crate::test_helpers::generate;
-    use crate::{repo, router};
+    use crate::test_helpers::fakes::{self, CapturingMailer};
+    use crate::test_helpers::{self, generate, login, response_json};
+    use crate::{repo, router, router_with_repositories};
     use gotham::test::{TestResponse, TestServer};
     use hyper::header::HeaderValue;
     use serde_json::{json, Value};
 
-    use std::str::from_utf8;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn register_and_login() {
-        let server = TestServer::new(router(repo())).unwrap();
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
         let user = generate::new_user();
 
-        register_user(&server, &user);
+        let registered = register_user(&server, &user);
+        assert!(registered["user"]["token"].is_string());
         let token = login_user(&server, &user);
         assert!(token.len() > 0);
-        // let user_details = get_user_details(&server, &token);
+        let user_details = get_user_details(&server, &token);
+
+        assert_eq!(user_details["user"]["username"], user.username);
+        assert_eq!(user_details["user"]["email"], user.email);
+        assert!(user_details["user"]["token"].is_string());
+    }
+
+    #[test]
+    fn repeated_login_failures_lock_out() {
+        let mut config = test_helpers::config();
+        config.login.max_failures = 3;
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            Arc::new(StdoutMailer),
+            config,
+        ))
+        .unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+
+        // A successful login clears earlier failures.
+        for _ in 0..2 {
+            let res = login(&server, &user.email, "not my password");
+            assert_eq!(res.status(), 401);
+        }
+        login_user(&server, &user);
+        for _ in 0..3 {
+            let res = login(&server, &user.email, "not my password");
+            assert_eq!(res.status(), 401);
+        }
 
-        // assert_eq!(user_details["user"]["username"], user.username);
-        // assert_eq!(user_details["user"]["email"], user.email);
+        let res = login(&server, &user.email.to_uppercase(), &user.password);
+        assert_eq!(res.status(), 429);
+        let retry_after = res.headers()["Retry-After"].to_str().unwrap();
+        assert!(retry_after.parse::<u64>().unwrap() <= 900);
     }
 
-    pub fn response_json(res: TestResponse) -> Value {
-        let body = res.read_body().unwrap();
-        serde_json::from_str(from_utf8(&body).unwrap()).expect("Could not parse body.")
+    #[test]
+    fn verify_email() {
+        let mut config = test_helpers::config();
+        config.login.require_verified_email = true;
+        let mailer = Arc::new(CapturingMailer::default());
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            mailer.clone(),
+            config,
+        ))
+        .unwrap();
+        let user = generate::new_user();
+        let registered = register_user(&server, &user);
+        // No token until the email is verified.
+        assert!(registered["user"]["token"].is_null());
+
+        let res = login(&server, &user.email, &user.password);
+        assert_eq!(res.status(), 403);
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, user.email.to_lowercase());
+        let link = sent[0]
+            .body
+            .lines()
+            .find(|line| line.starts_with("http://localhost:7878/api/users/verify/"))
+            .expect("Verification link not found");
+        let res = server.client().get(link).perform().unwrap();
+        assert_eq!(res.status(), 200);
+        // Links only work once.
+        let res = server.client().get(link).perform().unwrap();
+        assert_eq!(res.status(), 404);
+
+        login_user(&server, &user);
+    }
+
+    #[test]
+    fn reset_forgotten_password() {
+        let mailer = Arc::new(CapturingMailer::default());
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            mailer.clone(),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let verification_emails = mailer.sent().len();
+
+        // Unknown emails get the same response, but no email.
+        let res = forgot_password(&server, "nobody@example.com");
+        assert_eq!(res.status(), 200);
+        assert_eq!(mailer.sent().len(), verification_emails);
+
+        let res = forgot_password(&server, &user.email);
+        assert_eq!(res.status(), 200);
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), verification_emails + 1);
+        let token = sent[verification_emails]
+            .body
+            .lines()
+            .find(|line| line.len() == 32 && line.chars().all(|c| c.is_ascii_hexdigit()))
+            .expect("Reset token not found")
+            .to_string();
+
+        let new_password = "correct horse battery staple";
+        let res = reset_password(&server, &token, "short");
+        assert_eq!(res.status(), 422);
+        let res = reset_password(&server, &token, new_password);
+        assert_eq!(res.status(), 200);
+        assert!(response_json(res)["user"]["token"].is_string());
+        // Tokens can only be used once.
+        let res = reset_password(&server, &token, new_password);
+        assert_eq!(res.status(), 422);
+
+        login_user(
+            &server,
+            &NewUser {
+                password: new_password.to_string(),
+                ..user
+            },
+        );
+    }
+
+    #[test]
+    fn password_is_never_returned() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let user = generate::new_user();
+
+        let registered = register_user(&server, &user);
+        let token = login_user(&server, &user);
+        let details = get_user_details(&server, &token);
+        let res = server
+            .client()
+            .put(
+                "http://localhost/api/user",
+                json!({"user": {"bio": "I work at statefarm"}}).to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .with_header(
+                "Authorization",
+                HeaderValue::from_str(&format!("Token {}", token)).unwrap(),
+            )
+            .perform()
+            .unwrap();
+        let updated = response_json(res);
+        assert_eq!(updated["user"]["bio"], "I work at statefarm");
+        assert!(updated["user"]["token"].is_string());
+
+        for body in &[registered, details, updated] {
+            let body = body.to_string();
+            assert!(!body.contains("password"), "{}", body);
+            assert!(!body.contains(&user.password), "{}", body);
+            assert!(!body.contains("$argon2"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn change_password_revokes_old_tokens() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let old_token = login_user(&server, &user);
+        // Tokens only record the second they were issued in.
+        thread::sleep(Duration::from_secs(1));
+
+        let new_password = "correct horse battery staple";
+        let res = change_password(&server, &old_token, "not my password", new_password);
+        assert_eq!(res.status(), 422);
+        let res = change_password(&server, &old_token, &user.password, new_password);
+        assert_eq!(res.status(), 200);
+        let new_token = response_json(res)["user"]["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let res = server
+            .client()
+            .get("http://localhost/api/user")
+            .with_header(
+                "Authorization",
+                HeaderValue::from_str(&format!("Token {}", old_token)).unwrap(),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+        get_user_details(&server, &new_token);
+        login_user(
+            &server,
+            &NewUser {
+                password: new_password.to_string(),
+                ..user
+            },
+        );
+    }
+
+    #[test]
+    fn logout_revokes_token() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let token = login_user(&server, &user);
+        let other_token = login_user(&server, &user);
+        let authorization = HeaderValue::from_str(&format!("Token {}", token)).unwrap();
+
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/users/logout",
+                "",
+                mime::APPLICATION_JSON,
+            )
+            .with_header("Authorization", authorization.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+
+        let res = server
+            .client()
+            .get("http://localhost/api/user")
+            .with_header("Authorization", authorization)
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+        // Other sessions stay signed in.
+        get_user_details(&server, &other_token);
+    }
+
+    #[test]
+    fn update_does_not_change_password() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let token = login_user(&server, &user);
+
+        let res = server
+            .client()
+            .put(
+                "http://localhost/api/user",
+                json!({"user": {"password": "taken over"}}).to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .with_header(
+                "Authorization",
+                HeaderValue::from_str(&format!("Token {}", token)).unwrap(),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 422);
+        assert!(response_json(res)["errors"]["password"][0].is_string());
+
+        let res = login(&server, &user.email, "taken over");
+        assert_eq!(res.status(), 401);
+        login_user(&server, &user);
+        get_user_details(&server, &token);
+    }
+
+    #[test]
+    fn export_user_data() {
+        let server = TestServer::new(router(repo(), test_helpers::config())).unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let token = login_user(&server, &user);
+
+        let res = server
+            .client()
+            .get("http://localhost/api/user/export")
+            .with_header(
+                "Authorization",
+                HeaderValue::from_str(&format!("Token {}", token)).unwrap(),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let disposition = res.headers()["Content-Disposition"].to_str().unwrap();
+        assert!(disposition.starts_with("attachment"));
+        let export = response_json(res);
+        assert_eq!(export["user"]["username"], user.username);
+        assert_eq!(export["articles"], json!([]));
+        assert!(!export.to_string().contains(&user.password));
+    }
+
+    #[test]
+    fn delete_account() {
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            Arc::new(StdoutMailer),
+            test_helpers::config(),
+        ))
+        .unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let token = login_user(&server, &user);
+        let authorization = HeaderValue::from_str(&format!("Token {}", token)).unwrap();
+
+        let res = server
+            .client()
+            .delete("http://localhost/api/user")
+            .with_header("Authorization", authorization.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+
+        let res = server
+            .client()
+            .get("http://localhost/api/user")
+            .with_header("Authorization", authorization)
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+        let res = login(&server, &user.email, &user.password);
+        assert_eq!(res.status(), 401);
+        // The username is free to be taken again.
+        let registered = register_user(&server, &user);
+        assert_eq!(registered["user"]["username"], user.username);
+        assert!(registered["user"]["token"].is_string());
+    }
+
+    #[test]
+    fn oversized_body_is_refused() {
+        let mut config = test_helpers::config();
+        config.max_body_size = 1024;
+        let server = TestServer::new(router_with_repositories(
+            test_helpers::unconnected_repo(),
+            test_helpers::fakes::repositories(),
+            Arc::new(StdoutMailer),
+            config,
+        ))
+        .unwrap();
+        let body = json!({"user": {"bio": "x".repeat(2048)}}).to_string();
+        let res = server
+            .client()
+            .post("http://localhost/api/users", body, mime::APPLICATION_JSON)
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 413);
+        let body: Value = serde_json::from_slice(&res.read_body().unwrap()).unwrap();
+        assert_eq!(
+            body["errors"]["body"][0],
+            "is too large, the limit is 1024 bytes"
+        );
+    }
+
+    #[test]
+    fn body_must_be_labelled_json() {
+        let post = |config| {
+            let server = TestServer::new(router_with_repositories(
+                test_helpers::unconnected_repo(),
+                test_helpers::fakes::repositories(),
+                Arc::new(StdoutMailer),
+                config,
+            ))
+            .unwrap();
+            let user = generate::new_user();
+            let body = json!({
+                "user": {
+                    "email": user.email,
+                    "password": user.password,
+                    "username": user.username,
+                }
+            })
+            .to_string();
+            server
+                .client()
+                .post("http://localhost/api/users", body, mime::TEXT_PLAIN)
+                .perform()
+                .unwrap()
+                .status()
+        };
+        assert_eq!(post(test_helpers::config()), 415);
+
+        let mut config = test_helpers::config();
+        config.require_json_content_type = false;
+        assert_eq!(post(config), 200);
+    }
+
+    #[test]
+    fn register_duplicate_email() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+
+        let duplicate = generate::user().email(&user.email).build();
+        let response = register_user(&server, &duplicate);
+        assert_eq!(response["errors"]["email"][0], "has already been taken");
+    }
+
+    #[test]
+    fn refresh_token() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let user = generate::new_user();
+        register_user(&server, &user);
+        let token = login_user(&server, &user);
+
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/users/refresh",
+                "",
+                mime::APPLICATION_JSON,
+            )
+            .with_header(
+                "Authorization",
+                HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let response_json = response_json(res);
+        assert_eq!(response_json["user"]["email"], user.email);
+        assert!(response_json["user"]["token"].is_string());
+    }
+
+    #[test]
+    fn refresh_without_token() {
+        let server = TestServer::new(fakes::router(test_helpers::config())).unwrap();
+        let res = server
+            .client()
+            .post(
+                "http://localhost/api/users/refresh",
+                "",
+                mime::APPLICATION_JSON,
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(res.status(), 401);
+    }
+
+    fn change_password(
+        server: &TestServer,
+        token: &str,
+        current_password: &str,
+        new_password: &str,
+    ) -> TestResponse {
+        server
+            .client()
+            .put(
+                "http://localhost/api/user/password",
+                json!({
+                    "current_password": current_password,
+                    "new_password": new_password,
+                })
+                .to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .with_header(
+                "Authorization",
+                HeaderValue::from_str(&format!("Token {}", token)).unwrap(),
+            )
+            .perform()
+            .unwrap()
     }
 
     fn register_user<'a>(server: &'a TestServer, user: &'a NewUser) -> Value {
@@ -172,22 +1100,38 @@ mod tests {
         response_json(res)
     }
 
-    fn login_user<'a>(server: &'a TestServer, user: &'a NewUser) -> String {
-        let res = server
+    fn forgot_password(server: &TestServer, email: &str) -> TestResponse {
+        server
+            .client()
+            .post(
+                "http://localhost/api/users/password/forgot",
+                json!({ "user": { "email": email } }).to_string(),
+                mime::APPLICATION_JSON,
+            )
+            .perform()
+            .unwrap()
+    }
+
+    fn reset_password(server: &TestServer, token: &str, password: &str) -> TestResponse {
+        server
             .client()
             .post(
-                "http://localhost/api/users/login",
+                "http://localhost/api/users/password/reset",
                 json!({
                     "user": {
-                        "email": user.email,
-                        "password": user.password,
+                        "token": token,
+                        "password": password,
                     }
                 })
                 .to_string(),
                 mime::APPLICATION_JSON,
             )
             .perform()
-            .unwrap();
+            .unwrap()
+    }
+
+    fn login_user<'a>(server: &'a TestServer, user: &'a NewUser) -> String {
+        let res = login(server, &user.email, &user.password);
         assert_eq!(res.status(), 200);
 
         let response_json = response_json(res);
@@ -202,15 +1146,14 @@ mod tests {
     fn get_user_details<'a>(server: &'a TestServer, token: &'a String) -> Value {
         let res = server
             .client()
-            .get("/api/user")
+            .get("http://localhost/api/user")
             .with_header(
                 "Authorization",
-                HeaderValue::from_str(&format!("token: {}", token)).unwrap(),
+                HeaderValue::from_str(&format!("Token {}", token)).unwrap(),
             )
             .perform()
             .unwrap();
         assert_eq!(res.status(), 200);
         response_json(res)
     }
-
 }
@@ -0,0 +1,310 @@
+use crate::conduit::articles::Visibility;
+use crate::models::{NewUser, UpdateArticle, UpdateUser};
+use crate::web::errors::ApiError;
+use url::Url;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+/// Request payloads that can be checked before they reach the database.
+pub trait Validate {
+    /// Report every problem with the payload as a 422 with field-level messages.
+    fn validate(&self) -> Result<(), ApiError>;
+}
+
+/// Collects failed checks so they can all be reported together.
+#[derive(Default)]
+pub struct Validator {
+    error: Option<ApiError>,
+}
+
+impl Validator {
+    pub fn check(mut self, valid: bool, field: &str, message: &str) -> Self {
+        if !valid {
+            self.error = Some(match self.error {
+                Some(error) => error.and(field, message),
+                None => ApiError::unprocessable_entity(field, message),
+            });
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<(), ApiError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn is_blank(value: &str) -> bool {
+    value.trim().is_empty()
+}
+
+/// A deliberately loose check: something before and after a single @, with a dot in the domain.
+pub fn is_email(value: &str) -> bool {
+    let mut parts = value.split('@');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !value.chars().any(char::is_whitespace)
+        }
+        _ => false,
+    }
+}
+
+pub fn is_username(value: &str) -> bool {
+    value.len() <= MAX_USERNAME_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+pub fn is_http_url(value: &str) -> bool {
+    match Url::parse(value) {
+        Ok(url) => (url.scheme() == "http" || url.scheme() == "https") && url.has_host(),
+        Err(_) => false,
+    }
+}
+
+pub fn password_too_short() -> String {
+    format!(
+        "is too short (minimum is {} characters)",
+        MIN_PASSWORD_LENGTH
+    )
+}
+
+impl Validate for NewUser {
+    fn validate(&self) -> Result<(), ApiError> {
+        Validator::default()
+            .check(!is_blank(&self.username), "username", "can't be blank")
+            .check(is_username(&self.username), "username", "is invalid")
+            .check(!is_blank(&self.email), "email", "can't be blank")
+            // Surrounding whitespace is trimmed before the email is stored.
+            .check(is_email(self.email.trim()), "email", "is invalid")
+            .check(
+                self.password.chars().count() >= MIN_PASSWORD_LENGTH,
+                "password",
+                &password_too_short(),
+            )
+            .finish()
+    }
+}
+
+impl Validate for UpdateUser {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut validator = Validator::default();
+        if let Some(ref username) = self.username {
+            validator = validator
+                .check(!is_blank(username), "username", "can't be blank")
+                .check(is_username(username), "username", "is invalid");
+        }
+        if let Some(ref email) = self.email {
+            validator = validator.check(is_email(email.trim()), "email", "is invalid");
+        }
+        // Changing the password takes the current one, at `PUT /api/user/password`.
+        validator
+            .check(
+                self.password.is_none(),
+                "password",
+                "can only be changed with the current password, at /api/user/password",
+            )
+            .finish()
+    }
+}
+
+impl Validate for UpdateArticle {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut validator = Validator::default();
+        if let Some(ref title) = self.title {
+            validator = validator.check(!is_blank(title), "title", "can't be blank");
+        }
+        if let Some(ref description) = self.description {
+            validator = validator.check(!is_blank(description), "description", "can't be blank");
+        }
+        if let Some(ref body) = self.body {
+            validator = validator.check(!is_blank(body), "body", "can't be blank");
+        }
+        if let Some(ref visibility) = self.visibility {
+            validator = validator.check(
+                Visibility::from_name(visibility).is_some(),
+                "visibility",
+                "must be public, unlisted or private",
+            );
+        }
+        if let Some(ref cover_image) = self.cover_image {
+            validator = validator.check(
+                is_http_url(cover_image),
+                "coverImage",
+                "must be an http or https URL",
+            );
+        }
+        if let Some(ref canonical_url) = self.canonical_url {
+            validator = validator.check(
+                is_http_url(canonical_url),
+                "canonicalUrl",
+                "must be an http or https URL",
+            );
+        }
+        validator.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::generate;
+    use crate::web::articles::NewArticleData;
+    use crate::web::comments::NewCommentData;
+    use hyper::StatusCode;
+    use proptest::prelude::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_is_email() {
+        assert!(is_email("jake@jake.jake"));
+        assert!(!is_email("jake"));
+        assert!(!is_email("jake@jake"));
+        assert!(!is_email("@jake.jake"));
+        assert!(!is_email("jake@@jake.jake"));
+        assert!(!is_email("jake @jake.jake"));
+    }
+
+    #[test]
+    fn test_is_http_url() {
+        assert!(is_http_url("https://example.com/cover.png"));
+        assert!(is_http_url("http://example.com"));
+        assert!(!is_http_url("ftp://example.com/cover.png"));
+        assert!(!is_http_url("example.com/cover.png"));
+        assert!(!is_http_url("https://"));
+    }
+
+    #[test]
+    fn test_is_username() {
+        assert!(is_username("jake_the-dog.1"));
+        assert!(!is_username("jake the dog"));
+        assert!(!is_username("jake/dog"));
+    }
+
+    #[test]
+    fn test_valid_new_user() {
+        assert!(generate::new_user().validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_new_user() {
+        let user = NewUser {
+            username: "".to_string(),
+            email: "jake".to_string(),
+            password: "short".to_string(),
+        };
+        let error = user.validate().unwrap_err();
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "errors": {
+                    "username": ["can't be blank"],
+                    "email": ["is invalid"],
+                    "password": ["is too short (minimum is 8 characters)"],
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_update_only_checks_present_fields() {
+        assert!(UpdateUser::default().validate().is_ok());
+        let changes = UpdateArticle {
+            title: Some(" ".to_string()),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_err());
+        let changes = UpdateArticle {
+            visibility: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_err());
+        let changes = UpdateArticle {
+            canonical_url: Some("ftp://example.com/article".to_string()),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_err());
+    }
+
+    /// The fields request payloads have.
+    const FIELDS: &[&str] = &[
+        "username",
+        "email",
+        "password",
+        "bio",
+        "image",
+        "title",
+        "description",
+        "body",
+        "tagList",
+        "coverImage",
+        "canonicalUrl",
+    ];
+
+    fn json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            "\\PC*".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 32, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+                prop::collection::btree_map("\\PC*", inner, 0..8)
+                    .prop_map(|fields| Value::Object(fields.into_iter().collect())),
+            ]
+        })
+    }
+
+    /// Objects with any JSON in the fields payloads have, and sometimes in others, so that
+    /// many of them deserialize.
+    fn payloads() -> impl Strategy<Value = Value> {
+        let field = prop_oneof![
+            prop::sample::select(FIELDS).prop_map(str::to_string),
+            "\\PC*",
+        ];
+        prop::collection::btree_map(field, json(), 0..6)
+            .prop_map(|fields| Value::Object(fields.into_iter().collect()))
+    }
+
+    /// Validate the payload as a `T` when it deserializes as one.
+    fn validate_as<T: DeserializeOwned + Validate>(payload: &Value) -> Result<(), TestCaseError> {
+        if let Ok(data) = serde_json::from_value::<T>(payload.clone()) {
+            if let Err(error) = data.validate() {
+                prop_assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn validators_never_panic(payload in payloads()) {
+            validate_as::<NewUser>(&payload)?;
+            validate_as::<UpdateUser>(&payload)?;
+            validate_as::<UpdateArticle>(&payload)?;
+            validate_as::<NewArticleData>(&payload)?;
+            validate_as::<NewCommentData>(&payload)?;
+        }
+
+        #[test]
+        fn checks_never_panic(value in "\\PC*") {
+            is_blank(&value);
+            is_email(&value);
+            is_username(&value);
+            is_http_url(&value);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use gotham::handler::IntoResponse;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::{Body, Response, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::conduit;
+use crate::events::EVENT_NAMES;
+use crate::models::Webhook;
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::validation::{is_http_url, Validate, Validator};
+use crate::web::{extract_valid_json, handler, json_response};
+use crate::Repo;
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct WebhookPath {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct NewWebhookRequest {
+    webhook: NewWebhookData,
+}
+
+#[derive(Deserialize)]
+pub struct NewWebhookData {
+    url: String,
+    events: Vec<String>,
+}
+
+impl Validate for NewWebhookRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        let webhook = &self.webhook;
+        Validator::default()
+            .check(
+                is_http_url(&webhook.url),
+                "url",
+                "must be an http or https URL",
+            )
+            .check(!webhook.events.is_empty(), "events", "can't be empty")
+            .check(
+                webhook
+                    .events
+                    .iter()
+                    .all(|name| EVENT_NAMES.contains(&name.as_str())),
+                "events",
+                &format!("must be among {}", EVENT_NAMES.join(", ")),
+            )
+            .finish()
+    }
+}
+
+/// A new webhook, with its secret as the only time it's shown.
+#[derive(Serialize)]
+pub struct CreatedWebhookResponse {
+    webhook: CreatedWebhook,
+}
+
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
+
+#[derive(Serialize)]
+pub struct WebhooksResponse {
+    webhooks: Vec<Webhook>,
+}
+
+/// Draw the routes admins manage webhooks with.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.admin, |route| {
+        route.post("/admin/webhooks").to(handler(create));
+        route.get("/admin/webhooks").to(handler(list));
+        route
+            .delete("/admin/webhooks/:id")
+            .with_path_extractor::<WebhookPath>()
+            .to(handler(delete));
+    });
+}
+
+/// Register a URL to be sent events. The response is the only place its secret appears.
+pub async fn create(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let result = match extract_valid_json::<NewWebhookRequest>(&mut state).await {
+        Ok(body) => {
+            let NewWebhookData { url, mut events } = body.webhook;
+            events.sort();
+            events.dedup();
+            conduit::webhooks::create(repo, url, events)
+                .await
+                .map_err(ApiError::from)
+        }
+        Err(e) => Err(e),
+    };
+    let res = match result {
+        Ok(webhook) => {
+            let secret = webhook.secret.clone();
+            let response = CreatedWebhookResponse {
+                webhook: CreatedWebhook { webhook, secret },
+            };
+            json_response(&state, StatusCode::OK, &response)
+        }
+        Err(e) => e.into_response(&state),
+    };
+    (state, res)
+}
+
+/// Every webhook, without their secrets.
+pub async fn list(state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let res = match conduit::webhooks::list(repo).await {
+        Ok(webhooks) => json_response(&state, StatusCode::OK, &WebhooksResponse { webhooks }),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+/// Stop sending events to a webhook.
+pub async fn delete(mut state: State) -> (State, Response<Body>) {
+    let repo = Repo::borrow_from(&state).clone();
+    let path = WebhookPath::take_from(&mut state);
+    let res = match conduit::webhooks::delete(repo, path.id).await {
+        Ok(()) => create_empty_response(&state, StatusCode::OK),
+        Err(e) => ApiError::from(e).into_response(&state),
+    };
+    (state, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(url: &str, events: &[&str]) -> NewWebhookRequest {
+        NewWebhookRequest {
+            webhook: NewWebhookData {
+                url: url.to_string(),
+                events: events.iter().map(|name| name.to_string()).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(request("https://example.com/hook", &["commentAdded"])
+            .validate()
+            .is_ok());
+        assert!(request("ftp://example.com/hook", &["commentAdded"])
+            .validate()
+            .is_err());
+        assert!(request("not a url", &["commentAdded"]).validate().is_err());
+        assert!(request("https://example.com/hook", &[]).validate().is_err());
+        assert!(request("https://example.com/hook", &["articleDeleted"])
+            .validate()
+            .is_err());
+    }
+}
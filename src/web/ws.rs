@@ -0,0 +1,175 @@
+use chrono::NaiveDateTime;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::compat::{Future01CompatExt, Sink01CompatExt, Stream01CompatExt};
+use futures::future::{self, FutureExt, TryFutureExt};
+use futures::sink::SinkExt;
+use futures::stream::{self, StreamExt};
+use futures01::Stream as Stream01;
+use gotham::handler::IntoResponse;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use hyper::header::{
+    HeaderMap, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE,
+};
+use hyper::{Body, Response, StatusCode};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use sha1::Sha1;
+use tokio_tungstenite::tungstenite::protocol::{Message, Role};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::auth::decode_token;
+use crate::auth::middleware::authenticate_token;
+use crate::conduit::Repositories;
+use crate::config::{Config, JwtConfig};
+use crate::events::{self, Event};
+use crate::web::errors::ApiError;
+use crate::web::routes::{Chain, Chains, Pipelines};
+use crate::web::{handler, optional_user_id};
+
+/// Appended to the client's key to accept a WebSocket handshake, from RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Browsers can't set headers on WebSocket requests, so the token can come in the query.
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct WsQuery {
+    token: Option<String>,
+}
+
+/// Draw the route clients open a WebSocket on to be notified as it happens.
+pub fn register_routes<D, C, P, R, O, A>(route: &mut D, chains: Chains<R, O, A>)
+where
+    D: DrawRoutes<C, P>,
+    C: Chain<P>,
+    P: Pipelines,
+    R: Chain<P>,
+    O: Chain<P>,
+    A: Chain<P>,
+{
+    route.with_pipeline_chain(chains.auth_optional, |route| {
+        route
+            .get("/ws")
+            .with_query_string_extractor::<WsQuery>()
+            .to(handler(connect));
+    });
+}
+
+/// Upgrade to a WebSocket, which is sent a text message whenever someone follows or mentions
+/// the user, or comments on or favorites one of their articles: the same JSON webhooks are
+/// sent.
+pub async fn connect(mut state: State) -> (State, Response<Body>) {
+    let query = WsQuery::take_from(&mut state);
+    let signed_in = match optional_user_id(&state) {
+        Some(user_id) => Ok(Some(user_id)),
+        None => {
+            let repositories = Repositories::borrow_from(&state).clone();
+            let config = Config::borrow_from(&state).jwt.clone();
+            user_from_token(repositories, config, query.token).await
+        }
+    };
+    let user_id = match signed_in {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return error_response(state, ApiError::unauthorized()),
+        Err(e) => return error_response(state, e),
+    };
+    let accept = match HeaderMap::borrow_from(&state).get(SEC_WEBSOCKET_KEY) {
+        Some(key) => accept_key(key.as_bytes()),
+        None => return error_response(state, ApiError::bad_request("expected a WebSocket")),
+    };
+    let body = Body::take_from(&mut state);
+    let notifications = events::subscribe_recipient(user_id);
+    tokio::spawn(serve(body, notifications).unit_error().boxed().compat());
+
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = res.headers_mut();
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(
+        SEC_WEBSOCKET_ACCEPT,
+        HeaderValue::from_str(&accept).expect("Base64 is a valid header"),
+    );
+    (state, res)
+}
+
+fn error_response(state: State, e: ApiError) -> (State, Response<Body>) {
+    let res = e.into_response(&state);
+    (state, res)
+}
+
+/// The user the `token` in the query signs in, checked as the `Authorization` header is.
+async fn user_from_token(
+    repositories: Repositories,
+    config: JwtConfig,
+    token: Option<String>,
+) -> Result<Option<i32>, ApiError> {
+    let claims = match token.and_then(|token| decode_token(&config, &token)) {
+        Some(claims) => claims,
+        None => return Ok(None),
+    };
+    let claims = authenticate_token(&repositories, claims).await?;
+    Ok(claims.map(|claims| claims.user_id()))
+}
+
+/// The `Sec-WebSocket-Accept` for the client's `Sec-WebSocket-Key`.
+fn accept_key(key: &[u8]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(HANDSHAKE_GUID.as_bytes());
+    base64::encode(&sha1.digest().bytes())
+}
+
+enum Input {
+    Client(Option<Message>),
+    Notification(Event, NaiveDateTime),
+}
+
+/// Once the connection is upgraded, pass notifications on until either side goes away.
+/// Pings are answered by the WebSocket itself.
+async fn serve(body: Body, notifications: UnboundedReceiver<(Event, NaiveDateTime)>) {
+    let upgraded = match body.on_upgrade().compat().await {
+        Ok(upgraded) => upgraded,
+        Err(e) => {
+            warn!("Could not upgrade to a WebSocket: {}", e);
+            return;
+        }
+    };
+    let (sink, stream) = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).split();
+    let mut sink = sink.sink_compat();
+    // An error reading is as good as the client closing the connection.
+    let from_client = stream
+        .compat()
+        .map(|message| Input::Client(message.ok()))
+        .chain(stream::once(future::ready(Input::Client(None))));
+    let notifications =
+        notifications.map(|(event, occurred_at)| Input::Notification(event, occurred_at));
+    let mut inputs = stream::select(from_client, notifications);
+    while let Some(input) = inputs.next().await {
+        match input {
+            Input::Client(None) | Input::Client(Some(Message::Close(_))) => break,
+            Input::Client(Some(_)) => (),
+            Input::Notification(event, occurred_at) => {
+                let message = Message::Text(event.payload(occurred_at));
+                if let Err(e) = sink.send(message).await {
+                    debug!("Could not send a notification: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        // The example in RFC 6455.
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kvGzzhZRbK+xOo="
+        );
+    }
+}
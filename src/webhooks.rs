@@ -0,0 +1,120 @@
+use futures::compat::Future01CompatExt;
+use hyper::client::HttpConnector;
+use hyper::header::{CONTENT_TYPE, USER_AGENT};
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::timer::Timeout;
+
+use crate::models::Webhook;
+
+/// The header carrying the name of the event, e.g. `commentAdded`.
+pub const EVENT_HEADER: &str = "X-Conduit-Event";
+/// The header carrying `sha256=` and the hex encoded HMAC-SHA256 of the body, keyed with
+/// the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Conduit-Signature";
+
+/// The block size of SHA-256, which HMAC pads keys to.
+const BLOCK_SIZE: usize = 64;
+
+/// How long a receiver gets to respond, so a slow one doesn't hold up the job queue.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref CLIENT: Client<HttpsConnector<HttpConnector>> =
+        Client::builder().build(HttpsConnector::new(4).expect("TLS is unavailable"));
+}
+
+/// POST `payload` to the webhook. Anything but a 2xx response in time is an error, so the
+/// delivery is retried.
+pub async fn deliver(webhook: &Webhook, event: &str, payload: String) -> Result<(), String> {
+    let request = Request::post(webhook.url.as_str())
+        .header(CONTENT_TYPE, "application/json")
+        .header(USER_AGENT, "realworld-gotham")
+        .header(EVENT_HEADER, event)
+        .header(SIGNATURE_HEADER, signature(&webhook.secret, &payload))
+        .body(Body::from(payload))
+        .map_err(|e| e.to_string())?;
+    let response = Timeout::new(CLIENT.request(request), DELIVERY_TIMEOUT)
+        .compat()
+        .await
+        .map_err(|e| {
+            if e.is_elapsed() {
+                format!("{} did not respond in time", webhook.url)
+            } else if let Some(e) = e.into_inner() {
+                format!("{} could not be reached: {}", webhook.url, e)
+            } else {
+                format!("{} could not be reached: the timer failed", webhook.url)
+            }
+        })?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "{} responded with {}",
+            webhook.url,
+            response.status()
+        ));
+    }
+    Ok(())
+}
+
+/// What the `SIGNATURE_HEADER` of `payload` should be, for receivers to check it against.
+pub fn signature(secret: &str, payload: &str) -> String {
+    let mac: String = hmac_sha256(secret.as_bytes(), payload.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", mac)
+}
+
+/// HMAC-SHA256, as in RFC 2104.
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut padded_key = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        padded_key[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        padded_key[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.input(
+        padded_key
+            .iter()
+            .map(|byte| byte ^ 0x36)
+            .collect::<Vec<u8>>(),
+    );
+    inner.input(message);
+    let mut outer = Sha256::new();
+    outer.input(
+        padded_key
+            .iter()
+            .map(|byte| byte ^ 0x5c)
+            .collect::<Vec<u8>>(),
+    );
+    outer.input(inner.result());
+    outer.result().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // From RFC 4231, test case 2.
+        assert_eq!(
+            signature("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first; test case 6.
+        let mac = hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        let hex: String = mac.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(
+            hex,
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Runs the RealWorld API scenarios, as the official Postman collection does, against the
+//! server binary: register, log in, write, comment on and favorite an article, follow its
+//! author, and check every response has the shape the spec gives it.
+//!
+//! The server is started with the `DATABASE_URL` from `.env.test`, or the environment, and
+//! migrates that database. Users get unique names, so runs don't clash with earlier ones.
+
+use futures01::{Future, Stream};
+use hyper::client::HttpConnector;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Client, Method, Request, StatusCode};
+use rand::Rng;
+use serde_json::{json, Value};
+use std::env;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::current_thread::Runtime;
+
+/// How long the server gets to connect to the database and start listening.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The server binary, running until it's dropped.
+struct Server {
+    process: Child,
+    address: String,
+}
+
+impl Server {
+    fn start() -> Server {
+        dotenv::from_filename(".env.test").ok();
+        if env::var("DATABASE_URL").is_err() {
+            panic!("DATABASE_URL must be set, in .env.test or the environment");
+        }
+        let address = free_address();
+        let process = Command::new(server_binary())
+            .env("LISTEN_ADDRESS", &address)
+            .env("JWT_SECRET", "conformance")
+            .env("MAIL_TRANSPORT", "stdout")
+            .env_remove("REDIS_URL")
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start the server");
+        let mut server = Server { process, address };
+        server.wait_until_listening();
+        server
+    }
+
+    fn wait_until_listening(&mut self) {
+        let started = Instant::now();
+        while TcpStream::connect(&self.address).is_err() {
+            if let Some(status) = self.process.try_wait().unwrap() {
+                panic!("The server exited on startup with {}", status);
+            }
+            if started.elapsed() > STARTUP_TIMEOUT {
+                panic!("The server didn't start listening on {}", self.address);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// The binary Cargo builds alongside the tests, in the directory above theirs.
+fn server_binary() -> PathBuf {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join(format!("realworld-gotham{}", env::consts::EXE_SUFFIX))
+}
+
+fn free_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// A client for the API, signed in once `token` is set.
+struct Api {
+    runtime: Runtime,
+    client: Client<HttpConnector>,
+    base: String,
+    token: Option<String>,
+}
+
+impl Api {
+    fn new(server: &Server) -> Api {
+        Api {
+            runtime: Runtime::new().unwrap(),
+            client: Client::new(),
+            base: format!("http://{}/api", server.address),
+            token: None,
+        }
+    }
+
+    fn get(&mut self, path: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, path, None)
+    }
+
+    fn post(&mut self, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::POST, path, Some(body))
+    }
+
+    fn put(&mut self, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::PUT, path, Some(body))
+    }
+
+    fn delete(&mut self, path: &str) -> (StatusCode, Value) {
+        self.request(Method::DELETE, path, None)
+    }
+
+    /// Make a request, with the body as JSON, and read the response body as JSON, or
+    /// `Value::Null` when it's empty.
+    fn request(&mut self, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let mut builder = Request::builder();
+        builder.method(method).uri(format!("{}{}", self.base, path));
+        if let Some(ref token) = self.token {
+            builder.header(AUTHORIZATION, format!("Token {}", token));
+        }
+        let body = match body {
+            Some(body) => {
+                builder.header(CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let request = builder.body(body).unwrap();
+        let response = self.client.request(request).and_then(|res| {
+            let status = res.status();
+            res.into_body().concat2().map(move |body| (status, body))
+        });
+        let (status, body) = self.runtime.block_on(response).unwrap();
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+        (status, body)
+    }
+}
+
+fn assert_string(value: &Value, field: &str) {
+    assert!(
+        value[field].is_string(),
+        "{} should be a string in {}",
+        field,
+        value
+    );
+}
+
+fn assert_nullable_string(value: &Value, field: &str) {
+    assert!(
+        value[field].is_string() || value[field].is_null(),
+        "{} should be a string or null in {}",
+        field,
+        value
+    );
+}
+
+fn assert_user(user: &Value) {
+    for field in &["email", "username", "token"] {
+        assert_string(user, field);
+    }
+    assert_nullable_string(user, "bio");
+    assert_nullable_string(user, "image");
+}
+
+fn assert_profile(profile: &Value) {
+    assert_string(profile, "username");
+    assert_nullable_string(profile, "bio");
+    assert_nullable_string(profile, "image");
+    assert!(profile["following"].is_boolean());
+}
+
+/// The spec's article, with the author's id in `userId` as well as their profile.
+fn assert_article(article: &Value) {
+    let fields = &[
+        "slug",
+        "title",
+        "description",
+        "body",
+        "status",
+        "visibility",
+        "createdAt",
+        "updatedAt",
+    ];
+    for field in fields {
+        assert_string(article, field);
+    }
+    assert!(article["tagList"].is_array());
+    assert!(article["favorited"].is_boolean());
+    assert!(article["favoritesCount"].is_i64());
+    assert!(article["commentsCount"].is_i64());
+    assert!(article["wordCount"].is_i64());
+    assert!(article["readingTime"].is_i64());
+    assert_nullable_string(article, "coverImage");
+    assert_nullable_string(article, "canonicalUrl");
+    assert!(article["userId"].is_i64());
+    assert_profile(&article["author"]);
+}
+
+/// The spec's comment, with the author given as `userId` like articles.
+fn assert_comment(comment: &Value) {
+    assert!(comment["id"].is_i64());
+    for field in &["body", "createdAt", "updatedAt"] {
+        assert_string(comment, field);
+    }
+    assert!(comment["userId"].is_i64());
+    assert!(comment["mentions"].is_array());
+}
+
+/// Register a user with a name no earlier run used, returning their username and password.
+fn register(api: &mut Api) -> (String, String) {
+    let username = format!("conformance{}", rand::thread_rng().gen::<u32>());
+    let password = "correct horse battery staple".to_string();
+    let (status, body) = api.post(
+        "/users",
+        json!({"user": {
+            "username": username,
+            "email": format!("{}@example.com", username),
+            "password": password,
+        }}),
+    );
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_user(&body["user"]);
+    assert_eq!(body["user"]["username"], username.as_str());
+    (username, password)
+}
+
+fn login(api: &mut Api, username: &str, password: &str) {
+    let (status, body) = api.post(
+        "/users/login",
+        json!({"user": {"email": format!("{}@example.com", username), "password": password}}),
+    );
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_user(&body["user"]);
+    api.token = Some(body["user"]["token"].as_str().unwrap().to_string());
+}
+
+fn auth(api: &mut Api) -> String {
+    let (username, password) = register(api);
+
+    let (status, body) = api.post(
+        "/users",
+        json!({"user": {
+            "username": username,
+            "email": format!("{}@example.com", username),
+            "password": password,
+        }}),
+    );
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"].is_object());
+
+    login(api, &username, &password);
+    let (status, body) = api.get("/user");
+    assert_eq!(status, StatusCode::OK);
+    assert_user(&body["user"]);
+
+    let (status, body) = api.put("/user", json!({"user": {"bio": "I like to skateboard"}}));
+    assert_eq!(status, StatusCode::OK);
+    assert_user(&body["user"]);
+    assert_eq!(body["user"]["bio"], "I like to skateboard");
+    username
+}
+
+fn articles_and_comments(api: &mut Api, username: &str) {
+    let (status, body) = api.post(
+        "/articles",
+        json!({"article": {
+            "title": "How to train your dragon",
+            "description": "Ever wonder how?",
+            "body": "Very carefully.",
+            "tagList": ["training", "dragons"],
+        }}),
+    );
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let article = &body["article"];
+    assert_article(article);
+    assert_eq!(article["tagList"], json!(["training", "dragons"]));
+    assert_eq!(article["favorited"], false);
+    assert_eq!(article["favoritesCount"], 0);
+    let slug = article["slug"].as_str().unwrap().to_string();
+
+    let (status, body) = api.get(&format!("/articles?author={}", username));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["articlesCount"], 1);
+    assert_article(&body["articles"][0]);
+
+    let (status, body) = api.get("/articles/feed");
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["articles"].is_array());
+    assert!(body["articlesCount"].is_i64());
+
+    let (status, body) = api.get(&format!("/articles/{}", slug));
+    assert_eq!(status, StatusCode::OK);
+    assert_article(&body["article"]);
+
+    let (status, body) = api.put(
+        &format!("/articles/{}", slug),
+        json!({"article": {"body": "With two hands"}}),
+    );
+    assert_eq!(status, StatusCode::OK);
+    assert_article(&body["article"]);
+    assert_eq!(body["article"]["body"], "With two hands");
+
+    let (status, body) = api.post(&format!("/articles/{}/favorite", slug), json!({}));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["favorited"], true);
+    assert_eq!(body["article"]["favoritesCount"], 1);
+
+    let (status, body) = api.get(&format!("/articles?favorited={}", username));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["articles"][0]["slug"], slug.as_str());
+
+    let (status, body) = api.delete(&format!("/articles/{}/favorite", slug));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["favorited"], false);
+    assert_eq!(body["article"]["favoritesCount"], 0);
+
+    let (status, body) = api.post(
+        &format!("/articles/{}/comments", slug),
+        json!({"comment": {"body": "Thank you so much!"}}),
+    );
+    assert_eq!(status, StatusCode::OK);
+    assert_comment(&body["comment"]);
+    let comment_id = body["comment"]["id"].as_i64().unwrap();
+
+    let (status, body) = api.get(&format!("/articles/{}/comments", slug));
+    assert_eq!(status, StatusCode::OK);
+    assert_comment(&body["comments"][0]);
+
+    let (status, _) = api.delete(&format!("/articles/{}/comments/{}", slug, comment_id));
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = api.get("/tags");
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["tags"].as_array().unwrap().contains(&json!("dragons")));
+
+    let (status, _) = api.delete(&format!("/articles/{}", slug));
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = api.get(&format!("/articles/{}", slug));
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+fn profiles(api: &mut Api) {
+    let signed_in = api.token.take();
+    let (celeb, _) = register(api);
+    api.token = signed_in;
+
+    let (status, body) = api.get(&format!("/profiles/{}", celeb));
+    assert_eq!(status, StatusCode::OK);
+    assert_profile(&body["profile"]);
+    assert_eq!(body["profile"]["following"], false);
+
+    let (status, body) = api.post(&format!("/profiles/{}/follow", celeb), json!({}));
+    assert_eq!(status, StatusCode::OK);
+    assert_profile(&body["profile"]);
+    assert_eq!(body["profile"]["following"], true);
+
+    let (status, body) = api.delete(&format!("/profiles/{}/follow", celeb));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["profile"]["following"], false);
+}
+
+#[test]
+fn realworld_scenarios() {
+    let server = Server::start();
+    let mut api = Api::new(&server);
+
+    let (status, body) = api.get("/user");
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body["errors"].is_object());
+
+    let username = auth(&mut api);
+    articles_and_comments(&mut api, &username);
+    profiles(&mut api);
+}